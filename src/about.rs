@@ -41,7 +41,7 @@ pub fn present_window(transient_for: Option<&impl IsA<gtk::Window>>) {
     win.present();
 }
 
-fn debug_info() -> String {
+pub fn debug_info() -> String {
     let is_flatpak = Path::new("/.flatpak-info").exists();
 
     let language_names = glib::language_names().join(", ");
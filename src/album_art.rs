@@ -1,107 +1,1515 @@
 use std::{
-    cell::{OnceCell, RefCell},
+    cell::{Cell, OnceCell, RefCell},
     collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
     rc::Rc,
+    time::{Duration, Instant},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use async_lock::Semaphore;
+use async_trait::async_trait;
 use futures_util::lock::Mutex;
-use gtk::{gdk, glib};
+use gtk::{
+    gdk, gdk_pixbuf, gio,
+    glib::{self, translate::IntoGlib},
+    prelude::*,
+};
 use soup::prelude::*;
 
+use std::{error, fmt};
+
+use crate::{rate_limiter, settings::AlbumArtCacheFormat, utils};
+
 // TODO
-// - Don't load AlbumArt if network is metered
-// - Retry downloading once network is back
+// - Retry downloading automatically once network is back
 // - Integrate more with AlbumCover widget
-// - Load only at most n AlbumArt at a time
-// - Sanitize the arbitrary data downloaded before converting it to texture
+
+/// Delay before a failed album art can be retried for the first time. This is
+/// doubled on every succeeding failure.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Delay before downloading again after a transient failure, e.g., the server
+/// is overloaded, within the same load. This is doubled on every attempt.
+const DOWNLOAD_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+/// Longest delay waited within the same load, including the one the server
+/// asks for. Longer ones fail the load instead, so it is retried later.
+const DOWNLOAD_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+const DEFAULT_DOWNLOAD_ATTEMPTS: u32 = 3;
+
+/// Maximum number of sampled pixels per row and column when finding the
+/// dominant color
+const DOMINANT_COLOR_SAMPLE_SIZE: usize = 64;
+
+/// Returns how long to wait after the last failure before retrying.
+fn retry_delay(n_failures: u32) -> Duration {
+    if n_failures == 0 {
+        return Duration::ZERO;
+    }
+
+    RETRY_BASE_DELAY
+        .saturating_mul(2_u32.saturating_pow(n_failures - 1))
+        .min(RETRY_MAX_DELAY)
+}
+
+/// Returns how long to wait before the next attempt of a download that failed
+/// transiently `n_attempts` times, or `None` if it must not be attempted
+/// again within the same load.
+fn download_retry_delay(
+    n_attempts: u32,
+    max_attempts: u32,
+    retry_after: Option<Duration>,
+) -> Option<Duration> {
+    if n_attempts >= max_attempts {
+        return None;
+    }
+
+    let delay = retry_after.unwrap_or_else(|| {
+        DOWNLOAD_RETRY_BASE_DELAY.saturating_mul(2_u32.saturating_pow(n_attempts - 1))
+    });
+    (delay <= DOWNLOAD_RETRY_MAX_DELAY).then_some(delay)
+}
+
+/// The server responded with an unsuccessful HTTP status
+#[derive(Debug)]
+struct HttpError {
+    status: u32,
+    /// Set from the server's `Retry-After`
+    retry_after: Option<Duration>,
+}
+
+impl HttpError {
+    /// Whether downloading again later may succeed, i.e., the server is rate
+    /// limiting or failing temporarily
+    fn is_transient(&self) -> bool {
+        self.status == 429 || (500..600).contains(&self.status)
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Server responded with HTTP status {}", self.status)
+    }
+}
+
+impl error::Error for HttpError {}
+
+/// Downloads the raw bytes of album arts.
+#[async_trait(?Send)]
+trait Downloader {
+    /// Unsuccessful responses must fail with an [`HttpError`], so transient
+    /// failures can be retried.
+    async fn download(&self, url: &str) -> Result<glib::Bytes>;
+}
+
+#[async_trait(?Send)]
+impl Downloader for soup::Session {
+    async fn download(&self, url: &str) -> Result<glib::Bytes> {
+        let message = soup::Message::new("GET", url)?;
+        // Album arts are already stored in our own disk cache
+        message.disable_feature(soup::Cache::static_type());
+
+        let bytes = self
+            .send_and_read_future(&message, glib::Priority::LOW)
+            .await?;
+
+        let status = message.status().into_glib() as u32;
+        if !(200..300).contains(&status) {
+            let retry_after = message
+                .response_headers()
+                .and_then(|headers| headers.one("Retry-After"))
+                .and_then(|value| rate_limiter::parse_retry_after(&value));
+            return Err(HttpError {
+                status,
+                retry_after,
+            }
+            .into());
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// How an album art is loaded by [`AlbumArt::load`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadKind {
+    /// Only download if automatic downloading is enabled
+    Auto,
+    /// Download even if automatic downloading is disabled
+    Force,
+    /// Download again after it failed, waiting for the backoff if needed
+    Retry,
+}
+
+/// Handle to an album art load started by [`AlbumArt::load`].
+///
+/// The load is cancelled when this is dropped, so it must be kept alive for as
+/// long as the result is needed, e.g., while the widget showing it is mapped.
+#[derive(Debug)]
+#[must_use = "the load is cancelled when the request is dropped"]
+pub struct AlbumArtRequest {
+    cancellable: gio::Cancellable,
+}
+
+impl AlbumArtRequest {
+    /// Stops the load, including the download if it is running or queued.
+    /// The callback is not called afterwards.
+    pub fn cancel(&self) {
+        self.cancellable.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellable.is_cancelled()
+    }
+}
+
+impl Drop for AlbumArtRequest {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// How album arts are stored in the disk cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheOptions {
+    /// Whether to store the downloaded bytes as is
+    pub keep_original: bool,
+    pub format: AlbumArtCacheFormat,
+    /// Maximum width and height, or 0 for no limit
+    pub max_size: u32,
+    /// Quality from 1 to 100, only used for lossy formats
+    pub quality: u32,
+    /// Maximum total size of the cache dir in bytes, or 0 for no limit
+    pub max_total_size: u64,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            keep_original: true,
+            format: AlbumArtCacheFormat::Png,
+            max_size: 0,
+            quality: 90,
+            max_total_size: 0,
+        }
+    }
+}
+
+impl AlbumArtCacheFormat {
+    fn pixbuf_format_name(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpeg",
+            Self::Webp => "webp",
+            Self::Avif => "avif",
+        }
+    }
+
+    fn is_lossy(self) -> bool {
+        !matches!(self, Self::Png)
+    }
+
+    /// Whether the installed gdk-pixbuf loaders can save in this format
+    fn is_writable(self) -> bool {
+        gdk_pixbuf::Pixbuf::formats().iter().any(|format| {
+            format.is_writable() && format.name().as_deref() == Some(self.pixbuf_format_name())
+        })
+    }
+}
+
+/// Returns the bytes to store in the cache, i.e., the album art scaled down
+/// and converted according to `options`, and the texture of the album art.
+///
+/// The image is only decoded once, and this fails if `bytes` can't be
+/// decoded, so invalid downloads are never cached. This blocks, so it must be
+/// called on a separate thread.
+fn prepare_for_cache(
+    bytes: &glib::Bytes,
+    options: CacheOptions,
+) -> Result<(glib::Bytes, gdk::Texture)> {
+    let pixbuf = gdk_pixbuf::Pixbuf::from_stream(
+        &gio::MemoryInputStream::from_bytes(bytes),
+        gio::Cancellable::NONE,
+    )
+    .context("Album art is not a valid image")?;
+
+    if options.keep_original {
+        return Ok((bytes.clone(), gdk::Texture::for_pixbuf(&pixbuf)));
+    }
+
+    if !options.format.is_writable() {
+        tracing::warn!(format = ?options.format, "Album art cache format is not supported, keeping original");
+        return Ok((bytes.clone(), gdk::Texture::for_pixbuf(&pixbuf)));
+    }
+
+    let (width, height) = (pixbuf.width() as u32, pixbuf.height() as u32);
+    let pixbuf = match scaled_size(width, height, options.max_size) {
+        Some((scaled_width, scaled_height)) => pixbuf
+            .scale_simple(
+                scaled_width as i32,
+                scaled_height as i32,
+                gdk_pixbuf::InterpType::Bilinear,
+            )
+            .context("Failed to scale album art")?,
+        None => pixbuf,
+    };
+
+    let quality = options.quality.clamp(1, 100).to_string();
+    let save_options = if options.format.is_lossy() {
+        vec![("quality", quality.as_str())]
+    } else {
+        Vec::new()
+    };
+    let converted = pixbuf
+        .save_to_bufferv(options.format.pixbuf_format_name(), &save_options)
+        .with_context(|| format!("Failed to convert album art to {:?}", options.format))?;
+    let texture = gdk::Texture::for_pixbuf(&pixbuf);
+
+    // Converting a small and already compressed image may make it bigger
+    if converted.len() >= bytes.len() && pixbuf.width() as u32 == width {
+        return Ok((bytes.clone(), texture));
+    }
+
+    Ok((glib::Bytes::from_owned(converted), texture))
+}
+
+/// Loads the album art stored in the cache, or `None` if it is not cached,
+/// and marks it as recently used.
+///
+/// This blocks, so it must be called on a separate thread.
+fn load_from_cache(cache_file: &gio::File) -> Result<Option<gdk::Texture>> {
+    let bytes = match cache_file.load_bytes(gio::Cancellable::NONE) {
+        Ok((bytes, _)) => bytes,
+        Err(err) if err.matches(gio::IOErrorEnum::NotFound) => return Ok(None),
+        Err(err) => return Err(err).context("Failed to read cached album art"),
+    };
+
+    // Whatever format it was stored in, it can be loaded as long as it is
+    // supported by the system
+    let texture = gdk::Texture::from_bytes(&bytes).context("Failed to decode cached album art")?;
+
+    // The least recently used are the first to be pruned
+    if let Err(err) = cache_file.set_attribute_uint64(
+        gio::FILE_ATTRIBUTE_TIME_MODIFIED,
+        (glib::real_time() / 1_000_000) as u64,
+        gio::FileQueryInfoFlags::NONE,
+        gio::Cancellable::NONE,
+    ) {
+        tracing::debug!("Failed to mark cached album art as used: {:?}", err);
+    }
+
+    Ok(Some(texture))
+}
+
+/// Removes the least recently used album arts from `cache_dir` until their
+/// total size is within `max_total_size` bytes, returning the number of
+/// removed ones.
+///
+/// This blocks, so it must be called on a separate thread.
+fn prune_cache(cache_dir: &Path, max_total_size: u64) -> Result<usize> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(cache_dir).context("Failed to read cache dir")? {
+        let entry = entry.context("Failed to read cache dir entry")?;
+        let metadata = entry.metadata().context("Failed to query cache file")?;
+        if metadata.is_file() {
+            entries.push((entry.path(), metadata.len(), metadata.modified()?));
+        }
+    }
+
+    let mut total_size = entries.iter().map(|(_, size, _)| size).sum::<u64>();
+    if total_size <= max_total_size {
+        return Ok(0);
+    }
+
+    entries.sort_by_key(|(_, _, modified)| *modified);
+
+    let mut n_removed = 0;
+    for (path, size, _) in entries {
+        if total_size <= max_total_size {
+            break;
+        }
+
+        fs::remove_file(&path).with_context(|| format!("Failed to remove `{}`", path.display()))?;
+        total_size -= size;
+        n_removed += 1;
+    }
+
+    Ok(n_removed)
+}
+
+/// Returns the size that fits within `max_size` while keeping the aspect
+/// ratio, or `None` if it already fits.
+fn scaled_size(width: u32, height: u32, max_size: u32) -> Option<(u32, u32)> {
+    if max_size == 0 || (width <= max_size && height <= max_size) {
+        return None;
+    }
+
+    let scale = max_size as f64 / width.max(height) as f64;
+    Some((
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    ))
+}
+
+/// Returns the most common color of the texture, ignoring transparent pixels,
+/// or `None` if it is fully transparent.
+///
+/// Similar colors are grouped together, and the average of the largest group
+/// is returned.
+pub fn dominant_color(texture: &gdk::Texture) -> Option<gdk::RGBA> {
+    let mut downloader = gdk::TextureDownloader::new(texture);
+    downloader.set_format(gdk::MemoryFormat::R8g8b8a8);
+    let (bytes, stride) = downloader.download_bytes();
+
+    let width = texture.width() as usize;
+    let height = texture.height() as usize;
+    let step = (width.max(height) / DOMINANT_COLOR_SAMPLE_SIZE).max(1);
+
+    // Keyed by the 3 most significant bits of each channel
+    let mut groups = HashMap::<(u8, u8, u8), (u32, [u32; 3])>::new();
+    for y in (0..height).step_by(step) {
+        for x in (0..width).step_by(step) {
+            let offset = y * stride + x * 4;
+            let [r, g, b, a] = [
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ];
+
+            if a < 128 {
+                continue;
+            }
+
+            let (n_pixels, sums) = groups.entry((r >> 5, g >> 5, b >> 5)).or_default();
+            *n_pixels += 1;
+            sums[0] += r as u32;
+            sums[1] += g as u32;
+            sums[2] += b as u32;
+        }
+    }
+
+    let (n_pixels, sums) = groups.into_values().max_by_key(|(n_pixels, _)| *n_pixels)?;
+
+    Some(gdk::RGBA::new(
+        sums[0] as f32 / n_pixels as f32 / 255.0,
+        sums[1] as f32 / n_pixels as f32 / 255.0,
+        sums[2] as f32 / n_pixels as f32 / 255.0,
+        1.0,
+    ))
+}
+
+/// Returns a texture filled with the `rgba` pixel, e.g., to test colors
+/// derived from album arts.
+#[cfg(test)]
+pub fn new_solid_texture(width: i32, height: i32, rgba: [u8; 4]) -> gdk::Texture {
+    gdk::MemoryTexture::new(
+        width,
+        height,
+        gdk::MemoryFormat::R8g8b8a8,
+        &glib::Bytes::from_owned(rgba.repeat((width * height) as usize)),
+        width as usize * 4,
+    )
+    .upcast()
+}
+
+/// State shared between the store and all of its album arts
+struct Shared {
+    downloader: Box<dyn Downloader>,
+    /// Limits the number of downloads running at the same time
+    download_slots: Semaphore,
+    auto_download: Cell<bool>,
+    /// Whether only cached album arts are loaded
+    is_offline: Cell<bool>,
+    /// Where downloaded album arts are stored, or `None` to not store them
+    cache_dir: RefCell<Option<PathBuf>>,
+    cache_options: Cell<CacheOptions>,
+    /// Maximum number of times a download is attempted within the same
+    /// load if it fails transiently
+    download_attempts: Cell<u32>,
+}
+
+impl Shared {
+    fn new(downloader: impl Downloader + 'static, max_concurrent_downloads: usize) -> Self {
+        Self {
+            downloader: Box::new(downloader),
+            download_slots: Semaphore::new(max_concurrent_downloads.max(1)),
+            auto_download: Cell::new(true),
+            is_offline: Cell::new(false),
+            cache_dir: RefCell::new(None),
+            cache_options: Cell::new(CacheOptions::default()),
+            download_attempts: Cell::new(DEFAULT_DOWNLOAD_ATTEMPTS),
+        }
+    }
+}
 
 pub struct AlbumArtStore {
-    session: soup::Session,
+    shared: Rc<Shared>,
     map: RefCell<HashMap<String, Rc<AlbumArt>>>,
 }
 
 impl AlbumArtStore {
-    pub fn new(session: soup::Session) -> Self {
+    /// Downloads exceeding `max_concurrent_downloads` are queued until a
+    /// running one finishes.
+    pub fn new(session: soup::Session, max_concurrent_downloads: usize) -> Self {
         // TODO Remove from store on low memory (Use LRU Cache)
 
         Self {
-            session,
+            shared: Rc::new(Shared::new(session, max_concurrent_downloads)),
             map: RefCell::default(),
         }
     }
 
+    /// Sets whether album arts are downloaded without explicit user request.
+    pub fn set_auto_download(&self, auto_download: bool) {
+        self.shared.auto_download.set(auto_download);
+    }
+
+    pub fn auto_download(&self) -> bool {
+        self.shared.auto_download.get()
+    }
+
+    /// Sets whether album arts are only loaded from the cache, without
+    /// downloading anything.
+    pub fn set_offline(&self, is_offline: bool) {
+        self.shared.is_offline.set(is_offline);
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.shared.is_offline.get()
+    }
+
+    /// Sets where downloaded album arts are stored, so they are not
+    /// downloaded again on next start.
+    pub fn set_cache_dir(&self, cache_dir: PathBuf) {
+        self.shared.cache_dir.replace(Some(cache_dir));
+    }
+
+    /// Sets how newly downloaded album arts are stored. Already cached ones
+    /// are kept as is.
+    pub fn set_cache_options(&self, options: CacheOptions) {
+        self.shared.cache_options.set(options);
+    }
+
+    /// Sets how many times a download is attempted when the server fails
+    /// transiently, e.g., with HTTP 429 or 503, before the load fails.
+    pub fn set_download_attempts(&self, download_attempts: u32) {
+        self.shared.download_attempts.set(download_attempts.max(1));
+    }
+
     pub fn get_or_init(&self, download_url: &str) -> Rc<AlbumArt> {
         Rc::clone(
             self.map
                 .borrow_mut()
                 .entry(download_url.to_string())
-                .or_insert_with(|| Rc::new(AlbumArt::new(self.session.clone(), download_url))),
+                .or_insert_with(|| Rc::new(AlbumArt::new(Rc::clone(&self.shared), download_url))),
         )
     }
+
+    /// Like [`Self::get_or_init`], but the album art falls back to the one at
+    /// `fallback_url` if it fails to load, e.g., when it does not exist.
+    pub fn get_or_init_with_fallback(
+        &self,
+        download_url: &str,
+        fallback_url: &str,
+    ) -> Rc<AlbumArt> {
+        let album_art = self.get_or_init(download_url);
+
+        if download_url != fallback_url && album_art.fallback.get().is_none() {
+            let _ = album_art.fallback.set(self.get_or_init(fallback_url));
+        }
+
+        album_art
+    }
+
+    /// Starts loading the album art in the background, so it is ready by the
+    /// time it is shown.
+    ///
+    /// This does nothing and returns `None` if automatic downloading is disabled.
+    pub fn prefetch(&self, download_url: &str) -> Option<Rc<AlbumArt>> {
+        if !self.auto_download() {
+            return None;
+        }
+
+        let album_art = self.get_or_init(download_url);
+
+        let album_art_clone = Rc::clone(&album_art);
+        utils::spawn(glib::Priority::LOW, async move {
+            if let Err(err) = album_art_clone.auto_texture().await {
+                tracing::debug!(
+                    download_url = album_art_clone.download_url(),
+                    "Failed to prefetch album art: {:?}",
+                    err
+                );
+            }
+        });
+
+        Some(album_art)
+    }
+
+    /// Forgets the album art and removes it from the cache dir, e.g., when
+    /// no song uses it anymore.
+    pub fn remove(&self, download_url: &str) -> Result<()> {
+        self.map.borrow_mut().remove(download_url);
+
+        let Some(cache_file) = AlbumArt::new(Rc::clone(&self.shared), download_url).cache_file()
+        else {
+            return Ok(());
+        };
+
+        match cache_file.delete(gio::Cancellable::NONE) {
+            Ok(()) => Ok(()),
+            Err(err) if err.matches(gio::IOErrorEnum::NotFound) => Ok(()),
+            Err(err) => Err(err).context("Failed to remove album art from cache"),
+        }
+    }
+
+    /// Downloads the album art into the cache dir without keeping it in
+    /// memory. This does nothing if it is already cached.
+    pub async fn cache(&self, download_url: &str) -> Result<()> {
+        let album_art = AlbumArt::new(Rc::clone(&self.shared), download_url);
+
+        let cache_file = album_art.cache_file().context("No cache dir set")?;
+        if cache_file.query_exists(gio::Cancellable::NONE) {
+            return Ok(());
+        }
+
+        album_art.download_texture().await?;
+
+        Ok(())
+    }
 }
 
 pub struct AlbumArt {
-    session: soup::Session,
+    shared: Rc<Shared>,
     download_url: String,
 
+    /// Number of consecutive failed downloads
+    n_failures: Cell<u32>,
+    last_failed: Cell<Option<Instant>>,
+    /// How long the server asked to wait after the last failure
+    last_failed_retry_after: Cell<Option<Duration>>,
+
     cache: OnceCell<gdk::Texture>,
     cache_guard: Mutex<()>,
+
+    /// Loaded instead if this fails to load
+    fallback: OnceCell<Rc<AlbumArt>>,
 }
 
 impl AlbumArt {
-    fn new(session: soup::Session, download_url: &str) -> Self {
+    fn new(shared: Rc<Shared>, download_url: &str) -> Self {
         Self {
-            session,
+            shared,
             download_url: download_url.to_string(),
+            n_failures: Cell::new(0),
+            last_failed: Cell::new(None),
+            last_failed_retry_after: Cell::new(None),
             cache: OnceCell::new(),
             cache_guard: Mutex::new(()),
+            fallback: OnceCell::new(),
         }
     }
 
-    /// Whether the album art is loaded in memory.
+    /// Whether the album art, or its fallback, is loaded in memory.
     pub fn is_loaded(&self) -> bool {
-        self.cache.get().is_some()
+        self.loaded_texture().is_some()
+    }
+
+    /// Returns the texture only if it, or the fallback, is already loaded
+    /// in memory.
+    pub fn loaded_texture(&self) -> Option<&gdk::Texture> {
+        self.cache.get().or_else(|| {
+            self.fallback
+                .get()
+                .and_then(|fallback| fallback.cache.get())
+        })
     }
 
     pub fn download_url(&self) -> &str {
         &self.download_url
     }
 
+    /// Whether the last download of the album art, and of its fallback if
+    /// it has one, failed.
+    pub fn is_failed(&self) -> bool {
+        self.n_failures.get() != 0
+            && self
+                .fallback
+                .get()
+                .map_or(true, |fallback| fallback.n_failures.get() != 0)
+    }
+
+    /// Returns how long until [`Self::retry`] is allowed. This grows on
+    /// repeated failures, and is at least as long as the server asked for.
+    pub fn retry_available_in(&self) -> Duration {
+        let Some(last_failed) = self.last_failed.get() else {
+            return Duration::ZERO;
+        };
+
+        let delay = retry_delay(self.n_failures.get())
+            .max(self.last_failed_retry_after.get().unwrap_or_default());
+        delay.saturating_sub(last_failed.elapsed())
+    }
+
+    /// Downloads the album art again after it failed.
+    pub async fn retry(&self) -> Result<&gdk::Texture> {
+        ensure!(
+            self.retry_available_in().is_zero(),
+            "Retrying album art download too soon"
+        );
+
+        self.texture().await
+    }
+
+    /// Like [`Self::texture`], but only downloads the texture if automatic
+    /// downloading is enabled. Otherwise, returns `None` if it is not loaded yet.
+    pub async fn auto_texture(&self) -> Result<Option<&gdk::Texture>> {
+        if !self.is_loaded() && !self.shared.auto_download.get() {
+            return Ok(None);
+        }
+
+        // Don't download failed album arts again until the failure expires
+        if !self.is_loaded() && self.is_failed() && !self.retry_available_in().is_zero() {
+            bail!("Album art recently failed to download");
+        }
+
+        self.texture().await.map(Some)
+    }
+
     pub async fn texture(&self) -> Result<&gdk::Texture> {
+        let Some(fallback) = self.fallback.get() else {
+            return self.own_texture().await;
+        };
+
+        // Don't download a missing album art every time, only once it
+        // could be retried.
+        if self.n_failures.get() == 0 || self.retry_available_in().is_zero() {
+            match self.own_texture().await {
+                Ok(texture) => return Ok(texture),
+                Err(err) => {
+                    tracing::debug!(
+                        download_url = ?self.download_url,
+                        fallback_url = ?fallback.download_url,
+                        "Failed to load album art, falling back: {:?}",
+                        err
+                    );
+                }
+            }
+        }
+
+        fallback.own_texture().await
+    }
+
+    async fn own_texture(&self) -> Result<&gdk::Texture> {
         let _guard = self.cache_guard.lock().await;
 
         if let Some(texture) = self.cache.get() {
             return Ok(texture);
         }
 
-        let bytes = self
-            .session
-            .send_and_read_future(
-                &soup::Message::new("GET", &self.download_url)?,
-                glib::Priority::LOW,
-            )
-            .await
-            .context("Failed to download album art bytes")?;
+        match self.download_texture().await {
+            Ok(texture) => {
+                self.n_failures.set(0);
+                self.last_failed.set(None);
+                self.last_failed_retry_after.set(None);
+
+                self.cache.set(texture).unwrap();
+
+                Ok(self.cache.get().unwrap())
+            }
+            Err(err) => {
+                // Not being able to download while offline is not a failure
+                if !self.shared.is_offline.get() {
+                    self.n_failures.set(self.n_failures.get().saturating_add(1));
+                    self.last_failed.set(Some(Instant::now()));
+                    self.last_failed_retry_after.set(
+                        err.downcast_ref::<HttpError>()
+                            .and_then(|err| err.retry_after),
+                    );
+                }
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Loads the texture in the background, returning immediately.
+    ///
+    /// `callback` is called with the texture once loaded, or with `None` if
+    /// it is not downloaded automatically. It is never called if the returned
+    /// request is cancelled or dropped before that.
+    pub fn load(
+        self: &Rc<Self>,
+        kind: LoadKind,
+        callback: impl FnOnce(Result<Option<gdk::Texture>>) + 'static,
+    ) -> AlbumArtRequest {
+        let cancellable = gio::Cancellable::new();
+
+        let this = Rc::clone(self);
+        let fut = async move {
+            match kind {
+                LoadKind::Auto => this.auto_texture().await.map(|texture| texture.cloned()),
+                LoadKind::Force => this.texture().await.map(|texture| Some(texture.clone())),
+                LoadKind::Retry => {
+                    glib::timeout_future(this.retry_available_in()).await;
+                    this.retry().await.map(|texture| Some(texture.clone()))
+                }
+            }
+        };
+
+        // Dropping the future also drops the download and its slot, so
+        // cancelled loads don't use any bandwidth past this point.
+        let cancellable_fut = gio::CancellableFuture::new(fut, cancellable.clone());
+        utils::spawn(glib::Priority::LOW, async move {
+            match cancellable_fut.await {
+                Ok(res) => callback(res),
+                Err(_) => tracing::trace!("Cancelled loading album art"),
+            }
+        });
+
+        AlbumArtRequest { cancellable }
+    }
+
+    async fn download_texture(&self) -> Result<gdk::Texture> {
+        let cache_file = self.cache_file();
+
+        if let Some(cache_file) = cache_file.clone() {
+            match gio::spawn_blocking(move || load_from_cache(&cache_file))
+                .await
+                .map_err(|err| anyhow!("Failed to spawn blocking task: {:?}", err))
+                .and_then(|res| res)
+            {
+                Ok(Some(texture)) => return Ok(texture),
+                Ok(None) => {}
+                Err(err) => tracing::warn!("Failed to load cached album art: {:?}", err),
+            }
+        }
+
+        ensure!(
+            !self.shared.is_offline.get(),
+            "Album art is not cached and offline mode is active"
+        );
+
+        let bytes = self.download_bytes().await?;
         tracing::trace!(download_url = ?self.download_url, "Downloaded album art bytes");
 
-        let texture = gdk::Texture::from_bytes(&bytes)
-            .context("Failed to load album art texture from bytes")?;
-        self.cache.set(texture).unwrap();
+        let options = self.shared.cache_options.get();
+        let (bytes, texture) = gio::spawn_blocking(move || prepare_for_cache(&bytes, options))
+            .await
+            .map_err(|err| anyhow!("Failed to spawn blocking task: {:?}", err))
+            .and_then(|res| res)?;
+
+        if let Some(cache_file) = cache_file {
+            if let Err(err) = store_to_cache(&cache_file, &bytes).await {
+                tracing::warn!("Failed to store album art to cache: {:?}", err);
+            } else if options.max_total_size > 0 {
+                self.prune_cache(options.max_total_size).await;
+            }
+        }
 
-        Ok(self.cache.get().unwrap())
+        Ok(texture)
+    }
+
+    async fn prune_cache(&self, max_total_size: u64) {
+        let Some(cache_dir) = self.shared.cache_dir.borrow().clone() else {
+            return;
+        };
+
+        match gio::spawn_blocking(move || prune_cache(&cache_dir, max_total_size))
+            .await
+            .map_err(|err| anyhow!("Failed to spawn blocking task: {:?}", err))
+            .and_then(|res| res)
+        {
+            Ok(0) => {}
+            Ok(n_removed) => tracing::debug!(n_removed, "Pruned album art cache"),
+            Err(err) => tracing::warn!("Failed to prune album art cache: {:?}", err),
+        }
+    }
+
+    /// Downloads the bytes, attempting again with a backoff if the server
+    /// fails transiently.
+    async fn download_bytes(&self) -> Result<glib::Bytes> {
+        let max_attempts = self.shared.download_attempts.get();
+        let mut n_attempts = 0;
+
+        loop {
+            n_attempts += 1;
+
+            let res = {
+                // This is released when the download finishes or when it is
+                // dropped, e.g., when the album cover requesting it is scrolled
+                // out of view, and while waiting to download again.
+                let _slot = self.shared.download_slots.acquire().await;
+                self.shared.downloader.download(&self.download_url).await
+            };
+
+            let err = match res {
+                Ok(bytes) => return Ok(bytes),
+                Err(err) => err,
+            };
+
+            let delay = err
+                .downcast_ref::<HttpError>()
+                .filter(|err| err.is_transient())
+                .and_then(|http_err| {
+                    download_retry_delay(n_attempts, max_attempts, http_err.retry_after)
+                });
+            let Some(delay) = delay else {
+                return Err(err).context("Failed to download album art bytes");
+            };
+
+            tracing::debug!(
+                download_url = ?self.download_url,
+                n_attempts,
+                ?delay,
+                "Album art download failed transiently; trying again: {:?}",
+                err
+            );
+            glib::timeout_future(delay).await;
+        }
+    }
+
+    fn cache_file(&self) -> Option<gio::File> {
+        let cache_dir = self.shared.cache_dir.borrow();
+        let cache_dir = cache_dir.as_ref()?;
+        let file_name =
+            glib::compute_checksum_for_str(glib::ChecksumType::Sha256, &self.download_url)?;
+        Some(gio::File::for_path(cache_dir.join(file_name.as_str())))
     }
 }
 
+async fn store_to_cache(cache_file: &gio::File, bytes: &glib::Bytes) -> Result<()> {
+    if let Some(parent) = cache_file.parent() {
+        if let Err(err) = parent.make_directory_with_parents(gio::Cancellable::NONE) {
+            if !err.matches(gio::IOErrorEnum::Exists) {
+                return Err(err).context("Failed to create cache dir");
+            }
+        }
+    }
+
+    cache_file
+        .replace_contents_future(
+            bytes.clone(),
+            None,
+            false,
+            gio::FileCreateFlags::REPLACE_DESTINATION,
+        )
+        .await
+        .map_err(|(_, err)| err)
+        .context("Failed to write album art to cache")?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use std::collections::VecDeque;
+
     use futures_util::future;
 
+    /// Returns the queued results in order
+    #[derive(Default)]
+    struct StubDownloader(RefCell<VecDeque<Result<glib::Bytes>>>);
+
+    #[async_trait(?Send)]
+    impl Downloader for StubDownloader {
+        async fn download(&self, _url: &str) -> Result<glib::Bytes> {
+            self.0.borrow_mut().pop_front().unwrap()
+        }
+    }
+
+    /// Keeps track of the maximum number of downloads running at once
+    #[derive(Default)]
+    struct ConcurrencyTrackingDownloader {
+        n_running: Rc<Cell<u32>>,
+        max_running: Rc<Cell<u32>>,
+    }
+
+    #[async_trait(?Send)]
+    impl Downloader for ConcurrencyTrackingDownloader {
+        async fn download(&self, _url: &str) -> Result<glib::Bytes> {
+            self.n_running.set(self.n_running.get() + 1);
+            self.max_running
+                .set(self.max_running.get().max(self.n_running.get()));
+
+            glib::timeout_future(Duration::from_millis(20)).await;
+
+            self.n_running.set(self.n_running.get() - 1);
+            Ok(png_bytes())
+        }
+    }
+
+    /// Takes a while to download, counting the started and finished downloads
+    #[derive(Default)]
+    struct SlowDownloader {
+        n_started: Rc<Cell<u32>>,
+        n_finished: Rc<Cell<u32>>,
+    }
+
+    #[async_trait(?Send)]
+    impl Downloader for SlowDownloader {
+        async fn download(&self, _url: &str) -> Result<glib::Bytes> {
+            self.n_started.set(self.n_started.get() + 1);
+            glib::timeout_future(Duration::from_millis(50)).await;
+            self.n_finished.set(self.n_finished.get() + 1);
+            Ok(png_bytes())
+        }
+    }
+
+    fn png_bytes() -> glib::Bytes {
+        let texture = gdk::MemoryTexture::new(
+            1,
+            1,
+            gdk::MemoryFormat::R8g8b8a8,
+            &glib::Bytes::from_static(&[0, 0, 0, 255]),
+            4,
+        );
+        texture.save_to_png_bytes()
+    }
+
+    fn large_png_bytes() -> glib::Bytes {
+        let pixbuf =
+            gdk_pixbuf::Pixbuf::new(gdk_pixbuf::Colorspace::Rgb, false, 8, 2000, 1000).unwrap();
+        pixbuf.fill(0x336699ff);
+        glib::Bytes::from_owned(pixbuf.save_to_bufferv("png", &[]).unwrap())
+    }
+
+    fn texture_size(bytes: &glib::Bytes) -> (i32, i32) {
+        let texture = gdk::Texture::from_bytes(bytes).unwrap();
+        (texture.width(), texture.height())
+    }
+
+    #[test]
+    fn scaled_size_keeps_aspect_ratio() {
+        assert_eq!(scaled_size(2000, 1000, 0), None);
+        assert_eq!(scaled_size(300, 200, 300), None);
+        assert_eq!(scaled_size(2000, 1000, 300), Some((300, 150)));
+        assert_eq!(scaled_size(1000, 2000, 300), Some((150, 300)));
+        assert_eq!(scaled_size(3000, 1, 300), Some((300, 1)));
+    }
+
+    #[gtk::test]
+    fn prepare_for_cache_resized() {
+        let bytes = large_png_bytes();
+        let options = CacheOptions {
+            keep_original: false,
+            format: AlbumArtCacheFormat::Png,
+            max_size: 300,
+            quality: 90,
+            max_total_size: 0,
+        };
+
+        let (prepared, texture) = prepare_for_cache(&bytes, options).unwrap();
+        assert!(prepared.len() < bytes.len());
+        assert_eq!(texture_size(&prepared), (300, 150));
+        assert_eq!((texture.width(), texture.height()), (300, 150));
+
+        let (original, texture) = prepare_for_cache(
+            &bytes,
+            CacheOptions {
+                keep_original: true,
+                ..options
+            },
+        )
+        .unwrap();
+        assert_eq!(original, bytes);
+        assert_eq!((texture.width(), texture.height()), (2000, 1000));
+    }
+
+    #[gtk::test]
+    fn prepare_for_cache_invalid() {
+        let bytes = glib::Bytes::from_static(b"<html>Not found</html>");
+        assert!(prepare_for_cache(&bytes, CacheOptions::default()).is_err());
+        assert!(prepare_for_cache(
+            &bytes,
+            CacheOptions {
+                keep_original: false,
+                ..CacheOptions::default()
+            }
+        )
+        .is_err());
+    }
+
+    #[gtk::test]
+    async fn cached_to_disk() {
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        let downloader = StubDownloader::default();
+        downloader.0.borrow_mut().push_back(Ok(large_png_bytes()));
+        let shared = Rc::new(Shared::new(downloader, 4));
+        shared
+            .cache_dir
+            .replace(Some(cache_dir.path().to_path_buf()));
+        shared.cache_options.set(CacheOptions {
+            keep_original: false,
+            format: AlbumArtCacheFormat::Png,
+            max_size: 300,
+            quality: 90,
+            max_total_size: 0,
+        });
+
+        let download_url = "https://example.test/album-art.png";
+        let album_art = AlbumArt::new(Rc::clone(&shared), download_url);
+        let texture = album_art.texture().await.unwrap();
+        assert_eq!((texture.width(), texture.height()), (300, 150));
+
+        let (cached_bytes, _) = album_art
+            .cache_file()
+            .unwrap()
+            .load_contents_future()
+            .await
+            .unwrap();
+        assert!(cached_bytes.len() < large_png_bytes().len());
+
+        // Loaded from the disk, as the downloader has nothing left
+        let album_art = AlbumArt::new(shared, download_url);
+        let texture = album_art.texture().await.unwrap();
+        assert_eq!((texture.width(), texture.height()), (300, 150));
+    }
+
+    #[test]
+    fn prune_cache_least_recently_used() {
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        for (name, modified) in [("a", 1), ("b", 3), ("c", 2)] {
+            let path = cache_dir.path().join(name);
+            fs::write(&path, [0; 10]).unwrap();
+            gio::File::for_path(&path)
+                .set_attribute_uint64(
+                    gio::FILE_ATTRIBUTE_TIME_MODIFIED,
+                    modified,
+                    gio::FileQueryInfoFlags::NONE,
+                    gio::Cancellable::NONE,
+                )
+                .unwrap();
+        }
+
+        let remaining = || {
+            let mut names = fs::read_dir(cache_dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+
+        assert_eq!(prune_cache(cache_dir.path(), 30).unwrap(), 0);
+        assert_eq!(remaining(), ["a", "b", "c"]);
+
+        assert_eq!(prune_cache(cache_dir.path(), 25).unwrap(), 1);
+        assert_eq!(remaining(), ["b", "c"]);
+
+        assert_eq!(prune_cache(cache_dir.path(), 10).unwrap(), 1);
+        assert_eq!(remaining(), ["b"]);
+    }
+
+    #[gtk::test]
+    async fn cache() {
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        let downloader = StubDownloader::default();
+        downloader.0.borrow_mut().push_back(Ok(png_bytes()));
+        let store = AlbumArtStore {
+            shared: Rc::new(Shared::new(downloader, 4)),
+            map: RefCell::default(),
+        };
+        assert!(store
+            .cache("https://example.test/album-art.png")
+            .await
+            .is_err());
+
+        store.set_cache_dir(cache_dir.path().to_path_buf());
+        store
+            .cache("https://example.test/album-art.png")
+            .await
+            .unwrap();
+        assert!(!store
+            .get_or_init("https://example.test/album-art.png")
+            .is_loaded());
+
+        // Already cached, so the downloader is not called again
+        store
+            .cache("https://example.test/album-art.png")
+            .await
+            .unwrap();
+        assert!(store
+            .get_or_init("https://example.test/album-art.png")
+            .texture()
+            .await
+            .is_ok());
+    }
+
+    #[gtk::test]
+    async fn remove() {
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        let downloader = StubDownloader::default();
+        downloader.0.borrow_mut().push_back(Ok(png_bytes()));
+        let store = AlbumArtStore {
+            shared: Rc::new(Shared::new(downloader, 4)),
+            map: RefCell::default(),
+        };
+        store.set_cache_dir(cache_dir.path().to_path_buf());
+
+        let download_url = "https://example.test/album-art.png";
+        let album_art = store.get_or_init(download_url);
+        album_art.texture().await.unwrap();
+        let cache_file = album_art.cache_file().unwrap();
+        assert!(cache_file.query_exists(gio::Cancellable::NONE));
+
+        store.remove(download_url).unwrap();
+        assert!(!cache_file.query_exists(gio::Cancellable::NONE));
+        assert!(!Rc::ptr_eq(&album_art, &store.get_or_init(download_url)));
+
+        // Nothing to remove
+        store.remove(download_url).unwrap();
+    }
+
+    #[gtk::test]
+    async fn offline() {
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        // Panics when called, as it has nothing queued
+        let downloader = StubDownloader::default();
+        let store = AlbumArtStore {
+            shared: Rc::new(Shared::new(downloader, 4)),
+            map: RefCell::default(),
+        };
+        store.set_cache_dir(cache_dir.path().to_path_buf());
+        store.set_offline(true);
+
+        let album_art = store.get_or_init("https://example.test/album-art.png");
+        assert!(album_art.texture().await.is_err());
+        assert!(!album_art.is_failed());
+        assert!(store
+            .cache("https://example.test/album-art.png")
+            .await
+            .is_err());
+
+        // Cached ones are still served
+        AlbumArt::new(Rc::clone(&store.shared), "https://example.test/cached.png")
+            .cache_file()
+            .unwrap()
+            .replace_contents(
+                &png_bytes(),
+                None,
+                false,
+                gio::FileCreateFlags::NONE,
+                gio::Cancellable::NONE,
+            )
+            .unwrap();
+        assert!(store
+            .get_or_init("https://example.test/cached.png")
+            .texture()
+            .await
+            .is_ok());
+    }
+
+    #[gtk::test]
+    fn dominant_color_solid() {
+        let texture = new_solid_texture(300, 300, [51, 102, 153, 255]);
+        assert_eq!(
+            dominant_color(&texture),
+            Some(gdk::RGBA::new(0.2, 0.4, 0.6, 1.0))
+        );
+
+        let texture = new_solid_texture(4, 4, [0, 0, 0, 0]);
+        assert_eq!(dominant_color(&texture), None);
+    }
+
+    #[gtk::test]
+    fn dominant_color_majority() {
+        // Left three quarters are red, the rest is blue
+        let width = 8;
+        let height = 8;
+        let bytes = (0..height)
+            .flat_map(|_| {
+                (0..width).map(|x| {
+                    if x < 6 {
+                        [200, 10, 10, 255]
+                    } else {
+                        [10, 10, 200, 255]
+                    }
+                })
+            })
+            .flatten()
+            .collect::<Vec<u8>>();
+        let texture = gdk::MemoryTexture::new(
+            width,
+            height,
+            gdk::MemoryFormat::R8g8b8a8,
+            &glib::Bytes::from_owned(bytes),
+            width as usize * 4,
+        );
+
+        let color = dominant_color(texture.upcast_ref()).unwrap();
+        assert!((color.red() - 200.0 / 255.0).abs() < 1e-6);
+        assert!((color.blue() - 10.0 / 255.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn retry_delay_backoff() {
+        assert_eq!(retry_delay(0), Duration::ZERO);
+        assert_eq!(retry_delay(1), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(2), RETRY_BASE_DELAY * 2);
+        assert_eq!(retry_delay(3), RETRY_BASE_DELAY * 4);
+        assert_eq!(retry_delay(100), RETRY_MAX_DELAY);
+    }
+
+    fn http_error(status: u32, retry_after: Option<Duration>) -> Result<glib::Bytes> {
+        Err(HttpError {
+            status,
+            retry_after,
+        }
+        .into())
+    }
+
+    #[test]
+    fn download_retry_delay_backoff() {
+        assert_eq!(
+            download_retry_delay(1, 3, None),
+            Some(DOWNLOAD_RETRY_BASE_DELAY)
+        );
+        assert_eq!(
+            download_retry_delay(2, 3, None),
+            Some(DOWNLOAD_RETRY_BASE_DELAY * 2)
+        );
+        assert_eq!(download_retry_delay(3, 3, None), None);
+        assert_eq!(download_retry_delay(20, 30, None), None);
+
+        // The server's delay is honored, unless it is too long to wait for
+        assert_eq!(
+            download_retry_delay(1, 3, Some(Duration::from_secs(5))),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            download_retry_delay(1, 3, Some(DOWNLOAD_RETRY_MAX_DELAY * 2)),
+            None
+        );
+    }
+
+    #[gtk::test]
+    async fn transient_failures_retried() {
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        let downloader = StubDownloader::default();
+        downloader.0.borrow_mut().extend([
+            http_error(503, Some(Duration::ZERO)),
+            http_error(429, Some(Duration::from_millis(10))),
+            Ok(png_bytes()),
+        ]);
+        let shared = Rc::new(Shared::new(downloader, 1));
+        shared
+            .cache_dir
+            .replace(Some(cache_dir.path().to_path_buf()));
+
+        let album_art = AlbumArt::new(shared, "https://example.test/album-art.png");
+        assert!(album_art.texture().await.is_ok());
+        assert!(album_art.is_loaded());
+        assert!(!album_art.is_failed());
+        assert!(album_art
+            .cache_file()
+            .unwrap()
+            .query_exists(gio::Cancellable::NONE));
+    }
+
+    #[gtk::test]
+    async fn transient_failures_exhausted() {
+        let downloader = StubDownloader::default();
+        downloader.0.borrow_mut().extend([
+            http_error(500, Some(Duration::ZERO)),
+            http_error(502, Some(Duration::ZERO)),
+            http_error(429, Some(DOWNLOAD_RETRY_MAX_DELAY * 4)),
+            http_error(404, None),
+            Ok(png_bytes()),
+        ]);
+        let shared = Rc::new(Shared::new(downloader, 4));
+        shared.download_attempts.set(5);
+
+        // Waiting for the server's delay is left to later loads
+        let album_art = AlbumArt::new(Rc::clone(&shared), "https://example.test/a.png");
+        assert!(album_art.texture().await.is_err());
+        assert!(album_art.is_failed());
+        assert!(album_art.retry_available_in() > DOWNLOAD_RETRY_MAX_DELAY * 3);
+        assert!(album_art.auto_texture().await.is_err());
+
+        // Permanent failures are not retried
+        let album_art = AlbumArt::new(Rc::clone(&shared), "https://example.test/b.png");
+        assert!(album_art.texture().await.is_err());
+        assert!(album_art.is_failed());
+
+        // Once the failure expires, it is downloaded automatically again
+        album_art
+            .last_failed
+            .set(Some(Instant::now() - RETRY_BASE_DELAY));
+        assert!(album_art.auto_texture().await.unwrap().is_some());
+        assert!(!album_art.is_failed());
+    }
+
+    #[gtk::test]
+    async fn failed_retry_loaded() {
+        let downloader = StubDownloader::default();
+        downloader
+            .0
+            .borrow_mut()
+            .extend([Err(anyhow::anyhow!("Network blip")), Ok(png_bytes())]);
+        let album_art = AlbumArt::new(
+            Rc::new(Shared::new(downloader, 4)),
+            "https://example.test/album-art.png",
+        );
+
+        assert!(album_art.texture().await.is_err());
+        assert!(album_art.is_failed());
+        assert!(!album_art.is_loaded());
+        assert!(!album_art.retry_available_in().is_zero());
+
+        // Failed album arts must not be downloaded again automatically
+        assert!(album_art.auto_texture().await.is_err());
+        // Nor retried before the delay
+        assert!(album_art.retry().await.is_err());
+
+        album_art
+            .last_failed
+            .set(Some(Instant::now() - RETRY_BASE_DELAY));
+        assert!(album_art.retry_available_in().is_zero());
+
+        assert!(album_art.retry().await.is_ok());
+        assert!(!album_art.is_failed());
+        assert!(album_art.is_loaded());
+        assert!(album_art.auto_texture().await.unwrap().is_some());
+    }
+
+    #[gtk::test]
+    async fn fallback() {
+        let downloader = StubDownloader::default();
+        downloader
+            .0
+            .borrow_mut()
+            .extend([Err(anyhow::anyhow!("Not found")), Ok(png_bytes())]);
+        let store = AlbumArtStore {
+            shared: Rc::new(Shared::new(downloader, 4)),
+            map: RefCell::default(),
+        };
+
+        let album_art = store.get_or_init_with_fallback(
+            "https://example.test/front.png",
+            "https://example.test/thumbnail.png",
+        );
+        assert!(album_art.texture().await.is_ok());
+        assert!(album_art.is_loaded());
+        assert!(!album_art.is_failed());
+        assert!(store
+            .get_or_init("https://example.test/thumbnail.png")
+            .is_loaded());
+
+        // The missing album art is not downloaded again right away, or the
+        // stub would panic
+        assert!(album_art.auto_texture().await.unwrap().is_some());
+        assert!(album_art.texture().await.is_ok());
+
+        assert!(Rc::ptr_eq(
+            &album_art,
+            &store.get_or_init_with_fallback(
+                "https://example.test/front.png",
+                "https://example.test/thumbnail.png"
+            )
+        ));
+    }
+
+    #[gtk::test]
+    async fn prefetch() {
+        let downloader = StubDownloader::default();
+        downloader.0.borrow_mut().push_back(Ok(png_bytes()));
+        let store = AlbumArtStore {
+            shared: Rc::new(Shared::new(downloader, 4)),
+            map: RefCell::default(),
+        };
+
+        store.set_auto_download(false);
+        assert!(store
+            .prefetch("https://example.test/album-art.png")
+            .is_none());
+        assert!(!store
+            .get_or_init("https://example.test/album-art.png")
+            .is_loaded());
+
+        store.set_auto_download(true);
+        let album_art = store
+            .prefetch("https://example.test/album-art.png")
+            .unwrap();
+        assert!(Rc::ptr_eq(
+            &album_art,
+            &store.get_or_init("https://example.test/album-art.png")
+        ));
+
+        for _ in 0..100 {
+            if album_art.is_loaded() {
+                break;
+            }
+            glib::timeout_future(Duration::from_millis(10)).await;
+        }
+        assert!(album_art.is_loaded());
+    }
+
+    #[gtk::test]
+    async fn load_cancelled() {
+        let downloader = SlowDownloader::default();
+        let n_started = Rc::clone(&downloader.n_started);
+        let n_finished = Rc::clone(&downloader.n_finished);
+        let album_art = Rc::new(AlbumArt::new(
+            Rc::new(Shared::new(downloader, 1)),
+            "https://example.test/album-art.png",
+        ));
+
+        let n_called = Rc::new(Cell::new(0));
+
+        let n_called_clone = Rc::clone(&n_called);
+        let request = album_art.load(LoadKind::Auto, move |_| {
+            n_called_clone.set(n_called_clone.get() + 1);
+        });
+        // Queued behind the first one, and dropped when cancelled
+        let other_album_art = Rc::new(AlbumArt::new(
+            Rc::clone(&album_art.shared),
+            "https://example.test/other-album-art.png",
+        ));
+        let n_called_clone = Rc::clone(&n_called);
+        let other_request = other_album_art.load(LoadKind::Auto, move |_| {
+            n_called_clone.set(n_called_clone.get() + 1);
+        });
+
+        glib::timeout_future(Duration::from_millis(10)).await;
+        assert_eq!(n_started.get(), 1);
+
+        request.cancel();
+        assert!(request.is_cancelled());
+        drop(other_request);
+
+        glib::timeout_future(Duration::from_millis(100)).await;
+        assert_eq!(n_started.get(), 1);
+        assert_eq!(n_finished.get(), 0);
+        assert_eq!(n_called.get(), 0);
+        assert!(!album_art.is_loaded());
+        // Cancelling is not a failure
+        assert!(!album_art.is_failed());
+
+        // Loads fine when not cancelled
+        let (sender, receiver) = futures_channel::oneshot::channel();
+        let _request = album_art.load(LoadKind::Auto, move |res| {
+            let _ = sender.send(res.map(|texture| texture.is_some()));
+        });
+        assert!(receiver.await.unwrap().unwrap());
+        assert_eq!(n_finished.get(), 1);
+        assert!(album_art.is_loaded());
+    }
+
     #[gtk::test]
     async fn identity() {
-        let store = AlbumArtStore::new(soup::Session::new());
+        let store = AlbumArtStore::new(soup::Session::new(), 4);
         let download_url =
             "https://www.google.com/images/branding/googlelogo/2x/googlelogo_color_272x92dp.png";
         let access_1 = store.get_or_init(download_url);
@@ -113,11 +1521,56 @@ mod test {
         );
     }
 
+    #[gtk::test]
+    async fn concurrent_downloads_limit() {
+        let downloader = ConcurrencyTrackingDownloader::default();
+        let max_running = Rc::clone(&downloader.max_running);
+        let shared = Rc::new(Shared::new(downloader, 2));
+
+        let album_arts = (0..6)
+            .map(|i| {
+                AlbumArt::new(
+                    Rc::clone(&shared),
+                    &format!("https://example.test/album-art-{}.png", i),
+                )
+            })
+            .collect::<Vec<_>>();
+        let results =
+            future::join_all(album_arts.iter().map(|album_art| album_art.texture())).await;
+
+        assert!(results.iter().all(|res| res.is_ok()));
+        assert_eq!(max_running.get(), 2);
+    }
+
+    #[gtk::test]
+    async fn auto_download_disabled() {
+        let store = AlbumArtStore::new(soup::Session::new(), 4);
+        store.set_auto_download(false);
+        assert!(!store.auto_download());
+
+        // This would fail if it was actually downloaded
+        let album_art = store.get_or_init("https://invalid.invalid/album-art.png");
+        assert!(album_art.auto_texture().await.unwrap().is_none());
+        assert!(!album_art.is_loaded());
+    }
+
+    #[gtk::test]
+    async fn auto_download_disabled_with_cache() {
+        let store = AlbumArtStore::new(soup::Session::new(), 4);
+        let download_url =
+            "https://www.google.com/images/branding/googlelogo/2x/googlelogo_color_272x92dp.png";
+        let album_art = store.get_or_init(download_url);
+        let texture = album_art.texture().await.unwrap();
+
+        store.set_auto_download(false);
+        assert_eq!(album_art.auto_texture().await.unwrap(), Some(texture));
+    }
+
     #[gtk::test]
     async fn download() {
         let download_url =
             "https://www.google.com/images/branding/googlelogo/2x/googlelogo_color_272x92dp.png";
-        let album_art = AlbumArt::new(soup::Session::new(), download_url);
+        let album_art = AlbumArt::new(Rc::new(Shared::new(soup::Session::new(), 4)), download_url);
         assert!(!album_art.is_loaded());
         assert_eq!(album_art.download_url(), download_url);
 
@@ -135,7 +1588,7 @@ mod test {
     async fn concurrent_downloads() {
         let download_url =
             "https://www.google.com/images/branding/googlelogo/2x/googlelogo_color_272x92dp.png";
-        let album_art = AlbumArt::new(soup::Session::new(), download_url);
+        let album_art = AlbumArt::new(Rc::new(Shared::new(soup::Session::new(), 4)), download_url);
 
         // Should not panic on the following line in `AlbumArt::texture`.
         // debug_assert!(self.guard.borrow().is_none());
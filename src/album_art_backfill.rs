@@ -0,0 +1,350 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use gtk::{gio, glib};
+
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{
+    album_art::AlbumArtStore, cancelled::Cancelled, itunes_search, song::Song, song_list::SongList,
+    uid::Uid,
+};
+
+/// Default interval between fetches, so the backfill does not compete with
+/// the album arts shown to the user.
+pub const DEFAULT_BACKFILL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Fetches album arts of songs in the background.
+#[async_trait(?Send)]
+pub trait AlbumArtFetcher {
+    /// Downloads the album art and stores it in the cache, if it is not
+    /// cached yet.
+    async fn fetch(&self, album_art_link: &str) -> Result<()>;
+
+    /// Looks up the album art link of a song that has none. Returns `None`
+    /// if no link could be found.
+    async fn resolve_link(&self, song: &Song) -> Result<Option<String>>;
+}
+
+#[async_trait(?Send)]
+impl AlbumArtFetcher for AlbumArtStore {
+    async fn fetch(&self, album_art_link: &str) -> Result<()> {
+        self.cache(album_art_link).await
+    }
+
+    async fn resolve_link(&self, song: &Song) -> Result<Option<String>> {
+        itunes_search::rate_limiter().acquire().await?;
+
+        let track = itunes_search::search_track(song).await?;
+        Ok(track.and_then(|track| track.artwork_url()))
+    }
+}
+
+/// Ids of the songs that were already backfilled, stored on disk so the
+/// backfill continues where it left off on next start.
+///
+/// Ids are appended one JSON string per line, so marking a song done doesn't
+/// rewrite the others.
+pub struct BackfillProgress {
+    path: PathBuf,
+    done: RefCell<HashSet<Uid>>,
+}
+
+impl BackfillProgress {
+    /// Loads the progress at `path`, starting from scratch if there is none.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let done = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                // A line cut short, e.g., by a crash, is only backfilled again
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read progress at {}", path.display()))
+            }
+        };
+
+        Ok(Self {
+            path,
+            done: RefCell::new(done),
+        })
+    }
+
+    pub fn contains(&self, song_id: &Uid) -> bool {
+        self.done.borrow().contains(song_id)
+    }
+
+    pub fn n_done(&self) -> usize {
+        self.done.borrow().len()
+    }
+
+    fn mark_done(&self, song_id: &Uid) -> Result<()> {
+        if !self.done.borrow_mut().insert(song_id.clone()) {
+            return Ok(());
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create dir at {}", parent.display()))?;
+        }
+
+        let mut line = serde_json::to_string(song_id)?;
+        line.push('\n');
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("Failed to write progress at {}", self.path.display()))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BackfillSummary {
+    /// Number of album arts that were fetched
+    pub n_fetched: u32,
+    /// Number of songs that got a new album art link
+    pub n_links_resolved: u32,
+    /// Number of fetches or lookups that failed
+    pub n_failed: u32,
+}
+
+/// Fetches the album arts of songs in the list that were not backfilled yet,
+/// looking up the links of songs without one.
+///
+/// Songs whose fetch or lookup failed, or whose link could not be found, are
+/// tried again on the next run.
+pub async fn backfill_album_arts(
+    song_list: &SongList,
+    fetcher: &dyn AlbumArtFetcher,
+    progress: &BackfillProgress,
+    interval: Duration,
+    cancellable: &gio::Cancellable,
+) -> Result<BackfillSummary> {
+    let to_process = song_list
+        .iter::<Song>()
+        .filter_map(|item| item.ok())
+        .filter(|song| !song.is_placeholder() && !progress.contains(song.id_ref()))
+        .collect::<Vec<_>>();
+
+    let mut summary = BackfillSummary::default();
+    let mut has_called_fetcher = false;
+
+    tracing::debug!(
+        n_done = progress.n_done(),
+        "Backfilling album arts of {} songs",
+        to_process.len()
+    );
+
+    for song in &to_process {
+        let album_art_link = match song.album_art_link() {
+            Some(album_art_link) => album_art_link,
+            None => {
+                let res =
+                    gio::CancellableFuture::new(fetcher.resolve_link(song), cancellable.clone())
+                        .await
+                        .map_err(|_| Cancelled::new("backfilling album arts"))?;
+
+                match res {
+                    Ok(Some(album_art_link)) => {
+                        // This is written to the db as the song is bound to it
+                        song.replace_album_art_link(&album_art_link);
+                        summary.n_links_resolved += 1;
+                        album_art_link
+                    }
+                    Ok(None) => continue,
+                    Err(err) => {
+                        tracing::warn!(
+                            "Failed to look up album art link of song `{}`: {:?}",
+                            song.id_ref().as_str(),
+                            err
+                        );
+                        summary.n_failed += 1;
+                        continue;
+                    }
+                }
+            }
+        };
+
+        if has_called_fetcher {
+            gio::CancellableFuture::new(glib::timeout_future(interval), cancellable.clone())
+                .await
+                .map_err(|_| Cancelled::new("backfilling album arts"))?;
+        }
+
+        let res = gio::CancellableFuture::new(fetcher.fetch(&album_art_link), cancellable.clone())
+            .await
+            .map_err(|_| Cancelled::new("backfilling album arts"))?;
+        has_called_fetcher = true;
+
+        if let Err(err) = res {
+            tracing::warn!(
+                "Failed to fetch album art of song `{}`: {:?}",
+                song.id_ref().as_str(),
+                err
+            );
+            summary.n_failed += 1;
+            continue;
+        }
+
+        summary.n_fetched += 1;
+
+        progress.mark_done(song.id_ref())?;
+    }
+
+    tracing::debug!("Backfilled album arts: {:?}", summary);
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    use crate::database;
+
+    /// Records fetched links and cancels after `cancel_after` fetches
+    struct MockFetcher {
+        fetched: RefCell<Vec<String>>,
+        cancel_after: Cell<Option<(usize, gio::Cancellable)>>,
+    }
+
+    impl MockFetcher {
+        fn new(cancel_after: Option<(usize, gio::Cancellable)>) -> Self {
+            Self {
+                fetched: RefCell::default(),
+                cancel_after: Cell::new(cancel_after),
+            }
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl AlbumArtFetcher for MockFetcher {
+        async fn fetch(&self, album_art_link: &str) -> Result<()> {
+            if album_art_link.ends_with("broken.png") {
+                anyhow::bail!("Broken link");
+            }
+
+            self.fetched.borrow_mut().push(album_art_link.to_string());
+
+            if let Some((n, cancellable)) = self.cancel_after.take() {
+                if self.fetched.borrow().len() >= n {
+                    cancellable.cancel();
+                } else {
+                    self.cancel_after.set(Some((n, cancellable)));
+                }
+            }
+
+            Ok(())
+        }
+
+        async fn resolve_link(&self, song: &Song) -> Result<Option<String>> {
+            if song.album().is_empty() {
+                return Ok(None);
+            }
+
+            Ok(Some(format!("https://art.test/{}.png", song.album())))
+        }
+    }
+
+    #[gtk::test]
+    fn backfill_and_resume() {
+        let (env, tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env.clone()).unwrap();
+        song_list
+            .insert_many(vec![
+                Song::builder(&Uid::from("a"), "A", "A", "A")
+                    .album_art_link("https://art.test/a.png")
+                    .build(),
+                Song::builder(&Uid::from("b"), "B", "B", "B")
+                    .album_art_link("https://art.test/b.png")
+                    .build(),
+                Song::builder(&Uid::from("c"), "C", "C", "Album C").build(),
+                Song::builder(&Uid::from("d"), "D", "D", "").build(),
+                Song::builder(&Uid::from("e"), "E", "E", "E")
+                    .album_art_link("https://art.test/broken.png")
+                    .build(),
+            ])
+            .unwrap();
+
+        let progress_path = tempdir
+            .path()
+            .join("progress")
+            .join("album-art-backfill.jsonl");
+
+        // Cancel halfway through
+        let cancellable = gio::Cancellable::new();
+        let fetcher = MockFetcher::new(Some((1, cancellable.clone())));
+        let progress = BackfillProgress::load(progress_path.clone()).unwrap();
+        let err = glib::MainContext::default()
+            .block_on(backfill_album_arts(
+                &song_list,
+                &fetcher,
+                &progress,
+                Duration::ZERO,
+                &cancellable,
+            ))
+            .unwrap_err();
+        assert!(err.is::<Cancelled>());
+        assert_eq!(fetcher.fetched.borrow().len(), 1);
+        assert_eq!(progress.n_done(), 1);
+
+        // Resume from the stored progress
+        let fetcher = MockFetcher::new(None);
+        let progress = BackfillProgress::load(progress_path.clone()).unwrap();
+        assert_eq!(progress.n_done(), 1);
+        let summary = glib::MainContext::default()
+            .block_on(backfill_album_arts(
+                &song_list,
+                &fetcher,
+                &progress,
+                Duration::ZERO,
+                &gio::Cancellable::new(),
+            ))
+            .unwrap();
+        assert_eq!(
+            summary,
+            BackfillSummary {
+                n_fetched: 2,
+                n_links_resolved: 1,
+                n_failed: 1,
+            }
+        );
+        assert_eq!(fetcher.fetched.borrow().len(), 2);
+        assert!(fetcher
+            .fetched
+            .borrow()
+            .contains(&"https://art.test/Album C.png".to_string()));
+
+        // Only the broken one and the one without a link are left
+        let progress = BackfillProgress::load(progress_path).unwrap();
+        assert_eq!(progress.n_done(), 3);
+        assert!(!progress.contains(&Uid::from("d")));
+        assert!(!progress.contains(&Uid::from("e")));
+
+        // Resolved links must also be written to the db
+        let reloaded = SongList::load_from_env(env).unwrap();
+        assert_eq!(
+            reloaded
+                .get(&Uid::from("c"))
+                .unwrap()
+                .album_art_link()
+                .as_deref(),
+            Some("https://art.test/Album C.png")
+        );
+        assert_eq!(
+            reloaded.get(&Uid::from("d")).unwrap().album_art_link(),
+            None
+        );
+    }
+}
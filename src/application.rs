@@ -1,21 +1,43 @@
 use adw::{prelude::*, subclass::prelude::*};
 use anyhow::{Context, Result};
-use gtk::{gio, glib};
+use gettextrs::gettext;
+use gtk::{
+    gio,
+    glib::{self, clone},
+};
 use soup::prelude::*;
 
-use std::{cell::OnceCell, time::Instant};
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashSet,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 use crate::{
     about,
-    album_art::AlbumArtStore,
+    album_art::{AlbumArtStore, CacheOptions},
+    album_art_backfill::{self, BackfillProgress, DEFAULT_BACKFILL_INTERVAL},
+    cancelled::Cancelled,
     config::{APP_ID, PKGDATADIR, PROFILE, VERSION},
-    database::{self, EnvExt, Migrations},
+    database::{
+        self, CompactionReport, DatabaseBusyError, EnvExt, Migrations, RawDatabase,
+        RECORDINGS_DB_NAME, SONG_LIST_DB_NAME,
+    },
     database_error_window::DatabaseErrorWindow,
+    date_time::DateTime,
+    deep_link,
+    i18n::{gettext_f, ngettext_f},
     inspector_page::InspectorPage,
+    paths,
     preferences_window::PreferencesWindow,
-    recognizer::Recordings,
+    rate_limiter::RateLimiters,
+    recognizer::{RecognizeMode, Recordings},
+    sample_store::SampleStore,
     settings::Settings,
+    song::{RecognitionSource, Song},
     song_list::SongList,
+    utils,
     window::Window,
 };
 
@@ -26,9 +48,19 @@ mod imp {
     #[derive(Default)]
     pub struct Application {
         pub(super) window: OnceCell<WeakRef<Window>>,
+        pub(super) database_error_window: RefCell<Option<WeakRef<DatabaseErrorWindow>>>,
         pub(super) session: OnceCell<(soup::Session, soup::Cache)>,
         pub(super) album_art_store: OnceCell<AlbumArtStore>,
+        pub(super) sample_store: OnceCell<SampleStore>,
+        pub(super) rate_limiters: OnceCell<RateLimiters>,
+        pub(super) album_art_backfill_cancellable: RefCell<Option<gio::Cancellable>>,
         pub(super) env: OnceCell<(heed::Env, SongList, Recordings)>,
+        /// Held while compacting or opening the db, as the window is only
+        /// set up after
+        pub(super) startup_hold: RefCell<Option<gio::ApplicationHoldGuard>>,
+        pub(super) compaction_report: RefCell<Option<CompactionReport>>,
+        /// Links received before the window was set up
+        pub(super) pending_files: RefCell<Vec<gio::File>>,
         pub(super) settings: Settings,
     }
 
@@ -39,9 +71,80 @@ mod imp {
         type ParentType = adw::Application;
     }
 
-    impl ObjectImpl for Application {}
+    impl ObjectImpl for Application {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            self.obj().add_main_option(
+                "data-dir",
+                glib::Char::from(b'\0'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::Filename,
+                &gettext("Store the database and caches in DIR"),
+                Some("DIR"),
+            );
+            self.obj().add_main_option(
+                "recognize",
+                glib::Char::from(b'\0'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::None,
+                &gettext("Start recognizing a song"),
+                None,
+            );
+            self.obj().add_main_option(
+                "hum",
+                glib::Char::from(b'\0'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::None,
+                &gettext("Start recognizing a hummed song"),
+                None,
+            );
+        }
+    }
 
     impl ApplicationImpl for Application {
+        fn handle_local_options(&self, options: &glib::VariantDict) -> glib::ExitCode {
+            let has_data_dir = match options.lookup::<PathBuf>("data-dir") {
+                Ok(Some(data_dir)) => {
+                    paths::set_data_dir_override(data_dir);
+                    true
+                }
+                Ok(None) => false,
+                Err(err) => {
+                    tracing::warn!("Invalid data dir option: {:?}", err);
+                    false
+                }
+            };
+
+            let action_name = forwarded_action_name(options);
+
+            if !has_data_dir && action_name.is_none() {
+                return self.parent_handle_local_options(options);
+            }
+
+            // Register early to know whether there is already a primary
+            // instance, which owns the db.
+            let obj = self.obj();
+            if let Err(err) = obj.register(gio::Cancellable::NONE) {
+                tracing::error!("Failed to register app: {:?}", err);
+                return glib::ExitCode::FAILURE;
+            }
+
+            if obj.is_remote() && has_data_dir {
+                tracing::warn!("Mousai is already running, ignoring the data dir option");
+            }
+
+            if let Some(action_name) = action_name {
+                obj.activate_action(action_name, None);
+
+                if obj.is_remote() {
+                    return glib::ExitCode::SUCCESS;
+                }
+            }
+
+            self.parent_handle_local_options(options)
+        }
+
         fn activate(&self) {
             self.parent_activate();
 
@@ -53,26 +156,28 @@ mod imp {
                 return;
             }
 
-            let obj = self.obj();
+            // Activated again once done
+            if self.startup_hold.borrow().is_some() {
+                return;
+            }
 
-            // TODO use `get_or_try_init` once it's stable
-            match init_env() {
-                Ok((env, song_history, recordings)) => {
-                    let window = Window::new(&obj);
-                    window.bind_models(&song_history, &recordings);
-                    self.window.set(window.downgrade()).unwrap();
-                    self.env.set((env, song_history, recordings)).unwrap();
-                    window.present();
-                }
-                Err(err) => {
-                    tracing::error!("Failed to setup db env: {:?}", err);
+            let obj = self.obj();
 
-                    // TODO don't spawn a new window if one is already open
-                    // or find a better solution in handling these errors
-                    let err_window = DatabaseErrorWindow::new(&*obj);
-                    err_window.present();
-                }
+            if obj.settings().database_compaction_pending() {
+                obj.compact_database();
+                return;
             }
+
+            obj.open_env();
+        }
+
+        fn open(&self, files: &[gio::File], _hint: &str) {
+            // Handled once the window is set up, e.g., after compacting or
+            // opening the db
+            self.pending_files.borrow_mut().extend_from_slice(files);
+
+            self.obj().activate();
+            self.open_pending_files();
         }
 
         fn startup(&self) {
@@ -88,6 +193,10 @@ mod imp {
         }
 
         fn shutdown(&self) {
+            if let Some(cancellable) = self.album_art_backfill_cancellable.take() {
+                cancellable.cancel();
+            }
+
             if let Some((env, _, _)) = self.env.get() {
                 if let Err(err) = env.force_sync() {
                     tracing::error!("Failed to sync db env on shutdown: {:?}", err);
@@ -109,6 +218,127 @@ mod imp {
 
     impl GtkApplicationImpl for Application {}
     impl AdwApplicationImpl for Application {}
+
+    impl Application {
+        /// Reveals the songs linked by the received links, if the window is
+        /// already set up.
+        fn open_pending_files(&self) {
+            let Some(window) = self.window.get().and_then(|window| window.upgrade()) else {
+                return;
+            };
+
+            for file in self.pending_files.take() {
+                let uri = file.uri();
+                match deep_link::parse_song_uri(&uri) {
+                    Some(song_id) => window.reveal_song(&song_id),
+                    None => tracing::warn!(%uri, "Received unknown link"),
+                }
+            }
+        }
+
+        /// Sets up the window with the opened env, or shows why it could not
+        /// be opened.
+        pub(super) fn setup_window(&self, res: Result<(heed::Env, SongList, Recordings)>) {
+            let obj = self.obj();
+
+            match res {
+                Ok((env, song_history, recordings)) => {
+                    song_history.connect_song_removed(clone!(@weak obj => move |_, song| {
+                        if let Err(err) = obj.sample_store().remove(song.id_ref()) {
+                            tracing::warn!("Failed to remove sample of removed song: {:?}", err);
+                        }
+
+                        let settings = obj.settings();
+                        if settings.compact_database_after_removal()
+                            && !settings.database_compaction_pending()
+                        {
+                            settings.set_database_compaction_pending(true);
+                        }
+                    }));
+
+                    let n_pruned = obj.prune_history(&song_history)
+                        + obj.enforce_max_history_size(&song_history);
+
+                    let window = Window::new(&obj);
+                    window.bind_models(&song_history, &recordings);
+                    self.window.set(window.downgrade()).unwrap();
+
+                    obj.start_album_art_backfill(&song_history);
+                    obj.settings().connect_auto_download_album_art_changed(
+                        clone!(@weak obj, @weak song_history => move |_| {
+                            obj.start_album_art_backfill(&song_history);
+                        }),
+                    );
+                    obj.settings().connect_offline_mode_changed(
+                        clone!(@weak obj, @weak song_history => move |_| {
+                            obj.start_album_art_backfill(&song_history);
+                        }),
+                    );
+
+                    self.env.set((env, song_history, recordings)).unwrap();
+                    window.present();
+                    window.run_startup_action();
+                    self.open_pending_files();
+
+                    // Opened after retrying from the error window
+                    if let Some(err_window) = self
+                        .database_error_window
+                        .take()
+                        .and_then(|err_window| err_window.upgrade())
+                    {
+                        err_window.close();
+                    }
+
+                    if let Some(report) = self
+                        .compaction_report
+                        .take()
+                        .filter(|r| r.n_reclaimed() > 0)
+                    {
+                        window.add_message_toast(&gettext_f(
+                            // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                            "Optimized database, freeing {size}",
+                            &[("size", &glib::format_size(report.n_reclaimed()))],
+                        ));
+                    }
+
+                    if n_pruned > 0 {
+                        window.add_message_toast(&ngettext_f(
+                            // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                            "Removed {n_songs} old song from history",
+                            "Removed {n_songs} old songs from history",
+                            n_pruned,
+                            &[("n_songs", &n_pruned.to_string())],
+                        ));
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("Failed to setup db env: {:?}", err);
+
+                    let is_busy = DatabaseBusyError::is_cause_of(&err);
+
+                    if let Some(err_window) = self
+                        .database_error_window
+                        .borrow()
+                        .as_ref()
+                        .and_then(|err_window| err_window.upgrade())
+                    {
+                        if err_window.is_busy() == is_busy {
+                            err_window.present();
+                            return;
+                        }
+
+                        err_window.close();
+                    }
+
+                    // TODO find a better solution in handling these errors
+                    let err_window = DatabaseErrorWindow::new(&*obj, is_busy);
+                    self.database_error_window
+                        .replace(Some(err_window.downgrade()));
+                    err_window.present();
+                }
+            }
+        }
+    }
 }
 
 glib::wrapper! {
@@ -122,6 +352,7 @@ impl Application {
         glib::Object::builder()
             .property("application-id", APP_ID)
             .property("resource-base-path", "/io/github/seadve/Mousai/")
+            .property("flags", gio::ApplicationFlags::HANDLES_OPEN)
             .build()
     }
 
@@ -153,8 +384,8 @@ impl Application {
             let session = soup::Session::new();
 
             let cache_dir = {
-                let mut path = glib::user_cache_dir();
-                path.push("mousai/soup_cache");
+                let mut path = paths::cache_dir();
+                path.push("soup_cache");
                 path
             };
             let cache_dir_str = cache_dir.to_str();
@@ -177,9 +408,165 @@ impl Application {
     }
 
     pub fn album_art_store(&self) -> &AlbumArtStore {
-        self.imp()
-            .album_art_store
-            .get_or_init(|| AlbumArtStore::new(self.session().clone()))
+        self.imp().album_art_store.get_or_init(|| {
+            let settings = self.settings();
+
+            let store = AlbumArtStore::new(
+                self.session().clone(),
+                settings.max_concurrent_album_art_downloads() as usize,
+            );
+            store.set_auto_download(settings.auto_download_album_art());
+            store.set_offline(settings.offline_mode());
+            settings.connect_offline_mode_changed(clone!(@weak self as obj => move |settings| {
+                obj.album_art_store().set_offline(settings.offline_mode());
+            }));
+            settings.connect_auto_download_album_art_changed(
+                clone!(@weak self as obj => move |_| {
+                    obj.album_art_store()
+                        .set_auto_download(obj.settings().auto_download_album_art());
+                }),
+            );
+            store.set_download_attempts(settings.album_art_download_attempts());
+            settings.connect_album_art_download_attempts_changed(
+                clone!(@weak self as obj => move |settings| {
+                    obj.album_art_store()
+                        .set_download_attempts(settings.album_art_download_attempts());
+                }),
+            );
+
+            store.set_cache_dir(paths::cache_dir().join("album-arts"));
+            store.set_cache_options(album_art_cache_options(settings));
+            settings.connect_changed(
+                None,
+                clone!(@weak self as obj => move |_, key| {
+                    if key.starts_with("album-art-cache-") || key == "keep-original-album-art" {
+                        obj.album_art_store()
+                            .set_cache_options(album_art_cache_options(obj.settings()));
+                    }
+                }),
+            );
+
+            store
+        })
+    }
+
+    pub fn sample_store(&self) -> &SampleStore {
+        self.imp().sample_store.get_or_init(|| {
+            let settings = self.settings();
+
+            let store = SampleStore::new(paths::samples_dir(), recorded_samples_max_size(settings));
+            settings.connect_recorded_samples_max_size_changed(
+                clone!(@weak self as obj => move |settings| {
+                    obj.sample_store()
+                        .set_max_total_size(recorded_samples_max_size(settings));
+                }),
+            );
+
+            store
+        })
+    }
+
+    /// Returns the rate limiters shared by everything that sends requests
+    /// to providers.
+    pub fn rate_limiters(&self) -> &RateLimiters {
+        self.imp().rate_limiters.get_or_init(|| {
+            let settings = self.settings();
+
+            let (capacity, interval) = provider_rate_limit(settings);
+            let rate_limiters = RateLimiters::new(capacity, interval);
+            settings.connect_changed(
+                None,
+                clone!(@weak self as obj => move |_, key| {
+                    if key.starts_with("provider-rate-limit-") {
+                        let (capacity, interval) = provider_rate_limit(obj.settings());
+                        obj.rate_limiters().set_limit(capacity, interval);
+                    }
+                }),
+            );
+
+            rate_limiters
+        })
+    }
+
+    pub fn env(&self) -> &heed::Env {
+        let (env, _, _) = self
+            .imp()
+            .env
+            .get()
+            .expect("env must be initialized on activate");
+        env
+    }
+
+    /// Returns the already opened named dbs viewed as raw bytes.
+    pub fn raw_databases(&self) -> Vec<(&'static str, RawDatabase)> {
+        let (_, song_history, recordings) = self
+            .imp()
+            .env
+            .get()
+            .expect("env must be initialized on activate");
+        vec![
+            (SONG_LIST_DB_NAME, song_history.raw_database()),
+            (RECORDINGS_DB_NAME, recordings.raw_database()),
+        ]
+    }
+
+    /// Compacts the db on a separate thread, as it can take a while for big
+    /// dbs, then activates again to open it.
+    ///
+    /// This must be done while no env is open.
+    fn compact_database(&self) {
+        let imp = self.imp();
+        debug_assert!(imp.env.get().is_none(), "env must not be open");
+
+        imp.startup_hold.replace(Some(self.hold()));
+
+        utils::spawn(
+            glib::Priority::default(),
+            clone!(@weak self as obj => async move {
+                let db_dir = paths::db_dir();
+                let res = gio::spawn_blocking(move || database::compact_at(&db_dir))
+                    .await
+                    .map_err(|err| anyhow::anyhow!("Failed to spawn blocking task: {:?}", err))
+                    .and_then(|res| res);
+
+                // A failure here is not worth failing the startup for
+                let report = match res {
+                    Ok(report) => Some(report),
+                    Err(err) => {
+                        tracing::warn!("Failed to compact db: {:?}", err);
+                        None
+                    }
+                };
+
+                let imp = obj.imp();
+                obj.settings().set_database_compaction_pending(false);
+                imp.compaction_report.replace(report);
+
+                // Keep the app alive until the window is presented
+                let _hold = imp.startup_hold.take();
+                obj.activate();
+            }),
+        );
+    }
+
+    /// Opens the db env off the main thread, as it waits while the env is
+    /// busy, then sets up the window.
+    fn open_env(&self) {
+        let imp = self.imp();
+        debug_assert!(imp.env.get().is_none(), "env must not be open");
+
+        imp.startup_hold.replace(Some(self.hold()));
+
+        utils::spawn(
+            glib::Priority::default(),
+            clone!(@weak self as obj => async move {
+                let res = init_env().await;
+
+                // Keep the app alive until the window is presented
+                let _hold = obj.imp().startup_hold.take();
+                obj.imp().setup_window(res);
+            }),
+        );
     }
 
     pub fn settings(&self) -> &Settings {
@@ -222,7 +609,157 @@ impl Application {
                 about::present_window(Some(&obj.window()));
             })
             .build();
-        self.add_action_entries([quit_action, show_preferences_action, show_about_action]);
+        let recognize_action = gio::ActionEntry::builder("recognize")
+            .activate(|obj: &Self, _, _| {
+                obj.start_recognize(RecognizeMode::Music);
+            })
+            .build();
+        let recognize_hum_action = gio::ActionEntry::builder("recognize-hum")
+            .activate(|obj: &Self, _, _| {
+                obj.start_recognize(RecognizeMode::Hum);
+            })
+            .build();
+        self.add_action_entries([
+            quit_action,
+            show_preferences_action,
+            show_about_action,
+            recognize_action,
+            recognize_hum_action,
+        ]);
+
+        self.add_action(&self.settings().create_action("offline-mode"));
+    }
+
+    /// Raises the window and starts recognizing, e.g., when launched with
+    /// `--recognize` while already running.
+    fn start_recognize(&self, mode: RecognizeMode) {
+        self.activate();
+
+        if let Some(window) = self.imp().window.get().and_then(|window| window.upgrade()) {
+            window.start_recognize(mode);
+        }
+    }
+
+    /// Removes songs older than the configured retention, returning the
+    /// number of removed songs.
+    fn prune_history(&self, song_list: &SongList) -> u32 {
+        let n_days = self.settings().history_retention_days();
+
+        if n_days == 0 {
+            return 0;
+        }
+
+        let res = DateTime::now_utc()
+            .add_days(-(n_days as i32))
+            .and_then(|cutoff| song_list.remove_heard_before(&cutoff));
+
+        match res {
+            Ok(n_pruned) => {
+                tracing::info!(n_days, "Pruned {} songs from history", n_pruned);
+                n_pruned
+            }
+            Err(err) => {
+                tracing::error!("Failed to prune history: {:?}", err);
+                0
+            }
+        }
+    }
+
+    /// Removes the least recently heard songs beyond the configured maximum,
+    /// along with the album arts that no other song uses. Samples are removed
+    /// on song removal. Returns the number of removed songs.
+    pub fn enforce_max_history_size(&self, song_list: &SongList) -> u32 {
+        let max_n_songs = self.settings().max_history_size();
+
+        if max_n_songs == 0 {
+            return 0;
+        }
+
+        let removed = match song_list.remove_oldest_beyond(max_n_songs) {
+            Ok(removed) => removed,
+            Err(err) => {
+                tracing::error!("Failed to enforce max history size: {:?}", err);
+                return 0;
+            }
+        };
+
+        // Include all links, as the preference may have changed since they were cached
+        let used_album_art_links = song_list
+            .iter::<Song>()
+            .filter_map(|item| item.ok())
+            .flat_map(|song| song.album_art_links(true))
+            .collect::<HashSet<_>>();
+
+        for song in &removed {
+            for album_art_link in song.album_art_links(true) {
+                if used_album_art_links.contains(&album_art_link) {
+                    continue;
+                }
+
+                if let Err(err) = self.album_art_store().remove(&album_art_link) {
+                    tracing::warn!("Failed to remove album art of evicted song: {:?}", err);
+                }
+            }
+        }
+
+        if !removed.is_empty() {
+            tracing::info!(max_n_songs, "Evicted {} songs from history", removed.len());
+        }
+
+        removed.len() as u32
+    }
+
+    /// Fetches the album arts that were never cached in the background,
+    /// continuing where the last run left off.
+    ///
+    /// This restarts a running backfill, or only stops it if automatic
+    /// downloading is disabled or offline mode is active.
+    fn start_album_art_backfill(&self, song_list: &SongList) {
+        let imp = self.imp();
+
+        if let Some(cancellable) = imp.album_art_backfill_cancellable.take() {
+            cancellable.cancel();
+        }
+
+        if !self.settings().auto_download_album_art() || self.settings().offline_mode() {
+            return;
+        }
+
+        let progress = match BackfillProgress::load(paths::album_art_backfill_progress_file()) {
+            Ok(progress) => progress,
+            Err(err) => {
+                tracing::warn!("Failed to load album art backfill progress: {:?}", err);
+                return;
+            }
+        };
+
+        let cancellable = gio::Cancellable::new();
+        imp.album_art_backfill_cancellable
+            .replace(Some(cancellable.clone()));
+
+        utils::spawn(
+            glib::Priority::LOW,
+            clone!(@weak self as obj, @weak song_list => async move {
+                let res = album_art_backfill::backfill_album_arts(
+                    &song_list,
+                    obj.album_art_store(),
+                    &progress,
+                    DEFAULT_BACKFILL_INTERVAL,
+                    &cancellable,
+                )
+                .await;
+
+                match res {
+                    Ok(_) => {}
+                    Err(err) if err.is::<Cancelled>() => {
+                        tracing::debug!("{:?}", err);
+                    }
+                    Err(err) => {
+                        tracing::warn!("Failed to backfill album arts: {:?}", err);
+                    }
+                }
+            }),
+        );
     }
 
     fn setup_accels(&self) {
@@ -231,6 +768,7 @@ impl Application {
         self.set_accels_for_action("window.close", &["<Control>w"]);
         self.set_accels_for_action("win.toggle-playback", &["<Control>space"]);
         self.set_accels_for_action("win.toggle-recognize", &["<Control>r"]);
+        self.set_accels_for_action("win.toggle-hum-recognize", &["<Control><Shift>r"]);
         self.set_accels_for_action("win.toggle-search", &["<Control>f"]);
     }
 }
@@ -241,6 +779,39 @@ impl Default for Application {
     }
 }
 
+/// Returns the name of the app action that the command line options ask for,
+/// which runs in the primary instance if there is one.
+fn forwarded_action_name(options: &glib::VariantDict) -> Option<&'static str> {
+    if options.contains("hum") {
+        Some("recognize-hum")
+    } else if options.contains("recognize") {
+        Some("recognize")
+    } else {
+        None
+    }
+}
+
+fn album_art_cache_options(settings: &Settings) -> CacheOptions {
+    CacheOptions {
+        keep_original: settings.keep_original_album_art(),
+        format: settings.album_art_cache_format(),
+        max_size: settings.album_art_cache_max_size(),
+        quality: settings.album_art_cache_quality(),
+        max_total_size: settings.album_art_cache_max_total_size() as u64 * 1024 * 1024,
+    }
+}
+
+fn recorded_samples_max_size(settings: &Settings) -> u64 {
+    settings.recorded_samples_max_size() as u64 * 1024 * 1024
+}
+
+fn provider_rate_limit(settings: &Settings) -> (u32, Duration) {
+    (
+        settings.provider_rate_limit_requests(),
+        Duration::from_secs(settings.provider_rate_limit_interval() as u64),
+    )
+}
+
 fn setup_inspector_page() {
     if gio::IOExtensionPoint::lookup("gtk-inspector-page").is_some() {
         gio::IOExtensionPoint::implement(
@@ -254,7 +825,25 @@ fn setup_inspector_page() {
     }
 }
 
-fn init_env() -> Result<(heed::Env, SongList, Recordings)> {
+/// Opens the db env after running the migrations, then loads the models.
+async fn init_env() -> Result<(heed::Env, SongList, Recordings)> {
+    let env = gio::spawn_blocking(open_migrated_env)
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to spawn blocking task: {:?}", err))
+        .and_then(|res| res)?;
+
+    let song_history =
+        SongList::load_from_env(env.clone()).context("Failed to load song history")?;
+    let recordings = Recordings::load_from_env(env.clone())?;
+
+    Ok((env, song_history, recordings))
+}
+
+/// Opens the db env after running the migrations.
+///
+/// This blocks while the env is busy, so it must not be called on the main
+/// thread.
+fn open_migrated_env() -> Result<heed::Env> {
     {
         let env = database::new_env()?;
 
@@ -292,6 +881,24 @@ fn init_env() -> Result<(heed::Env, SongList, Recordings)> {
 
                 Ok(())
             });
+            // These fields were added at the end of `Song`, so appending their
+            // value for existing songs is enough to get the new format.
+            migrations.add_append("SongList: Add Song::is_favorite", SONG_LIST_DB_NAME, false);
+            migrations.add_append(
+                "SongList: Add Song::is_explicit",
+                SONG_LIST_DB_NAME,
+                None::<bool>,
+            );
+            migrations.add_append(
+                "SongList: Add Song::source",
+                SONG_LIST_DB_NAME,
+                RecognitionSource::Microphone,
+            );
+            migrations.add_append(
+                "SongList: Add Song::normalized_release_date",
+                SONG_LIST_DB_NAME,
+                None::<String>,
+            );
             migrations
                 .run(&env, wtxn)
                 .context("Failed to run migrations")
@@ -303,10 +910,27 @@ fn init_env() -> Result<(heed::Env, SongList, Recordings)> {
         env.prepare_for_closing().wait();
     }
 
-    let env = database::new_env()?;
-    let song_history =
-        SongList::load_from_env(env.clone()).context("Failed to load song history")?;
-    let recordings = Recordings::load_from_env(env.clone())?;
+    database::new_env()
+}
 
-    Ok((env, song_history, recordings))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarded_action() {
+        let options = glib::VariantDict::new(None);
+        assert_eq!(forwarded_action_name(&options), None);
+
+        // Not forwarded
+        options.insert_value("data-dir", &"/tmp".to_variant());
+        assert_eq!(forwarded_action_name(&options), None);
+
+        options.insert_value("recognize", &true.to_variant());
+        assert_eq!(forwarded_action_name(&options), Some("recognize"));
+
+        // Humming takes precedence
+        options.insert_value("hum", &true.to_variant());
+        assert_eq!(forwarded_action_name(&options), Some("recognize-hum"));
+    }
 }
@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use heed::CompactionOption;
+
+use std::{fs, path::Path, time::Instant};
+
+use super::new_env_at;
+
+const DATA_FILE_NAME: &str = "data.mdb";
+const COMPACTED_FILE_NAME: &str = "data.mdb.compacted";
+
+/// Size of the data file before and after compaction, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionReport {
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+impl CompactionReport {
+    /// Number of bytes freed by the compaction
+    pub fn n_reclaimed(&self) -> u64 {
+        self.size_before.saturating_sub(self.size_after)
+    }
+}
+
+/// Rewrites the env at `path` without its free pages, as LMDB never shrinks
+/// its data file on its own, e.g., after removing many songs.
+///
+/// This opens and closes the env itself, so it must only be called while no
+/// other env is open at `path`. As a consequence, it also never runs in the
+/// middle of a write txn.
+pub fn compact_at(path: &Path) -> Result<CompactionReport> {
+    let start_time = Instant::now();
+
+    let data_path = path.join(DATA_FILE_NAME);
+    let compacted_path = path.join(COMPACTED_FILE_NAME);
+
+    let size_before = fs::metadata(&data_path)
+        .context("Failed to get data file metadata")?
+        .len();
+
+    // A leftover from an interrupted compaction, which may be incomplete
+    if compacted_path.exists() {
+        fs::remove_file(&compacted_path).context("Failed to remove stale compacted file")?;
+    }
+
+    {
+        let env = new_env_at(path)?;
+        env.copy_to_file(&compacted_path, CompactionOption::Enabled)
+            .context("Failed to copy env with compaction")?;
+        env.prepare_for_closing().wait();
+    }
+
+    let size_after = fs::metadata(&compacted_path)
+        .context("Failed to get compacted file metadata")?
+        .len();
+
+    // Renaming within the same dir is atomic, so the data file is either the
+    // old or the compacted one
+    fs::rename(&compacted_path, &data_path).context("Failed to replace data file")?;
+
+    let report = CompactionReport {
+        size_before,
+        size_after,
+    };
+    tracing::debug!(?report, "Compacted db in {:?}", start_time.elapsed());
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gtk::prelude::*;
+
+    use crate::{song::Song, song_list::SongList, uid::Uid};
+
+    #[gtk::test]
+    fn reclaims_removed() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        {
+            let env = new_env_at(tempdir.path()).unwrap();
+            let song_list = SongList::load_from_env(env.clone()).unwrap();
+
+            let songs = (0..500)
+                .map(|i| {
+                    let id = i.to_string();
+                    Song::builder(&Uid::from(id.as_str()), &id, &id, &id)
+                        .lyrics(&"Some song lyrics ".repeat(50))
+                        .build()
+                })
+                .collect::<Vec<_>>();
+            song_list.insert_many(songs).unwrap();
+
+            let to_remove = (1..500)
+                .map(|i| Uid::from(i.to_string()))
+                .collect::<Vec<_>>();
+            song_list
+                .remove_many(&to_remove.iter().collect::<Vec<_>>())
+                .unwrap();
+
+            drop(song_list);
+            env.prepare_for_closing().wait();
+        }
+
+        let report = compact_at(tempdir.path()).unwrap();
+        assert!(report.size_after < report.size_before);
+        assert!(report.n_reclaimed() > 0);
+        assert_eq!(
+            fs::metadata(tempdir.path().join(DATA_FILE_NAME))
+                .unwrap()
+                .len(),
+            report.size_after
+        );
+        assert!(!tempdir.path().join(COMPACTED_FILE_NAME).exists());
+
+        // The remaining songs are kept
+        let env = new_env_at(tempdir.path()).unwrap();
+        let song_list = SongList::load_from_env(env).unwrap();
+        assert_eq!(song_list.n_items(), 1);
+        assert!(song_list.contains(&Uid::from("0")));
+    }
+}
@@ -0,0 +1,284 @@
+use anyhow::{Context, Result};
+use heed::{
+    types::{Bytes, SerdeBincode},
+    BytesDecode,
+};
+
+use std::{fmt, time::Instant};
+
+use super::{EnvExt, RECORDINGS_DB_NAME, SONG_LIST_DB_NAME};
+use crate::{
+    recognizer::Recording,
+    song::Song,
+    uid::{Uid, UidCodec},
+};
+
+/// An entry that cannot be read back by the app
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptEntry {
+    pub db_name: &'static str,
+    pub key: Vec<u8>,
+    pub reason: String,
+}
+
+impl fmt::Display for CorruptEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}: {}",
+            self.db_name,
+            String::from_utf8_lossy(&self.key),
+            self.reason
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IntegrityReport {
+    pub n_checked: usize,
+    pub corrupt: Vec<CorruptEntry>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.corrupt.is_empty()
+    }
+}
+
+/// Db viewed as raw bytes, so entries can be walked and deleted even if they
+/// can't be decoded
+pub type RawDatabase = heed::Database<Bytes, Bytes>;
+
+/// Entries of a named db as stored, not decoded yet
+#[derive(Debug)]
+pub struct RawEntries {
+    db_name: &'static str,
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// Reads every entry of the given already opened dbs.
+///
+/// This only takes a read txn and doesn't decode anything, so it is safe to
+/// run on a separate thread while the app is still writing. Pass the result
+/// to [`check_integrity`] on the main thread.
+///
+/// Errors are returned when a db cannot be walked at all, e.g., when the
+/// b-tree itself is damaged.
+pub fn read_entries(
+    env: &heed::Env,
+    databases: &[(&'static str, RawDatabase)],
+) -> Result<Vec<RawEntries>> {
+    let rtxn = env.read_txn().context("Failed to create read txn")?;
+
+    databases
+        .iter()
+        .map(|(db_name, db)| {
+            let entries = db
+                .iter(&rtxn)
+                .with_context(|| format!("Failed to iter `{}` db", db_name))?
+                .map(|item| item.map(|(key, value)| (key.to_vec(), value.to_vec())))
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Failed to read `{}` db", db_name))?;
+            Ok(RawEntries { db_name, entries })
+        })
+        .collect()
+}
+
+/// Checks that both the key and the value of every entry can be decoded.
+///
+/// Values are decoded into the app's objects, so this must be called on the
+/// main thread.
+pub fn check_integrity(raw_entries: &[RawEntries]) -> IntegrityReport {
+    let start_time = Instant::now();
+
+    let mut report = IntegrityReport::default();
+
+    for raw in raw_entries {
+        match raw.db_name {
+            SONG_LIST_DB_NAME => check_entries::<Song>(raw, &mut report, |uid, song| {
+                (song.id_ref() != uid)
+                    .then(|| format!("Song id {:?} does not match key", song.id_ref()))
+            }),
+            RECORDINGS_DB_NAME => check_entries::<Recording>(raw, &mut report, |_, _| None),
+            other => unreachable!("unknown db `{}`", other),
+        }
+    }
+
+    tracing::debug!(
+        n_checked = report.n_checked,
+        n_corrupt = report.corrupt.len(),
+        "Checked db integrity in {:?}",
+        start_time.elapsed()
+    );
+
+    report
+}
+
+/// Deletes the corrupt entries found in `report` from the given already
+/// opened dbs, returning the number of deleted entries.
+///
+/// Note: If a corrupt entry also has a healthy copy loaded in memory, it will
+/// be written back on its next change.
+pub fn repair_integrity(
+    env: &heed::Env,
+    databases: &[(&'static str, RawDatabase)],
+    report: &IntegrityReport,
+) -> Result<usize> {
+    env.with_write_txn(|wtxn| {
+        let mut n_deleted = 0;
+
+        for entry in &report.corrupt {
+            let Some((_, db)) = databases
+                .iter()
+                .find(|(db_name, _)| *db_name == entry.db_name)
+            else {
+                continue;
+            };
+
+            if db
+                .delete(wtxn, &entry.key)
+                .with_context(|| format!("Failed to delete corrupt entry {}", entry))?
+            {
+                n_deleted += 1;
+            }
+        }
+
+        Ok(n_deleted)
+    })
+}
+
+fn check_entries<T: 'static>(
+    raw: &RawEntries,
+    report: &mut IntegrityReport,
+    validate: impl Fn(&Uid, &T) -> Option<String>,
+) where
+    SerdeBincode<T>: for<'a> BytesDecode<'a, DItem = T>,
+{
+    for (key, value) in &raw.entries {
+        report.n_checked += 1;
+
+        let res = UidCodec::bytes_decode(key)
+            .map_err(|err| format!("Invalid key: {}", err))
+            .and_then(|uid| {
+                let item = SerdeBincode::<T>::bytes_decode(value)
+                    .map_err(|err| format!("Invalid value: {}", err))?;
+                validate(&uid, &item).map_or(Ok(()), Err)
+            });
+
+        if let Err(reason) = res {
+            report.corrupt.push(CorruptEntry {
+                db_name: raw.db_name,
+                key: key.clone(),
+                reason,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{database, recognizer::Recordings, song_list::SongList};
+
+    fn new_env() -> (heed::Env, tempfile::TempDir) {
+        let tempdir = tempfile::tempdir().unwrap();
+        let env = heed::EnvOpenOptions::new()
+            .map_size(100 * 1024 * 1024) // 100 MiB
+            .max_dbs(database::N_NAMED_DBS)
+            .open(&tempdir)
+            .unwrap();
+        (env, tempdir)
+    }
+
+    fn new_test_song(id: &str) -> Song {
+        Song::builder(&Uid::from(id), id, id, id).build()
+    }
+
+    fn raw_databases(
+        song_list: &SongList,
+        recordings: &Recordings,
+    ) -> Vec<(&'static str, RawDatabase)> {
+        vec![
+            (SONG_LIST_DB_NAME, song_list.raw_database()),
+            (RECORDINGS_DB_NAME, recordings.raw_database()),
+        ]
+    }
+
+    #[gtk::test]
+    fn empty() {
+        let (env, _tempdir) = new_env();
+
+        let song_list = SongList::load_from_env(env.clone()).unwrap();
+        let recordings = Recordings::load_from_env(env.clone()).unwrap();
+        let databases = raw_databases(&song_list, &recordings);
+
+        let report = check_integrity(&read_entries(&env, &databases).unwrap());
+        assert!(report.is_ok());
+        assert_eq!(report.n_checked, 0);
+    }
+
+    #[gtk::test]
+    fn ok() {
+        let (env, _tempdir) = new_env();
+
+        let song_list = SongList::load_from_env(env.clone()).unwrap();
+        song_list.insert(new_test_song("a")).unwrap();
+        song_list.insert(new_test_song("b")).unwrap();
+        let recordings = Recordings::load_from_env(env.clone()).unwrap();
+        let databases = raw_databases(&song_list, &recordings);
+
+        let report = check_integrity(&read_entries(&env, &databases).unwrap());
+        assert!(report.is_ok());
+        assert_eq!(report.n_checked, 2);
+    }
+
+    #[gtk::test]
+    fn corrupt_and_repair() {
+        let (env, _tempdir) = new_env();
+
+        let song_list = SongList::load_from_env(env.clone()).unwrap();
+        song_list.insert(new_test_song("a")).unwrap();
+        song_list.insert(new_test_song("b")).unwrap();
+        let recordings = Recordings::load_from_env(env.clone()).unwrap();
+        let databases = raw_databases(&song_list, &recordings);
+
+        env.with_write_txn(|wtxn| {
+            let db = song_list.raw_database();
+            db.put(wtxn, b"b".as_slice(), [0xff, 0x00].as_slice())?;
+            db.put(wtxn, [0xff, 0xfe].as_slice(), [].as_slice())?;
+            Ok(())
+        })
+        .unwrap();
+
+        // Walking the dbs doesn't decode anything, so it works on other threads
+        let raw_entries = std::thread::scope(|scope| {
+            scope
+                .spawn(|| read_entries(&env, &databases))
+                .join()
+                .unwrap()
+        })
+        .unwrap();
+
+        let report = check_integrity(&raw_entries);
+        assert!(!report.is_ok());
+        assert_eq!(report.n_checked, 3);
+        assert_eq!(
+            report
+                .corrupt
+                .iter()
+                .map(|entry| (entry.db_name, entry.key.as_slice()))
+                .collect::<Vec<_>>(),
+            [
+                (SONG_LIST_DB_NAME, b"b".as_slice()),
+                (SONG_LIST_DB_NAME, [0xff, 0xfe].as_slice())
+            ]
+        );
+
+        assert_eq!(repair_integrity(&env, &databases, &report).unwrap(), 2);
+
+        let report = check_integrity(&read_entries(&env, &databases).unwrap());
+        assert!(report.is_ok());
+        assert_eq!(report.n_checked, 1);
+    }
+}
@@ -1,8 +1,9 @@
 use anyhow::{Context, Result};
 use heed::{
     byteorder::LE,
-    types::{Str, U64},
+    types::{Bytes, Str, U64},
 };
+use serde::Serialize;
 
 use std::time::Instant;
 
@@ -43,6 +44,32 @@ impl Migrations {
         });
     }
 
+    /// Adds a migration that appends `value` to every item of the db named
+    /// `db_name`, for when a field is added at the end of a type stored with
+    /// bincode.
+    pub fn add_append(&mut self, name: &'static str, db_name: &'static str, value: impl Serialize) {
+        let suffix = bincode::serialize(&value).expect("value must be serializable");
+
+        self.add(name, move |env, wtxn| {
+            if let Some(db) = env.open_database::<Bytes, Bytes>(wtxn, Some(db_name))? {
+                let items = db
+                    .iter(wtxn)
+                    .context("Failed to iter db")?
+                    .map(|item| item.map(|(key, bytes)| (key.to_vec(), bytes.to_vec())))
+                    .collect::<Result<Vec<_>, _>>()
+                    .context("Failed to collect items")?;
+
+                for (key, mut bytes) in items {
+                    bytes.extend_from_slice(&suffix);
+                    db.put(wtxn, key.as_slice(), bytes.as_slice())
+                        .context("Failed to put item")?;
+                }
+            }
+
+            Ok(())
+        });
+    }
+
     pub fn run(&self, env: &heed::Env, wtxn: &mut heed::RwTxn<'_>) -> Result<()> {
         if self.migrations.is_empty() {
             tracing::debug!("No migrations to run");
@@ -153,4 +180,31 @@ mod tests {
         assert_eq!(migrations.max_version(), 4);
         assert_eq!(current_version(&env, &wtxn).unwrap(), 4);
     }
+
+    #[test]
+    fn append() {
+        let (env, _tempdir) = database::new_test_env();
+        let mut wtxn = env.write_txn().unwrap();
+
+        let db = env
+            .create_database::<Bytes, Bytes>(&mut wtxn, Some("items"))
+            .unwrap();
+        db.put(&mut wtxn, b"a", &[1]).unwrap();
+        db.put(&mut wtxn, b"b", &[2, 3]).unwrap();
+
+        let mut migrations = Migrations::new();
+        migrations.add_append("Add bool", "items", true);
+        migrations.add_append("Add u32", "items", 4_u32);
+        migrations.add_append("Add to missing db", "missing", true);
+        migrations.run(&env, &mut wtxn).unwrap();
+
+        assert_eq!(
+            db.get(&wtxn, b"a").unwrap(),
+            Some([1, 1, 4, 0, 0, 0].as_slice())
+        );
+        assert_eq!(
+            db.get(&wtxn, b"b").unwrap(),
+            Some([2, 3, 1, 4, 0, 0, 0].as_slice())
+        );
+    }
 }
@@ -1,14 +1,24 @@
+mod compaction;
+mod integrity;
 mod migrations;
+mod stats;
 
 use anyhow::{Context, Result};
-use gtk::glib;
 
 use std::{
-    fs,
+    error, fmt, io,
+    path::Path,
+    thread,
     time::{Duration, Instant},
 };
 
-pub use self::migrations::Migrations;
+pub use self::{
+    compaction::{compact_at, CompactionReport},
+    integrity::{check_integrity, read_entries, repair_integrity, IntegrityReport, RawDatabase},
+    migrations::Migrations,
+    stats::{compute_stats, Stats},
+};
+use crate::paths;
 
 pub const USER_VERSION_KEY: &str = "user_version";
 
@@ -16,16 +26,110 @@ const N_NAMED_DBS: u32 = 2;
 pub const SONG_LIST_DB_NAME: &str = "song_list";
 pub const RECORDINGS_DB_NAME: &str = "saved_recordings";
 
+/// How many times opening the env is attempted while it is busy
+const MAX_OPEN_ATTEMPTS: u32 = 5;
+
+/// How long to wait before opening the env again while it is busy
+const OPEN_RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The env stayed busy, e.g., locked by another process or filled with stale
+/// readers, after all attempts to open it.
+#[derive(Debug)]
+pub struct DatabaseBusyError {
+    n_attempts: u32,
+    source: heed::Error,
+}
+
+impl fmt::Display for DatabaseBusyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Database is still busy after {} attempts",
+            self.n_attempts
+        )
+    }
+}
+
+impl error::Error for DatabaseBusyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl DatabaseBusyError {
+    /// Returns whether `err` was caused by the env being busy.
+    pub fn is_cause_of(err: &anyhow::Error) -> bool {
+        err.chain().any(|cause| cause.is::<Self>())
+    }
+}
+
+/// Returns whether the error is temporary and opening again may succeed.
+fn is_busy(err: &heed::Error) -> bool {
+    match err {
+        heed::Error::Io(err) => err.kind() == io::ErrorKind::WouldBlock,
+        heed::Error::Mdb(heed::MdbError::ReadersFull) => true,
+        _ => false,
+    }
+}
+
+/// Calls `open` until it doesn't fail with a busy error, up to
+/// `max_attempts` times, waiting `interval` between attempts.
+///
+/// This blocks the thread while waiting, so it must not be called on the main
+/// thread.
+fn retry_while_busy<T>(
+    max_attempts: u32,
+    interval: Duration,
+    mut open: impl FnMut() -> heed::Result<T>,
+) -> Result<T> {
+    let mut n_attempts = 0;
+
+    loop {
+        n_attempts += 1;
+
+        match open() {
+            Ok(ret) => return Ok(ret),
+            Err(err) if is_busy(&err) => {
+                if n_attempts >= max_attempts {
+                    return Err(DatabaseBusyError {
+                        n_attempts,
+                        source: err,
+                    }
+                    .into());
+                }
+
+                tracing::warn!(?err, n_attempts, "Database is busy, retrying");
+                thread::sleep(interval);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
 /// Note: This must be only called once.
 pub fn new_env() -> Result<heed::Env> {
-    let path = glib::user_data_dir().join("mousai/db");
-    fs::create_dir_all(&path)
-        .with_context(|| format!("Failed to create db dir at {}", path.display()))?;
-    let env = heed::EnvOpenOptions::new()
-        .map_size(100 * 1024 * 1024) // 100 MiB
-        .max_dbs(N_NAMED_DBS)
-        .open(&path)
-        .with_context(|| format!("Failed to open heed env at {}", path.display()))?;
+    new_env_at(&paths::db_dir())
+}
+
+/// Opens an env at `path`, creating the dir if it doesn't exist yet.
+///
+/// This retries for a bit while the env is busy, blocking the thread, and
+/// fails with [`DatabaseBusyError`] if it stays busy.
+pub fn new_env_at(path: &Path) -> Result<heed::Env> {
+    paths::ensure_writable_dir(path).context("Failed to prepare db dir")?;
+    let env = retry_while_busy(MAX_OPEN_ATTEMPTS, OPEN_RETRY_INTERVAL, || {
+        let env = heed::EnvOpenOptions::new()
+            .map_size(100 * 1024 * 1024) // 100 MiB
+            .max_dbs(N_NAMED_DBS)
+            .open(path)?;
+
+        // Also catch a reader table that is full, so it is not only found
+        // out on the first txn.
+        drop(env.read_txn()?);
+
+        Ok(env)
+    })
+    .with_context(|| format!("Failed to open heed env at {}", path.display()))?;
 
     tracing::debug!(
         ?path,
@@ -75,3 +179,59 @@ impl EnvExt for heed::Env {
         Ok(ret)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    fn busy_error() -> heed::Error {
+        heed::Error::Io(io::Error::from(io::ErrorKind::WouldBlock))
+    }
+
+    #[test]
+    fn retry_while_busy_succeeds() {
+        let n_calls = Cell::new(0);
+        let ret = retry_while_busy(5, Duration::ZERO, || {
+            n_calls.set(n_calls.get() + 1);
+
+            if n_calls.get() < 3 {
+                Err(busy_error())
+            } else {
+                Ok("opened")
+            }
+        });
+
+        assert_eq!(ret.unwrap(), "opened");
+        assert_eq!(n_calls.get(), 3);
+    }
+
+    #[test]
+    fn retry_while_busy_gives_up() {
+        let n_calls = Cell::new(0);
+        let err = retry_while_busy(5, Duration::ZERO, || -> heed::Result<()> {
+            n_calls.set(n_calls.get() + 1);
+            Err(heed::Error::Mdb(heed::MdbError::ReadersFull))
+        })
+        .context("Failed to open")
+        .unwrap_err();
+
+        assert_eq!(n_calls.get(), 5);
+        assert!(DatabaseBusyError::is_cause_of(&err));
+    }
+
+    #[test]
+    fn retry_while_busy_not_busy() {
+        let n_calls = Cell::new(0);
+        let err = retry_while_busy(5, Duration::ZERO, || -> heed::Result<()> {
+            n_calls.set(n_calls.get() + 1);
+            Err(heed::Error::Mdb(heed::MdbError::Corrupted))
+        })
+        .unwrap_err();
+
+        // Only busy errors are retried
+        assert_eq!(n_calls.get(), 1);
+        assert!(!DatabaseBusyError::is_cause_of(&err));
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{Context, Result};
+use heed::types::SerdeBincode;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Instant,
+};
+
+use super::SONG_LIST_DB_NAME;
+use crate::{song::Song, uid::UidCodec};
+
+/// Number of artists in [`Stats::top_artists`]
+pub const N_TOP_ARTISTS: usize = 5;
+
+/// Aggregates of the songs in the history
+///
+/// Only the last time each song was heard is stored, so every song is
+/// counted once, at that time, instead of once per recognition.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    /// Number of songs, including placeholders
+    pub n_songs: u32,
+    pub n_matches: u32,
+    /// Number of placeholders of recognitions without matches, which are
+    /// only known if placeholders are saved
+    pub n_no_matches: u32,
+    /// Artists with the most songs, most first
+    pub top_artists: Vec<(String, u32)>,
+    /// Number of songs last heard per day of the week, from Monday to Sunday
+    pub n_per_weekday: [u32; 7],
+    /// Number of songs last heard per hour of the day
+    pub n_per_hour: [u32; 24],
+    /// Match rate of the songs last heard per year and month, oldest first
+    pub match_rate_per_month: Vec<((i32, i32), f64)>,
+}
+
+impl Stats {
+    /// Returns the ratio of songs that are not placeholders, or `None` if
+    /// there are no songs.
+    pub fn match_rate(&self) -> Option<f64> {
+        (self.n_songs != 0).then(|| self.n_matches as f64 / self.n_songs as f64)
+    }
+
+    /// Returns the day of the week with the most songs last heard, from
+    /// 1 (Monday) to 7 (Sunday).
+    pub fn busiest_weekday(&self) -> Option<i32> {
+        busiest(&self.n_per_weekday).map(|index| index as i32 + 1)
+    }
+
+    /// Returns the hour of the day with the most songs last heard.
+    pub fn busiest_hour(&self) -> Option<i32> {
+        busiest(&self.n_per_hour).map(|index| index as i32)
+    }
+}
+
+/// Aggregates all songs in the db, using local time for the time-based stats.
+///
+/// Like [`read_entries`](super::read_entries), this only takes a read
+/// txn, so it can be run on a separate thread.
+pub fn compute_stats(env: &heed::Env) -> Result<Stats> {
+    let start_time = Instant::now();
+
+    let rtxn = env.read_txn().context("Failed to create read txn")?;
+    let mut stats = Stats::default();
+
+    let Some(db) = env
+        .open_database::<UidCodec, SerdeBincode<Song>>(&rtxn, Some(SONG_LIST_DB_NAME))
+        .context("Failed to open songs db")?
+    else {
+        return Ok(stats);
+    };
+
+    let mut n_per_artist = HashMap::<String, u32>::new();
+    // Number of matches and total per month
+    let mut n_per_month = BTreeMap::<(i32, i32), (u32, u32)>::new();
+
+    for item in db.iter(&rtxn).context("Failed to iter songs db")? {
+        let (_, song) = item.context("Failed to read songs db")?;
+        let is_match = !song.is_placeholder();
+
+        stats.n_songs += 1;
+        if is_match {
+            stats.n_matches += 1;
+            *n_per_artist.entry(song.artist()).or_default() += 1;
+        } else {
+            stats.n_no_matches += 1;
+        }
+
+        if let Some(last_heard) = song.last_heard() {
+            let last_heard = last_heard.to_local();
+            stats.n_per_weekday[last_heard.day_of_week() as usize - 1] += 1;
+            stats.n_per_hour[last_heard.hour() as usize] += 1;
+
+            let (n_matches, n_total) = n_per_month.entry(last_heard.year_month()).or_default();
+            *n_total += 1;
+            if is_match {
+                *n_matches += 1;
+            }
+        }
+    }
+
+    let mut top_artists = n_per_artist.into_iter().collect::<Vec<_>>();
+    // Ties are ordered by name so the result is stable
+    top_artists.sort_by(|(a_artist, a_n), (b_artist, b_n)| {
+        b_n.cmp(a_n).then_with(|| a_artist.cmp(b_artist))
+    });
+    top_artists.truncate(N_TOP_ARTISTS);
+    stats.top_artists = top_artists;
+
+    stats.match_rate_per_month = n_per_month
+        .into_iter()
+        .map(|(month, (n_matches, n_total))| (month, n_matches as f64 / n_total as f64))
+        .collect();
+
+    tracing::debug!(
+        n_songs = stats.n_songs,
+        "Computed stats in {:?}",
+        start_time.elapsed()
+    );
+
+    Ok(stats)
+}
+
+/// Returns the index of the largest non-zero count, preferring the first one
+/// on ties.
+fn busiest(counts: &[u32]) -> Option<usize> {
+    counts
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| **n != 0)
+        .fold(
+            None,
+            |busiest: Option<(usize, u32)>, (index, &n)| match busiest {
+                Some((_, busiest_n)) if busiest_n >= n => busiest,
+                _ => Some((index, n)),
+            },
+        )
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{database, date_time::DateTime, song_list::SongList, uid::Uid};
+
+    fn new_test_song(id: &str, artist: &str, last_heard: &str) -> Song {
+        let song = Song::builder(&Uid::from(id), id, artist, "").build();
+        song.set_last_heard(Some(DateTime::from_iso8601(last_heard).unwrap()));
+        song
+    }
+
+    #[gtk::test]
+    fn empty() {
+        let (env, _tempdir) = database::new_test_env();
+        let stats = compute_stats(&env).unwrap();
+        assert_eq!(stats, Stats::default());
+        assert_eq!(stats.match_rate(), None);
+        assert_eq!(stats.busiest_weekday(), None);
+        assert_eq!(stats.busiest_hour(), None);
+    }
+
+    #[gtk::test]
+    fn seeded() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env.clone()).unwrap();
+
+        // Midday, so the day is the same in every time zone
+        let no_match_time = DateTime::from_iso8601("2023-02-01T12:00:00Z").unwrap();
+        song_list
+            .insert_many(vec![
+                // Monday
+                new_test_song("a", "Someone", "2023-01-02T12:00:00Z"),
+                new_test_song("b", "Someone", "2023-01-09T12:00:00Z"),
+                new_test_song("c", "Someone", "2023-01-16T12:00:00Z"),
+                // Wednesday
+                new_test_song("d", "Other", "2023-02-01T12:00:00Z"),
+                new_test_song("e", "Another", "2023-02-01T12:00:00Z"),
+                Song::new_placeholder(&no_match_time),
+            ])
+            .unwrap();
+
+        let stats = compute_stats(&env).unwrap();
+        assert_eq!(stats.n_songs, 6);
+        assert_eq!(stats.n_matches, 5);
+        assert_eq!(stats.n_no_matches, 1);
+        assert_eq!(
+            stats.top_artists,
+            vec![
+                ("Someone".to_string(), 3),
+                ("Another".to_string(), 1),
+                ("Other".to_string(), 1),
+            ]
+        );
+        assert_eq!(stats.n_per_weekday, [3, 0, 3, 0, 0, 0, 0]);
+        assert_eq!(stats.busiest_weekday(), Some(1));
+        assert_eq!(stats.busiest_hour(), Some(no_match_time.to_local().hour()));
+        assert_eq!(stats.match_rate(), Some(5.0 / 6.0));
+        assert_eq!(
+            stats.match_rate_per_month,
+            vec![((2023, 1), 1.0), ((2023, 2), 2.0 / 3.0)]
+        );
+    }
+
+    #[test]
+    fn busiest_ties() {
+        assert_eq!(busiest(&[]), None);
+        assert_eq!(busiest(&[0, 0]), None);
+        assert_eq!(busiest(&[1, 3, 3, 2]), Some(1));
+    }
+}
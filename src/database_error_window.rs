@@ -1,12 +1,26 @@
 use adw::{prelude::*, subclass::prelude::*};
+use gettextrs::gettext;
 use gtk::glib;
 
+use std::cell::Cell;
+
 mod imp {
     use super::*;
 
-    #[derive(Default, gtk::CompositeTemplate)]
+    #[derive(Default, glib::Properties, gtk::CompositeTemplate)]
+    #[properties(wrapper_type = super::DatabaseErrorWindow)]
     #[template(resource = "/io/github/seadve/Mousai/ui/database-error-window.ui")]
-    pub struct DatabaseErrorWindow;
+    pub struct DatabaseErrorWindow {
+        /// Whether the database is only busy, e.g., locked by another
+        /// process, rather than broken, so opening it again may succeed
+        #[property(get, set, construct_only)]
+        pub(super) is_busy: Cell<bool>,
+
+        #[template_child]
+        pub(super) status_page: TemplateChild<adw::StatusPage>,
+        #[template_child]
+        pub(super) retry_button: TemplateChild<gtk::Button>,
+    }
 
     #[glib::object_subclass]
     impl ObjectSubclass for DatabaseErrorWindow {
@@ -16,6 +30,13 @@ mod imp {
 
         fn class_init(klass: &mut Self::Class) {
             klass.bind_template();
+
+            klass.install_action("database-error-window.retry", None, |obj, _, _| {
+                // The application closes this once the database is opened.
+                if let Some(application) = obj.application() {
+                    application.activate();
+                }
+            });
         }
 
         fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
@@ -23,7 +44,23 @@ mod imp {
         }
     }
 
-    impl ObjectImpl for DatabaseErrorWindow {}
+    #[glib::derived_properties]
+    impl ObjectImpl for DatabaseErrorWindow {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            if self.is_busy.get() {
+                self.status_page
+                    .set_icon_name(Some("dialog-warning-symbolic"));
+                self.status_page.set_title(&gettext("Database Is in Use"));
+                self.status_page.set_description(Some(&gettext(
+                    "Another process, such as another copy of Mousai, is holding the database. Close it and try again",
+                )));
+                self.retry_button.set_visible(true);
+            }
+        }
+    }
+
     impl WidgetImpl for DatabaseErrorWindow {}
     impl WindowImpl for DatabaseErrorWindow {}
     impl ApplicationWindowImpl for DatabaseErrorWindow {}
@@ -36,9 +73,10 @@ glib::wrapper! {
 }
 
 impl DatabaseErrorWindow {
-    pub fn new(application: &impl IsA<gtk::Application>) -> Self {
+    pub fn new(application: &impl IsA<gtk::Application>, is_busy: bool) -> Self {
         glib::Object::builder()
             .property("application", application)
+            .property("is-busy", is_busy)
             .build()
     }
 }
@@ -17,6 +17,13 @@ impl DateTime {
         Self(self.0.to_local().unwrap())
     }
 
+    pub fn add_days(&self, days: i32) -> Result<Self> {
+        self.0
+            .add_days(days)
+            .map(Self)
+            .with_context(|| format!("Failed to add {} days to datetime", days))
+    }
+
     pub fn from_iso8601(string: &str) -> Result<Self> {
         glib::DateTime::from_iso8601(string, None)
             .map(Self)
@@ -38,6 +45,26 @@ impl DateTime {
         .expect("format must be correct")
     }
 
+    /// Returns the year, the month, from 1 to 12, and the day of the month.
+    pub fn ymd(&self) -> (i32, i32, i32) {
+        self.0.ymd()
+    }
+
+    /// Returns the year and the month, from 1 to 12.
+    pub fn year_month(&self) -> (i32, i32) {
+        (self.0.year(), self.0.month())
+    }
+
+    /// Returns the day of the week, from 1 (Monday) to 7 (Sunday).
+    pub fn day_of_week(&self) -> i32 {
+        self.0.day_of_week()
+    }
+
+    /// Returns the hour of the day, from 0 to 23.
+    pub fn hour(&self) -> i32 {
+        self.0.hour()
+    }
+
     pub fn format_iso8601(&self) -> glib::GString {
         self.0.format_iso8601().unwrap()
     }
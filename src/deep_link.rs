@@ -0,0 +1,86 @@
+use gtk::glib;
+
+use crate::uid::Uid;
+
+/// URI scheme that the app is registered to handle
+pub const SCHEME: &str = "mousai";
+
+const SONG_HOST: &str = "song";
+
+/// Returns the link that reveals the song in the app, i.e.,
+/// `mousai://song/<id>`.
+pub fn song_uri(song_id: &Uid) -> String {
+    format!(
+        "{}://{}/{}",
+        SCHEME,
+        SONG_HOST,
+        glib::Uri::escape_string(song_id.as_str(), None, false)
+    )
+}
+
+/// Returns the id of the song that the link points to, or `None` if it is
+/// not a song link.
+pub fn parse_song_uri(uri: &str) -> Option<Uid> {
+    let uri = glib::Uri::parse(uri, glib::UriFlags::ENCODED_PATH).ok()?;
+
+    if !uri.scheme().eq_ignore_ascii_case(SCHEME) || uri.host().as_deref() != Some(SONG_HOST) {
+        return None;
+    }
+
+    let escaped_id = uri.path();
+    let escaped_id = escaped_id.strip_prefix('/')?;
+
+    if escaped_id.is_empty() || escaped_id.contains('/') {
+        return None;
+    }
+
+    let id = glib::Uri::unescape_string(escaped_id, None)?;
+    Some(Uid::from(id.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{database, song::Song, song_list::SongList};
+
+    #[test]
+    fn round_trip() {
+        for id in ["a", "AudD-some/id", "with space", "ünïcode?#"] {
+            let uid = Uid::from(id);
+            let uri = song_uri(&uid);
+            assert!(uri.starts_with("mousai://song/"));
+            assert_eq!(parse_song_uri(&uri), Some(uid), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(parse_song_uri("https://song/a"), None);
+        assert_eq!(parse_song_uri("mousai://album/a"), None);
+        assert_eq!(parse_song_uri("mousai://song/"), None);
+        assert_eq!(parse_song_uri("mousai://song/a/b"), None);
+        assert_eq!(parse_song_uri("not a uri"), None);
+    }
+
+    #[gtk::test]
+    fn resolve() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+        song_list
+            .insert_many(vec![
+                Song::builder(&Uid::from("AudD-a/1"), "A", "A", "A").build(),
+                Song::builder(&Uid::from("b"), "B", "B", "B").build(),
+            ])
+            .unwrap();
+
+        let song_id = parse_song_uri("mousai://song/AudD-a%2F1").unwrap();
+        assert_eq!(song_list.get(&song_id).unwrap().title(), "A");
+
+        let song_id = parse_song_uri(&song_uri(&Uid::from("b"))).unwrap();
+        assert_eq!(song_list.get(&song_id).unwrap().title(), "B");
+
+        let song_id = parse_song_uri("mousai://song/missing").unwrap();
+        assert!(song_list.get(&song_id).is_none());
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::Error;
+
+use crate::{about, recognizer::RecognizeContext};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Builds a report for the given error that can be attached to bug reports.
+///
+/// All occurences of `secrets` are redacted from the report.
+pub fn error_report(err: &Error, secrets: &[&str]) -> String {
+    let context = err.downcast_ref::<RecognizeContext>().map_or_else(
+        || "- Recognize Context: <none>".to_string(),
+        |context| {
+            format!(
+                "- Provider: {:?}\n- Device: {}\n- Listen Duration: {:?}",
+                context.provider,
+                context.device_name.as_deref().unwrap_or("<unknown>"),
+                context.listen_duration
+            )
+        },
+    );
+
+    let report = format!("{}\n\n{}\n\n{:?}", about::debug_info(), context, err);
+    redact(&report, secrets)
+}
+
+fn redact(text: &str, secrets: &[&str]) -> String {
+    secrets
+        .iter()
+        .filter(|secret| !secret.is_empty())
+        .fold(text.to_string(), |text, secret| {
+            text.replace(secret, REDACTED)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::anyhow;
+
+    #[test]
+    fn redact_secrets() {
+        assert_eq!(
+            redact("token=abc123&other=abc", &["abc123", ""]),
+            "token=[REDACTED]&other=abc"
+        );
+    }
+
+    #[test]
+    fn error_report_redacted() {
+        let err = anyhow!("Request failed with api_token=secret-token");
+        let report = error_report(&err.context("Failed to recognize"), &["secret-token"]);
+
+        assert!(!report.contains("secret-token"));
+        assert!(report.contains("api_token=[REDACTED]"));
+        assert!(report.contains("Failed to recognize"));
+    }
+}
@@ -0,0 +1,474 @@
+use anyhow::{ensure, Result};
+use gtk::glib;
+use serde_json::json;
+
+use crate::{
+    settings::{ArtistTitleOrder, CopyFormat},
+    song::Song,
+};
+
+/// Format to export songs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// CSV with `Title`, `Artist`, `Album`, `Release Date`, `Last Heard`,
+    /// and `Link` columns
+    Csv,
+    /// Extended M3U playlist of the songs with a link
+    M3u,
+    /// JSON array of song objects
+    Json,
+}
+
+impl ExportFormat {
+    pub const ALL: [Self; 3] = [Self::Csv, Self::M3u, Self::Json];
+
+    /// Guesses the format from the file name's extension.
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+
+        Self::ALL
+            .into_iter()
+            .find(|format| extension.eq_ignore_ascii_case(format.extension()))
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::M3u => "m3u",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// Exports the given songs, in the given order, into text.
+///
+/// This fails if there are no songs, as the result would be useless.
+pub fn export<'a>(
+    format: ExportFormat,
+    songs: impl IntoIterator<Item = &'a Song>,
+) -> Result<String> {
+    let songs = songs.into_iter().collect::<Vec<_>>();
+    ensure!(!songs.is_empty(), "There are no songs to export");
+
+    let text = match format {
+        ExportFormat::Csv => export_csv(&songs),
+        ExportFormat::M3u => export_m3u(&songs),
+        ExportFormat::Json => export_json(&songs),
+    };
+
+    Ok(text)
+}
+
+/// Returns the text copied to the clipboard for the given songs, in the given
+/// order, or `None` if there are no songs.
+///
+/// Songs are separated by newlines, except for JSON, where a single song is
+/// copied as an object and several as an array.
+pub fn copy_text(format: CopyFormat, order: ArtistTitleOrder, songs: &[Song]) -> Option<String> {
+    if songs.is_empty() {
+        return None;
+    }
+
+    let text = match format {
+        CopyFormat::Plain => songs
+            .iter()
+            .map(|song| song.copy_term(order))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        CopyFormat::Markdown => songs
+            .iter()
+            .map(|song| markdown_link(song, order))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        CopyFormat::Json => match songs {
+            [song] => serde_json::to_string_pretty(&song_json(song)).unwrap(),
+            songs => export_json(&songs.iter().collect::<Vec<_>>()),
+        },
+    };
+
+    Some(text)
+}
+
+/// Returns a journal entry of the given songs, one line each formatted with
+/// `template`, or `None` if there are no songs.
+///
+/// The template may contain `{time}`, `{title}`, `{artist}`, `{album}`, and
+/// `{link}`, which are replaced with the local time the song was last heard,
+/// e.g., `13:21`, its metadata, and its web link, or nothing if it has none.
+pub fn journal_entry(template: &str, songs: &[Song]) -> Option<String> {
+    if songs.is_empty() {
+        return None;
+    }
+
+    let lines = songs
+        .iter()
+        .map(|song| {
+            let time = song
+                .last_heard()
+                .and_then(|last_heard| last_heard.to_local().format("%R").ok())
+                .unwrap_or_default();
+
+            template
+                .replace("{time}", &time)
+                .replace("{title}", &song.title())
+                .replace("{artist}", &song.artist())
+                .replace("{album}", &song.album())
+                .replace("{link}", &song.web_link().unwrap_or_default())
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+
+    Some(lines.join("\n"))
+}
+
+/// Returns a Markdown link to the song's web link, or just the escaped copy
+/// term if it has none.
+fn markdown_link(song: &Song, order: ArtistTitleOrder) -> String {
+    let text = song
+        .copy_term(order)
+        .replace('\\', "\\\\")
+        .replace('[', "\\[")
+        .replace(']', "\\]");
+
+    match song.web_link() {
+        // Parentheses and spaces are escaped so they don't end the link early
+        Some(link) => format!(
+            "[{}]({})",
+            text,
+            glib::Uri::escape_string(&link, Some("!$&'*+,;=:@/?#%"), false)
+        ),
+        None => text,
+    }
+}
+
+fn export_csv(songs: &[&Song]) -> String {
+    let mut text = String::from("Title,Artist,Album,Release Date,Last Heard,Link\n");
+
+    for song in songs {
+        let fields = [
+            song.title(),
+            song.artist(),
+            song.album(),
+            song.release_date().unwrap_or_default(),
+            song.last_heard()
+                .map(|last_heard| last_heard.format_iso8601().to_string())
+                .unwrap_or_default(),
+            song.web_link().unwrap_or_default(),
+        ];
+        text.push_str(
+            &fields
+                .iter()
+                .map(|field| escape_csv_field(field))
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        text.push('\n');
+    }
+
+    text
+}
+
+/// Songs without a link are skipped, as M3U entries must have a location.
+fn export_m3u(songs: &[&Song]) -> String {
+    let mut text = String::from("#EXTM3U\n");
+
+    for song in songs {
+        let Some(link) = song.playback_link().or_else(|| song.web_link()) else {
+            continue;
+        };
+
+        // Players expect the conventional order in the display title
+        text.push_str(&format!(
+            "#EXTINF:-1,{}\n{}\n",
+            song.copy_term(ArtistTitleOrder::ArtistFirst),
+            link
+        ));
+    }
+
+    text
+}
+
+fn export_json(songs: &[&Song]) -> String {
+    let entries = songs.iter().map(|song| song_json(song)).collect::<Vec<_>>();
+
+    serde_json::to_string_pretty(&entries).unwrap()
+}
+
+fn song_json(song: &Song) -> serde_json::Value {
+    json!({
+        "id": song.id(),
+        "title": song.title(),
+        "artist": song.artist(),
+        "album": song.album(),
+        "release_date": song.release_date(),
+        "last_heard": song.last_heard().map(|last_heard| last_heard.format_iso8601().to_string()),
+        "is_favorite": song.is_favorite(),
+        "link": song.web_link(),
+        "playback_link": song.playback_link(),
+        "album_art_link": song.album_art_link(),
+    })
+}
+
+/// Quotes the field if it has a comma, a quote, or a line break.
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        database, date_time::DateTime, external_links::ExternalLinkKey, song_list::SongList,
+        uid::Uid,
+    };
+
+    fn new_test_songs() -> Vec<Song> {
+        let a = Song::builder(&Uid::from("a"), "Song, A", "Someone", "Album")
+            .external_link(ExternalLinkKey::AudDUrl, "https://aud_d.link/a")
+            .build();
+        a.set_last_heard(Some(
+            DateTime::from_iso8601("2022-01-01T00:00:00Z").unwrap(),
+        ));
+        let b = Song::builder(&Uid::from("b"), "Song \"B\"", "Someone", "")
+            .playback_link("https://b.mp3")
+            .build();
+        let c = Song::builder(&Uid::from("c"), "Song C", "Someone Else", "").build();
+        vec![a, b, c]
+    }
+
+    #[test]
+    fn format_from_file_name() {
+        assert_eq!(
+            ExportFormat::from_file_name("history.CSV"),
+            Some(ExportFormat::Csv)
+        );
+        assert_eq!(
+            ExportFormat::from_file_name("history.m3u"),
+            Some(ExportFormat::M3u)
+        );
+        assert_eq!(
+            ExportFormat::from_file_name("history.json"),
+            Some(ExportFormat::Json)
+        );
+        assert_eq!(ExportFormat::from_file_name("history"), None);
+    }
+
+    #[test]
+    fn csv_field() {
+        assert_eq!(escape_csv_field("a"), "a");
+        assert_eq!(escape_csv_field("a, b"), "\"a, b\"");
+        assert_eq!(escape_csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_csv_field("a\nb"), "\"a\nb\"");
+        assert_eq!(escape_csv_field("a\r\nb"), "\"a\r\nb\"");
+        assert_eq!(escape_csv_field("a\rb"), "\"a\rb\"");
+    }
+
+    #[test]
+    fn empty() {
+        for format in ExportFormat::ALL {
+            assert!(export(format, Vec::<&Song>::new()).is_err());
+        }
+    }
+
+    #[test]
+    fn csv_subset() {
+        let songs = new_test_songs();
+
+        let text = export(ExportFormat::Csv, [&songs[0], &songs[2]]).unwrap();
+        assert_eq!(
+            text,
+            "Title,Artist,Album,Release Date,Last Heard,Link
+\"Song, A\",Someone,Album,,2022-01-01T00:00:00Z,https://aud_d.link/a
+Song C,Someone Else,,,,
+"
+        );
+    }
+
+    #[test]
+    fn m3u() {
+        let songs = new_test_songs();
+
+        let text = export(ExportFormat::M3u, &songs).unwrap();
+        assert_eq!(
+            text,
+            "#EXTM3U
+#EXTINF:-1,Someone - Song, A
+https://aud_d.link/a
+#EXTINF:-1,Someone - Song \"B\"
+https://b.mp3
+"
+        );
+    }
+
+    #[test]
+    fn json_subset() {
+        let songs = new_test_songs();
+
+        let text = export(ExportFormat::Json, &songs[1..]).unwrap();
+        let entries = serde_json::from_str::<Vec<serde_json::Value>>(&text).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["id"], "b");
+        assert_eq!(entries[0]["title"], "Song \"B\"");
+        assert_eq!(entries[0]["playback_link"], "https://b.mp3");
+        assert_eq!(entries[1]["id"], "c");
+        assert_eq!(entries[1]["link"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn copy_text_empty() {
+        for format in [CopyFormat::Plain, CopyFormat::Markdown, CopyFormat::Json] {
+            assert_eq!(copy_text(format, ArtistTitleOrder::ArtistFirst, &[]), None);
+        }
+    }
+
+    #[test]
+    fn copy_text_plain() {
+        let songs = new_test_songs();
+
+        assert_eq!(
+            copy_text(
+                CopyFormat::Plain,
+                ArtistTitleOrder::ArtistFirst,
+                &songs[..1]
+            )
+            .as_deref(),
+            Some("Someone - Song, A")
+        );
+        assert_eq!(
+            copy_text(
+                CopyFormat::Plain,
+                ArtistTitleOrder::ArtistFirst,
+                &songs[1..]
+            )
+            .as_deref(),
+            Some("Someone - Song \"B\"\nSomeone Else - Song C")
+        );
+    }
+
+    #[test]
+    fn copy_text_plain_title_first() {
+        let songs = new_test_songs();
+
+        assert_eq!(
+            copy_text(CopyFormat::Plain, ArtistTitleOrder::TitleFirst, &songs[1..]).as_deref(),
+            Some("Song \"B\" - Someone\nSong C - Someone Else")
+        );
+        assert_eq!(
+            copy_text(
+                CopyFormat::Markdown,
+                ArtistTitleOrder::TitleFirst,
+                &songs[..1]
+            )
+            .as_deref(),
+            Some("[Song, A - Someone](https://aud_d.link/a)")
+        );
+    }
+
+    #[test]
+    fn copy_text_markdown() {
+        let songs = new_test_songs();
+
+        assert_eq!(
+            copy_text(
+                CopyFormat::Markdown,
+                ArtistTitleOrder::ArtistFirst,
+                &songs[..1]
+            )
+            .as_deref(),
+            Some("[Someone - Song, A](https://aud_d.link/a)")
+        );
+        // No web link, so it degrades to plain text
+        assert_eq!(
+            copy_text(
+                CopyFormat::Markdown,
+                ArtistTitleOrder::ArtistFirst,
+                &songs[2..]
+            )
+            .as_deref(),
+            Some("Someone Else - Song C")
+        );
+
+        let song = Song::builder(&Uid::from("d"), "[Live] (Remix)", "A\\B", "")
+            .external_link(
+                ExternalLinkKey::SpotifyUrl,
+                "https://open.spotify.test/search/a b (c)",
+            )
+            .external_link(ExternalLinkKey::AudDUrl, "https://aud_d.link/d")
+            .build();
+        assert_eq!(
+            copy_text(CopyFormat::Markdown, ArtistTitleOrder::ArtistFirst, &[song]).as_deref(),
+            Some("[A\\\\B - \\[Live\\] (Remix)](https://open.spotify.test/search/a%20b%20%28c%29)")
+        );
+    }
+
+    #[test]
+    fn copy_text_json() {
+        let songs = new_test_songs();
+
+        let text = copy_text(CopyFormat::Json, ArtistTitleOrder::ArtistFirst, &songs[..1]).unwrap();
+        let entry = serde_json::from_str::<serde_json::Value>(&text).unwrap();
+        assert_eq!(entry["id"], "a");
+        assert_eq!(entry["link"], "https://aud_d.link/a");
+
+        let text = copy_text(CopyFormat::Json, ArtistTitleOrder::ArtistFirst, &songs[2..]).unwrap();
+        let entry = serde_json::from_str::<serde_json::Value>(&text).unwrap();
+        assert_eq!(entry["id"], "c");
+        assert_eq!(entry["link"], serde_json::Value::Null);
+
+        let text = copy_text(CopyFormat::Json, ArtistTitleOrder::ArtistFirst, &songs).unwrap();
+        let entries = serde_json::from_str::<Vec<serde_json::Value>>(&text).unwrap();
+        assert_eq!(entries.len(), 3);
+    }
+
+    #[test]
+    fn journal_entry_of_day() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let new_song = |id: &str, (hour, minute): (i32, i32), day: i32| {
+            let song = Song::builder(&Uid::from(id), id, "Someone", "Album").build();
+            song.set_last_heard(Some(DateTime::from(
+                glib::DateTime::from_local(2023, 5, day, hour, minute, 0.0).unwrap(),
+            )));
+            song
+        };
+        let evening = Song::builder(&Uid::from("evening"), "Evening", "Someone", "Album")
+            .external_link(ExternalLinkKey::AudDUrl, "https://aud_d.link/evening")
+            .build();
+        evening.set_last_heard(Some(DateTime::from(
+            glib::DateTime::from_local(2023, 5, 14, 21, 5, 0.0).unwrap(),
+        )));
+        song_list
+            .insert_many(vec![
+                evening,
+                new_song("Morning", (9, 30), 14),
+                new_song("Yesterday", (23, 59), 13),
+                new_song("Tomorrow", (0, 0), 15),
+            ])
+            .unwrap();
+
+        let day = DateTime::from(glib::DateTime::from_local(2023, 5, 14, 12, 0, 0.0).unwrap());
+        let songs = song_list.heard_on(&day);
+        assert_eq!(
+            journal_entry("- {time} {artist} - {title} {link}", &songs).as_deref(),
+            Some(
+                "- 09:30 Someone - Morning
+- 21:05 Someone - Evening https://aud_d.link/evening"
+            )
+        );
+        assert_eq!(
+            journal_entry("{title} ({album})", &songs).as_deref(),
+            Some("Morning (Album)\nEvening (Album)")
+        );
+
+        let empty_day = DateTime::from(glib::DateTime::from_local(2023, 5, 1, 12, 0, 0.0).unwrap());
+        assert!(song_list.heard_on(&empty_day).is_empty());
+        assert_eq!(journal_entry("{title}", &[]), None);
+    }
+}
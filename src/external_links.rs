@@ -1,21 +1,75 @@
 use gtk::{gio, glib, prelude::*, subclass::prelude::*};
 use indexmap::IndexMap;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 
-use std::{cell::RefCell, collections::HashMap};
+use std::{cell::RefCell, collections::HashMap, fmt, str::FromStr};
 
 use super::external_link::ExternalLink;
+use crate::settings::PreferredStreamingService;
 
 /// Known keys for external links.
-#[derive(Debug, Clone, Copy, strum::EnumString, strum::AsRefStr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::EnumString, strum::AsRefStr)]
 #[strum(serialize_all = "kebab-case")]
 pub enum ExternalLinkKey {
     AppleMusicUrl,
     AudDUrl,
+    GeniusUrl,
+    /// MBID of the release, i.e., the album, on MusicBrainz
+    MusicbrainzReleaseId,
     SpotifyUrl,
     YoutubeSearchTerm,
 }
 
+impl ExternalLinkKey {
+    /// Keys of links to where a song can be listened to, tried in this order
+    /// after the preferred one
+    const DEFAULT_LISTEN_PRIORITY: [Self; 4] = [
+        Self::SpotifyUrl,
+        Self::AppleMusicUrl,
+        Self::YoutubeSearchTerm,
+        Self::AudDUrl,
+    ];
+
+    /// Returns the keys to try when opening a link to listen to a song, with
+    /// `preferred` first.
+    pub fn listen_priority(preferred: Self) -> Vec<Self> {
+        let mut priority = vec![preferred];
+        priority.extend(
+            Self::DEFAULT_LISTEN_PRIORITY
+                .into_iter()
+                .filter(|key| *key != preferred),
+        );
+        priority
+    }
+
+    /// Converts the value of a link with this key to a launchable uri.
+    pub fn to_uri(self, value: &str) -> String {
+        match self {
+            Self::AppleMusicUrl | Self::AudDUrl | Self::GeniusUrl | Self::SpotifyUrl => {
+                value.to_string()
+            }
+            Self::MusicbrainzReleaseId => format!("https://musicbrainz.org/release/{}", value),
+            Self::YoutubeSearchTerm => format!(
+                "https://www.youtube.com/results?search_query={}",
+                glib::Uri::escape_string(value, None, true)
+            ),
+        }
+    }
+}
+
+impl From<PreferredStreamingService> for ExternalLinkKey {
+    fn from(service: PreferredStreamingService) -> Self {
+        match service {
+            PreferredStreamingService::Spotify => Self::SpotifyUrl,
+            PreferredStreamingService::AppleMusic => Self::AppleMusicUrl,
+            PreferredStreamingService::Youtube => Self::YoutubeSearchTerm,
+        }
+    }
+}
+
 mod imp {
     use super::*;
 
@@ -88,6 +142,43 @@ impl ExternalLinks {
     pub fn get(&self, key: ExternalLinkKey) -> Option<String> {
         self.imp().map.borrow().get(key.as_ref()).cloned()
     }
+
+    /// Returns the key and value of the first link in `priority` that exists.
+    pub fn first_of(&self, priority: &[ExternalLinkKey]) -> Option<(ExternalLinkKey, String)> {
+        let map = self.imp().map.borrow();
+        priority
+            .iter()
+            .find_map(|key| map.get(key.as_ref()).map(|value| (*key, value.clone())))
+    }
+
+    /// Inserts the links from `other` whose keys are not in self yet, including
+    /// unknown keys.
+    ///
+    /// This returns the number of links inserted.
+    pub fn merge(&self, other: &ExternalLinks) -> u32 {
+        let to_insert = {
+            let map = self.imp().map.borrow();
+            other
+                .imp()
+                .map
+                .borrow()
+                .iter()
+                .filter(|(key, _)| !map.contains_key(*key))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        if to_insert.is_empty() {
+            return 0;
+        }
+
+        let n_inserted = to_insert.len() as u32;
+        let position = self.n_items();
+        self.imp().map.borrow_mut().extend(to_insert);
+        self.items_changed(position, 0, n_inserted);
+
+        n_inserted
+    }
 }
 
 impl Default for ExternalLinks {
@@ -102,9 +193,42 @@ impl Serialize for ExternalLinks {
     }
 }
 
+/// Collapses duplicate keys, where the last value wins but the position of the
+/// first occurrence is kept.
+///
+/// Unknown keys are kept, so links from newer versions are not lost.
+struct ExternalLinksMapVisitor;
+
+impl<'de> Visitor<'de> for ExternalLinksMapVisitor {
+    type Value = IndexMap<String, String>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a map of external link keys to values")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut map = IndexMap::new();
+
+        while let Some((key, value)) = access.next_entry::<String, String>()? {
+            if ExternalLinkKey::from_str(&key).is_err() {
+                tracing::warn!("Found unknown external link key `{}`", key);
+            }
+
+            if let Some(prev_value) = map.insert(key, value) {
+                tracing::warn!(
+                    "Found duplicate external link key; replaced `{}`",
+                    prev_value
+                );
+            }
+        }
+
+        Ok(map)
+    }
+}
+
 impl<'de> Deserialize<'de> for ExternalLinks {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        let map = IndexMap::<String, String>::deserialize(deserializer)?;
+        let map = deserializer.deserialize_map(ExternalLinksMapVisitor)?;
 
         let this = Self::new();
         this.imp().map.replace(map);
@@ -185,6 +309,96 @@ mod tests {
         assert_eq!(n_called.get(), 1);
     }
 
+    #[test]
+    fn merge() {
+        let links = ExternalLinks::default();
+        links.insert(ExternalLinkKey::SpotifyUrl, "A".to_string());
+
+        let other = ExternalLinks::default();
+        other.insert(ExternalLinkKey::SpotifyUrl, "B".to_string());
+        other.insert(ExternalLinkKey::AudDUrl, "C".to_string());
+
+        let n_called = Rc::new(Cell::new(0));
+
+        let n_called_clone = Rc::clone(&n_called);
+        links.connect_items_changed(move |_, index, removed, added| {
+            assert_eq!(index, 1);
+            assert_eq!(removed, 0);
+            assert_eq!(added, 1);
+            n_called_clone.set(n_called_clone.get() + 1);
+        });
+
+        assert_eq!(links.merge(&other), 1);
+        assert_eq!(n_called.get(), 1);
+        assert_eq!(links.get(ExternalLinkKey::SpotifyUrl).as_deref(), Some("A"));
+        assert_eq!(links.get(ExternalLinkKey::AudDUrl).as_deref(), Some("C"));
+
+        assert_eq!(links.merge(&other), 0);
+        assert_eq!(n_called.get(), 1);
+    }
+
+    #[test]
+    fn first_of() {
+        let priority = ExternalLinkKey::listen_priority(ExternalLinkKey::AppleMusicUrl);
+        assert_eq!(
+            priority,
+            [
+                ExternalLinkKey::AppleMusicUrl,
+                ExternalLinkKey::SpotifyUrl,
+                ExternalLinkKey::YoutubeSearchTerm,
+                ExternalLinkKey::AudDUrl,
+            ]
+        );
+
+        let links = ExternalLinks::default();
+        assert_eq!(links.first_of(&priority), None);
+
+        // Not a place to listen to the song
+        links.insert(ExternalLinkKey::GeniusUrl, "genius".to_string());
+        assert_eq!(links.first_of(&priority), None);
+
+        links.insert(ExternalLinkKey::AudDUrl, "audd".to_string());
+        assert_eq!(
+            links.first_of(&priority),
+            Some((ExternalLinkKey::AudDUrl, "audd".to_string()))
+        );
+
+        links.insert(ExternalLinkKey::YoutubeSearchTerm, "youtube".to_string());
+        links.insert(ExternalLinkKey::SpotifyUrl, "spotify".to_string());
+        assert_eq!(
+            links.first_of(&priority),
+            Some((ExternalLinkKey::SpotifyUrl, "spotify".to_string()))
+        );
+
+        links.insert(ExternalLinkKey::AppleMusicUrl, "apple".to_string());
+        assert_eq!(
+            links.first_of(&priority),
+            Some((ExternalLinkKey::AppleMusicUrl, "apple".to_string()))
+        );
+
+        let priority = ExternalLinkKey::listen_priority(ExternalLinkKey::YoutubeSearchTerm);
+        assert_eq!(
+            links.first_of(&priority),
+            Some((ExternalLinkKey::YoutubeSearchTerm, "youtube".to_string()))
+        );
+    }
+
+    #[test]
+    fn to_uri() {
+        assert_eq!(
+            ExternalLinkKey::SpotifyUrl.to_uri("https://spotify.link"),
+            "https://spotify.link"
+        );
+        assert_eq!(
+            ExternalLinkKey::YoutubeSearchTerm.to_uri("Someone - Some song"),
+            "https://www.youtube.com/results?search_query=Someone%20-%20Some%20song"
+        );
+        assert_eq!(
+            ExternalLinkKey::MusicbrainzReleaseId.to_uri("3ce8e4e5-8a6c-4afa-b2c6-4be35961fbc9"),
+            "https://musicbrainz.org/release/3ce8e4e5-8a6c-4afa-b2c6-4be35961fbc9"
+        );
+    }
+
     #[test]
     fn serde_bincode() {
         let val = ExternalLinks::new();
@@ -232,6 +446,52 @@ mod tests {
         );
     }
 
+    #[test]
+    fn deserialize_duplicate_and_unknown_keys() {
+        let links: ExternalLinks = serde_json::from_str(
+            r#"{
+            "spotify-url": "https://spotify.link/old",
+            "unknown": "unknown",
+            "aud-d-url": "https://aud_d.link",
+            "spotify-url": "https://spotify.link/new",
+            "aud-d-url": "https://aud_d.link/new"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(links.n_items(), 3);
+        assert_eq!(
+            links.get(ExternalLinkKey::SpotifyUrl).as_deref(),
+            Some("https://spotify.link/new")
+        );
+        assert_eq!(
+            links.get(ExternalLinkKey::AudDUrl).as_deref(),
+            Some("https://aud_d.link/new")
+        );
+
+        // Position of the first occurrence is kept
+        let first = links.item(0).unwrap().downcast::<ExternalLink>().unwrap();
+        assert_eq!(first.key(), ExternalLinkKey::SpotifyUrl.as_ref());
+        let second = links.item(1).unwrap().downcast::<ExternalLink>().unwrap();
+        assert_eq!(second.key(), "unknown");
+        let third = links.item(2).unwrap().downcast::<ExternalLink>().unwrap();
+        assert_eq!(third.key(), ExternalLinkKey::AudDUrl.as_ref());
+    }
+
+    #[test]
+    fn unknown_keys_round_trip() {
+        let links: ExternalLinks =
+            serde_json::from_str(r#"{"unknown": "https://unknown.link"}"#).unwrap();
+        assert_eq!(links.n_items(), 1);
+
+        let links: ExternalLinks =
+            serde_json::from_str(&serde_json::to_string(&links).unwrap()).unwrap();
+        assert_eq!(links.n_items(), 1);
+        let link = links.item(0).unwrap().downcast::<ExternalLink>().unwrap();
+        assert_eq!(link.key(), "unknown");
+        assert_eq!(link.value(), "https://unknown.link");
+    }
+
     #[test]
     fn serialize() {
         let links = ExternalLinks::new();
@@ -0,0 +1,686 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use indexmap::{map::Entry, IndexMap};
+
+use crate::{
+    date_time::DateTime, external_links::ExternalLinkKey, song::Song, song_list::SongList, uid::Uid,
+};
+
+const SHAZAM_UID_PREFIX: &str = "Shazam";
+const SOUND_HOUND_UID_PREFIX: &str = "SoundHound";
+
+/// Format of a history exported from another app
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// CSV from Shazam's data export, with `Title`, `Artist`, `TagTime`,
+    /// and `TrackKey` columns
+    Shazam,
+    /// JSON array of SoundHound history entries
+    SoundHound,
+    /// JSON array from Mousai's own export, which keeps the ids and the
+    /// favorites
+    Mousai,
+}
+
+impl ImportFormat {
+    /// Guesses the format from the file name's extension.
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let (_, extension) = file_name.rsplit_once('.')?;
+
+        if extension.eq_ignore_ascii_case("csv") {
+            Some(Self::Shazam)
+        } else if extension.eq_ignore_ascii_case("json") {
+            Some(Self::SoundHound)
+        } else {
+            None
+        }
+    }
+
+    /// Like [`ImportFormat::from_file_name`], but also tells Mousai's own
+    /// JSON export apart from SoundHound's by its fields.
+    pub fn detect(file_name: &str, text: &str) -> Option<Self> {
+        match Self::from_file_name(file_name)? {
+            Self::SoundHound if is_mousai_export(text) => Some(Self::Mousai),
+            format => Some(format),
+        }
+    }
+}
+
+/// A row that could not be mapped into a song
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedRow {
+    /// 1-based line number for CSV, or 1-based entry index for JSON
+    pub position: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedImport {
+    pub songs: Vec<Song>,
+    pub skipped: Vec<SkippedRow>,
+}
+
+/// Parses the exported `text` into songs.
+///
+/// Rows that can't be mapped are collected in [`ParsedImport::skipped`]. This
+/// only fails if the text as a whole is unreadable, e.g., missing the header.
+pub fn parse(format: ImportFormat, text: &str) -> Result<ParsedImport> {
+    match format {
+        ImportFormat::Shazam => parse_shazam(text),
+        ImportFormat::SoundHound => parse_sound_hound(text),
+        ImportFormat::Mousai => parse_mousai(text),
+    }
+}
+
+/// Imported songs split by how they relate to the history
+#[derive(Debug, Default)]
+pub struct ReconciledImport {
+    /// Songs to insert, as they are not in the history yet
+    pub new_songs: Vec<Song>,
+    /// Number of songs merged into the one already in the history
+    pub n_merged: usize,
+    /// Number of songs repeated within the import
+    pub n_duplicates: usize,
+}
+
+/// Merges the songs that are already in `song_list` into the existing ones,
+/// and dedups the songs that are repeated within `songs`, keeping the most
+/// recently heard of the repeated ones.
+///
+/// See [`Song::merge_user_data`] for how conflicts are resolved, so importing
+/// a backup never loses, e.g., a favorite on either side.
+pub fn reconcile_with(song_list: &SongList, mut songs: Vec<Song>) -> Result<ReconciledImport> {
+    let mut ret = ReconciledImport::default();
+
+    songs.sort_by_key(|song| std::cmp::Reverse(song.last_heard()));
+
+    let mut kept = IndexMap::<_, Song>::new();
+    for song in songs {
+        if song_list
+            .merge_into_existing(&song)
+            .context("Failed to merge imported song")?
+            .is_some()
+        {
+            ret.n_merged += 1;
+            continue;
+        }
+
+        match kept.entry(song_list.dedup_key(&song)) {
+            Entry::Occupied(entry) => {
+                entry.get().merge_user_data(&song);
+                ret.n_duplicates += 1;
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(song);
+            }
+        }
+    }
+
+    ret.new_songs = kept.into_values().collect();
+    Ok(ret)
+}
+
+fn parse_shazam(text: &str) -> Result<ParsedImport> {
+    let mut lines = text.lines().enumerate();
+
+    // The export starts with a "Shazam Library" title line before the header
+    let header = lines
+        .by_ref()
+        .map(|(_, line)| split_csv_line(line))
+        .find(|fields| fields.iter().any(|field| field == "Title"))
+        .context("Missing Shazam CSV header")?;
+    let column = |name: &str| header.iter().position(|field| field == name);
+
+    let (Some(title_column), Some(artist_column)) = (column("Title"), column("Artist")) else {
+        bail!("Shazam CSV header must have `Title` and `Artist` columns");
+    };
+    let tag_time_column = column("TagTime");
+    let track_key_column = column("TrackKey");
+
+    let mut ret = ParsedImport::default();
+
+    for (index, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields = split_csv_line(line);
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| fields.get(column))
+                .map(|field| field.trim())
+                .filter(|field| !field.is_empty())
+        };
+
+        let (Some(title), Some(artist)) = (field(Some(title_column)), field(Some(artist_column)))
+        else {
+            ret.skipped.push(SkippedRow {
+                position: index + 1,
+                reason: "Missing title or artist".to_string(),
+            });
+            continue;
+        };
+
+        let last_heard = match field(tag_time_column).map(parse_date_time).transpose() {
+            Ok(last_heard) => last_heard,
+            Err(err) => {
+                ret.skipped.push(SkippedRow {
+                    position: index + 1,
+                    reason: format!("{:#}", err),
+                });
+                continue;
+            }
+        };
+
+        let uid = field(track_key_column).map_or_else(Uid::generate, |track_key| {
+            Uid::from_prefixed(SHAZAM_UID_PREFIX, track_key)
+        });
+
+        ret.songs
+            .push(new_song(&uid, title, artist, None, last_heard));
+    }
+
+    Ok(ret)
+}
+
+#[derive(Debug, Deserialize)]
+struct SoundHoundEntry {
+    id: Option<String>,
+    #[serde(alias = "title")]
+    track_name: Option<String>,
+    #[serde(alias = "artist")]
+    artist_name: Option<String>,
+    #[serde(alias = "album")]
+    album_name: Option<String>,
+    #[serde(alias = "timestamp")]
+    date: Option<String>,
+}
+
+fn parse_sound_hound(text: &str) -> Result<ParsedImport> {
+    let entries = serde_json::from_str::<Vec<serde_json::Value>>(text)
+        .context("SoundHound export must be a JSON array")?;
+
+    let mut ret = ParsedImport::default();
+
+    for (index, value) in entries.into_iter().enumerate() {
+        let position = index + 1;
+
+        let entry = match serde_json::from_value::<SoundHoundEntry>(value) {
+            Ok(entry) => entry,
+            Err(err) => {
+                ret.skipped.push(SkippedRow {
+                    position,
+                    reason: err.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let (Some(title), Some(artist)) = (
+            entry.track_name.filter(|s| !s.trim().is_empty()),
+            entry.artist_name.filter(|s| !s.trim().is_empty()),
+        ) else {
+            ret.skipped.push(SkippedRow {
+                position,
+                reason: "Missing title or artist".to_string(),
+            });
+            continue;
+        };
+
+        let last_heard = match entry.date.as_deref().map(parse_date_time).transpose() {
+            Ok(last_heard) => last_heard,
+            Err(err) => {
+                ret.skipped.push(SkippedRow {
+                    position,
+                    reason: format!("{:#}", err),
+                });
+                continue;
+            }
+        };
+
+        let uid = entry.id.map_or_else(Uid::generate, |id| {
+            Uid::from_prefixed(SOUND_HOUND_UID_PREFIX, &id)
+        });
+
+        ret.songs.push(new_song(
+            &uid,
+            title.trim(),
+            artist.trim(),
+            entry.album_name.as_deref().map(str::trim),
+            last_heard,
+        ));
+    }
+
+    Ok(ret)
+}
+
+#[derive(Debug, Deserialize)]
+struct MousaiEntry {
+    id: String,
+    title: String,
+    artist: String,
+    #[serde(default)]
+    album: String,
+    release_date: Option<String>,
+    last_heard: Option<String>,
+    #[serde(default)]
+    is_favorite: bool,
+    playback_link: Option<String>,
+    album_art_link: Option<String>,
+}
+
+/// Whether the text is a JSON array whose first entry has the fields only
+/// Mousai's export has
+fn is_mousai_export(text: &str) -> bool {
+    serde_json::from_str::<Vec<serde_json::Value>>(text).is_ok_and(|entries| {
+        entries
+            .first()
+            .is_some_and(|entry| entry.get("id").is_some() && entry.get("is_favorite").is_some())
+    })
+}
+
+fn parse_mousai(text: &str) -> Result<ParsedImport> {
+    let entries = serde_json::from_str::<Vec<serde_json::Value>>(text)
+        .context("Mousai export must be a JSON array")?;
+
+    let mut ret = ParsedImport::default();
+
+    for (index, value) in entries.into_iter().enumerate() {
+        let position = index + 1;
+
+        let entry = match serde_json::from_value::<MousaiEntry>(value) {
+            Ok(entry) => entry,
+            Err(err) => {
+                ret.skipped.push(SkippedRow {
+                    position,
+                    reason: err.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let last_heard = match entry.last_heard.as_deref().map(parse_date_time).transpose() {
+            Ok(last_heard) => last_heard,
+            Err(err) => {
+                ret.skipped.push(SkippedRow {
+                    position,
+                    reason: format!("{:#}", err),
+                });
+                continue;
+            }
+        };
+
+        let mut builder = Song::builder(
+            &Uid::from(entry.id),
+            &entry.title,
+            &entry.artist,
+            &entry.album,
+        );
+        builder.favorite(entry.is_favorite).external_link(
+            ExternalLinkKey::YoutubeSearchTerm,
+            format!("{} - {}", entry.artist, entry.title),
+        );
+        if let Some(ref release_date) = entry.release_date {
+            builder.release_date(release_date);
+        }
+        if let Some(ref playback_link) = entry.playback_link {
+            builder.playback_link(playback_link);
+        }
+        if let Some(ref album_art_link) = entry.album_art_link {
+            builder.album_art_link(album_art_link);
+        }
+        let song = builder.build();
+        song.set_last_heard(last_heard);
+        ret.songs.push(song);
+    }
+
+    Ok(ret)
+}
+
+fn new_song(
+    uid: &Uid,
+    title: &str,
+    artist: &str,
+    album: Option<&str>,
+    last_heard: Option<DateTime>,
+) -> Song {
+    let song = Song::builder(uid, title, artist, album.unwrap_or_default())
+        .external_link(
+            ExternalLinkKey::YoutubeSearchTerm,
+            format!("{} - {}", artist, title),
+        )
+        .build();
+    song.set_last_heard(last_heard);
+    song
+}
+
+/// Parses ISO 8601, also accepting a space instead of `T` and a missing
+/// timezone, which is assumed to be UTC.
+fn parse_date_time(text: &str) -> Result<DateTime> {
+    let text = text.trim().replacen(' ', "T", 1);
+
+    DateTime::from_iso8601(&format!("{}Z", text)).or_else(|_| DateTime::from_iso8601(&text))
+}
+
+/// Splits a CSV line, handling quoted fields and escaped quotes (`""`).
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::database;
+
+    fn summarize(songs: &[Song]) -> Vec<(Uid, String, String, Option<String>)> {
+        songs
+            .iter()
+            .map(|song| {
+                (
+                    song.id(),
+                    song.title(),
+                    song.artist(),
+                    song.last_heard().map(|dt| dt.format_iso8601().to_string()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn csv_line() {
+        assert_eq!(split_csv_line("a,b,,c"), vec!["a", "b", "", "c"]);
+        assert_eq!(
+            split_csv_line(r#"1,"Hello, World","Say ""Hi""""#),
+            vec!["1", "Hello, World", r#"Say "Hi""#]
+        );
+    }
+
+    #[test]
+    fn format_from_file_name() {
+        assert_eq!(
+            ImportFormat::from_file_name("shazamlibrary.CSV"),
+            Some(ImportFormat::Shazam)
+        );
+        assert_eq!(
+            ImportFormat::from_file_name("history.json"),
+            Some(ImportFormat::SoundHound)
+        );
+        assert_eq!(ImportFormat::from_file_name("history"), None);
+    }
+
+    #[test]
+    fn shazam() {
+        let parsed = parse(
+            ImportFormat::Shazam,
+            r#"Shazam Library
+Index,TagTime,Title,Artist,URL,TrackKey
+1,2022-01-02T03:04:05Z,"Song, With Comma",Someone,https://www.shazam.com/track/1,111
+2,2022-02-03 04:05:06,Another Song,Someone Else,https://www.shazam.com/track/2,222
+3,2022-02-03,,No Title,,333
+4,not a date,Bad Date,Someone,,444
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            summarize(&parsed.songs),
+            vec![
+                (
+                    Uid::from("Shazam-111"),
+                    "Song, With Comma".to_string(),
+                    "Someone".to_string(),
+                    Some("2022-01-02T03:04:05Z".to_string())
+                ),
+                (
+                    Uid::from("Shazam-222"),
+                    "Another Song".to_string(),
+                    "Someone Else".to_string(),
+                    Some("2022-02-03T04:05:06Z".to_string())
+                ),
+            ]
+        );
+        assert_eq!(
+            parsed
+                .skipped
+                .iter()
+                .map(|row| row.position)
+                .collect::<Vec<_>>(),
+            vec![5, 6]
+        );
+    }
+
+    #[test]
+    fn shazam_missing_header() {
+        assert!(parse(ImportFormat::Shazam, "1,2,3\n4,5,6").is_err());
+    }
+
+    #[test]
+    fn sound_hound() {
+        let parsed = parse(
+            ImportFormat::SoundHound,
+            r#"[
+                {
+                    "id": "abc",
+                    "track_name": "Some Song",
+                    "artist_name": "Someone",
+                    "album_name": "Some Album",
+                    "date": "2021-06-07T08:09:10Z"
+                },
+                {
+                    "title": "Other Song",
+                    "artist": "Other Artist"
+                },
+                {
+                    "track_name": "No Artist"
+                },
+                "not an object"
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(parsed.songs.len(), 2);
+        assert_eq!(parsed.songs[0].id(), Uid::from("SoundHound-abc"));
+        assert_eq!(parsed.songs[0].title(), "Some Song");
+        assert_eq!(parsed.songs[0].artist(), "Someone");
+        assert_eq!(parsed.songs[0].album(), "Some Album");
+        assert_eq!(
+            parsed.songs[0].last_heard(),
+            Some(DateTime::from_iso8601("2021-06-07T08:09:10Z").unwrap())
+        );
+        assert_eq!(parsed.songs[1].title(), "Other Song");
+        assert_eq!(parsed.songs[1].artist(), "Other Artist");
+        assert_eq!(parsed.songs[1].album(), "");
+        assert_eq!(parsed.songs[1].last_heard(), None);
+        assert_eq!(
+            parsed
+                .skipped
+                .iter()
+                .map(|row| row.position)
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+    }
+
+    #[test]
+    fn mousai() {
+        let text = r#"[
+            {
+                "id": "AudD-a",
+                "title": "Some Song",
+                "artist": "Someone",
+                "album": "Some Album",
+                "release_date": "2020-01-01",
+                "last_heard": "2021-06-07T08:09:10Z",
+                "is_favorite": true,
+                "link": "https://spotify.link",
+                "playback_link": "https://a.mp3",
+                "album_art_link": null
+            },
+            {
+                "id": "AudD-b",
+                "artist": "No Title",
+                "is_favorite": false
+            }
+        ]"#;
+        assert_eq!(
+            ImportFormat::detect("mousai-history.json", text),
+            Some(ImportFormat::Mousai)
+        );
+        assert_eq!(
+            ImportFormat::detect("history.json", r#"[{"id": "a", "title": "A"}]"#),
+            Some(ImportFormat::SoundHound)
+        );
+
+        let parsed = parse(ImportFormat::Mousai, text).unwrap();
+        assert_eq!(parsed.songs.len(), 1);
+        let song = &parsed.songs[0];
+        assert_eq!(song.id(), Uid::from("AudD-a"));
+        assert_eq!(song.album(), "Some Album");
+        assert_eq!(song.release_date().as_deref(), Some("2020-01-01"));
+        assert_eq!(song.playback_link().as_deref(), Some("https://a.mp3"));
+        assert!(song.is_favorite());
+        assert_eq!(
+            song.last_heard(),
+            Some(DateTime::from_iso8601("2021-06-07T08:09:10Z").unwrap())
+        );
+        assert_eq!(
+            parsed
+                .skipped
+                .iter()
+                .map(|row| row.position)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn reconcile() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let existing = Song::builder(&Uid::from("AudD-a"), "Some Song", "Someone", "")
+            .external_link(ExternalLinkKey::AudDUrl, "https://aud_d.link/a")
+            .build();
+        existing.set_last_heard(Some(
+            DateTime::from_iso8601("2023-01-01T00:00:00Z").unwrap(),
+        ));
+        let other_existing = Song::builder(&Uid::from("AudD-b"), "Other", "Someone", "")
+            .favorite(true)
+            .build();
+        song_list
+            .insert_many(vec![existing.clone(), other_existing.clone()])
+            .unwrap();
+
+        let parsed = parse(
+            ImportFormat::Mousai,
+            r#"[
+                {
+                    "id": "AudD-a",
+                    "title": "Some Song",
+                    "artist": "Someone",
+                    "last_heard": "2022-01-01T00:00:00Z",
+                    "is_favorite": true
+                },
+                {
+                    "id": "AudD-b",
+                    "title": "Other",
+                    "artist": "Someone",
+                    "last_heard": "2024-01-01T00:00:00Z",
+                    "is_favorite": false
+                },
+                {
+                    "id": "AudD-c",
+                    "title": "New",
+                    "artist": "Someone",
+                    "last_heard": "2022-01-01T00:00:00Z",
+                    "is_favorite": false
+                },
+                {
+                    "id": "Shazam-c",
+                    "title": "new",
+                    "artist": "SOMEONE",
+                    "last_heard": "2021-01-01T00:00:00Z",
+                    "is_favorite": true
+                }
+            ]"#,
+        )
+        .unwrap();
+
+        let reconciled = reconcile_with(&song_list, parsed.songs).unwrap();
+        assert_eq!(reconciled.n_merged, 2);
+        assert_eq!(reconciled.n_duplicates, 1);
+        assert_eq!(reconciled.new_songs.len(), 1);
+
+        // Favorite on either side is kept, and so are the links and the most
+        // recent time heard
+        assert!(existing.is_favorite());
+        assert_eq!(
+            existing.last_heard(),
+            Some(DateTime::from_iso8601("2023-01-01T00:00:00Z").unwrap())
+        );
+        assert!(existing
+            .external_links()
+            .get(ExternalLinkKey::AudDUrl)
+            .is_some());
+        assert!(existing
+            .external_links()
+            .get(ExternalLinkKey::YoutubeSearchTerm)
+            .is_some());
+        assert!(other_existing.is_favorite());
+        assert_eq!(
+            other_existing.last_heard(),
+            Some(DateTime::from_iso8601("2024-01-01T00:00:00Z").unwrap())
+        );
+        assert_eq!(song_list.n_items(), 2);
+
+        // The most recently heard of the repeated ones, with the favorite of
+        // the other
+        let new_song = &reconciled.new_songs[0];
+        assert_eq!(new_song.id(), Uid::from("AudD-c"));
+        assert!(new_song.is_favorite());
+    }
+
+    #[test]
+    fn dedup() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+        song_list
+            .insert(Song::builder(&Uid::from("existing"), "Existing", "Someone", "").build())
+            .unwrap();
+
+        let parsed = parse(
+            ImportFormat::Shazam,
+            "Index,TagTime,Title,Artist,URL,TrackKey
+1,2022-01-01T00:00:00Z,existing,SOMEONE,,1
+2,2022-01-01T00:00:00Z,New,Someone,,2
+3,2023-01-01T00:00:00Z,New,Someone,,3",
+        )
+        .unwrap();
+
+        let reconciled = reconcile_with(&song_list, parsed.songs).unwrap();
+        assert_eq!(reconciled.n_merged, 1);
+        assert_eq!(reconciled.n_duplicates, 1);
+        assert_eq!(reconciled.new_songs.len(), 1);
+        assert_eq!(reconciled.new_songs[0].id(), Uid::from("Shazam-3"));
+    }
+}
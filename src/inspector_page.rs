@@ -6,7 +6,7 @@ use gtk::{
 
 use std::{cell::RefCell, time::Duration};
 
-use crate::recognizer::{ProviderSettings, ProviderType, TestProviderMode};
+use crate::recognizer::{ProviderConcurrency, ProviderSettings, ProviderType, TestProviderMode};
 
 const INSPECTOR_TITLE: &str = "Mousai";
 
@@ -32,6 +32,14 @@ mod imp {
         #[template_child]
         pub(super) provider_model: TemplateChild<adw::EnumListModel>,
         #[template_child]
+        pub(super) fallback_provider_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub(super) fallback_provider_model: TemplateChild<adw::EnumListModel>,
+        #[template_child]
+        pub(super) provider_concurrency_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub(super) provider_concurrency_model: TemplateChild<adw::EnumListModel>,
+        #[template_child]
         pub(super) test_provider_mode_row: TemplateChild<adw::ComboRow>,
         #[template_child]
         pub(super) test_provider_mode_model: TemplateChild<adw::EnumListModel>,
@@ -49,6 +57,7 @@ mod imp {
 
         fn class_init(klass: &mut Self::Class) {
             ProviderType::static_type();
+            ProviderConcurrency::static_type();
             TestProviderMode::static_type();
 
             klass.bind_template();
@@ -144,6 +153,56 @@ impl InspectorPage {
                 obj.update_test_rows_sensitivity();
             }));
 
+        let active = ProviderSettings::lock().active;
+        let fallback = ProviderSettings::lock()
+            .fallbacks
+            .first()
+            .copied()
+            .unwrap_or(active);
+        imp.fallback_provider_row
+            .set_selected(imp.fallback_provider_model.find_position(fallback as i32));
+        imp.fallback_provider_row
+            .set_expression(imp.provider_row.expression().as_ref());
+        imp.fallback_provider_row
+            .connect_selected_notify(|fallback_provider_row| {
+                // Same as the active provider is dropped by `by_priority`
+                ProviderSettings::lock().fallbacks = fallback_provider_row
+                    .selected_item()
+                    .map(|item| {
+                        item.downcast_ref::<adw::EnumListItem>()
+                            .unwrap()
+                            .value()
+                            .try_into()
+                            .unwrap()
+                    })
+                    .into_iter()
+                    .collect();
+            });
+
+        imp.provider_concurrency_row.set_selected(
+            imp.provider_concurrency_model
+                .find_position(ProviderSettings::lock().concurrency as i32),
+        );
+        imp.provider_concurrency_row
+            .set_expression(Some(&gtk::ClosureExpression::new::<glib::GString>(
+                &[] as &[gtk::Expression],
+                closure!(|list_item: adw::EnumListItem| list_item.name()),
+            )));
+        imp.provider_concurrency_row
+            .connect_selected_notify(|provider_concurrency_row| {
+                if let Some(ref item) = provider_concurrency_row.selected_item() {
+                    ProviderSettings::lock().concurrency = item
+                        .downcast_ref::<adw::EnumListItem>()
+                        .unwrap()
+                        .value()
+                        .try_into()
+                        .unwrap();
+                } else {
+                    tracing::warn!("provider_concurrency_row doesn't have a selected item");
+                    ProviderSettings::lock().concurrency = ProviderConcurrency::default();
+                }
+            });
+
         imp.test_provider_mode_row.set_selected(
             imp.test_provider_mode_model
                 .find_position(ProviderSettings::lock().test_mode as i32),
@@ -0,0 +1,120 @@
+use anyhow::{ensure, Result};
+use gtk::{glib, glib::translate::IntoGlib};
+use serde::Deserialize;
+use soup::prelude::*;
+
+use std::rc::Rc;
+
+use crate::{
+    rate_limiter::RateLimiter, settings::ArtistTitleOrder, song::Song, song_list, Application,
+};
+
+/// Key of the rate limiter shared by all requests to the iTunes Search API
+const RATE_LIMITER_KEY: &str = "iTunes";
+
+/// Max number of tracks to look through for one that matches the song
+const SEARCH_LIMIT: u32 = 5;
+
+/// A track found on the iTunes Search API, which needs no credentials
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Track {
+    #[serde(rename = "trackName")]
+    pub title: String,
+    #[serde(rename = "artistName")]
+    pub artist: String,
+    /// Link to the track on Apple Music
+    #[serde(rename = "trackViewUrl")]
+    pub view_url: Option<String>,
+    /// Link to the 100x100 album art
+    #[serde(rename = "artworkUrl100")]
+    pub artwork_url_100: Option<String>,
+}
+
+impl Track {
+    /// Returns the link to the album art at 600x600, the same size as the
+    /// ones from AudD.
+    pub fn artwork_url(&self) -> Option<String> {
+        self.artwork_url_100
+            .as_ref()
+            .map(|url| url.replace("100x100", "600x600"))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    results: Vec<Track>,
+}
+
+/// Returns the limiter shared by all requests to the iTunes Search API.
+pub fn rate_limiter() -> Rc<RateLimiter> {
+    Application::get().rate_limiters().get(RATE_LIMITER_KEY)
+}
+
+/// Looks up the track with the same title and artist as the song, or returns
+/// `None` if there is none.
+pub async fn search_track(song: &Song) -> Result<Option<Track>> {
+    let uri = format!(
+        "https://itunes.apple.com/search?media=music&entity=song&limit={}&term={}",
+        SEARCH_LIMIT,
+        glib::Uri::escape_string(&song.copy_term(ArtistTitleOrder::ArtistFirst), None, false)
+    );
+    let message = soup::Message::new("GET", &uri)?;
+
+    let response_bytes = Application::get()
+        .session()
+        .send_and_read_future(&message, glib::Priority::LOW)
+        .await?;
+
+    let status = message.status().into_glib();
+    ensure!((200..300).contains(&status), "Got HTTP status {}", status);
+
+    find_match(song, &response_bytes)
+}
+
+/// Returns the first track in the response with the same title and artist as
+/// the song, ignoring the case and whitespaces, so that a search result for a
+/// different song is never mistaken for it.
+fn find_match(song: &Song, response_bytes: &[u8]) -> Result<Option<Track>> {
+    let response = serde_json::from_slice::<Response>(response_bytes)?;
+
+    let song_key = song_list::normalized_song_key(song);
+    Ok(response.results.into_iter().find(|track| {
+        song_list::normalized_title_artist_key(&track.title, &track.artist) == song_key
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::uid::Uid;
+
+    const RESPONSE_STR: &str = r#"{"resultCount":3,"results":[{"wrapperType":"track","kind":"song","artistName":"5 Seconds of Summer","trackName":"Amnesia (Acoustic)","trackViewUrl":"https://music.apple.com/us/album/amnesia-acoustic/1?i=10","artworkUrl100":"https://is1-ssl.mzstatic.com/image/thumb/a/100x100bb.jpg"},{"wrapperType":"track","kind":"song","artistName":"5 Seconds of Summer","trackName":"Amnesia","trackViewUrl":"https://music.apple.com/us/album/amnesia/2?i=20","artworkUrl100":"https://is1-ssl.mzstatic.com/image/thumb/b/100x100bb.jpg"},{"wrapperType":"track","kind":"song","artistName":"5 Seconds of Summer","trackName":"Amnesia","trackViewUrl":"https://music.apple.com/us/album/amnesia/3?i=30"}]}"#;
+
+    #[test]
+    fn match_title_and_artist() {
+        let song = Song::builder(&Uid::from("a"), "Amnesia", "5 Seconds Of  Summer", "").build();
+
+        let track = find_match(&song, RESPONSE_STR.as_bytes()).unwrap().unwrap();
+        assert_eq!(track.title, "Amnesia");
+        assert_eq!(
+            track.view_url.as_deref(),
+            Some("https://music.apple.com/us/album/amnesia/2?i=20")
+        );
+        assert_eq!(
+            track.artwork_url().as_deref(),
+            Some("https://is1-ssl.mzstatic.com/image/thumb/b/600x600bb.jpg")
+        );
+    }
+
+    #[test]
+    fn no_match() {
+        let song = Song::builder(&Uid::from("a"), "Youngblood", "5 Seconds Of Summer", "").build();
+        assert_eq!(find_match(&song, RESPONSE_STR.as_bytes()).unwrap(), None);
+
+        let response_str = r#"{"resultCount":0,"results":[]}"#;
+        assert_eq!(find_match(&song, response_str.as_bytes()).unwrap(), None);
+
+        assert!(find_match(&song, b"").is_err());
+    }
+}
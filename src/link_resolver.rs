@@ -0,0 +1,320 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use gtk::{gio, glib, prelude::*};
+
+use std::{rc::Rc, time::Duration};
+
+use crate::{
+    cancelled::Cancelled, external_links::ExternalLinkKey, itunes_search,
+    rate_limiter::RateLimiter, settings::ArtistTitleOrder, song::Song, song_list::SongList,
+};
+
+/// Default interval between resolver calls, so we don't hammer remote services.
+pub const DEFAULT_RESOLVE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Resolves a single kind of external link for a song.
+#[async_trait(?Send)]
+pub trait LinkResolver {
+    /// The key of the link this resolver produces.
+    fn key(&self) -> ExternalLinkKey;
+
+    /// Returns `None` if no link could be found for the song.
+    async fn resolve(&self, song: &Song) -> Result<Option<String>>;
+
+    /// The limiter shared with other requests to the same provider, if this
+    /// sends requests to one.
+    fn rate_limiter(&self) -> Option<Rc<RateLimiter>> {
+        None
+    }
+}
+
+/// Resolves the YouTube search term from the song's artist and title.
+#[derive(Debug, Default)]
+pub struct YoutubeSearchTermResolver;
+
+#[async_trait(?Send)]
+impl LinkResolver for YoutubeSearchTermResolver {
+    fn key(&self) -> ExternalLinkKey {
+        ExternalLinkKey::YoutubeSearchTerm
+    }
+
+    async fn resolve(&self, song: &Song) -> Result<Option<String>> {
+        Ok(Some(song.copy_term(ArtistTitleOrder::ArtistFirst)))
+    }
+}
+
+/// Looks up the Apple Music link of the song on the iTunes Search API.
+#[derive(Debug, Default)]
+pub struct AppleMusicUrlResolver;
+
+#[async_trait(?Send)]
+impl LinkResolver for AppleMusicUrlResolver {
+    fn key(&self) -> ExternalLinkKey {
+        ExternalLinkKey::AppleMusicUrl
+    }
+
+    async fn resolve(&self, song: &Song) -> Result<Option<String>> {
+        let track = itunes_search::search_track(song).await?;
+        Ok(track.and_then(|track| track.view_url))
+    }
+
+    fn rate_limiter(&self) -> Option<Rc<RateLimiter>> {
+        Some(itunes_search::rate_limiter())
+    }
+}
+
+/// Returns the resolvers that are currently available.
+pub fn default_resolvers() -> Vec<Box<dyn LinkResolver>> {
+    vec![
+        Box::new(YoutubeSearchTermResolver),
+        Box::new(AppleMusicUrlResolver),
+    ]
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReResolveSummary {
+    /// Number of songs that were missing at least one link
+    pub n_checked: u32,
+    /// Number of songs that got at least one new link
+    pub n_updated: u32,
+    /// Total number of links added
+    pub n_links_added: u32,
+    /// Number of resolver calls that failed
+    pub n_failed: u32,
+}
+
+/// Runs the given resolvers over all songs in the list that are missing the
+/// resolver's link, then writes the updated songs back to the database.
+///
+/// `progress_cb` is called with the number of processed songs and the total
+/// number of songs to process.
+pub async fn re_resolve_external_links(
+    song_list: &SongList,
+    resolvers: &[Box<dyn LinkResolver>],
+    interval: Duration,
+    cancellable: &gio::Cancellable,
+    progress_cb: impl Fn(u32, u32),
+) -> Result<ReResolveSummary> {
+    let to_process = song_list
+        .iter::<Song>()
+        .filter_map(|item| item.ok())
+        .filter(|song| !song.is_placeholder())
+        .filter(|song| {
+            let external_links = song.external_links();
+            resolvers
+                .iter()
+                .any(|resolver| external_links.get(resolver.key()).is_none())
+        })
+        .collect::<Vec<_>>();
+
+    let total = to_process.len() as u32;
+    let mut summary = ReResolveSummary::default();
+    let mut has_called_resolver = false;
+
+    tracing::debug!("Re-resolving external links of {} songs", total);
+
+    for (index, song) in to_process.iter().enumerate() {
+        let external_links = song.external_links();
+        let mut n_added = 0;
+
+        for resolver in resolvers {
+            if external_links.get(resolver.key()).is_some() {
+                continue;
+            }
+
+            if has_called_resolver {
+                gio::CancellableFuture::new(glib::timeout_future(interval), cancellable.clone())
+                    .await
+                    .map_err(|_| Cancelled::new("re-resolving external links"))?;
+            }
+            has_called_resolver = true;
+
+            if let Some(rate_limiter) = resolver.rate_limiter() {
+                let res = gio::CancellableFuture::new(rate_limiter.acquire(), cancellable.clone())
+                    .await
+                    .map_err(|_| Cancelled::new("re-resolving external links"))?;
+
+                if let Err(err) = res {
+                    tracing::warn!(
+                        id = ?song.id_ref(),
+                        "Skipped resolving `{}`: {}",
+                        resolver.key().as_ref(),
+                        err
+                    );
+                    summary.n_failed += 1;
+                    continue;
+                }
+            }
+
+            let res = gio::CancellableFuture::new(resolver.resolve(song), cancellable.clone())
+                .await
+                .map_err(|_| Cancelled::new("re-resolving external links"))?;
+
+            match res {
+                Ok(Some(value)) => {
+                    external_links.insert(resolver.key(), value);
+                    n_added += 1;
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::warn!(
+                        id = ?song.id_ref(),
+                        "Failed to resolve `{}`: {:?}",
+                        resolver.key().as_ref(),
+                        err
+                    );
+                    summary.n_failed += 1;
+                }
+            }
+        }
+
+        if n_added != 0 {
+            song_list.update(song)?;
+            summary.n_updated += 1;
+            summary.n_links_added += n_added;
+        }
+
+        summary.n_checked += 1;
+        progress_cb(index as u32 + 1, total);
+    }
+
+    tracing::debug!("Re-resolved external links: {:?}", summary);
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    use crate::{database, uid::Uid};
+
+    struct MockResolver;
+
+    #[async_trait(?Send)]
+    impl LinkResolver for MockResolver {
+        fn key(&self) -> ExternalLinkKey {
+            ExternalLinkKey::SpotifyUrl
+        }
+
+        async fn resolve(&self, song: &Song) -> Result<Option<String>> {
+            Ok(Some(format!("https://spotify.test/{}", song.title())))
+        }
+    }
+
+    #[gtk::test]
+    fn re_resolve() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env.clone()).unwrap();
+
+        let a = Song::builder(&Uid::from("a"), "A", "A", "A").build();
+        let b = Song::builder(&Uid::from("b"), "B", "B", "B")
+            .external_link(ExternalLinkKey::SpotifyUrl, "existing")
+            .build();
+        let c = Song::builder(&Uid::from("c"), "C", "C", "C").build();
+        song_list.insert_many(vec![a, b, c]).unwrap();
+
+        let resolvers: Vec<Box<dyn LinkResolver>> = vec![Box::new(MockResolver)];
+
+        let progress = RefCell::new(Vec::new());
+        let summary = glib::MainContext::default()
+            .block_on(re_resolve_external_links(
+                &song_list,
+                &resolvers,
+                Duration::ZERO,
+                &gio::Cancellable::new(),
+                |n_processed, total| progress.borrow_mut().push((n_processed, total)),
+            ))
+            .unwrap();
+
+        assert_eq!(
+            summary,
+            ReResolveSummary {
+                n_checked: 2,
+                n_updated: 2,
+                n_links_added: 2,
+                n_failed: 0,
+            }
+        );
+        assert_eq!(progress.into_inner(), vec![(1, 2), (2, 2)]);
+
+        let get_link = |id: &str| {
+            song_list
+                .get(&Uid::from(id))
+                .unwrap()
+                .external_links()
+                .get(ExternalLinkKey::SpotifyUrl)
+        };
+        assert_eq!(get_link("a").as_deref(), Some("https://spotify.test/A"));
+        assert_eq!(get_link("b").as_deref(), Some("existing"));
+        assert_eq!(get_link("c").as_deref(), Some("https://spotify.test/C"));
+
+        // Links must also be written to the db
+        let reloaded = SongList::load_from_env(env).unwrap();
+        assert_eq!(
+            reloaded
+                .get(&Uid::from("a"))
+                .unwrap()
+                .external_links()
+                .get(ExternalLinkKey::SpotifyUrl)
+                .as_deref(),
+            Some("https://spotify.test/A")
+        );
+    }
+
+    struct RateLimitedResolver(Rc<RateLimiter>);
+
+    #[async_trait(?Send)]
+    impl LinkResolver for RateLimitedResolver {
+        fn key(&self) -> ExternalLinkKey {
+            ExternalLinkKey::SpotifyUrl
+        }
+
+        async fn resolve(&self, song: &Song) -> Result<Option<String>> {
+            Ok(Some(format!("https://spotify.test/{}", song.title())))
+        }
+
+        fn rate_limiter(&self) -> Option<Rc<RateLimiter>> {
+            Some(Rc::clone(&self.0))
+        }
+    }
+
+    #[gtk::test]
+    fn re_resolve_rate_limited() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+        song_list
+            .insert_many(vec![
+                Song::builder(&Uid::from("a"), "A", "A", "A").build(),
+                Song::builder(&Uid::from("b"), "B", "B", "B").build(),
+            ])
+            .unwrap();
+
+        // Only allows a single request, and fails right away afterwards
+        let rate_limiter = Rc::new(RateLimiter::new(1, Duration::from_secs(60), Duration::ZERO));
+        let resolvers: Vec<Box<dyn LinkResolver>> =
+            vec![Box::new(RateLimitedResolver(rate_limiter))];
+
+        let summary = glib::MainContext::default()
+            .block_on(re_resolve_external_links(
+                &song_list,
+                &resolvers,
+                Duration::ZERO,
+                &gio::Cancellable::new(),
+                |_, _| {},
+            ))
+            .unwrap();
+
+        assert_eq!(
+            summary,
+            ReResolveSummary {
+                n_checked: 2,
+                n_updated: 1,
+                n_links_added: 1,
+                n_failed: 1,
+            }
+        );
+    }
+}
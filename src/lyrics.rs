@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+/// A line of lyrics that should be shown starting at `time`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedLine {
+    pub time: Duration,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lyrics {
+    /// Lyrics without timing data
+    Plain(String),
+    /// Lyrics parsed from LRC, sorted by time
+    Timed(Vec<TimedLine>),
+}
+
+impl Lyrics {
+    /// Parses LRC-style lyrics, where lines are prefixed with one or more
+    /// `[mm:ss.xx]` tags.
+    ///
+    /// Metadata tags (e.g., `[ar:Artist]`) and lines without valid tags are
+    /// skipped. If there are no timed lines at all, this returns the text as is.
+    pub fn parse(text: &str) -> Self {
+        let mut lines = Vec::new();
+
+        for line in text.lines() {
+            let mut rest = line.trim();
+            let mut times = Vec::new();
+
+            while let Some((tag, after)) = rest
+                .strip_prefix('[')
+                .and_then(|stripped| stripped.split_once(']'))
+            {
+                match parse_time_tag(tag) {
+                    Some(time) => times.push(time),
+                    None => break,
+                }
+                rest = after;
+            }
+
+            let text = rest.trim();
+            lines.extend(times.into_iter().map(|time| TimedLine {
+                time,
+                text: text.to_string(),
+            }));
+        }
+
+        if lines.is_empty() {
+            return Self::Plain(text.to_string());
+        }
+
+        lines.sort_by_key(|line| line.time);
+        Self::Timed(lines)
+    }
+}
+
+/// Returns the index of the line that should be shown at `position`.
+pub fn current_line_index(lines: &[TimedLine], position: Duration) -> Option<usize> {
+    lines
+        .partition_point(|line| line.time <= position)
+        .checked_sub(1)
+}
+
+/// Parses `mm:ss`, `mm:ss.xx`, or `mm:ss.xxx`
+fn parse_time_tag(tag: &str) -> Option<Duration> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes = minutes.parse::<u64>().ok()?;
+
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let seconds = seconds.parse::<u64>().ok()?;
+    if seconds >= 60 {
+        return None;
+    }
+
+    let millis = match fraction.len() {
+        0 => 0,
+        1..=3 if fraction.bytes().all(|b| b.is_ascii_digit()) => {
+            fraction.parse::<u64>().ok()? * 10_u64.pow(3 - fraction.len() as u32)
+        }
+        _ => return None,
+    };
+
+    Some(Duration::from_millis(
+        (minutes * 60 + seconds) * 1000 + millis,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(millis: u64, text: &str) -> TimedLine {
+        TimedLine {
+            time: Duration::from_millis(millis),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn time_tag() {
+        assert_eq!(parse_time_tag("00:00"), Some(Duration::ZERO));
+        assert_eq!(
+            parse_time_tag("01:02.5"),
+            Some(Duration::from_millis(62_500))
+        );
+        assert_eq!(
+            parse_time_tag("01:02.34"),
+            Some(Duration::from_millis(62_340))
+        );
+        assert_eq!(
+            parse_time_tag("01:02.345"),
+            Some(Duration::from_millis(62_345))
+        );
+        assert_eq!(parse_time_tag("10:00.00"), Some(Duration::from_secs(600)));
+
+        assert_eq!(parse_time_tag("ar:Someone"), None);
+        assert_eq!(parse_time_tag("01:60"), None);
+        assert_eq!(parse_time_tag("01:02.3456"), None);
+        assert_eq!(parse_time_tag("01:02.-1"), None);
+        assert_eq!(parse_time_tag("0102"), None);
+        assert_eq!(parse_time_tag(""), None);
+    }
+
+    #[test]
+    fn parse_lrc() {
+        let lyrics = Lyrics::parse(
+            "[ar:Someone]
+[ti:Some song]
+
+[00:12.00]First line
+[00:17.20]Second line
+not a timed line
+[00:21.10][01:02.00]Chorus
+[00:30.xx]Malformed
+[00:40.00]",
+        );
+
+        assert_eq!(
+            lyrics,
+            Lyrics::Timed(vec![
+                line(12_000, "First line"),
+                line(17_200, "Second line"),
+                line(21_100, "Chorus"),
+                line(40_000, ""),
+                line(62_000, "Chorus"),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_plain() {
+        let text = "First line\n[Chorus]\nSecond line";
+        assert_eq!(Lyrics::parse(text), Lyrics::Plain(text.to_string()));
+    }
+
+    #[test]
+    fn current_line() {
+        let lines = vec![line(1000, "A"), line(2000, "B"), line(3000, "C")];
+
+        assert_eq!(current_line_index(&lines, Duration::ZERO), None);
+        assert_eq!(
+            current_line_index(&lines, Duration::from_millis(1000)),
+            Some(0)
+        );
+        assert_eq!(
+            current_line_index(&lines, Duration::from_millis(2500)),
+            Some(1)
+        );
+        assert_eq!(current_line_index(&lines, Duration::from_secs(60)), Some(2));
+        assert_eq!(current_line_index(&[], Duration::from_secs(60)), None);
+    }
+}
@@ -25,6 +25,7 @@
 
 mod about;
 mod album_art;
+mod album_art_backfill;
 mod application;
 mod audio_device;
 mod cancelled;
@@ -32,19 +33,36 @@ mod config;
 mod database;
 mod database_error_window;
 mod date_time;
+mod deep_link;
+mod diagnostics;
+mod export;
 mod external_link;
 mod external_links;
 mod i18n;
+mod import;
 mod inspector_page;
+mod itunes_search;
+mod link_resolver;
+mod lyrics;
+mod mic_permission;
+mod paths;
 mod player;
 mod preferences_window;
+mod rate_limiter;
 mod recognizer;
+mod release_date;
+mod ring_recorder;
+mod sample_store;
+mod search_cache;
 mod serde_helpers;
 mod settings;
+mod share_card;
 mod song;
 mod song_filter;
 mod song_list;
 mod song_sorter;
+mod sound_cue;
+mod sound_trigger;
 mod uid;
 mod utils;
 mod window;
@@ -0,0 +1,143 @@
+use anyhow::{Context, Result};
+use gtk::{
+    gio::{self, prelude::*},
+    glib,
+};
+
+use std::{collections::HashMap, path::Path};
+
+use crate::config::APP_ID;
+
+const PERMISSION_STORE_BUS_NAME: &str = "org.freedesktop.impl.portal.PermissionStore";
+const PERMISSION_STORE_OBJECT_PATH: &str = "/org/freedesktop/impl/portal/PermissionStore";
+
+/// Table and id the portals store the choice about the microphone in
+const PERMISSION_TABLE: &str = "devices";
+const PERMISSION_ID: &str = "microphone";
+
+/// Error returned by the permission store if no choice was stored yet
+const NOT_FOUND_ERROR_NAME: &str = "org.freedesktop.portal.Error.NotFound";
+
+/// Link to the microphone privacy settings, where access can be granted again
+const SETTINGS_URI: &str = "settings://privacy/microphone";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MicAccess {
+    Granted,
+    Denied,
+}
+
+impl MicAccess {
+    /// Only an explicit "no" denies access; without a stored choice, the
+    /// user is asked once the microphone is opened.
+    fn from_stored_permissions(permissions: &[String]) -> Self {
+        if permissions.iter().any(|permission| permission == "no") {
+            Self::Denied
+        } else {
+            Self::Granted
+        }
+    }
+}
+
+/// Looks up the stored choice about the microphone, so a denial is known
+/// before recording instead of from a failing pipeline.
+///
+/// Outside the sandbox, or if the permission store is unavailable, this
+/// assumes access is granted and leaves it to the sound server.
+pub async fn check() -> Result<MicAccess> {
+    if !Path::new("/.flatpak-info").exists() {
+        return Ok(MicAccess::Granted);
+    }
+
+    let connection = gio::bus_get_future(gio::BusType::Session)
+        .await
+        .context("Failed to get session bus")?;
+
+    let res = connection
+        .call_future(
+            Some(PERMISSION_STORE_BUS_NAME),
+            PERMISSION_STORE_OBJECT_PATH,
+            PERMISSION_STORE_BUS_NAME,
+            "Lookup",
+            Some(&(PERMISSION_TABLE, PERMISSION_ID).to_variant()),
+            Some(glib::VariantTy::new("(a{sas}v)").unwrap()),
+            gio::DBusCallFlags::NONE,
+            -1,
+        )
+        .await;
+
+    let access = match res {
+        Ok(reply) => {
+            let (permissions, _) = reply
+                .get::<(HashMap<String, Vec<String>>, glib::Variant)>()
+                .context("Received invalid permissions")?;
+            permissions
+                .get(APP_ID)
+                .map_or(MicAccess::Granted, |permissions| {
+                    MicAccess::from_stored_permissions(permissions)
+                })
+        }
+        Err(err) if is_remote_error(&err, NOT_FOUND_ERROR_NAME) => MicAccess::Granted,
+        Err(err) if err.matches(gio::DBusError::ServiceUnknown) => {
+            tracing::debug!("Permission store is unavailable: {:?}", err);
+            MicAccess::Granted
+        }
+        Err(err) => return Err(err).context("Failed to look up microphone access"),
+    };
+    tracing::debug!(?access, "Looked up microphone access");
+
+    Ok(access)
+}
+
+/// Opens the microphone privacy settings, where access can be granted again.
+pub async fn open_settings(parent: Option<&impl IsA<gtk::Window>>) -> Result<()> {
+    gtk::UriLauncher::new(SETTINGS_URI)
+        .launch_future(parent)
+        .await
+        .context("Failed to launch microphone settings")?;
+
+    Ok(())
+}
+
+/// Whether `err` was returned by the remote peer with the given D-Bus error
+/// name, which GDBus keeps as a prefix of the message.
+fn is_remote_error(err: &glib::Error, name: &str) -> bool {
+    err.message()
+        .strip_prefix("GDBus.Error:")
+        .and_then(|message| message.strip_prefix(name))
+        .is_some_and(|rest| rest.starts_with(':'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_stored_permissions() {
+        assert_eq!(
+            MicAccess::from_stored_permissions(&["yes".to_string()]),
+            MicAccess::Granted
+        );
+        assert_eq!(
+            MicAccess::from_stored_permissions(&["no".to_string()]),
+            MicAccess::Denied
+        );
+        assert_eq!(MicAccess::from_stored_permissions(&[]), MicAccess::Granted);
+    }
+
+    #[test]
+    fn remote_error() {
+        let err = glib::Error::new(
+            gio::IOErrorEnum::DbusError,
+            "GDBus.Error:org.freedesktop.portal.Error.NotFound: No entry for microphone",
+        );
+        assert!(is_remote_error(&err, NOT_FOUND_ERROR_NAME));
+        assert!(!is_remote_error(
+            &err,
+            "org.freedesktop.portal.Error.NotAllowed"
+        ));
+
+        let err = glib::Error::new(gio::IOErrorEnum::Failed, "No entry for microphone");
+        assert!(!is_remote_error(&err, NOT_FOUND_ERROR_NAME));
+    }
+}
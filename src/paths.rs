@@ -0,0 +1,141 @@
+use anyhow::{Context, Result};
+use gtk::glib;
+
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// Environment variable that overrides the directory where the db and caches
+/// are stored.
+pub const DATA_DIR_ENV_VAR: &str = "MOUSAI_DATA_DIR";
+
+const WRITE_PROBE_FILE_NAME: &str = ".mousai-write-probe";
+
+static DATA_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Overrides the data dir, e.g., from the `--data-dir` command line option.
+/// This takes precedence over `MOUSAI_DATA_DIR`.
+///
+/// This must be called before any of the dirs are accessed.
+pub fn set_data_dir_override(path: PathBuf) {
+    if DATA_DIR_OVERRIDE.set(path).is_err() {
+        tracing::warn!("Data dir override was already set");
+    }
+}
+
+/// Directory where the db is stored.
+pub fn db_dir() -> PathBuf {
+    data_dir(data_dir_override()).join("db")
+}
+
+/// Directory where the recorded samples of songs are stored.
+pub fn samples_dir() -> PathBuf {
+    data_dir(data_dir_override()).join("samples")
+}
+
+/// File where the progress of the album art backfill is stored.
+pub fn album_art_backfill_progress_file() -> PathBuf {
+    data_dir(data_dir_override()).join("album-art-backfill.jsonl")
+}
+
+/// Directory where caches, like the soup cache, are stored.
+pub fn cache_dir() -> PathBuf {
+    cache_dir_for(data_dir_override())
+}
+
+/// Creates the dir if it doesn't exist yet and checks that it is writable.
+pub fn ensure_writable_dir(path: &Path) -> Result<()> {
+    fs::create_dir_all(path)
+        .with_context(|| format!("Failed to create dir at {}", path.display()))?;
+
+    let probe_path = path.join(WRITE_PROBE_FILE_NAME);
+    fs::write(&probe_path, b"")
+        .and_then(|_| fs::remove_file(&probe_path))
+        .with_context(|| format!("Dir at {} is not writable", path.display()))?;
+
+    Ok(())
+}
+
+fn data_dir_override() -> Option<PathBuf> {
+    resolve_override(
+        DATA_DIR_OVERRIDE.get().cloned(),
+        env::var_os(DATA_DIR_ENV_VAR),
+    )
+}
+
+fn resolve_override(cli_value: Option<PathBuf>, env_value: Option<OsString>) -> Option<PathBuf> {
+    cli_value.or_else(|| {
+        env_value
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    })
+}
+
+fn data_dir(override_dir: Option<PathBuf>) -> PathBuf {
+    override_dir.unwrap_or_else(|| glib::user_data_dir().join("mousai"))
+}
+
+fn cache_dir_for(override_dir: Option<PathBuf>) -> PathBuf {
+    match override_dir {
+        Some(dir) => dir.join("cache"),
+        None => glib::user_cache_dir().join("mousai"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::database;
+
+    #[test]
+    fn resolve_override_precedence() {
+        assert_eq!(resolve_override(None, None), None);
+        assert_eq!(resolve_override(None, Some(OsString::new())), None);
+        assert_eq!(
+            resolve_override(None, Some(OsString::from("/env"))),
+            Some(PathBuf::from("/env"))
+        );
+        assert_eq!(
+            resolve_override(Some(PathBuf::from("/cli")), Some(OsString::from("/env"))),
+            Some(PathBuf::from("/cli"))
+        );
+    }
+
+    #[test]
+    fn overridden_dirs() {
+        let override_dir = Some(PathBuf::from("/portable"));
+        assert_eq!(
+            data_dir(override_dir.clone()).join("db"),
+            Path::new("/portable/db")
+        );
+        assert_eq!(cache_dir_for(override_dir), Path::new("/portable/cache"));
+    }
+
+    #[test]
+    fn db_lands_in_override() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let override_dir = resolve_override(None, Some(tempdir.path().as_os_str().to_owned()));
+        let db_dir = data_dir(override_dir).join("db");
+        assert!(!db_dir.exists());
+
+        let env = database::new_env_at(&db_dir).unwrap();
+        assert!(db_dir.join("data.mdb").exists());
+        assert!(!db_dir.join(WRITE_PROBE_FILE_NAME).exists());
+        env.prepare_for_closing().wait();
+    }
+
+    #[test]
+    fn ensure_writable_dir_not_a_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file_path = tempdir.path().join("file");
+        fs::write(&file_path, "").unwrap();
+
+        assert!(ensure_writable_dir(&file_path.join("dir")).is_err());
+    }
+}
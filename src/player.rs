@@ -1,5 +1,6 @@
 use anyhow::Result;
 use async_lock::OnceCell as AsyncOnceCell;
+use gettextrs::gettext;
 use gst::bus::BusWatchGuard;
 use gst_play::prelude::*;
 use gtk::{
@@ -14,10 +15,31 @@ use mpris_server::{
     PlaybackStatus, Property, Signal, Time, TrackId, Volume,
 };
 
-use std::cell::{Cell, OnceCell, RefCell};
+use std::{
+    cell::{Cell, OnceCell, RefCell},
+    collections::VecDeque,
+    error,
+    f64::consts::FRAC_PI_2,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use crate::{config::APP_ID, song::Song, uid::Uid, utils, Application};
 
+/// How long the player may stay buffering before giving up on the song
+const BUFFERING_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Interval between volume changes while crossfading
+const CROSSFADE_TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long before the crossfade the ending song is loaded on the second
+/// player, so it does not stutter when the crossfade starts
+const CROSSFADE_PRELOAD_LEAD: Duration = Duration::from_secs(2);
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
 #[enum_type(name = "MsaiPlayerState")]
 pub enum PlayerState {
@@ -26,12 +48,188 @@ pub enum PlayerState {
     Buffering,
     Paused,
     Playing,
+    /// The song failed to load. This is kept until another song is set or
+    /// playback is retried.
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, glib::ErrorDomain)]
+#[error_domain(name = "MsaiPlayer")]
+pub enum PlayerError {
+    /// The song was buffering for longer than [`BUFFERING_TIMEOUT`]
+    BufferingTimedOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackErrorKind {
+    /// The server could not be reached, e.g., there is no connection or its
+    /// name could not be resolved
+    Network,
+    /// The server responded with an error status
+    Http(u16),
+    /// The song loaded too slowly
+    TimedOut,
+    /// A plugin needed to play the song is not installed
+    MissingCodec,
+    /// The song is not in a format that can be played
+    UnsupportedFormat,
+    Other,
+}
+
+/// Error received while playing a song
+#[derive(Debug, Clone, glib::Boxed)]
+#[boxed_type(name = "MsaiPlaybackError")]
+pub struct PlaybackError {
+    kind: PlaybackErrorKind,
+    source: glib::Error,
+}
+
+impl PlaybackError {
+    /// `details` are the ones of the error message, where the HTTP source
+    /// puts the status of error responses.
+    pub fn from_gst_error(source: glib::Error, details: Option<&gst::StructureRef>) -> Self {
+        let http_status = details
+            .and_then(|details| details.get::<u32>("http-status-code").ok())
+            .and_then(|status| u16::try_from(status).ok())
+            .filter(|status| (400..600).contains(status));
+
+        let kind = if source.matches(PlayerError::BufferingTimedOut) {
+            PlaybackErrorKind::TimedOut
+        } else if let Some(status) = http_status {
+            PlaybackErrorKind::Http(status)
+        } else if source.matches(gst::CoreError::MissingPlugin)
+            || source.matches(gst::StreamError::CodecNotFound)
+        {
+            PlaybackErrorKind::MissingCodec
+        } else if source.matches(gst::StreamError::TypeNotFound)
+            || source.matches(gst::StreamError::WrongType)
+            || source.matches(gst::StreamError::Format)
+            || source.matches(gst::StreamError::Decode)
+            || source.matches(gst::StreamError::Demux)
+            || source.matches(gst::StreamError::Decrypt)
+            || source.matches(gst::StreamError::DecryptNokey)
+        {
+            PlaybackErrorKind::UnsupportedFormat
+        } else if source.matches(gst::ResourceError::NotFound)
+            || source.matches(gst::ResourceError::OpenRead)
+            || source.matches(gst::ResourceError::Read)
+        {
+            PlaybackErrorKind::Network
+        } else {
+            PlaybackErrorKind::Other
+        };
+
+        Self { kind, source }
+    }
+
+    pub fn kind(&self) -> PlaybackErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for PlaybackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            PlaybackErrorKind::Network => f.write_str(&gettext(
+                "Couldn't reach the preview. Check your connection and try again",
+            )),
+            PlaybackErrorKind::Http(status) if status < 500 => {
+                f.write_str(&gettext("The preview is no longer available"))
+            }
+            PlaybackErrorKind::Http(_) => f.write_str(&gettext(
+                "The server of the preview had a problem. Try again later",
+            )),
+            PlaybackErrorKind::TimedOut => f.write_str(&gettext("Couldn't load preview")),
+            PlaybackErrorKind::MissingCodec => f.write_str(&gettext(
+                "Can't play the preview. Installing additional codecs may help",
+            )),
+            PlaybackErrorKind::UnsupportedFormat => {
+                f.write_str(&gettext("The preview is in an unsupported format"))
+            }
+            PlaybackErrorKind::Other => f.write_str(&gettext("An error occurred in the player")),
+        }
+    }
+}
+
+impl error::Error for PlaybackError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Creates the audio filter that evens out the loudness of songs.
+///
+/// This uses the ReplayGain tags if available, and the limiter prevents clipping
+/// when songs without tags are too loud.
+fn create_normalization_filter() -> Result<gst::Element> {
+    let bin = gst::parse_bin_from_description(
+        "rgvolume fallback-gain=-6 ! rglimiter ! audioconvert",
+        true,
+    )?;
+    Ok(bin.upcast())
+}
+
+/// Decides when the crossfade between the ending song and the next one
+/// starts, and how their volumes ramp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CrossfadePlan {
+    duration: Duration,
+}
+
+impl CrossfadePlan {
+    /// Returns `None`, i.e., a hard cut, if crossfading is disabled or the
+    /// ending song is too short to fade out.
+    fn new(duration: Duration, song_duration: Duration) -> Option<Self> {
+        let plan = Self { duration };
+
+        if duration.is_zero() || !plan.fits(song_duration) {
+            return None;
+        }
+
+        Some(plan)
+    }
+
+    /// Whether a song is long enough to be faded in or out entirely.
+    fn fits(&self, song_duration: Duration) -> bool {
+        song_duration >= self.duration * 2
+    }
+
+    /// Position in the ending song where the crossfade starts.
+    fn start_position(&self, song_duration: Duration) -> Duration {
+        song_duration.saturating_sub(self.duration)
+    }
+
+    /// Returns the volumes of the ending and the next song at `elapsed` since
+    /// the crossfade started.
+    ///
+    /// This uses an equal-power curve, so the loudness does not dip midway.
+    fn volumes_at(&self, elapsed: Duration) -> (f64, f64) {
+        let progress = (elapsed.as_secs_f64() / self.duration.as_secs_f64()).clamp(0.0, 1.0);
+        (
+            (progress * FRAC_PI_2).cos().max(0.0),
+            (progress * FRAC_PI_2).sin(),
+        )
+    }
+
+    fn is_done(&self, elapsed: Duration) -> bool {
+        elapsed >= self.duration
+    }
+}
+
+/// Ending song that keeps playing on a second player while the next song
+/// fades in on the main player
+struct Crossfade {
+    plan: CrossfadePlan,
+    outgoing: gst_play::Play,
+    /// Whether the crossfade started, or the ending song is only preloaded
+    is_started: bool,
+    tick_source: Option<glib::SourceId>,
 }
 
 impl PlayerState {
     fn to_playback_status(self) -> PlaybackStatus {
         match self {
-            Self::Stopped | Self::Buffering => PlaybackStatus::Stopped,
+            Self::Stopped | Self::Buffering | Self::Error => PlaybackStatus::Stopped,
             Self::Playing => PlaybackStatus::Playing,
             Self::Paused => PlaybackStatus::Paused,
         }
@@ -57,6 +255,22 @@ mod imp {
         /// Duration of the active song
         #[property(get)]
         pub(super) duration: Cell<gst::ClockTime>,
+        /// Whether loudness is normalized. This applies starting on the next song.
+        #[property(get, set = Self::set_normalizes_loudness, explicit_notify)]
+        pub(super) normalizes_loudness: Cell<bool>,
+        /// Seconds to crossfade between queued songs, or 0 to cut
+        #[property(get, set)]
+        pub(super) crossfade_duration: Cell<u32>,
+        /// Whether the song restarts when it ends instead of playing the next song
+        #[property(get, set = Self::set_loops, explicit_notify)]
+        pub(super) loops: Cell<bool>,
+        /// Copy of `loops` for the streaming thread
+        pub(super) loops_shared: Arc<AtomicBool>,
+
+        /// Songs to play after the current song ends
+        pub(super) queue: RefCell<VecDeque<Song>>,
+        pub(super) buffering_timeout: RefCell<Option<glib::SourceId>>,
+        pub(super) crossfade: RefCell<Option<Crossfade>>,
 
         pub(super) gst_play: gst_play::Play,
         pub(super) bus_watch_guard: OnceCell<BusWatchGuard>,
@@ -76,7 +290,7 @@ mod imp {
         fn signals() -> &'static [Signal] {
             static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
                 vec![Signal::builder("error")
-                    .param_types([glib::Error::static_type()])
+                    .param_types([PlaybackError::static_type()])
                     .build()]
             });
 
@@ -103,6 +317,22 @@ mod imp {
                 )
                 .unwrap();
             self.bus_watch_guard.set(bus_watch_guard).unwrap();
+
+            // Queue the same uri again before the stream ends, so playbin
+            // continues into it without a gap instead of reaching EOS
+            let loops_shared = Arc::clone(&self.loops_shared);
+            self.gst_play
+                .pipeline()
+                .connect("about-to-finish", false, move |values| {
+                    if loops_shared.load(Ordering::Relaxed) {
+                        let playbin = values[0].get::<gst::Element>().unwrap();
+                        if let Some(uri) = playbin.property::<Option<String>>("current-uri") {
+                            playbin.set_property("uri", uri);
+                            tracing::debug!("Queued looping song");
+                        }
+                    }
+                    None
+                });
         }
 
         fn dispose(&self) {
@@ -111,6 +341,45 @@ mod imp {
     }
 
     impl Player {
+        fn set_normalizes_loudness(&self, normalizes_loudness: bool) {
+            let obj = self.obj();
+
+            if normalizes_loudness == obj.normalizes_loudness() {
+                return;
+            }
+
+            let audio_filter = if normalizes_loudness {
+                match create_normalization_filter() {
+                    Ok(filter) => Some(filter),
+                    Err(err) => {
+                        tracing::error!("Failed to create normalization filter: {:?}", err);
+                        return;
+                    }
+                }
+            } else {
+                None
+            };
+            self.gst_play
+                .pipeline()
+                .set_property("audio-filter", audio_filter);
+
+            self.normalizes_loudness.set(normalizes_loudness);
+            obj.notify_normalizes_loudness();
+        }
+
+        fn set_loops(&self, loops: bool) {
+            let obj = self.obj();
+
+            if loops == obj.loops() {
+                return;
+            }
+
+            self.loops.set(loops);
+            self.loops_shared.store(loops, Ordering::Relaxed);
+            obj.mpris_properties_changed([Property::LoopStatus(obj.loop_status_for_mpris())]);
+            obj.notify_loops();
+        }
+
         fn set_song(&self, song: Option<Song>) {
             let obj = self.obj();
 
@@ -118,7 +387,12 @@ mod imp {
                 return;
             }
 
+            // Explicitly setting a song replaces the queue
+            self.queue.borrow_mut().clear();
+
+            obj.finish_crossfade();
             self.gst_play.stop();
+            obj.set_state(PlayerState::Stopped);
 
             // FIXME This does not actually reset the position, especially when
             // a song is already playing and we switch to another song that took
@@ -175,30 +449,94 @@ impl Player {
 
     pub fn connect_error<F>(&self, f: F) -> glib::SignalHandlerId
     where
-        F: Fn(&Self, &glib::Error) + 'static,
+        F: Fn(&Self, &PlaybackError) + 'static,
     {
         self.connect_closure(
             "error",
             true,
-            closure_local!(|obj: &Self, error: &glib::Error| {
+            closure_local!(|obj: &Self, error: &PlaybackError| {
                 f(obj, error);
             }),
         )
     }
 
+    /// Sets the first song with a playback link as the current song, and queues the
+    /// rest of the songs with playback links to be played after it.
+    ///
+    /// This returns the number of songs skipped because they have no playback link.
+    pub fn set_queue(&self, songs: Vec<Song>) -> usize {
+        let n_songs = songs.len();
+        let mut queue = songs
+            .into_iter()
+            .filter(|song| song.playback_link().is_some())
+            .collect::<VecDeque<_>>();
+        let n_skipped = n_songs - queue.len();
+
+        let first = queue.pop_front();
+        self.set_song(first.as_ref());
+        self.imp().queue.replace(queue);
+
+        n_skipped
+    }
+
+    /// Plays the next song in the queue, if there is any.
+    ///
+    /// Returns whether there is a next song.
+    pub fn play_next(&self) -> bool {
+        let imp = self.imp();
+
+        let Some(next) = imp.queue.borrow_mut().pop_front() else {
+            return false;
+        };
+
+        let rest = imp.queue.take();
+        self.set_song(Some(next));
+        imp.queue.replace(rest);
+
+        self.play();
+
+        true
+    }
+
     pub fn is_active_song(&self, song_id: &Uid) -> bool {
         self.song().map_or(false, |song| song.id_ref() == song_id)
     }
 
     pub fn play(&self) {
+        // Retrying clears the error, so the next state change is not ignored
+        if self.state() == PlayerState::Error {
+            self.set_state(PlayerState::Stopped);
+        }
+
         self.imp().gst_play.play();
     }
 
     pub fn pause(&self) {
+        self.finish_crossfade();
         self.imp().gst_play.pause();
     }
 
+    /// Pauses the playback until the returned hold is dropped, e.g., so it is
+    /// not captured while recording the desktop audio.
+    pub fn hold(&self) -> PlaybackHold {
+        let held_song_id = matches!(self.state(), PlayerState::Playing | PlayerState::Buffering)
+            .then(|| self.song().map(|song| song.id()))
+            .flatten();
+
+        if held_song_id.is_some() {
+            tracing::debug!("Holding playback");
+            self.pause();
+        }
+
+        PlaybackHold {
+            player: self.downgrade(),
+            held_song_id,
+        }
+    }
+
     pub fn seek(&self, position: gst::ClockTime) {
+        self.finish_crossfade();
+
         if matches!(self.state(), PlayerState::Stopped) {
             self.pause();
         }
@@ -208,6 +546,51 @@ impl Player {
         self.imp().gst_play.seek(position);
     }
 
+    fn set_state(&self, state: PlayerState) {
+        let imp = self.imp();
+
+        if state == PlayerState::Buffering {
+            if imp.buffering_timeout.borrow().is_none() {
+                let source_id = glib::timeout_add_local_once(
+                    BUFFERING_TIMEOUT,
+                    clone!(@weak self as obj => move || {
+                        obj.imp().buffering_timeout.take();
+                        obj.handle_buffering_timed_out();
+                    }),
+                );
+                imp.buffering_timeout.replace(Some(source_id));
+            }
+        } else if let Some(source_id) = imp.buffering_timeout.take() {
+            source_id.remove();
+        }
+
+        let old_state = imp.state.replace(state);
+
+        if old_state == state {
+            return;
+        }
+
+        tracing::debug!("State changed from `{:?}` -> `{:?}`", old_state, state);
+
+        self.mpris_properties_changed([Property::PlaybackStatus(state.to_playback_status())]);
+        self.notify_state();
+    }
+
+    /// Releases the pipeline of a song that is stuck buffering, e.g., because
+    /// its link is dead.
+    fn handle_buffering_timed_out(&self) {
+        tracing::warn!("Timed out while buffering after {:?}", BUFFERING_TIMEOUT);
+
+        self.imp().gst_play.stop();
+        self.set_state(PlayerState::Error);
+
+        let error = PlaybackError::from_gst_error(
+            glib::Error::new(PlayerError::BufferingTimedOut, "Timed out while buffering"),
+            None,
+        );
+        self.emit_by_name::<()>("error", &[&error]);
+    }
+
     fn set_position(&self, position: gst::ClockTime) {
         self.imp().position.set(position);
         self.notify_position();
@@ -270,6 +653,182 @@ impl Player {
         );
     }
 
+    /// Preloads the ending song on a second player as the position approaches
+    /// the crossfade, then starts it once the position reaches it.
+    fn update_crossfade(&self, position: gst::ClockTime) {
+        let imp = self.imp();
+
+        if imp
+            .crossfade
+            .borrow()
+            .as_ref()
+            .is_some_and(|crossfade| crossfade.is_started)
+            || imp.queue.borrow().is_empty()
+            || self.loops()
+        {
+            return;
+        }
+
+        let song_duration = Duration::from_nanos(self.duration().nseconds());
+        let Some(plan) = CrossfadePlan::new(
+            Duration::from_secs(self.crossfade_duration() as u64),
+            song_duration,
+        ) else {
+            return;
+        };
+
+        let position = Duration::from_nanos(position.nseconds());
+        let start_position = plan.start_position(song_duration);
+
+        if imp.crossfade.borrow().is_none() {
+            if position + CROSSFADE_PRELOAD_LEAD < start_position {
+                return;
+            }
+
+            let Some(playback_link) = self.song().and_then(|song| song.playback_link()) else {
+                return;
+            };
+
+            let outgoing = gst_play::Play::default();
+            if self.normalizes_loudness() {
+                match create_normalization_filter() {
+                    Ok(filter) => outgoing.pipeline().set_property("audio-filter", filter),
+                    Err(err) => {
+                        tracing::error!("Failed to create normalization filter: {:?}", err);
+                    }
+                }
+            }
+            outgoing.set_uri(Some(&playback_link));
+            outgoing.pause();
+            outgoing.seek(gst::ClockTime::from_nseconds(
+                start_position.as_nanos() as u64
+            ));
+
+            imp.crossfade.replace(Some(Crossfade {
+                plan,
+                outgoing,
+                is_started: false,
+                tick_source: None,
+            }));
+
+            tracing::debug!(?start_position, "Preloaded song to crossfade");
+        }
+
+        if position >= start_position {
+            self.start_crossfade();
+        }
+    }
+
+    fn start_crossfade(&self) {
+        let imp = self.imp();
+
+        // Taken out, so advancing to the next song does not finish it
+        let Some(mut crossfade) = imp.crossfade.take() else {
+            return;
+        };
+
+        crossfade.outgoing.play();
+
+        if !self.play_next() {
+            crossfade.outgoing.stop();
+            return;
+        }
+
+        imp.gst_play.set_volume(0.0);
+
+        let start_time = Instant::now();
+        crossfade.tick_source = Some(glib::timeout_add_local(
+            CROSSFADE_TICK_INTERVAL,
+            clone!(@weak self as obj => @default-return glib::ControlFlow::Break, move || {
+                obj.crossfade_tick(start_time.elapsed())
+            }),
+        ));
+        crossfade.is_started = true;
+        imp.crossfade.replace(Some(crossfade));
+
+        tracing::debug!("Started crossfade");
+    }
+
+    /// Sets the volumes of both songs at `elapsed` since the crossfade started.
+    fn crossfade_tick(&self, elapsed: Duration) -> glib::ControlFlow {
+        let imp = self.imp();
+
+        let mut crossfade = imp.crossfade.borrow_mut();
+        let Some(crossfade_ref) = crossfade.as_mut().filter(|crossfade| crossfade.is_started)
+        else {
+            return glib::ControlFlow::Break;
+        };
+
+        if crossfade_ref.plan.is_done(elapsed) {
+            // The source is removed by returning `Break`
+            crossfade_ref.tick_source.take();
+            drop(crossfade);
+            self.finish_crossfade();
+            return glib::ControlFlow::Break;
+        }
+
+        let (outgoing_volume, incoming_volume) = crossfade_ref.plan.volumes_at(elapsed);
+        crossfade_ref.outgoing.set_volume(outgoing_volume);
+        imp.gst_play.set_volume(incoming_volume);
+
+        glib::ControlFlow::Continue
+    }
+
+    /// Stops the ending song of the crossfade, if there is one, and restores
+    /// the volume of the current song.
+    fn finish_crossfade(&self) {
+        let imp = self.imp();
+
+        let Some(crossfade) = imp.crossfade.take() else {
+            return;
+        };
+
+        if let Some(source_id) = crossfade.tick_source {
+            source_id.remove();
+        }
+        crossfade.outgoing.stop();
+        imp.gst_play.set_volume(1.0);
+
+        tracing::debug!(is_started = crossfade.is_started, "Finished crossfade");
+    }
+
+    fn handle_end_of_stream(&self) {
+        self.set_position(gst::ClockTime::ZERO);
+
+        if self.loops() && self.song().is_some() {
+            self.restart();
+            return;
+        }
+
+        // Unload the song when there is nothing left to play, so the
+        // song bar is hidden.
+        if !self.play_next() {
+            self.set_song(Song::NONE);
+        }
+    }
+
+    /// Plays the current song again from the start.
+    ///
+    /// Looping is normally gapless, as the song is queued again before it
+    /// ends. This is only for when it ended anyway, e.g., when looping was
+    /// turned on too close to the end, so there is a short gap.
+    fn restart(&self) {
+        let imp = self.imp();
+
+        imp.gst_play.seek(gst::ClockTime::ZERO);
+        imp.gst_play.play();
+
+        tracing::debug!("Restarted looping song");
+    }
+
+    fn loop_status_for_mpris(&self) -> LoopStatus {
+        if self.loops() {
+            LoopStatus::Track
+        } else {
+            LoopStatus::None
+        }
+    }
+
     fn handle_gst_play_message(&self, message: gst_play::PlayMessage) {
         use gst_play::{PlayMessage, PlayState};
 
@@ -277,10 +836,24 @@ impl Player {
 
         match message {
             PlayMessage::PositionUpdated { position } => {
-                self.set_position(position.unwrap_or_default());
+                let position = position.unwrap_or_default();
+                self.set_position(position);
+                self.update_crossfade(position);
             }
             PlayMessage::DurationChanged { duration } => {
-                self.set_duration(duration.unwrap_or_default());
+                let duration = duration.unwrap_or_default();
+                self.set_duration(duration);
+
+                // The next song is too short to fade in
+                if imp.crossfade.borrow().as_ref().is_some_and(|crossfade| {
+                    crossfade.is_started
+                        && !duration.is_zero()
+                        && !crossfade
+                            .plan
+                            .fits(Duration::from_nanos(duration.nseconds()))
+                }) {
+                    self.finish_crossfade();
+                }
             }
             PlayMessage::StateChanged { state } => {
                 let new_state = match state {
@@ -294,19 +867,16 @@ impl Player {
                     }
                 };
 
-                let old_state = imp.state.get();
-                tracing::debug!("State changed from `{:?}` -> `{:?}`", old_state, new_state);
-
-                imp.state.set(new_state);
+                // Stopping the pipeline on error must not hide the error
+                if self.state() == PlayerState::Error && new_state == PlayerState::Stopped {
+                    return;
+                }
 
-                self.mpris_properties_changed([Property::PlaybackStatus(
-                    new_state.to_playback_status(),
-                )]);
-                self.notify_state();
+                self.set_state(new_state);
             }
             PlayMessage::EndOfStream => {
                 tracing::debug!("Received end of stream message");
-                self.set_position(gst::ClockTime::ZERO);
+                self.handle_end_of_stream();
             }
             PlayMessage::SeekDone => {
                 tracing::debug!("Received seek done message");
@@ -316,6 +886,8 @@ impl Player {
             }
             PlayMessage::Error { error, details } => {
                 tracing::error!(state = ?self.state(), ?details, "Received error message: {:?}", error);
+                let error = PlaybackError::from_gst_error(error, details.as_deref());
+                tracing::debug!(kind = ?error.kind(), "Classified playback error");
                 self.emit_by_name::<()>("error", &[&error]);
             }
             PlayMessage::Warning { error, details } => {
@@ -353,6 +925,38 @@ impl Default for Player {
     }
 }
 
+/// Returned by [`Player::hold`]
+///
+/// When dropped, this resumes the playback if it was playing when held and
+/// the same song is still set.
+#[must_use]
+#[derive(Debug)]
+pub struct PlaybackHold {
+    player: glib::WeakRef<Player>,
+    held_song_id: Option<Uid>,
+}
+
+impl PlaybackHold {
+    fn should_resume(&self, player: &Player) -> bool {
+        self.held_song_id
+            .as_ref()
+            .is_some_and(|song_id| player.is_active_song(song_id))
+    }
+}
+
+impl Drop for PlaybackHold {
+    fn drop(&mut self) {
+        let Some(player) = self.player.upgrade() else {
+            return;
+        };
+
+        if self.should_resume(&player) {
+            tracing::debug!("Resuming held playback");
+            player.play();
+        }
+    }
+}
+
 #[async_trait(?Send)]
 impl LocalRootInterface for Player {
     async fn raise(&self) -> fdo::Result<()> {
@@ -411,7 +1015,8 @@ impl LocalRootInterface for Player {
 #[async_trait(?Send)]
 impl LocalPlayerInterface for Player {
     async fn next(&self) -> fdo::Result<()> {
-        Err(fdo::Error::NotSupported("Next is not supported".into()))
+        self.play_next();
+        Ok(())
     }
 
     async fn previous(&self) -> fdo::Result<()> {
@@ -468,13 +1073,16 @@ impl LocalPlayerInterface for Player {
     }
 
     async fn loop_status(&self) -> fdo::Result<LoopStatus> {
-        Ok(LoopStatus::None)
+        Ok(self.loop_status_for_mpris())
     }
 
-    async fn set_loop_status(&self, _loop_status: LoopStatus) -> zbus::Result<()> {
-        Err(zbus::Error::from(fdo::Error::NotSupported(
-            "SetLoopStatus is not supported".into(),
-        )))
+    async fn set_loop_status(&self, loop_status: LoopStatus) -> zbus::Result<()> {
+        // There is no playlist to loop, so the current song is looped instead
+        self.set_loops(matches!(
+            loop_status,
+            LoopStatus::Track | LoopStatus::Playlist
+        ));
+        Ok(())
     }
 
     async fn rate(&self) -> fdo::Result<PlaybackRate> {
@@ -524,7 +1132,7 @@ impl LocalPlayerInterface for Player {
     }
 
     async fn can_go_next(&self) -> fdo::Result<bool> {
-        Ok(false)
+        Ok(!self.imp().queue.borrow().is_empty())
     }
 
     async fn can_go_previous(&self) -> fdo::Result<bool> {
@@ -547,3 +1155,459 @@ impl LocalPlayerInterface for Player {
         Ok(true)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+
+    fn new_test_song(id: &str, has_playback_link: bool) -> Song {
+        let mut builder = Song::builder(&Uid::from(id), id, id, id);
+        if has_playback_link {
+            builder.playback_link(&format!("file:///{}.ogg", id));
+        }
+        builder.build()
+    }
+
+    #[gtk::test]
+    fn normalization_filter() {
+        gst::init().unwrap();
+
+        let filter = create_normalization_filter()
+            .unwrap()
+            .downcast::<gst::Bin>()
+            .unwrap();
+        assert_eq!(filter.children().len(), 3);
+        assert!(filter.static_pad("sink").is_some());
+        assert!(filter.static_pad("src").is_some());
+
+        let pipeline = gst::Pipeline::new();
+        let src = gst::ElementFactory::make("audiotestsrc").build().unwrap();
+        let sink = gst::ElementFactory::make("fakesink").build().unwrap();
+        pipeline
+            .add_many([&src, filter.upcast_ref(), &sink])
+            .unwrap();
+        gst::Element::link_many([&src, filter.upcast_ref(), &sink]).unwrap();
+    }
+
+    #[gtk::test]
+    fn set_normalizes_loudness() {
+        gst::init().unwrap();
+
+        let player = Player::new();
+        assert!(!player.normalizes_loudness());
+        let audio_filter = |player: &Player| {
+            player
+                .imp()
+                .gst_play
+                .pipeline()
+                .property::<Option<gst::Element>>("audio-filter")
+        };
+        assert!(audio_filter(&player).is_none());
+
+        player.set_normalizes_loudness(true);
+        assert!(player.normalizes_loudness());
+        assert!(audio_filter(&player).is_some());
+
+        player.set_normalizes_loudness(false);
+        assert!(audio_filter(&player).is_none());
+    }
+
+    #[gtk::test]
+    fn set_queue() {
+        gst::init().unwrap();
+
+        let player = Player::new();
+        let n_skipped = player.set_queue(vec![
+            new_test_song("a", false),
+            new_test_song("b", true),
+            new_test_song("c", false),
+            new_test_song("d", true),
+            new_test_song("e", true),
+        ]);
+        assert_eq!(n_skipped, 2);
+        assert!(player.is_active_song(&Uid::from("b")));
+        assert_eq!(
+            player
+                .imp()
+                .queue
+                .borrow()
+                .iter()
+                .map(|song| song.id())
+                .collect::<Vec<_>>(),
+            vec![Uid::from("d"), Uid::from("e")]
+        );
+
+        // Setting a song explicitly clears the queue
+        player.set_song(Some(new_test_song("f", true)));
+        assert!(player.imp().queue.borrow().is_empty());
+    }
+
+    #[gtk::test]
+    fn end_of_stream() {
+        gst::init().unwrap();
+
+        let player = Player::new();
+        player.set_queue(vec![new_test_song("a", true), new_test_song("b", true)]);
+        assert!(player.is_active_song(&Uid::from("a")));
+
+        player.handle_end_of_stream();
+        assert!(player.is_active_song(&Uid::from("b")));
+
+        player.handle_end_of_stream();
+        assert!(player.song().is_none());
+    }
+
+    #[gtk::test]
+    fn end_of_stream_looping() {
+        gst::init().unwrap();
+
+        let player = Player::new();
+        player.set_loops(true);
+        player.set_queue(vec![new_test_song("a", true), new_test_song("b", true)]);
+
+        // Restarts instead of advancing the queue
+        player.handle_end_of_stream();
+        assert!(player.is_active_song(&Uid::from("a")));
+        assert_eq!(player.position(), gst::ClockTime::ZERO);
+        assert_eq!(player.imp().queue.borrow().len(), 1);
+
+        player.set_loops(false);
+        player.handle_end_of_stream();
+        assert!(player.is_active_song(&Uid::from("b")));
+    }
+
+    #[gtk::test]
+    fn mpris_loop_status() {
+        gst::init().unwrap();
+
+        let player = Player::new();
+        let ctx = glib::MainContext::default();
+
+        ctx.block_on(LocalPlayerInterface::set_loop_status(
+            &player,
+            LoopStatus::Track,
+        ))
+        .unwrap();
+        assert!(player.loops());
+        assert!(player.imp().loops_shared.load(Ordering::Relaxed));
+        assert_eq!(
+            ctx.block_on(LocalPlayerInterface::loop_status(&player))
+                .unwrap(),
+            LoopStatus::Track
+        );
+
+        ctx.block_on(LocalPlayerInterface::set_loop_status(
+            &player,
+            LoopStatus::None,
+        ))
+        .unwrap();
+        assert!(!player.loops());
+        assert!(!player.imp().loops_shared.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn playback_error_kind() {
+        fn kind(error: glib::Error, http_status: Option<u32>) -> PlaybackErrorKind {
+            let details = http_status.map(|status| {
+                gst::Structure::builder("details")
+                    .field("http-status-code", status)
+                    .build()
+            });
+            PlaybackError::from_gst_error(error, details.as_deref()).kind()
+        }
+
+        assert_eq!(
+            kind(
+                glib::Error::new(PlayerError::BufferingTimedOut, "Timed out while buffering"),
+                None
+            ),
+            PlaybackErrorKind::TimedOut
+        );
+        assert_eq!(
+            kind(
+                glib::Error::new(
+                    gst::ResourceError::NotFound,
+                    "Could not resolve server name."
+                ),
+                None
+            ),
+            PlaybackErrorKind::Network
+        );
+        assert_eq!(
+            kind(
+                glib::Error::new(
+                    gst::ResourceError::OpenRead,
+                    "Could not establish connection to server."
+                ),
+                None
+            ),
+            PlaybackErrorKind::Network
+        );
+        assert_eq!(
+            kind(
+                glib::Error::new(gst::ResourceError::NotFound, "Not Found"),
+                Some(404)
+            ),
+            PlaybackErrorKind::Http(404)
+        );
+        assert_eq!(
+            kind(
+                glib::Error::new(gst::ResourceError::NotAuthorized, "Forbidden"),
+                Some(403)
+            ),
+            PlaybackErrorKind::Http(403)
+        );
+        assert_eq!(
+            kind(
+                glib::Error::new(gst::ResourceError::OpenRead, "Internal Server Error"),
+                Some(500)
+            ),
+            PlaybackErrorKind::Http(500)
+        );
+        assert_eq!(
+            kind(
+                glib::Error::new(gst::ResourceError::OpenRead, "Moved"),
+                Some(301)
+            ),
+            PlaybackErrorKind::Network
+        );
+        assert_eq!(
+            kind(
+                glib::Error::new(
+                    gst::CoreError::MissingPlugin,
+                    "Your GStreamer installation is missing a plug-in."
+                ),
+                None
+            ),
+            PlaybackErrorKind::MissingCodec
+        );
+        assert_eq!(
+            kind(
+                glib::Error::new(
+                    gst::StreamError::CodecNotFound,
+                    "No decoder available for type 'audio/mpeg'."
+                ),
+                None
+            ),
+            PlaybackErrorKind::MissingCodec
+        );
+        assert_eq!(
+            kind(
+                glib::Error::new(
+                    gst::StreamError::TypeNotFound,
+                    "Could not determine type of stream."
+                ),
+                None
+            ),
+            PlaybackErrorKind::UnsupportedFormat
+        );
+        assert_eq!(
+            kind(
+                glib::Error::new(gst::StreamError::Decode, "Failed to decode"),
+                None
+            ),
+            PlaybackErrorKind::UnsupportedFormat
+        );
+        assert_eq!(
+            kind(glib::Error::new(gst::LibraryError::Failed, "Failed"), None),
+            PlaybackErrorKind::Other
+        );
+    }
+
+    #[gtk::test]
+    fn buffering_timeout() {
+        gst::init().unwrap();
+
+        let player = Player::new();
+        player.set_song(Some(new_test_song("a", true)));
+
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        player.connect_error(clone!(@strong errors => move |_, error| {
+            errors.borrow_mut().push(error.clone());
+        }));
+
+        // Resolving in time disarms the timeout
+        player.set_state(PlayerState::Buffering);
+        assert!(player.imp().buffering_timeout.borrow().is_some());
+        player.set_state(PlayerState::Playing);
+        assert!(player.imp().buffering_timeout.borrow().is_none());
+
+        // Stuck buffering
+        player.set_state(PlayerState::Buffering);
+        assert!(player.imp().buffering_timeout.borrow().is_some());
+        player.handle_buffering_timed_out();
+        assert_eq!(player.state(), PlayerState::Error);
+        assert!(player.imp().buffering_timeout.borrow().is_none());
+        assert_eq!(errors.borrow().len(), 1);
+        assert_eq!(errors.borrow()[0].kind(), PlaybackErrorKind::TimedOut);
+
+        // Setting another song clears it
+        player.set_song(Some(new_test_song("b", true)));
+        assert_eq!(player.state(), PlayerState::Stopped);
+    }
+
+    #[gtk::test]
+    fn hold() {
+        gst::init().unwrap();
+
+        let player = Player::new();
+
+        // Nothing to resume
+        let hold = player.hold();
+        assert!(!hold.should_resume(&player));
+        drop(hold);
+
+        player.set_song(Some(new_test_song("a", true)));
+        let hold = player.hold();
+        assert!(!hold.should_resume(&player));
+        drop(hold);
+
+        player.set_state(PlayerState::Playing);
+        let hold = player.hold();
+        assert!(hold.should_resume(&player));
+
+        // The user picked another song in the meantime
+        player.set_song(Some(new_test_song("b", true)));
+        assert!(!hold.should_resume(&player));
+    }
+
+    #[test]
+    fn crossfade_plan() {
+        let duration = Duration::from_secs(2);
+
+        assert_eq!(
+            CrossfadePlan::new(Duration::ZERO, Duration::from_secs(30)),
+            None
+        );
+        assert_eq!(CrossfadePlan::new(duration, Duration::from_secs(3)), None);
+
+        let plan = CrossfadePlan::new(duration, Duration::from_secs(30)).unwrap();
+        assert_eq!(
+            plan.start_position(Duration::from_secs(30)),
+            Duration::from_secs(28)
+        );
+        assert!(plan.fits(Duration::from_secs(4)));
+        assert!(!plan.fits(Duration::from_secs(3)));
+
+        let assert_volumes = |elapsed: Duration, expected: (f64, f64)| {
+            let (outgoing, incoming) = plan.volumes_at(elapsed);
+            assert!((outgoing - expected.0).abs() < 1e-6, "{:?}", elapsed);
+            assert!((incoming - expected.1).abs() < 1e-6, "{:?}", elapsed);
+        };
+        assert_volumes(Duration::ZERO, (1.0, 0.0));
+        assert_volumes(Duration::from_secs(1), (0.5_f64.sqrt(), 0.5_f64.sqrt()));
+        assert_volumes(Duration::from_secs(2), (0.0, 1.0));
+        assert_volumes(Duration::from_secs(5), (0.0, 1.0));
+        assert!(!plan.is_done(Duration::from_millis(1999)));
+        assert!(plan.is_done(Duration::from_secs(2)));
+    }
+
+    #[gtk::test]
+    fn crossfade() {
+        gst::init().unwrap();
+
+        let player = Player::new();
+        player.set_crossfade_duration(2);
+        player.set_queue(vec![new_test_song("a", true), new_test_song("b", true)]);
+        player.set_duration(gst::ClockTime::from_seconds(10));
+
+        let crossfade_state = |player: &Player| {
+            player
+                .imp()
+                .crossfade
+                .borrow()
+                .as_ref()
+                .map(|crossfade| crossfade.is_started)
+        };
+
+        // Preloaded shortly before the crossfade starts
+        player.update_crossfade(gst::ClockTime::from_mseconds(5900));
+        assert_eq!(crossfade_state(&player), None);
+        player.update_crossfade(gst::ClockTime::from_seconds(6));
+        assert_eq!(crossfade_state(&player), Some(false));
+        assert!(player.is_active_song(&Uid::from("a")));
+
+        player.update_crossfade(gst::ClockTime::from_seconds(8));
+        assert_eq!(crossfade_state(&player), Some(true));
+        assert!(player.is_active_song(&Uid::from("b")));
+        assert_eq!(player.imp().gst_play.volume(), 0.0);
+
+        let volumes = |player: &Player| {
+            let imp = player.imp();
+            let crossfade = imp.crossfade.borrow();
+            (
+                crossfade.as_ref().unwrap().outgoing.volume(),
+                imp.gst_play.volume(),
+            )
+        };
+        assert_eq!(
+            player.crossfade_tick(Duration::from_millis(500)),
+            glib::ControlFlow::Continue
+        );
+        let (outgoing, incoming) = volumes(&player);
+        assert!(outgoing > incoming);
+        assert_eq!(
+            player.crossfade_tick(Duration::from_millis(1500)),
+            glib::ControlFlow::Continue
+        );
+        let (outgoing, incoming) = volumes(&player);
+        assert!(outgoing < incoming);
+
+        assert_eq!(
+            player.crossfade_tick(Duration::from_secs(2)),
+            glib::ControlFlow::Break
+        );
+        assert_eq!(crossfade_state(&player), None);
+        assert_eq!(player.imp().gst_play.volume(), 1.0);
+    }
+
+    #[gtk::test]
+    fn crossfade_hard_cut() {
+        gst::init().unwrap();
+
+        let player = Player::new();
+        player.set_crossfade_duration(2);
+
+        // Ending song is too short
+        player.set_queue(vec![new_test_song("a", true), new_test_song("b", true)]);
+        player.set_duration(gst::ClockTime::from_seconds(3));
+        player.update_crossfade(gst::ClockTime::from_mseconds(2900));
+        assert!(player.imp().crossfade.borrow().is_none());
+        assert!(player.is_active_song(&Uid::from("a")));
+
+        // Disabled
+        player.set_crossfade_duration(0);
+        player.set_duration(gst::ClockTime::from_seconds(10));
+        player.update_crossfade(gst::ClockTime::from_seconds(9));
+        assert!(player.imp().crossfade.borrow().is_none());
+
+        // Nothing queued
+        player.set_crossfade_duration(2);
+        player.set_song(Some(new_test_song("c", true)));
+        player.set_duration(gst::ClockTime::from_seconds(10));
+        player.update_crossfade(gst::ClockTime::from_seconds(9));
+        assert!(player.imp().crossfade.borrow().is_none());
+
+        // Pausing cuts a running crossfade
+        player.set_queue(vec![new_test_song("d", true), new_test_song("e", true)]);
+        player.set_duration(gst::ClockTime::from_seconds(10));
+        player.update_crossfade(gst::ClockTime::from_seconds(8));
+        assert!(player.imp().crossfade.borrow().is_some());
+        player.pause();
+        assert!(player.imp().crossfade.borrow().is_none());
+        assert_eq!(player.imp().gst_play.volume(), 1.0);
+    }
+
+    #[gtk::test]
+    fn set_queue_none_playable() {
+        gst::init().unwrap();
+
+        let player = Player::new();
+        let n_skipped = player.set_queue(vec![new_test_song("a", false)]);
+        assert_eq!(n_skipped, 1);
+        assert!(player.song().is_none());
+        assert!(!player.play_next());
+    }
+}
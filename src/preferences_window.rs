@@ -4,23 +4,37 @@ use gtk::glib::{self, clone};
 
 use std::cell::OnceCell;
 
-use crate::settings::{PreferredAudioSource, Settings};
-
-impl PreferredAudioSource {
-    fn from_position(index: u32) -> Self {
-        match index {
-            0 => Self::Microphone,
-            1 => Self::DesktopAudio,
-            _ => unreachable!(),
-        }
-    }
+use crate::{
+    recognizer::{AudD, Provider, RecognizeErrorKind},
+    settings::{
+        AlbumArtCacheFormat, ArtistTitleOrder, CopyFormat, PreferredAudioSource,
+        PreferredStreamingService, RecordingFormat, Settings, StartupAction,
+    },
+    utils,
+};
+
+/// Sets up `row` to choose between the `options`, in order, selecting
+/// `current` and calling `on_selected` with the newly selected option.
+fn setup_combo_row<T: Copy + PartialEq + 'static>(
+    row: &adw::ComboRow,
+    options: &[(T, &str)],
+    current: T,
+    on_selected: impl Fn(T) + 'static,
+) {
+    let (values, labels): (Vec<T>, Vec<&str>) = options.iter().copied().unzip();
 
-    fn as_position(self) -> u32 {
-        match self {
-            Self::Microphone => 0,
-            Self::DesktopAudio => 1,
+    row.set_model(Some(&gtk::StringList::new(&labels)));
+    row.set_selected(
+        values
+            .iter()
+            .position(|value| *value == current)
+            .expect("current must be one of the options") as u32,
+    );
+    row.connect_selected_notify(move |row| {
+        if let Some(value) = values.get(row.selected() as usize) {
+            on_selected(*value);
         }
-    }
+    });
 }
 
 mod imp {
@@ -33,10 +47,96 @@ mod imp {
         #[property(get, set, construct_only)]
         pub(super) settings: OnceCell<Settings>,
 
+        #[template_child]
+        pub(super) startup_action_row: TemplateChild<adw::ComboRow>,
         #[template_child]
         pub(super) preferred_audio_source_row: TemplateChild<adw::ComboRow>,
         #[template_child]
+        pub(super) recording_format_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub(super) recording_gain_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub(super) fallback_to_desktop_audio_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) pause_playback_while_recording_desktop_audio_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) align_to_music_start_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) sound_trigger_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) ring_recording_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) ring_recording_duration_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub(super) recognize_cooldown_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub(super) choose_from_multiple_matches_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) clean_up_results_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) save_recorded_samples_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) tag_recorded_samples_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) save_no_match_placeholders_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) merge_recognized_songs_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) reveal_new_songs_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) clear_search_for_new_songs_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) distinguish_explicit_songs_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) auto_download_album_art_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) prefer_cover_art_archive_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) keep_original_album_art_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) album_art_cache_max_size_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub(super) album_art_cache_format_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub(super) album_art_cache_quality_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub(super) album_art_cache_max_total_size_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub(super) normalize_loudness_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) crossfade_duration_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub(super) loop_previews_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) history_retention_days_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub(super) compact_database_after_removal_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) max_history_size_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub(super) search_lyrics_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) copy_format_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub(super) artist_title_order_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub(super) preferred_streaming_service_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub(super) listen_cue_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) completion_cue_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) large_tiles_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) narrow_breakpoint_row: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub(super) narrow_on_small_screens_only_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub(super) album_art_accent_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
         pub(super) aud_d_api_token_row: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub(super) aud_d_api_token_test_button: TemplateChild<gtk::Button>,
     }
 
     #[glib::object_subclass]
@@ -63,7 +163,11 @@ mod imp {
 
             if tracing::enabled!(tracing::Level::TRACE) {
                 obj.settings().connect_changed(None, |settings, key| {
-                    tracing::trace!("Settings changed: {} = {:?}", key, settings.value(key));
+                    if key == "aud-d-api-token" {
+                        tracing::trace!("Settings changed: {} = <redacted>", key);
+                    } else {
+                        tracing::trace!("Settings changed: {} = {:?}", key, settings.value(key));
+                    }
                 });
             }
 
@@ -93,32 +197,310 @@ impl PreferencesWindow {
         self.imp().aud_d_api_token_row.grab_focus()
     }
 
+    async fn test_aud_d_api_token(&self) {
+        let imp = self.imp();
+
+        let api_token = imp.aud_d_api_token_row.text();
+
+        imp.aud_d_api_token_test_button.set_sensitive(false);
+        let res = AudD::new(Some(&api_token)).validate_credentials().await;
+        imp.aud_d_api_token_test_button.set_sensitive(true);
+
+        let message = match res {
+            Ok(()) if api_token.is_empty() => {
+                gettext("No API token set, only a few trials per day are available")
+            }
+            Ok(()) => gettext("API token is valid"),
+            Err(err) => {
+                tracing::warn!(kind = ?err.kind(), "Failed to validate API token");
+
+                match err.kind() {
+                    RecognizeErrorKind::InvalidToken => gettext("Invalid API token"),
+                    RecognizeErrorKind::TokenLimitReached => gettext("API token limit reached"),
+                    RecognizeErrorKind::Connection => {
+                        gettext("Failed to check API token: no connection")
+                    }
+                    RecognizeErrorKind::RateLimited => {
+                        gettext("Failed to check API token: too many requests")
+                    }
+                    _ => gettext("Failed to check API token"),
+                }
+            }
+        };
+        self.add_toast(adw::Toast::new(&message));
+    }
+
     fn setup_rows(&self) {
         let imp = self.imp();
 
         let settings = self.settings();
 
-        imp.preferred_audio_source_row
-            .set_model(Some(&gtk::StringList::new(&[
-                &gettext("Microphone"),
-                &gettext("Desktop Audio"),
-            ])));
-        imp.preferred_audio_source_row
-            .set_selected(settings.preferred_audio_source().as_position());
-        imp.preferred_audio_source_row.connect_selected_notify(
-            clone!(@weak self as obj => move |provider_row| {
-                obj.settings()
-                    .set_preferred_audio_source(PreferredAudioSource::from_position(
-                        provider_row.selected(),
-                    ));
+        setup_combo_row(
+            &imp.startup_action_row,
+            &[
+                (StartupAction::ShowHistory, &gettext("Show History")),
+                (
+                    StartupAction::RestoreLastView,
+                    &gettext("Restore Last View"),
+                ),
+                (
+                    StartupAction::StartRecognizing,
+                    &gettext("Start Recognizing"),
+                ),
+            ],
+            settings.startup_action(),
+            clone!(@weak self as obj => move |action| {
+                obj.settings().set_startup_action(action);
             }),
         );
 
+        setup_combo_row(
+            &imp.preferred_audio_source_row,
+            &[
+                (PreferredAudioSource::Microphone, &gettext("Microphone")),
+                (
+                    PreferredAudioSource::DesktopAudio,
+                    &gettext("Desktop Audio"),
+                ),
+            ],
+            settings.preferred_audio_source(),
+            clone!(@weak self as obj => move |source| {
+                obj.settings().set_preferred_audio_source(source);
+            }),
+        );
+
+        setup_combo_row(
+            &imp.recording_format_row,
+            &[
+                (RecordingFormat::OggOpus, &gettext("Ogg Opus")),
+                (RecordingFormat::Wav, &gettext("WAV")),
+            ],
+            settings.recording_format(),
+            clone!(@weak self as obj => move |format| {
+                obj.settings().set_recording_format(format);
+            }),
+        );
+
+        settings
+            .bind_recording_gain(&imp.recording_gain_row.get(), "value")
+            .build();
+        settings
+            .bind_fallback_to_desktop_audio_when_busy(
+                &imp.fallback_to_desktop_audio_row.get(),
+                "active",
+            )
+            .build();
+        settings
+            .bind_pause_playback_while_recording_desktop_audio(
+                &imp.pause_playback_while_recording_desktop_audio_row.get(),
+                "active",
+            )
+            .build();
+        settings
+            .bind_align_to_music_start(&imp.align_to_music_start_row.get(), "active")
+            .build();
+        settings
+            .bind_sound_trigger(&imp.sound_trigger_row.get(), "active")
+            .build();
+        settings
+            .bind_ring_recording(&imp.ring_recording_row.get(), "active")
+            .build();
+        settings
+            .bind_ring_recording_duration(&imp.ring_recording_duration_row.get(), "value")
+            .build();
+        settings
+            .bind_ring_recording(&imp.ring_recording_duration_row.get(), "sensitive")
+            .get()
+            .build();
+        settings
+            .bind_recognize_cooldown(&imp.recognize_cooldown_row.get(), "value")
+            .build();
+        settings
+            .bind_choose_from_multiple_matches(
+                &imp.choose_from_multiple_matches_row.get(),
+                "active",
+            )
+            .build();
+        settings
+            .bind_clean_up_results(&imp.clean_up_results_row.get(), "active")
+            .build();
+        settings
+            .bind_save_recorded_samples(&imp.save_recorded_samples_row.get(), "active")
+            .build();
+        settings
+            .bind_tag_recorded_samples(&imp.tag_recorded_samples_row.get(), "active")
+            .build();
+        settings
+            .bind_save_recorded_samples(&imp.tag_recorded_samples_row.get(), "sensitive")
+            .get()
+            .build();
+        settings
+            .bind_save_no_match_placeholders(&imp.save_no_match_placeholders_row.get(), "active")
+            .build();
+        settings
+            .bind_merge_recognized_songs(&imp.merge_recognized_songs_row.get(), "active")
+            .build();
+        settings
+            .bind_reveal_new_songs(&imp.reveal_new_songs_row.get(), "active")
+            .build();
+        settings
+            .bind_clear_search_for_new_songs(&imp.clear_search_for_new_songs_row.get(), "active")
+            .build();
+        settings
+            .bind_reveal_new_songs(&imp.clear_search_for_new_songs_row.get(), "sensitive")
+            .get()
+            .build();
+        settings
+            .bind_distinguish_explicit_songs(&imp.distinguish_explicit_songs_row.get(), "active")
+            .build();
+        settings
+            .bind_auto_download_album_art(&imp.auto_download_album_art_row.get(), "active")
+            .build();
+        settings
+            .bind_prefer_cover_art_archive(&imp.prefer_cover_art_archive_row.get(), "active")
+            .build();
+        settings
+            .bind_keep_original_album_art(&imp.keep_original_album_art_row.get(), "active")
+            .build();
+        settings
+            .bind_album_art_cache_max_size(&imp.album_art_cache_max_size_row.get(), "value")
+            .build();
+        settings
+            .bind_keep_original_album_art(&imp.album_art_cache_max_size_row.get(), "sensitive")
+            .get()
+            .invert_boolean()
+            .build();
+
+        setup_combo_row(
+            &imp.album_art_cache_format_row,
+            &[
+                (AlbumArtCacheFormat::Png, &gettext("PNG")),
+                (AlbumArtCacheFormat::Jpeg, &gettext("JPEG")),
+                (AlbumArtCacheFormat::Webp, &gettext("WebP")),
+                (AlbumArtCacheFormat::Avif, &gettext("AVIF")),
+            ],
+            settings.album_art_cache_format(),
+            clone!(@weak self as obj => move |format| {
+                obj.settings().set_album_art_cache_format(format);
+            }),
+        );
+        settings
+            .bind_keep_original_album_art(&imp.album_art_cache_format_row.get(), "sensitive")
+            .get()
+            .invert_boolean()
+            .build();
+
+        settings
+            .bind_album_art_cache_quality(&imp.album_art_cache_quality_row.get(), "value")
+            .build();
+        settings
+            .bind_keep_original_album_art(&imp.album_art_cache_quality_row.get(), "sensitive")
+            .get()
+            .invert_boolean()
+            .build();
+        settings
+            .bind_album_art_cache_max_total_size(
+                &imp.album_art_cache_max_total_size_row.get(),
+                "value",
+            )
+            .build();
+        settings
+            .bind_normalize_loudness(&imp.normalize_loudness_row.get(), "active")
+            .build();
+        settings
+            .bind_crossfade_duration(&imp.crossfade_duration_row.get(), "value")
+            .build();
+        settings
+            .bind_loop_previews(&imp.loop_previews_row.get(), "active")
+            .build();
+        settings
+            .bind_history_retention_days(&imp.history_retention_days_row.get(), "value")
+            .build();
+        settings
+            .bind_compact_database_after_removal(
+                &imp.compact_database_after_removal_row.get(),
+                "active",
+            )
+            .build();
+        settings
+            .bind_max_history_size(&imp.max_history_size_row.get(), "value")
+            .build();
+        settings
+            .bind_search_lyrics(&imp.search_lyrics_row.get(), "active")
+            .build();
+
+        setup_combo_row(
+            &imp.copy_format_row,
+            &[
+                (CopyFormat::Plain, &gettext("Plain Text")),
+                (CopyFormat::Markdown, &gettext("Markdown Link")),
+                (CopyFormat::Json, &gettext("JSON")),
+            ],
+            settings.copy_format(),
+            clone!(@weak self as obj => move |format| {
+                obj.settings().set_copy_format(format);
+            }),
+        );
+
+        setup_combo_row(
+            &imp.artist_title_order_row,
+            &[
+                (ArtistTitleOrder::Locale, &gettext("Language Default")),
+                (ArtistTitleOrder::ArtistFirst, &gettext("Artist - Title")),
+                (ArtistTitleOrder::TitleFirst, &gettext("Title - Artist")),
+            ],
+            settings.artist_title_order(),
+            clone!(@weak self as obj => move |order| {
+                obj.settings().set_artist_title_order(order);
+            }),
+        );
+
+        setup_combo_row(
+            &imp.preferred_streaming_service_row,
+            &[
+                (PreferredStreamingService::Spotify, "Spotify"),
+                (PreferredStreamingService::AppleMusic, "Apple Music"),
+                (PreferredStreamingService::Youtube, "YouTube"),
+            ],
+            settings.preferred_streaming_service(),
+            clone!(@weak self as obj => move |service| {
+                obj.settings().set_preferred_streaming_service(service);
+            }),
+        );
+
+        settings
+            .bind_listen_cue(&imp.listen_cue_row.get(), "active")
+            .build();
+        settings
+            .bind_completion_cue(&imp.completion_cue_row.get(), "active")
+            .build();
+        settings
+            .bind_large_tiles(&imp.large_tiles_row.get(), "active")
+            .build();
+        settings
+            .bind_narrow_breakpoint(&imp.narrow_breakpoint_row.get(), "value")
+            .build();
+        settings
+            .bind_narrow_on_small_screens_only(
+                &imp.narrow_on_small_screens_only_row.get(),
+                "active",
+            )
+            .build();
+        settings
+            .bind_album_art_accent(&imp.album_art_accent_row.get(), "active")
+            .build();
+
         imp.aud_d_api_token_row
             .set_text(&settings.aud_d_api_token());
         imp.aud_d_api_token_row
             .connect_apply(clone!(@weak self as obj => move |row| {
                 obj.settings().set_aud_d_api_token(&row.text());
             }));
+        imp.aud_d_api_token_test_button
+            .connect_clicked(clone!(@weak self as obj => move |_| {
+                utils::spawn(glib::Priority::default(), async move {
+                    obj.test_aud_d_api_token().await;
+                });
+            }));
     }
 }
@@ -0,0 +1,349 @@
+use gtk::glib;
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    error, fmt,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// How long a request may be queued before failing instead
+pub const DEFAULT_MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// How long to back off when the server rate limits us without telling for
+/// how long
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Returned when a request would have to wait longer than allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited {
+    retry_in: Duration,
+}
+
+impl RateLimited {
+    /// How long until a request is allowed again
+    pub fn retry_in(&self) -> Duration {
+        self.retry_in
+    }
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Rate limited, try again in {} seconds",
+            self.retry_in.as_secs_f32().ceil()
+        )
+    }
+}
+
+impl error::Error for RateLimited {}
+
+#[derive(Debug)]
+struct State {
+    /// Negative if there are queued requests
+    tokens: f64,
+    last_refill: Instant,
+    /// Set from the server's `Retry-After`
+    blocked_until: Option<Instant>,
+}
+
+/// Token bucket that allows `capacity` requests per `interval`.
+///
+/// Requests beyond that are queued until a token is available, unless they
+/// would have to wait longer than the max wait, in which case they fail
+/// right away.
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: Cell<u32>,
+    interval: Cell<Duration>,
+    max_wait: Duration,
+    state: RefCell<State>,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, interval: Duration, max_wait: Duration) -> Self {
+        Self {
+            capacity: Cell::new(capacity),
+            interval: Cell::new(interval),
+            max_wait,
+            state: RefCell::new(State {
+                tokens: capacity as f64,
+                last_refill: Instant::now(),
+                blocked_until: None,
+            }),
+        }
+    }
+
+    /// Sets the number of requests allowed per interval. This applies to the
+    /// next requests.
+    pub fn set_limit(&self, capacity: u32, interval: Duration) {
+        self.refill(Instant::now());
+
+        self.capacity.set(capacity);
+        self.interval.set(interval);
+
+        let mut state = self.state.borrow_mut();
+        state.tokens = state.tokens.min(capacity as f64);
+    }
+
+    /// Waits until the request is allowed, or fails if it would have to wait
+    /// longer than the max wait.
+    pub async fn acquire(&self) -> Result<(), RateLimited> {
+        let delay = self.reserve(Instant::now())?;
+
+        if !delay.is_zero() {
+            tracing::debug!("Rate limited, waiting for {:?}", delay);
+            glib::timeout_future(delay).await;
+        }
+
+        Ok(())
+    }
+
+    /// Blocks all requests for the given duration, e.g., when the server
+    /// responded with `Retry-After`.
+    pub fn block_for(&self, duration: Duration) {
+        self.block_until(Instant::now() + duration);
+    }
+
+    fn block_until(&self, instant: Instant) {
+        let mut state = self.state.borrow_mut();
+
+        if state
+            .blocked_until
+            .map_or(true, |blocked_until| blocked_until < instant)
+        {
+            state.blocked_until = Some(instant);
+        }
+    }
+
+    /// Takes a token, returning how long to wait before the request can be
+    /// sent.
+    fn reserve(&self, now: Instant) -> Result<Duration, RateLimited> {
+        self.refill(now);
+
+        let mut state = self.state.borrow_mut();
+
+        let token_delay = if state.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - state.tokens) / self.refill_rate())
+        };
+        let blocked_delay = state.blocked_until.map_or(Duration::ZERO, |blocked_until| {
+            blocked_until.saturating_duration_since(now)
+        });
+        let delay = token_delay.max(blocked_delay);
+
+        if delay > self.max_wait {
+            return Err(RateLimited { retry_in: delay });
+        }
+
+        state.tokens -= 1.0;
+
+        Ok(delay)
+    }
+
+    fn refill(&self, now: Instant) {
+        let mut state = self.state.borrow_mut();
+
+        let elapsed = now.saturating_duration_since(state.last_refill);
+        state.tokens = (state.tokens + elapsed.as_secs_f64() * self.refill_rate())
+            .min(self.capacity.get() as f64);
+        state.last_refill = state.last_refill.max(now);
+    }
+
+    /// Tokens added per second
+    fn refill_rate(&self) -> f64 {
+        self.capacity.get() as f64 / self.interval.get().as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Rate limiters shared by everything that talks to a provider, keyed by the
+/// provider's name.
+#[derive(Debug)]
+pub struct RateLimiters {
+    capacity: Cell<u32>,
+    interval: Cell<Duration>,
+    limiters: RefCell<HashMap<&'static str, Rc<RateLimiter>>>,
+}
+
+impl RateLimiters {
+    pub fn new(capacity: u32, interval: Duration) -> Self {
+        Self {
+            capacity: Cell::new(capacity),
+            interval: Cell::new(interval),
+            limiters: RefCell::default(),
+        }
+    }
+
+    /// Returns the limiter of the provider, creating it if needed.
+    pub fn get(&self, provider_name: &'static str) -> Rc<RateLimiter> {
+        Rc::clone(
+            self.limiters
+                .borrow_mut()
+                .entry(provider_name)
+                .or_insert_with(|| {
+                    Rc::new(RateLimiter::new(
+                        self.capacity.get(),
+                        self.interval.get(),
+                        DEFAULT_MAX_WAIT,
+                    ))
+                }),
+        )
+    }
+
+    /// Sets the limit of all limiters, including ones created afterwards.
+    pub fn set_limit(&self, capacity: u32, interval: Duration) {
+        self.capacity.set(capacity);
+        self.interval.set(interval);
+
+        for limiter in self.limiters.borrow().values() {
+            limiter.set_limit(capacity, interval);
+        }
+    }
+}
+
+/// Parses the value of a `Retry-After` header, which is either a number of
+/// seconds or an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let now_unix = glib::DateTime::now_utc().ok()?.to_unix();
+    parse_retry_after_at(value, now_unix)
+}
+
+fn parse_retry_after_at(value: &str, now_unix: i64) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date_time = soup::date_time_new_from_http_string(value)?;
+    let secs = date_time.to_unix().saturating_sub(now_unix).max(0);
+    Some(Duration::from_secs(secs as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn queue_and_reject() {
+        let limiter = RateLimiter::new(2, SECOND, SECOND);
+        let start = limiter.state.borrow().last_refill;
+
+        // Within the bucket
+        assert_eq!(limiter.reserve(start), Ok(Duration::ZERO));
+        assert_eq!(limiter.reserve(start), Ok(Duration::ZERO));
+
+        // Queued until tokens are refilled
+        assert_eq!(limiter.reserve(start), Ok(SECOND / 2));
+        assert_eq!(limiter.reserve(start), Ok(SECOND));
+
+        // Would wait longer than the max wait
+        let err = limiter.reserve(start).unwrap_err();
+        assert_eq!(err.retry_in(), SECOND * 3 / 2);
+
+        // Rejected requests don't take a token
+        assert_eq!(limiter.reserve(start + SECOND), Ok(SECOND / 2));
+
+        // Refilled, but never beyond the capacity
+        let later = start + SECOND * 60;
+        assert_eq!(limiter.reserve(later), Ok(Duration::ZERO));
+        assert_eq!(limiter.reserve(later), Ok(Duration::ZERO));
+        assert_eq!(limiter.reserve(later), Ok(SECOND / 2));
+    }
+
+    #[test]
+    fn fail_fast() {
+        let limiter = RateLimiter::new(1, SECOND * 10, Duration::ZERO);
+        let start = limiter.state.borrow().last_refill;
+
+        assert_eq!(limiter.reserve(start), Ok(Duration::ZERO));
+        assert_eq!(limiter.reserve(start).unwrap_err().retry_in(), SECOND * 10);
+        assert_eq!(limiter.reserve(start + SECOND * 10), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn blocked() {
+        let limiter = RateLimiter::new(10, SECOND, SECOND * 5);
+        let start = limiter.state.borrow().last_refill;
+
+        limiter.block_until(start + SECOND * 3);
+        // Shorter blocks don't override longer ones
+        limiter.block_until(start + SECOND);
+
+        assert_eq!(limiter.reserve(start), Ok(SECOND * 3));
+        assert_eq!(limiter.reserve(start + SECOND), Ok(SECOND * 2));
+        assert_eq!(limiter.reserve(start + SECOND * 3), Ok(Duration::ZERO));
+
+        limiter.block_until(start + SECOND * 10);
+        assert_eq!(
+            limiter.reserve(start + SECOND * 3).unwrap_err().retry_in(),
+            SECOND * 7
+        );
+    }
+
+    #[test]
+    fn set_limit() {
+        let limiter = RateLimiter::new(10, SECOND, SECOND);
+        let start = limiter.state.borrow().last_refill;
+
+        limiter.set_limit(1, SECOND);
+        assert_eq!(limiter.reserve(start), Ok(Duration::ZERO));
+        assert_eq!(limiter.reserve(start), Ok(SECOND));
+    }
+
+    #[test]
+    fn shared() {
+        let limiters = RateLimiters::new(1, SECOND * 60);
+        assert!(Rc::ptr_eq(&limiters.get("AudD"), &limiters.get("AudD")));
+        assert!(!Rc::ptr_eq(&limiters.get("AudD"), &limiters.get("Other")));
+
+        let limiter = limiters.get("AudD");
+        let start = limiter.state.borrow().last_refill;
+        assert_eq!(limiter.reserve(start), Ok(Duration::ZERO));
+        assert!(limiters.get("AudD").reserve(start).is_err());
+        assert_eq!(limiters.get("Other").reserve(start), Ok(Duration::ZERO));
+    }
+
+    #[gtk::test]
+    fn acquire() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(50), Duration::from_millis(100));
+
+        let now = Instant::now();
+        glib::MainContext::default().block_on(async {
+            limiter.acquire().await.unwrap();
+            limiter.acquire().await.unwrap();
+        });
+        assert!(now.elapsed() >= Duration::from_millis(40));
+
+        limiter.block_for(SECOND);
+        let err = glib::MainContext::default()
+            .block_on(limiter.acquire())
+            .unwrap_err();
+        assert!(err.retry_in() > Duration::from_millis(100));
+    }
+
+    #[test]
+    fn retry_after() {
+        assert_eq!(parse_retry_after_at("120", 0), Some(SECOND * 120));
+        assert_eq!(parse_retry_after_at(" 0 ", 0), Some(Duration::ZERO));
+
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        let date_unix = 784_111_777;
+        assert_eq!(
+            parse_retry_after_at("Sun, 06 Nov 1994 08:49:37 GMT", date_unix - 30),
+            Some(SECOND * 30)
+        );
+        assert_eq!(
+            parse_retry_after_at("Sun, 06 Nov 1994 08:49:37 GMT", date_unix + 30),
+            Some(Duration::ZERO)
+        );
+
+        assert_eq!(parse_retry_after_at("soon", 0), None);
+        assert_eq!(parse_retry_after_at("-1", 0), None);
+    }
+}
@@ -0,0 +1,270 @@
+use gtk::{gio, glib};
+
+use std::{error, fmt};
+
+use super::{
+    recorder::RecordErrorKind, DecodeUriError, RecognizeError, RecognizeErrorKind, RecordError,
+};
+use crate::{cancelled::Cancelled, rate_limiter::RateLimited};
+
+/// Why recognizing failed, so callers can tailor how it is presented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecognitionFailureKind {
+    /// The provider found no song in the audio
+    NoMatch,
+    /// The provider or the network could not be reached
+    Network,
+    /// A request took too long
+    Timeout,
+    /// Too many requests were sent to the provider
+    RateLimited,
+    /// The provider did not accept the configured credentials
+    ProviderAuth,
+    /// Access to the microphone was denied, e.g., in the privacy settings
+    PermissionDenied,
+    /// The audio could not be recorded or used
+    Audio,
+    Cancelled,
+    Other,
+}
+
+/// Failure returned by the [`Recognizer`](super::Recognizer), which keeps the
+/// underlying error for reports.
+#[derive(Debug)]
+pub struct RecognitionFailure {
+    kind: RecognitionFailureKind,
+    inner: anyhow::Error,
+}
+
+impl RecognitionFailure {
+    pub fn kind(&self) -> RecognitionFailureKind {
+        self.kind
+    }
+
+    /// The error that this was mapped from
+    pub fn inner(&self) -> &anyhow::Error {
+        &self.inner
+    }
+
+    /// Whether trying again later may succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.kind,
+            RecognitionFailureKind::Network
+                | RecognitionFailureKind::Timeout
+                | RecognitionFailureKind::RateLimited
+        )
+    }
+}
+
+impl From<anyhow::Error> for RecognitionFailure {
+    fn from(inner: anyhow::Error) -> Self {
+        Self {
+            kind: kind_of(&inner),
+            inner,
+        }
+    }
+}
+
+impl fmt::Display for RecognitionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.inner)
+    }
+}
+
+impl error::Error for RecognitionFailure {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(self.inner.as_ref())
+    }
+}
+
+/// Returns the kind of the first error in the chain that is known.
+fn kind_of(err: &anyhow::Error) -> RecognitionFailureKind {
+    err.chain()
+        .find_map(|cause| {
+            if cause.is::<Cancelled>() {
+                Some(RecognitionFailureKind::Cancelled)
+            } else if let Some(recognize_error) = cause.downcast_ref::<RecognizeError>() {
+                Some(recognize_error_kind(recognize_error.kind()))
+            } else if let Some(record_error) = cause.downcast_ref::<RecordError>() {
+                Some(match record_error.kind() {
+                    RecordErrorKind::PermissionDenied => RecognitionFailureKind::PermissionDenied,
+                    RecordErrorKind::DeviceBusy | RecordErrorKind::Other => {
+                        RecognitionFailureKind::Audio
+                    }
+                })
+            } else if cause.is::<RateLimited>() {
+                Some(RecognitionFailureKind::RateLimited)
+            } else if let Some(decode_error) = cause.downcast_ref::<DecodeUriError>() {
+                Some(match decode_error {
+                    DecodeUriError::TimedOut => RecognitionFailureKind::Timeout,
+                    _ => RecognitionFailureKind::Audio,
+                })
+            } else {
+                cause
+                    .downcast_ref::<glib::Error>()
+                    .and_then(glib_error_kind)
+            }
+        })
+        .unwrap_or(RecognitionFailureKind::Other)
+}
+
+fn recognize_error_kind(kind: RecognizeErrorKind) -> RecognitionFailureKind {
+    match kind {
+        RecognizeErrorKind::NoMatches => RecognitionFailureKind::NoMatch,
+        RecognizeErrorKind::Fingerprint => RecognitionFailureKind::Audio,
+        RecognizeErrorKind::InvalidToken | RecognizeErrorKind::TokenLimitReached => {
+            RecognitionFailureKind::ProviderAuth
+        }
+        RecognizeErrorKind::Connection => RecognitionFailureKind::Network,
+        RecognizeErrorKind::RateLimited => RecognitionFailureKind::RateLimited,
+        RecognizeErrorKind::OtherPermanent => RecognitionFailureKind::Other,
+    }
+}
+
+fn glib_error_kind(err: &glib::Error) -> Option<RecognitionFailureKind> {
+    if err.matches(gio::IOErrorEnum::TimedOut) {
+        Some(RecognitionFailureKind::Timeout)
+    } else if err.matches(gio::IOErrorEnum::Cancelled) {
+        Some(RecognitionFailureKind::Cancelled)
+    } else if err.is::<gio::ResolverError>()
+        || err.matches(gio::IOErrorEnum::HostNotFound)
+        || err.matches(gio::IOErrorEnum::HostUnreachable)
+        || err.matches(gio::IOErrorEnum::NetworkUnreachable)
+        || err.matches(gio::IOErrorEnum::ConnectionRefused)
+        || err.matches(gio::IOErrorEnum::ConnectionClosed)
+    {
+        Some(RecognitionFailureKind::Network)
+    } else if err.is::<gst::ResourceError>()
+        || err.is::<gst::StreamError>()
+        || err.is::<gst::CoreError>()
+    {
+        Some(RecognitionFailureKind::Audio)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Context;
+
+    fn kind(err: impl Into<anyhow::Error>) -> RecognitionFailureKind {
+        RecognitionFailure::from(err.into()).kind()
+    }
+
+    #[test]
+    fn provider_errors() {
+        let new_err = |kind| RecognizeError::new(kind, None);
+
+        assert_eq!(
+            kind(new_err(RecognizeErrorKind::NoMatches)),
+            RecognitionFailureKind::NoMatch
+        );
+        assert_eq!(
+            kind(new_err(RecognizeErrorKind::Connection)),
+            RecognitionFailureKind::Network
+        );
+        assert_eq!(
+            kind(new_err(RecognizeErrorKind::InvalidToken)),
+            RecognitionFailureKind::ProviderAuth
+        );
+        assert_eq!(
+            kind(new_err(RecognizeErrorKind::TokenLimitReached)),
+            RecognitionFailureKind::ProviderAuth
+        );
+        assert_eq!(
+            kind(new_err(RecognizeErrorKind::RateLimited)),
+            RecognitionFailureKind::RateLimited
+        );
+        assert_eq!(
+            kind(new_err(RecognizeErrorKind::Fingerprint)),
+            RecognitionFailureKind::Audio
+        );
+        assert_eq!(
+            kind(new_err(RecognizeErrorKind::OtherPermanent)),
+            RecognitionFailureKind::Other
+        );
+    }
+
+    #[test]
+    fn glib_errors() {
+        assert_eq!(
+            kind(glib::Error::new(gio::IOErrorEnum::TimedOut, "Timed out")),
+            RecognitionFailureKind::Timeout
+        );
+        assert_eq!(
+            kind(glib::Error::new(
+                gio::ResolverError::NotFound,
+                "Could not resolve host"
+            )),
+            RecognitionFailureKind::Network
+        );
+        assert_eq!(
+            kind(glib::Error::new(
+                gio::IOErrorEnum::NetworkUnreachable,
+                "Network unreachable"
+            )),
+            RecognitionFailureKind::Network
+        );
+        assert_eq!(
+            kind(glib::Error::new(gst::ResourceError::Busy, "Device busy")),
+            RecognitionFailureKind::Audio
+        );
+        assert_eq!(
+            kind(glib::Error::new(gio::IOErrorEnum::NotFound, "Not found")),
+            RecognitionFailureKind::Other
+        );
+    }
+
+    #[test]
+    fn wrapped() {
+        let err = Err::<(), _>(RecognizeError::new(RecognizeErrorKind::NoMatches, None))
+            .context("Failed to recognize")
+            .unwrap_err();
+        let err = RecognitionFailure::from(err);
+        assert_eq!(err.kind(), RecognitionFailureKind::NoMatch);
+        assert!(err.inner().is::<RecognizeError>());
+        assert_eq!(err.to_string(), "Failed to recognize");
+
+        assert_eq!(
+            kind(RecordError::from_gst_error(glib::Error::new(
+                gst::ResourceError::NotAuthorized,
+                "Access denied"
+            ))),
+            RecognitionFailureKind::Audio
+        );
+        assert_eq!(
+            kind(RecordError::from_gst_error(glib::Error::new(
+                gst::ResourceError::Busy,
+                "Device busy"
+            ))),
+            RecognitionFailureKind::Audio
+        );
+
+        let err = Err::<(), _>(RecordError::mic_access_denied())
+            .context("Failed to start recording")
+            .unwrap_err();
+        let err = RecognitionFailure::from(err);
+        assert_eq!(err.kind(), RecognitionFailureKind::PermissionDenied);
+        assert!(!err.is_retryable());
+        assert_eq!(
+            kind(Cancelled::new("recognizing")),
+            RecognitionFailureKind::Cancelled
+        );
+        assert_eq!(
+            kind(anyhow::anyhow!("Other")),
+            RecognitionFailureKind::Other
+        );
+        assert!(!RecognitionFailure::from(anyhow::anyhow!("Other")).is_retryable());
+        assert!(
+            RecognitionFailure::from(anyhow::Error::new(RecognizeError::new(
+                RecognizeErrorKind::Connection,
+                None
+            )))
+            .is_retryable()
+        );
+    }
+}
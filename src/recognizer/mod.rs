@@ -1,9 +1,13 @@
+mod error;
 mod provider;
 mod recorder;
 mod recording;
 mod recordings;
+mod replay;
+mod uri_decoder;
 
 use anyhow::{ensure, Context, Result};
+use gettextrs::gettext;
 use gst::prelude::*;
 use gtk::{
     gio::{self, prelude::*},
@@ -12,30 +16,138 @@ use gtk::{
 
 use std::{
     cell::{Cell, OnceCell, RefCell},
+    fmt,
     rc::Rc,
+    time::{Duration, Instant},
 };
 
+use tracing::Instrument;
+
 pub use self::{
+    error::{RecognitionFailure, RecognitionFailureKind},
     provider::{
-        ProviderSettings, ProviderType, RecognizeError, RecognizeErrorKind, TestProviderMode,
+        AudD, Candidates, Capabilities, Provider, ProviderConcurrency, ProviderSettings,
+        ProviderType, RecognizeError, RecognizeErrorKind, RecognizeMode, TestProviderMode,
     },
+    recorder::{add_capture, level_peak, play_capture, RecordError},
     recordings::Recordings,
+    uri_decoder::DecodeUriError,
 };
 use self::{
-    recorder::Recorder,
+    recorder::{
+        ClippingDetector, MusicStartDetector, PeakSmoother, RecordErrorKind, RecordOptions,
+        Recorder,
+    },
     recording::{BoxedRecognizeResult, Recording},
+    replay::RecordingReplayer,
 };
 use crate::{
     audio_device::{self, AudioDeviceClass},
     cancelled::Cancelled,
     date_time::DateTime,
-    settings::PreferredAudioSource,
-    song::Song,
+    mic_permission::{self, MicAccess},
+    settings::{PreferredAudioSource, RecordingFormat},
+    song::{RecognitionSource, Song},
+    sound_cue::{PlaySoundCue, SoundCue, SoundCuePlayer},
     utils, Application,
 };
 
 const MAX_SAVED_RECORDING_RECOGNIZE_RETRIES: u8 = 3;
 
+/// Humming is usually slower and less precise than the actual song, so
+/// listen for longer to capture enough of the melody.
+const HUM_LISTEN_DURATION: Duration = Duration::from_secs(12);
+
+/// Returns how much longer a recording that has been going on for `elapsed`
+/// must continue to reach `minimum`.
+fn remaining_until_minimum(elapsed: Duration, minimum: Duration) -> Duration {
+    minimum.saturating_sub(elapsed)
+}
+
+/// Whether a recognition started at `now` is still within the `cooldown`
+/// of the last one that completed at `last_completed`.
+fn is_in_cooldown(last_completed: Option<Instant>, cooldown: Duration, now: Instant) -> bool {
+    last_completed
+        .is_some_and(|last_completed| now.saturating_duration_since(last_completed) < cooldown)
+}
+
+/// Context attached to errors returned by [`Recognizer::toggle_recognize`]
+/// after the provider is called.
+#[derive(Debug)]
+pub struct RecognizeContext {
+    pub provider: ProviderType,
+    pub device_name: Option<String>,
+    pub listen_duration: Duration,
+    pub recorded_time: DateTime,
+}
+
+impl fmt::Display for RecognizeContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to recognize with provider `{:?}`", self.provider)
+    }
+}
+
+/// How a recognition that did not fail ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::AsRefStr)]
+#[strum(serialize_all = "kebab-case")]
+pub enum RecognizeOutcome {
+    /// A song was recognized
+    Recognized,
+    /// The recording was saved to be recognized later
+    Saved,
+}
+
+/// Returns the span where the fields of a single recognition are recorded.
+fn recognize_span() -> tracing::Span {
+    tracing::info_span!(
+        "recognize",
+        mode = tracing::field::Empty,
+        provider = tracing::field::Empty,
+        device = tracing::field::Empty,
+        listen_duration = tracing::field::Empty,
+        n_bytes = tracing::field::Empty,
+        outcome = tracing::field::Empty,
+        error = tracing::field::Empty,
+    )
+}
+
+/// Returns a placeholder song for an error returned by
+/// [`Recognizer::toggle_recognize`] if it is enabled and there were no matches.
+pub fn no_match_placeholder(err: &RecognitionFailure, is_enabled: bool) -> Option<Song> {
+    if !is_enabled || err.kind() != RecognitionFailureKind::NoMatch {
+        return None;
+    }
+
+    let context = err.inner().downcast_ref::<RecognizeContext>()?;
+    Some(Song::new_placeholder(&context.recorded_time))
+}
+
+/// Plays the listen start cue to the end if enabled.
+async fn play_listen_cue(is_enabled: bool, cue_player: &impl PlaySoundCue) {
+    if !is_enabled {
+        return;
+    }
+
+    if let Err(err) = cue_player.play_to_end(SoundCue::ListenStart).await {
+        tracing::warn!("Failed to play listen cue: {:?}", err);
+    }
+}
+
+fn record_outcome(span: &tracing::Span, res: &Result<RecognizeOutcome>) {
+    match res {
+        Ok(outcome) => {
+            span.record("outcome", outcome.as_ref());
+        }
+        Err(err) if err.is::<Cancelled>() => {
+            span.record("outcome", "cancelled");
+        }
+        Err(err) => {
+            span.record("outcome", "failed");
+            span.record("error", tracing::field::display(format!("{:#}", err)));
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
 #[enum_type(name = "MsaiRecognizerState")]
 pub enum RecognizerState {
@@ -58,8 +170,27 @@ mod imp {
         /// Whether offline mode is active
         #[property(get)]
         pub(super) is_offline_mode: Cell<bool>,
+        /// Whether offline mode was turned on by the user, regardless of the
+        /// network connectivity
+        #[property(get, set = Self::set_is_forced_offline, explicit_notify)]
+        pub(super) is_forced_offline: Cell<bool>,
+        /// Mode of the current or last recognition
+        #[property(get, builder(RecognizeMode::default()))]
+        pub(super) mode: Cell<RecognizeMode>,
+        /// Whether the desktop audio is currently being recorded
+        #[property(get)]
+        pub(super) is_recording_desktop_audio: Cell<bool>,
+        /// How long after a recognition completes that new ones are ignored,
+        /// in milliseconds
+        #[property(get, set)]
+        pub(super) cooldown_duration: Cell<u32>,
+
+        /// When the last recognition that was not cancelled completed
+        pub(super) last_completed: Cell<Option<Instant>>,
 
         pub(super) recorder: Recorder,
+        pub(super) sound_cue_player: SoundCuePlayer,
+        pub(super) replayer: RecordingReplayer,
         pub(super) cancellable: RefCell<Option<gio::Cancellable>>,
 
         pub(super) saved_recordings: OnceCell<Recordings>,
@@ -79,8 +210,13 @@ mod imp {
                     Signal::builder("recording-peak-changed")
                         .param_types([f64::static_type()])
                         .build(),
+                    Signal::builder("recording-clipped").build(),
+                    Signal::builder("recognize-ignored").build(),
                     Signal::builder("song-recognized")
-                        .param_types([Song::static_type()])
+                        .param_types([Song::static_type(), glib::Bytes::static_type()])
+                        .build(),
+                    Signal::builder("candidates-recognized")
+                        .param_types([Candidates::static_type(), glib::Bytes::static_type()])
                         .build(),
                     Signal::builder("recording-saved")
                         .param_types([RecognizeError::static_type()])
@@ -91,6 +227,24 @@ mod imp {
             SIGNALS.as_ref()
         }
     }
+
+    impl Recognizer {
+        fn set_is_forced_offline(&self, is_forced_offline: bool) {
+            let obj = self.obj();
+
+            if is_forced_offline == obj.is_forced_offline() {
+                return;
+            }
+
+            self.is_forced_offline.set(is_forced_offline);
+            obj.update_offline_mode();
+            obj.notify_is_forced_offline();
+
+            if self.saved_recordings.get().is_some() {
+                obj.try_recognize_saved_recordings();
+            }
+        }
+    }
 }
 
 glib::wrapper! {
@@ -102,6 +256,48 @@ impl Recognizer {
         glib::Object::new()
     }
 
+    /// Whether there is a last recording to replay
+    pub fn has_last_recording(&self) -> bool {
+        self.imp().recorder.last_recording().is_some()
+    }
+
+    /// Plays back what was captured in the last recognition, e.g., to judge
+    /// why there were no matches.
+    ///
+    /// The recording is only kept in memory, so it is gone on exit, and it is
+    /// only persisted through the sample store if saving samples is enabled.
+    pub fn play_last_recording(&self) -> Result<()> {
+        let imp = self.imp();
+
+        ensure!(
+            self.state() == RecognizerState::Null,
+            "Can't replay while recognizing"
+        );
+
+        let bytes = imp
+            .recorder
+            .last_recording()
+            .context("There is no last recording")?;
+        imp.replayer.play(&bytes)
+    }
+
+    /// Stops the replay and drops the last recording.
+    pub fn clear_last_recording(&self) {
+        let imp = self.imp();
+        imp.replayer.stop();
+        imp.recorder.clear_last_recording();
+    }
+
+    /// Returns what the enabled providers can recognize.
+    pub fn capabilities(&self) -> Capabilities {
+        let providers = ProviderSettings::lock()
+            .by_priority()
+            .into_iter()
+            .map(ProviderType::to_provider)
+            .collect::<Vec<_>>();
+        Capabilities::aggregate(&providers)
+    }
+
     pub fn connect_recording_peak_changed<F>(&self, f: F) -> glib::SignalHandlerId
     where
         F: Fn(&Self, f64) + 'static,
@@ -119,21 +315,82 @@ impl Recognizer {
         self.emit_by_name::<()>("recording-peak-changed", &[&peak]);
     }
 
+    /// Emitted once per recording when the input keeps clipping, e.g., the
+    /// input volume is too high.
+    pub fn connect_recording_clipped<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self) + 'static,
+    {
+        self.connect_closure(
+            "recording-clipped",
+            true,
+            closure_local!(|obj: &Self| {
+                f(obj);
+            }),
+        )
+    }
+
+    fn emit_recording_clipped(&self) {
+        self.emit_by_name::<()>("recording-clipped", &[]);
+    }
+
+    /// Emitted when starting a recognition is ignored because the last one
+    /// completed too recently.
+    pub fn connect_recognize_ignored<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self) + 'static,
+    {
+        self.connect_closure(
+            "recognize-ignored",
+            true,
+            closure_local!(|obj: &Self| {
+                f(obj);
+            }),
+        )
+    }
+
+    fn emit_recognize_ignored(&self) {
+        self.emit_by_name::<()>("recognize-ignored", &[]);
+    }
+
+    /// The recording that the song was recognized from is passed along, so
+    /// it can be saved as the sample of the song that it ends up in.
     pub fn connect_song_recognized<F>(&self, f: F) -> glib::SignalHandlerId
     where
-        F: Fn(&Self, &Song) + 'static,
+        F: Fn(&Self, &Song, &glib::Bytes) + 'static,
     {
         self.connect_closure(
             "song-recognized",
             true,
-            closure_local!(|obj: &Self, song: &Song| {
-                f(obj, song);
+            closure_local!(|obj: &Self, song: &Song, recording_bytes: glib::Bytes| {
+                f(obj, song, &recording_bytes);
             }),
         )
     }
 
-    fn emit_song_recognized(&self, song: &Song) {
-        self.emit_by_name::<()>("song-recognized", &[song]);
+    fn emit_song_recognized(&self, song: &Song, recording_bytes: &glib::Bytes) {
+        self.emit_by_name::<()>("song-recognized", &[song, recording_bytes]);
+    }
+
+    /// Emitted instead of `song-recognized` when the user chooses from multiple
+    /// possible matches.
+    pub fn connect_candidates_recognized<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self, &Candidates, &glib::Bytes) + 'static,
+    {
+        self.connect_closure(
+            "candidates-recognized",
+            true,
+            closure_local!(
+                |obj: &Self, candidates: &Candidates, recording_bytes: glib::Bytes| {
+                    f(obj, candidates, &recording_bytes);
+                }
+            ),
+        )
+    }
+
+    fn emit_candidates_recognized(&self, candidates: &Candidates, recording_bytes: &glib::Bytes) {
+        self.emit_by_name::<()>("candidates-recognized", &[candidates, recording_bytes]);
     }
 
     pub fn connect_recording_saved<F>(&self, f: F) -> glib::SignalHandlerId
@@ -199,7 +456,19 @@ impl Recognizer {
             .peek_filtered(is_recording_ready_to_take)
     }
 
-    pub async fn toggle_recognize(&self) -> Result<()> {
+    /// Starts recognizing in the given `mode` if idle, otherwise cancels the
+    /// ongoing recognition.
+    ///
+    /// Starting is ignored within the cooldown duration after the last
+    /// recognition completed, e.g., when the button is pressed repeatedly,
+    /// unless that recognition was cancelled.
+    ///
+    /// This returns `None` if the recognition was cancelled or ignored, or if
+    /// this call cancelled an ongoing recognition.
+    pub async fn toggle_recognize(
+        &self,
+        mode: RecognizeMode,
+    ) -> Result<Option<RecognizeOutcome>, RecognitionFailure> {
         let imp = self.imp();
 
         match self.state() {
@@ -207,25 +476,121 @@ impl Recognizer {
                 if let Some(cancellable) = imp.cancellable.take() {
                     cancellable.cancel();
                 }
+                Ok(None)
             }
             RecognizerState::Null => {
+                let cooldown = Duration::from_millis(self.cooldown_duration().into());
+                if is_in_cooldown(imp.last_completed.get(), cooldown, Instant::now()) {
+                    tracing::debug!("Last recognition completed too recently, ignoring");
+                    self.emit_recognize_ignored();
+                    return Ok(None);
+                }
+
+                // Unlike when there is no connection, nothing is saved for
+                // later, as the user explicitly asked for no network activity.
+                if self.is_forced_offline() {
+                    return Err(anyhow::anyhow!(gettext(
+                        "Recognizing is unavailable in offline mode"
+                    ))
+                    .into());
+                }
+
                 let cancellable = gio::Cancellable::default();
                 imp.cancellable.replace(Some(cancellable.clone()));
 
-                if let Err(err) = self.recognize(&cancellable).await {
-                    if let Some(cancelled) = err.downcast_ref::<Cancelled>() {
-                        tracing::debug!("{}", cancelled);
-                    } else {
-                        return Err(err);
+                self.set_mode(mode);
+
+                let span = recognize_span();
+                let res = self.recognize(&cancellable).instrument(span.clone()).await;
+                record_outcome(&span, &res);
+
+                // Cancelling is explicit, so retrying right after is allowed
+                match res {
+                    Ok(outcome) => {
+                        imp.last_completed.set(Some(Instant::now()));
+                        Ok(Some(outcome))
+                    }
+                    Err(err) => {
+                        let err = RecognitionFailure::from(err);
+                        if err.kind() == RecognitionFailureKind::Cancelled {
+                            tracing::debug!("{}", err);
+                            imp.last_completed.set(None);
+                            Ok(None)
+                        } else {
+                            imp.last_completed.set(Some(Instant::now()));
+                            Err(err)
+                        }
                     }
                 }
             }
         }
+    }
+
+    /// Recognizes already recorded audio, e.g., the stored sample of a song,
+    /// with the enabled provider of the highest priority that supports music.
+    ///
+    /// Unlike [`Recognizer::toggle_recognize`], this does not change the state
+    /// and nothing is saved for later on failure.
+    pub async fn recognize_sample(&self, bytes: &[u8]) -> Result<Song, RecognitionFailure> {
+        if self.is_forced_offline() {
+            return Err(
+                anyhow::anyhow!(gettext("Recognizing is unavailable in offline mode")).into(),
+            );
+        }
+
+        let providers = ProviderSettings::lock()
+            .by_priority()
+            .into_iter()
+            .map(ProviderType::to_provider)
+            .collect::<Vec<_>>();
+        let provider = provider::select_provider(RecognizeMode::Music, providers)
+            .context("No enabled provider supports recognizing music")?;
+        tracing::debug!(?provider, "Recognizing sample");
+
+        provider
+            .recognize(bytes)
+            .await
+            .map_err(|err| RecognitionFailure::from(anyhow::Error::new(err)))
+    }
+
+    /// Recognizes the audio at `uri`, e.g., a link to a stream, decoding only
+    /// as much as the provider listens to.
+    ///
+    /// Like [`Recognizer::recognize_sample`], this does not change the state.
+    pub async fn recognize_uri(
+        &self,
+        uri: &str,
+        format: RecordingFormat,
+    ) -> Result<Song, RecognitionFailure> {
+        if self.is_forced_offline() {
+            return Err(
+                anyhow::anyhow!(gettext("Recognizing is unavailable in offline mode")).into(),
+            );
+        }
+
+        let providers = ProviderSettings::lock()
+            .by_priority()
+            .into_iter()
+            .map(ProviderType::to_provider)
+            .collect::<Vec<_>>();
+        let provider = provider::select_provider(RecognizeMode::Music, providers)
+            .context("No enabled provider supports recognizing music")?;
+        tracing::debug!(?provider, uri, "Recognizing link");
+
+        let heard_time = DateTime::now_utc();
+        let bytes = uri_decoder::decode(uri, provider.listen_duration(), format).await?;
 
-        Ok(())
+        let song = provider
+            .recognize(&bytes)
+            .await
+            .map_err(|err| RecognitionFailure::from(anyhow::Error::new(err)))?;
+        song.set_last_heard(Some(heard_time));
+        song.set_source(RecognitionSource::Link);
+
+        Ok(song)
     }
 
-    async fn recognize(&self, cancellable: &gio::Cancellable) -> Result<()> {
+    async fn recognize(&self, cancellable: &gio::Cancellable) -> Result<RecognizeOutcome> {
         struct Finally {
             weak: WeakRef<Recognizer>,
         }
@@ -234,7 +599,8 @@ impl Recognizer {
             fn drop(&mut self) {
                 if let Some(instance) = self.weak.upgrade() {
                     instance.set_state(RecognizerState::Null);
-                    let _ = instance.imp().recorder.stop();
+                    instance.imp().recorder.cancel();
+                    instance.set_is_recording_desktop_audio(false);
                 }
             }
         }
@@ -250,44 +616,186 @@ impl Recognizer {
 
         self.set_state(RecognizerState::Listening);
 
+        let app = Application::get();
+        let settings = app.settings();
+
+        let preferred_audio_source = settings.preferred_audio_source();
+
+        // Check access first, so a denial is reported as such instead of as a
+        // failing pipeline
+        if matches!(preferred_audio_source, PreferredAudioSource::Microphone) {
+            let access = gio::CancellableFuture::new(mic_permission::check(), cancellable.clone())
+                .await
+                .map_err(|_| Cancelled::new("recognizing while checking microphone access"))?;
+
+            match access {
+                Ok(MicAccess::Granted) => {}
+                Ok(MicAccess::Denied) => {
+                    return Err(anyhow::Error::new(RecordError::mic_access_denied())
+                        .context("Failed to start recording"));
+                }
+                Err(err) => tracing::warn!("Failed to check microphone access: {:?}", err),
+            }
+        }
+
         let device_name = gio::CancellableFuture::new(
-            audio_device::find_default_name(
-                match Application::get().settings().preferred_audio_source() {
-                    PreferredAudioSource::Microphone => AudioDeviceClass::Source,
-                    PreferredAudioSource::DesktopAudio => AudioDeviceClass::Sink,
-                },
-            ),
+            audio_device::find_default_name(match preferred_audio_source {
+                PreferredAudioSource::Microphone => AudioDeviceClass::Source,
+                PreferredAudioSource::DesktopAudio => AudioDeviceClass::Sink,
+            }),
             cancellable.clone(),
         )
         .await
         .map_err(|_| Cancelled::new("recognizing while finding default audio device name"))?
         .context("Failed to find default device name")?;
 
+        let span = tracing::Span::current();
+        span.record("device", device_name.as_str());
+
+        let mut source = match preferred_audio_source {
+            PreferredAudioSource::Microphone => RecognitionSource::Microphone,
+            PreferredAudioSource::DesktopAudio => RecognitionSource::DesktopAudio,
+        };
+
         let imp = self.imp();
 
-        imp.recorder
-            .start(
-                Some(&device_name),
-                clone!(@weak self as obj => move |peak| {
-                    obj.emit_recording_peak_changed(peak);
-                }),
+        let options = RecordOptions {
+            device_name: Some(device_name.clone()),
+            format: settings.recording_format(),
+            gain_db: settings.recording_gain(),
+            music_start_detector: settings
+                .align_to_music_start()
+                .then(|| MusicStartDetector::new(settings.music_start_threshold())),
+            clipping_detector: ClippingDetector::new(
+                settings.clipping_threshold(),
+                Duration::from_millis(settings.clipping_duration().into()),
+            ),
+            peak_smoother: PeakSmoother::new(
+                Duration::from_millis(settings.peak_meter_attack().into()),
+                Duration::from_millis(settings.peak_meter_decay().into()),
+            ),
+        };
+        let peak_callback = clone!(@weak self as obj => move |peak| {
+            obj.emit_recording_peak_changed(peak);
+        });
+        let clipping_callback = clone!(@weak self as obj => move || {
+            obj.emit_recording_clipped();
+        });
+
+        // Wait for the cue to finish, so it doesn't bleed into the recording
+        play_listen_cue(settings.listen_cue(), &imp.sound_cue_player).await;
+
+        if let Err(err) =
+            imp.recorder
+                .start(&options, peak_callback.clone(), clipping_callback.clone())
+        {
+            let is_device_busy = err
+                .downcast_ref::<RecordError>()
+                .map_or(false, |err| err.kind() == RecordErrorKind::DeviceBusy);
+
+            if !is_device_busy
+                || !matches!(preferred_audio_source, PreferredAudioSource::Microphone)
+                || !settings.fallback_to_desktop_audio_when_busy()
+            {
+                return Err(err.context("Failed to start recording"));
+            }
+
+            tracing::warn!(
+                "Device `{}` is busy; falling back to desktop audio",
+                device_name
+            );
+
+            let fallback_device_name = gio::CancellableFuture::new(
+                audio_device::find_default_name(AudioDeviceClass::Sink),
+                cancellable.clone(),
             )
-            .context("Failed to start recording")?;
+            .await
+            .map_err(|_| Cancelled::new("recognizing while finding fallback audio device name"))?
+            .context("Failed to find fallback device name")?;
+
+            let fallback_options = RecordOptions {
+                device_name: Some(fallback_device_name.clone()),
+                ..options
+            };
+            imp.recorder
+                .start(&fallback_options, peak_callback, clipping_callback)
+                .context("Failed to start recording with fallback device")?;
+            span.record("device", fallback_device_name.as_str());
+            source = RecognitionSource::DesktopAudio;
+        }
+        self.set_is_recording_desktop_audio(source == RecognitionSource::DesktopAudio);
         let recorded_time = DateTime::now_utc();
 
         cancellable.connect_cancelled_local(clone!(@weak _finally => move |_| {
             let _ = _finally.take();
         }));
 
-        let provider = ProviderSettings::lock().active.to_provider();
-        let listen_duration = provider.listen_duration();
-        tracing::debug!(?provider, ?listen_duration);
+        let mode = self.mode();
+        if mode == RecognizeMode::Hum {
+            source = RecognitionSource::Hum;
+        }
+
+        let (provider_types, providers): (Vec<_>, Vec<_>) = ProviderSettings::lock()
+            .by_priority()
+            .into_iter()
+            .map(|provider_type| (provider_type, provider_type.to_provider()))
+            .filter(|(_, provider)| provider.capabilities().supports(mode))
+            .unzip();
+        ensure!(
+            !providers.is_empty(),
+            match mode {
+                RecognizeMode::Music => "No enabled provider supports recognizing music",
+                RecognizeMode::Hum => "No enabled provider supports recognizing humming",
+            }
+        );
+        // Record long enough for every provider that may be called
+        let provider_listen_duration = providers
+            .iter()
+            .map(|provider| provider.listen_duration())
+            .max()
+            .unwrap_or_default();
+        let listen_duration = match mode {
+            RecognizeMode::Music => provider_listen_duration,
+            RecognizeMode::Hum => provider_listen_duration.max(HUM_LISTEN_DURATION),
+        };
+        let concurrency = ProviderSettings::lock().concurrency;
+        tracing::debug!(?mode, ?providers, ?concurrency, ?listen_duration);
+        span.record("mode", tracing::field::debug(mode));
+        span.record("provider", tracing::field::debug(provider_types[0]));
+        span.record("listen_duration", tracing::field::debug(listen_duration));
 
         gio::CancellableFuture::new(glib::timeout_future(listen_duration), cancellable.clone())
             .await
             .map_err(|_| Cancelled::new("recognizing while recording"))?;
 
-        let recording_bytes = imp.recorder.stop().context("Failed to stop recording")?;
+        // Extend recordings that are too short to be worth uploading, or that
+        // lost their start while waiting for music
+        let minimum_duration =
+            Duration::from_secs(settings.minimum_recording_duration().into()).max(listen_duration);
+        let remaining_duration =
+            remaining_until_minimum(imp.recorder.elapsed().unwrap_or_default(), minimum_duration);
+        if !remaining_duration.is_zero() {
+            tracing::debug!(
+                ?remaining_duration,
+                "Extending recording to the minimum of {:?}",
+                minimum_duration
+            );
+
+            gio::CancellableFuture::new(
+                glib::timeout_future(remaining_duration),
+                cancellable.clone(),
+            )
+            .await
+            .map_err(|_| Cancelled::new("recognizing while extending recording"))?;
+        }
+
+        let recording_bytes = imp
+            .recorder
+            .stop()
+            .await
+            .context("Failed to stop recording")?;
+        self.set_is_recording_desktop_audio(false);
+        span.record("n_bytes", recording_bytes.len());
         tracing::debug!(
             "Stopped recording with size {}",
             glib::format_size_full(
@@ -296,31 +804,61 @@ impl Recognizer {
             )
         );
 
+        // Saved recordings are later recognized as music, so don't save hums
+        if self.is_offline_mode() && mode == RecognizeMode::Hum {
+            anyhow::bail!("Humming can't be recognized while offline");
+        }
+
         if self.is_offline_mode() {
             self.saved_recordings()
                 .insert(Recording::new(&recording_bytes, &recorded_time))
                 .context("Failed to insert recording")?;
             self.emit_recording_saved(&RecognizeError::new(RecognizeErrorKind::Connection, None));
             tracing::debug!("Offline mode is active; saved recording for later recognition");
-            return Ok(());
+            return Ok(RecognizeOutcome::Saved);
         }
 
         self.set_state(RecognizerState::Recognizing);
 
-        let res =
-            gio::CancellableFuture::new(provider.recognize(&recording_bytes), cancellable.clone())
-                .await
-                .map_err(|_| Cancelled::new("recognizing while calling provider"))?;
+        // Cancelling drops the requests that are still in flight
+        let res = gio::CancellableFuture::new(
+            provider::recognize_candidates(&providers, &recording_bytes, concurrency),
+            cancellable.clone(),
+        )
+        .await
+        .map_err(|_| Cancelled::new("recognizing while calling provider"))?;
 
         match res {
-            Ok(song) => {
-                song.set_last_heard(recorded_time);
+            Ok((index, candidates)) => {
+                span.record("provider", tracing::field::debug(provider_types[index]));
+
+                for song in candidates.as_slice() {
+                    song.set_last_heard(recorded_time.clone());
+                    song.set_source(source);
+                }
 
-                self.emit_song_recognized(&song);
+                if candidates.has_alternates() && settings.choose_from_multiple_matches() {
+                    tracing::debug!(
+                        n_alternates = candidates.alternates().len(),
+                        "Recognized multiple candidates"
+                    );
+                    self.emit_candidates_recognized(&candidates, &recording_bytes);
+                } else {
+                    self.emit_song_recognized(candidates.top(), &recording_bytes);
+                }
+
+                Ok(RecognizeOutcome::Recognized)
             }
-            Err(err) => {
-                if err.is_permanent() {
-                    return Err(err.into());
+            Err((index, err)) => {
+                // Hummed recordings can't be recognized later, so they are
+                // not saved either
+                if err.is_permanent() || mode == RecognizeMode::Hum {
+                    return Err(anyhow::Error::new(err).context(RecognizeContext {
+                        provider: provider_types[index],
+                        device_name: Some(device_name),
+                        listen_duration,
+                        recorded_time,
+                    }));
                 }
 
                 self.saved_recordings()
@@ -328,10 +866,30 @@ impl Recognizer {
                     .context("Failed to insert recording")?;
                 self.emit_recording_saved(&err);
                 tracing::debug!("Recognition failed with non-permanent error `{:?}`; saved recording for later recognition", err);
+
+                Ok(RecognizeOutcome::Saved)
             }
         }
+    }
+
+    fn set_mode(&self, mode: RecognizeMode) {
+        if mode == self.mode() {
+            return;
+        }
 
-        Ok(())
+        self.imp().mode.set(mode);
+        self.notify_mode();
+    }
+
+    fn set_is_recording_desktop_audio(&self, is_recording_desktop_audio: bool) {
+        if is_recording_desktop_audio == self.is_recording_desktop_audio() {
+            return;
+        }
+
+        self.imp()
+            .is_recording_desktop_audio
+            .set(is_recording_desktop_audio);
+        self.notify_is_recording_desktop_audio();
     }
 
     fn set_state(&self, state: RecognizerState) {
@@ -414,7 +972,8 @@ impl Recognizer {
         // We catch the case anyway where the server is unreachable but network monitor shows
         // full connection, so it won't be a problem if we not enable offline mode on limited or
         // portal connection.
-        let is_offline_mode = network_monitor.connectivity() == gio::NetworkConnectivity::Local;
+        let is_offline_mode = self.is_forced_offline()
+            || network_monitor.connectivity() == gio::NetworkConnectivity::Local;
 
         if is_offline_mode == self.is_offline_mode() {
             return;
@@ -439,3 +998,320 @@ fn is_recording_ready_to_take(recording: &Recording) -> bool {
         Some(Err(ref err)) => err.is_permanent(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span,
+    };
+    use tracing_subscriber::{layer::Context as LayerContext, prelude::*, Layer};
+
+    /// Collects the fields of all spans named `recognize`
+    #[derive(Default, Clone)]
+    struct RecognizeSpanFields(Arc<Mutex<HashMap<String, String>>>);
+
+    impl Visit for RecognizeSpanFields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl<S> Layer<S> for RecognizeSpanFields
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_new_span(
+            &self,
+            attrs: &span::Attributes<'_>,
+            _id: &span::Id,
+            _ctx: LayerContext<'_, S>,
+        ) {
+            if attrs.metadata().name() == "recognize" {
+                attrs.record(&mut self.clone());
+            }
+        }
+
+        fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: LayerContext<'_, S>) {
+            if ctx
+                .span(id)
+                .map_or(false, |span| span.name() == "recognize")
+            {
+                values.record(&mut self.clone());
+            }
+        }
+    }
+
+    fn recorded_fields(f: impl FnOnce()) -> HashMap<String, String> {
+        let fields = RecognizeSpanFields::default();
+        let subscriber = tracing_subscriber::registry().with(fields.clone());
+        tracing::subscriber::with_default(subscriber, f);
+        let ret = fields.0.lock().unwrap().clone();
+        ret
+    }
+
+    #[test]
+    fn recognize_span_recognized() {
+        let fields = recorded_fields(|| {
+            let span = recognize_span();
+            span.record("provider", tracing::field::debug(ProviderType::AudD));
+            span.record("device", "alsa_input.mic");
+            span.record(
+                "listen_duration",
+                tracing::field::debug(Duration::from_secs(5)),
+            );
+            span.record("n_bytes", 1024);
+            record_outcome(&span, &Ok(RecognizeOutcome::Recognized));
+        });
+
+        assert_eq!(fields["provider"], "AudD");
+        assert_eq!(fields["device"], "alsa_input.mic");
+        assert_eq!(fields["listen_duration"], "5s");
+        assert_eq!(fields["n_bytes"], "1024");
+        assert_eq!(fields["outcome"], "recognized");
+        assert!(!fields.contains_key("error"));
+    }
+
+    #[test]
+    fn recognize_span_failed() {
+        let fields = recorded_fields(|| {
+            let span = recognize_span();
+            record_outcome(&span, &Err(anyhow::anyhow!("Failed to start recording")));
+        });
+
+        assert_eq!(fields["outcome"], "failed");
+        assert_eq!(fields["error"], "Failed to start recording");
+    }
+
+    #[test]
+    fn recognize_span_cancelled() {
+        let fields = recorded_fields(|| {
+            let span = recognize_span();
+            record_outcome(
+                &span,
+                &Err(Cancelled::new("recognizing while recording").into()),
+            );
+        });
+
+        assert_eq!(fields["outcome"], "cancelled");
+        assert!(!fields.contains_key("error"));
+    }
+
+    #[derive(Default)]
+    struct CueRecorder(RefCell<Vec<SoundCue>>);
+
+    #[async_trait::async_trait(?Send)]
+    impl PlaySoundCue for CueRecorder {
+        async fn play_to_end(&self, cue: SoundCue) -> Result<()> {
+            self.0.borrow_mut().push(cue);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn listen_cue_only_when_enabled() {
+        let ctx = glib::MainContext::default();
+
+        let cue_player = CueRecorder::default();
+        ctx.block_on(play_listen_cue(false, &cue_player));
+        assert!(cue_player.0.borrow().is_empty());
+
+        ctx.block_on(play_listen_cue(true, &cue_player));
+        assert_eq!(*cue_player.0.borrow(), vec![SoundCue::ListenStart]);
+    }
+
+    #[test]
+    fn no_match_placeholder_only_when_enabled() {
+        let recorded_time = DateTime::from_iso8601("2022-01-01T00:00:00Z").unwrap();
+        let new_err = |kind| {
+            anyhow::Error::new(RecognizeError::new(kind, None)).context(RecognizeContext {
+                provider: ProviderType::default(),
+                device_name: None,
+                listen_duration: Duration::from_secs(5),
+                recorded_time: recorded_time.clone(),
+            })
+        };
+
+        let err = RecognitionFailure::from(new_err(RecognizeErrorKind::NoMatches));
+        assert!(no_match_placeholder(&err, false).is_none());
+
+        let placeholder = no_match_placeholder(&err, true).unwrap();
+        assert!(placeholder.is_placeholder());
+        assert_eq!(placeholder.last_heard(), Some(recorded_time.clone()));
+
+        let err = RecognitionFailure::from(new_err(RecognizeErrorKind::Fingerprint));
+        assert!(no_match_placeholder(&err, true).is_none());
+
+        let err = RecognitionFailure::from(anyhow::Error::new(RecognizeError::new(
+            RecognizeErrorKind::NoMatches,
+            None,
+        )));
+        assert!(no_match_placeholder(&err, true).is_none());
+    }
+
+    #[test]
+    fn remaining_until_minimum_short() {
+        assert_eq!(
+            remaining_until_minimum(Duration::from_secs(2), Duration::from_secs(5)),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            remaining_until_minimum(Duration::ZERO, Duration::from_secs(5)),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn remaining_until_minimum_long_enough() {
+        assert_eq!(
+            remaining_until_minimum(Duration::from_secs(5), Duration::from_secs(5)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            remaining_until_minimum(Duration::from_secs(8), Duration::from_secs(5)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            remaining_until_minimum(Duration::from_secs(8), Duration::ZERO),
+            Duration::ZERO
+        );
+    }
+
+    #[gtk::test]
+    fn recognize_sample_keeps_user_fields() {
+        gst::init().unwrap();
+
+        let mut provider_settings = ProviderSettings::lock();
+        provider_settings.active = ProviderType::AudDMock;
+        provider_settings.test_mode = TestProviderMode::ValidOnly;
+        provider_settings.test_recognize_duration = Duration::ZERO;
+        drop(provider_settings);
+
+        let (env, _env_tempdir) = crate::database::new_test_env();
+        let song_list = crate::song_list::SongList::load_from_env(env.clone()).unwrap();
+
+        let last_heard = DateTime::from_iso8601("2023-01-01T12:00:00Z").unwrap();
+        let song = Song::builder(
+            &crate::uid::Uid::from("wrong-match"),
+            "Wrong Match",
+            "Someone",
+            "",
+        )
+        .build();
+        song.set_last_heard(Some(last_heard.clone()));
+        song.set_is_favorite(true);
+        song.set_source(RecognitionSource::Hum);
+        song_list.insert(song.clone()).unwrap();
+
+        let recognized = glib::MainContext::default()
+            .block_on(Recognizer::new().recognize_sample(b"sample"))
+            .unwrap();
+        let updated = song_list
+            .replace_metadata(song.id_ref(), &recognized)
+            .unwrap();
+
+        assert_eq!(updated.id_ref(), song.id_ref());
+        assert!(updated.is_favorite());
+        assert_eq!(updated.last_heard(), Some(last_heard));
+        assert_eq!(updated.source(), RecognitionSource::Hum);
+        assert_eq!(updated.title(), recognized.title());
+        assert_eq!(updated.artist(), recognized.artist());
+        assert_ne!(updated.title(), song.title());
+
+        // Replaced in place, including in the db
+        assert_eq!(song_list.n_items(), 1);
+        let reloaded = crate::song_list::SongList::load_from_env(env).unwrap();
+        let reloaded_song = reloaded.get(song.id_ref()).unwrap();
+        assert_eq!(reloaded_song.title(), recognized.title());
+        assert!(reloaded_song.is_favorite());
+
+        ProviderSettings::lock().reset();
+    }
+
+    #[test]
+    fn is_in_cooldown_window() {
+        let completed = Instant::now();
+        let cooldown = Duration::from_secs(1);
+
+        assert!(!is_in_cooldown(None, cooldown, completed));
+        assert!(is_in_cooldown(Some(completed), cooldown, completed));
+        assert!(is_in_cooldown(
+            Some(completed),
+            cooldown,
+            completed + Duration::from_millis(999)
+        ));
+        assert!(!is_in_cooldown(
+            Some(completed),
+            cooldown,
+            completed + Duration::from_secs(1)
+        ));
+        assert!(!is_in_cooldown(Some(completed), Duration::ZERO, completed));
+    }
+
+    #[gtk::test]
+    fn cooldown() {
+        gst::init().unwrap();
+
+        let recognizer = Recognizer::new();
+        recognizer.set_cooldown_duration(60_000);
+
+        let n_ignored = Rc::new(Cell::new(0));
+        recognizer.connect_recognize_ignored(clone!(@strong n_ignored => move |_| {
+            n_ignored.set(n_ignored.get() + 1);
+        }));
+
+        // Offline mode makes a trigger that proceeds fail before recording
+        recognizer.set_is_forced_offline(true);
+
+        recognizer.imp().last_completed.set(Some(Instant::now()));
+        let res = glib::MainContext::default()
+            .block_on(recognizer.toggle_recognize(RecognizeMode::Music));
+        assert!(matches!(res, Ok(None)));
+        assert_eq!(n_ignored.get(), 1);
+
+        let long_ago = Instant::now().checked_sub(Duration::from_secs(61)).unwrap();
+        recognizer.imp().last_completed.set(Some(long_ago));
+        let res = glib::MainContext::default()
+            .block_on(recognizer.toggle_recognize(RecognizeMode::Music));
+        assert!(res.is_err());
+        assert_eq!(n_ignored.get(), 1);
+    }
+
+    #[gtk::test]
+    fn forced_offline() {
+        gst::init().unwrap();
+
+        let recognizer = Recognizer::new();
+        recognizer.set_is_forced_offline(true);
+        assert!(recognizer.is_offline_mode());
+
+        // Fails before recording anything or calling the provider
+        let res = glib::MainContext::default()
+            .block_on(recognizer.toggle_recognize(RecognizeMode::Music));
+        assert!(res.is_err());
+        assert_eq!(recognizer.state(), RecognizerState::Null);
+
+        recognizer.set_is_forced_offline(false);
+        assert_eq!(
+            recognizer.is_offline_mode(),
+            gio::NetworkMonitor::default().connectivity() == gio::NetworkConnectivity::Local
+        );
+    }
+}
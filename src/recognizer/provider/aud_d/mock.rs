@@ -1,8 +1,11 @@
 use async_trait::async_trait;
 use gtk::glib;
 
-use super::{AudD, Song};
-use crate::recognizer::provider::{RecognizeError, TestProvider, TestProviderMode};
+use super::AudD;
+use crate::recognizer::provider::{Candidates, RecognizeError, TestProvider, TestProviderMode};
+
+/// Response with several possible matches, from the most to the least likely
+const MULTIPLE_MATCHES_RESPONSE_STR: &str = r#"{"status":"success","result":[{"artist":"5 Seconds Of Summer","title":"Amnesia","album":"Amnesia","release_date":"2014-06-24","label":"Universal Music","timecode":"01:02","song_link":"https://lis.tn/WSKAzD","spotify":{"album":{"name":"5 Seconds Of Summer","artists":[{"name":"5 Seconds of Summer","id":"5Rl15oVamLq7FbSb0NNBNy","uri":"spotify:artist:5Rl15oVamLq7FbSb0NNBNy","href":"https://api.spotify.com/v1/artists/5Rl15oVamLq7FbSb0NNBNy","external_urls":{"spotify":"https://open.spotify.com/artist/5Rl15oVamLq7FbSb0NNBNy"}}],"album_group":"","album_type":"album","id":"2LkWHNNHgD6BRNeZI2SL1L","uri":"spotify:album:2LkWHNNHgD6BRNeZI2SL1L","available_markets":null,"href":"https://api.spotify.com/v1/albums/2LkWHNNHgD6BRNeZI2SL1L","images":[{"height":640,"width":640,"url":"https://i.scdn.co/image/ab67616d0000b27393432e914046a003229378da"},{"height":300,"width":300,"url":"https://i.scdn.co/image/ab67616d00001e0293432e914046a003229378da"},{"height":64,"width":64,"url":"https://i.scdn.co/image/ab67616d0000485193432e914046a003229378da"}],"external_urls":{"spotify":"https://open.spotify.com/album/2LkWHNNHgD6BRNeZI2SL1L"},"release_date":"2014-06-27","release_date_precision":"day"},"external_ids":{"isrc":"GBUM71401926"},"popularity":69,"is_playable":true,"linked_from":null,"artists":[{"name":"5 Seconds of Summer","id":"5Rl15oVamLq7FbSb0NNBNy","uri":"spotify:artist:5Rl15oVamLq7FbSb0NNBNy","href":"https://api.spotify.com/v1/artists/5Rl15oVamLq7FbSb0NNBNy","external_urls":{"spotify":"https://open.spotify.com/artist/5Rl15oVamLq7FbSb0NNBNy"}}],"available_markets":null,"disc_number":1,"duration_ms":237247,"explicit":false,"external_urls":{"spotify":"https://open.spotify.com/track/1JCCdiru7fhstOIF4N7WJC"},"href":"https://api.spotify.com/v1/tracks/1JCCdiru7fhstOIF4N7WJC","id":"1JCCdiru7fhstOIF4N7WJC","name":"Amnesia","preview_url":"","track_number":12,"uri":"spotify:track:1JCCdiru7fhstOIF4N7WJC"}},{"artist":"Alessia Cara","title":"Scars To Your Beautiful","album":"Know-It-All","release_date":"2015-11-13","label":"EP Entertainment, LLC / Def Jam","timecode":"00:28","song_link":"https://lis.tn/ScarsToYourBeautiful","spotify":{"album":{"name":"Know-It-All (Deluxe)","artists":[{"name":"Alessia Cara","id":"2wUjUUtkb5lvLKcGKsKqsR","uri":"spotify:artist:2wUjUUtkb5lvLKcGKsKqsR","href":"https://api.spotify.com/v1/artists/2wUjUUtkb5lvLKcGKsKqsR","external_urls":{"spotify":"https://open.spotify.com/artist/2wUjUUtkb5lvLKcGKsKqsR"}}],"album_group":"","album_type":"album","id":"3rDbA12I5duZnlwakqDdZa","uri":"spotify:album:3rDbA12I5duZnlwakqDdZa","available_markets":null,"href":"https://api.spotify.com/v1/albums/3rDbA12I5duZnlwakqDdZa","images":[{"height":640,"width":640,"url":"https://i.scdn.co/image/ab67616d0000b273e3ae597159d6c2541c4ee61b"},{"height":300,"width":300,"url":"https://i.scdn.co/image/ab67616d00001e02e3ae597159d6c2541c4ee61b"},{"height":64,"width":64,"url":"https://i.scdn.co/image/ab67616d00004851e3ae597159d6c2541c4ee61b"}],"external_urls":{"spotify":"https://open.spotify.com/album/3rDbA12I5duZnlwakqDdZa"},"release_date":"2015-11-13","release_date_precision":"day"},"external_ids":{"isrc":"USUM71506811"},"popularity":75,"is_playable":true,"linked_from":null,"artists":[{"name":"Alessia Cara","id":"2wUjUUtkb5lvLKcGKsKqsR","uri":"spotify:artist:2wUjUUtkb5lvLKcGKsKqsR","href":"https://api.spotify.com/v1/artists/2wUjUUtkb5lvLKcGKsKqsR","external_urls":{"spotify":"https://open.spotify.com/artist/2wUjUUtkb5lvLKcGKsKqsR"}}],"available_markets":null,"disc_number":1,"duration_ms":230226,"explicit":false,"external_urls":{"spotify":"https://open.spotify.com/track/0prNGof3XqfTvNDxHonvdK"},"href":"https://api.spotify.com/v1/tracks/0prNGof3XqfTvNDxHonvdK","id":"0prNGof3XqfTvNDxHonvdK","name":"Scars To Your Beautiful","preview_url":"","track_number":10,"uri":"spotify:track:0prNGof3XqfTvNDxHonvdK"}},{"artist":"Daniel Boone","title":"Beautiful Sunday","album":"Pop Legend Vol.1","release_date":"2010-01-15","label":"Open Records","timecode":"00:33","song_link":"https://lis.tn/YTuccJ","spotify":{"album":{"name":"Cocktail Super Pop","artists":[{"name":"Various Artists","id":"0LyfQWJT6nXafLPZqxe9Of","uri":"spotify:artist:0LyfQWJT6nXafLPZqxe9Of","href":"https://api.spotify.com/v1/artists/0LyfQWJT6nXafLPZqxe9Of","external_urls":{"spotify":"https://open.spotify.com/artist/0LyfQWJT6nXafLPZqxe9Of"}}],"album_group":"","album_type":"compilation","id":"1ZsLymIsvlHEnGtQFen5xd","uri":"spotify:album:1ZsLymIsvlHEnGtQFen5xd","available_markets":null,"href":"https://api.spotify.com/v1/albums/1ZsLymIsvlHEnGtQFen5xd","images":[{"height":640,"width":640,"url":"https://i.scdn.co/image/ab67616d0000b273db8f64a52a4ec4cde9a9528a"},{"height":300,"width":300,"url":"https://i.scdn.co/image/ab67616d00001e02db8f64a52a4ec4cde9a9528a"},{"height":64,"width":64,"url":"https://i.scdn.co/image/ab67616d00004851db8f64a52a4ec4cde9a9528a"}],"external_urls":{"spotify":"https://open.spotify.com/album/1ZsLymIsvlHEnGtQFen5xd"},"release_date":"2013-01-18","release_date_precision":"day"},"external_ids":{"isrc":"ES5530914999"},"popularity":0,"is_playable":true,"linked_from":null,"artists":[{"name":"Daniel Boone","id":"3M5aUsJmembbwKbUx434lS","uri":"spotify:artist:3M5aUsJmembbwKbUx434lS","href":"https://api.spotify.com/v1/artists/3M5aUsJmembbwKbUx434lS","external_urls":{"spotify":"https://open.spotify.com/artist/3M5aUsJmembbwKbUx434lS"}}],"available_markets":null,"disc_number":1,"duration_ms":176520,"explicit":false,"external_urls":{"spotify":"https://open.spotify.com/track/6o3AMOtlfI6APSUooekMtt"},"href":"https://api.spotify.com/v1/tracks/6o3AMOtlfI6APSUooekMtt","id":"6o3AMOtlfI6APSUooekMtt","name":"Beautiful Sunday","preview_url":"https://p.scdn.co/mp3-preview/b2fa24732fe08a251b0c8d44774f37fd55378378?cid=e44e7b8278114c7db211c00ea273ac69","track_number":16,"uri":"spotify:track:6o3AMOtlfI6APSUooekMtt"}}]}"#;
 
 #[derive(Debug)]
 pub struct AudDMock;
@@ -13,15 +16,22 @@ impl TestProvider for AudDMock {
         &self,
         _: &[u8],
         mode: TestProviderMode,
-    ) -> Result<Song, RecognizeError> {
+    ) -> Result<Candidates, RecognizeError> {
         let response_str = random_response_str(mode);
         tracing::trace!(response_str);
 
-        AudD::build_song_from_response_bytes(response_str.as_bytes())
+        AudD::build_candidates_from_response_bytes(
+            response_str.as_bytes(),
+            &AudD::default_post_processors(),
+        )
     }
 }
 
 fn random_response_str(mode: TestProviderMode) -> &'static str {
+    if mode == TestProviderMode::MultipleMatches {
+        return MULTIPLE_MATCHES_RESPONSE_STR;
+    }
+
     let mut raw_responses = Vec::new();
     if matches!(mode, TestProviderMode::Both) || matches!(mode, TestProviderMode::ErrorOnly) {
         raw_responses.extend([
@@ -1,36 +1,187 @@
 mod mock;
 mod response;
 
-use std::time::Duration;
+use std::{fmt, time::Duration};
 
 use async_trait::async_trait;
-use gtk::{gio, glib};
+use gtk::{
+    gio,
+    glib::{self, translate::IntoGlib},
+};
 use serde_json::json;
 use soup::prelude::*;
 
 pub use self::mock::AudDMock;
-use self::response::Response;
-use super::{Provider, RecognizeError, RecognizeErrorKind};
-use crate::{external_links::ExternalLinkKey, song::Song, uid::Uid, Application};
+use self::response::{Data, Response};
+use super::{
+    post_process::{
+        self, PostProcessor, SongMetadata, StripArtistFromTitle, StripReleaseTypeSuffix,
+    },
+    sanitize::{sanitize_text, to_valid_utf8},
+    Candidates, Provider, RecognizeError, RecognizeErrorKind,
+};
+use crate::{
+    external_links::ExternalLinkKey,
+    rate_limiter::{self, DEFAULT_RETRY_AFTER},
+    song::Song,
+    uid::Uid,
+    Application,
+};
+
+/// Key of the rate limiter shared by all requests to AudD
+const RATE_LIMITER_KEY: &str = "AudD";
+
+/// HTTP status of responses to rate limited requests
+const TOO_MANY_REQUESTS_STATUS: i32 = 429;
 
-#[derive(Debug)]
 pub struct AudD {
     api_token: String,
+    post_processors: Vec<Box<dyn PostProcessor>>,
+}
+
+impl fmt::Debug for AudD {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never leak the token in the logs
+        f.debug_struct("AudD")
+            .field("has_api_token", &!self.api_token.is_empty())
+            .field("post_processors", &self.post_processors)
+            .finish()
+    }
 }
 
 impl AudD {
     pub fn new(api_token: Option<&str>) -> Self {
         Self {
             api_token: api_token.unwrap_or_default().to_string(),
+            post_processors: Self::default_post_processors(),
         }
     }
 
-    fn build_song_from_response_bytes(response_bytes: &[u8]) -> Result<Song, RecognizeError> {
-        let data = serde_json::from_slice::<Response>(response_bytes)
+    /// Cleans up the quirks of results from AudD, which come from the stores
+    /// that it links to.
+    pub fn default_post_processors() -> Vec<Box<dyn PostProcessor>> {
+        vec![
+            Box::new(StripReleaseTypeSuffix),
+            Box::new(StripArtistFromTitle),
+        ]
+    }
+
+    /// Replaces the post-processors run on results, e.g., with none to
+    /// keep them as is.
+    pub fn with_post_processors(mut self, post_processors: Vec<Box<dyn PostProcessor>>) -> Self {
+        self.post_processors = post_processors;
+        self
+    }
+
+    /// Maps the response to a request without audio to whether the token
+    /// was accepted.
+    ///
+    /// Errors other than auth errors mean that the token got through, as the
+    /// server only complains about the missing audio afterwards.
+    fn check_credentials_from_response_bytes(response_bytes: &[u8]) -> Result<(), RecognizeError> {
+        let res = serde_json::from_slice::<Response>(response_bytes)
             .map_err(|err| {
                 RecognizeError::new(RecognizeErrorKind::OtherPermanent, err.to_string())
             })?
-            .data()?;
+            .data();
+
+        match res {
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    RecognizeErrorKind::InvalidToken | RecognizeErrorKind::TokenLimitReached
+                ) =>
+            {
+                Err(err)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn send_request(&self, data: serde_json::Value) -> Result<glib::Bytes, RecognizeError> {
+        let message = soup::Message::new("POST", "https://api.audd.io/").map_err(|err| {
+            RecognizeError::new(
+                RecognizeErrorKind::OtherPermanent,
+                format!("Failed to create POST message: {}", err),
+            )
+        })?;
+        message.set_request_body_from_bytes(None, Some(&glib::Bytes::from_owned(data.to_string())));
+        message.set_priority(soup::MessagePriority::High);
+
+        let app = Application::get();
+        let rate_limiter = app.rate_limiters().get(RATE_LIMITER_KEY);
+        rate_limiter
+            .acquire()
+            .await
+            .map_err(|err| RecognizeError::new(RecognizeErrorKind::RateLimited, err.to_string()))?;
+
+        let response_bytes = app
+            .session()
+            .send_and_read_future(&message, glib::Priority::default())
+            .await
+            .map_err(|err| {
+                if err.matches(gio::ResolverError::NotFound)
+                    || err.matches(gio::ResolverError::TemporaryFailure)
+                {
+                    RecognizeError::new(RecognizeErrorKind::Connection, err.to_string())
+                } else {
+                    RecognizeError::new(RecognizeErrorKind::OtherPermanent, err.to_string())
+                }
+            })?;
+
+        tracing::trace!(server_response = ?std::str::from_utf8(&response_bytes));
+
+        if message.status().into_glib() == TOO_MANY_REQUESTS_STATUS {
+            let retry_after = message
+                .response_headers()
+                .and_then(|headers| headers.one("Retry-After"))
+                .and_then(|value| rate_limiter::parse_retry_after(&value))
+                .unwrap_or(DEFAULT_RETRY_AFTER);
+            rate_limiter.block_for(retry_after);
+
+            return Err(RecognizeError::new(
+                RecognizeErrorKind::RateLimited,
+                format!("Server asked to retry after {:?}", retry_after),
+            ));
+        }
+
+        Ok(response_bytes)
+    }
+
+    fn build_candidates_from_response_bytes(
+        response_bytes: &[u8],
+        post_processors: &[Box<dyn PostProcessor>],
+    ) -> Result<Candidates, RecognizeError> {
+        let mut songs = serde_json::from_str::<Response>(&to_valid_utf8(response_bytes))
+            .map_err(|err| {
+                RecognizeError::new(RecognizeErrorKind::OtherPermanent, err.to_string())
+            })?
+            .data()?
+            .into_iter()
+            .map(|data| Self::build_song(data, post_processors));
+
+        let top = songs.next().expect("there must be at least one result");
+        Ok(Candidates::new(top, songs.collect()))
+    }
+
+    fn build_song(mut data: Data, post_processors: &[Box<dyn PostProcessor>]) -> Song {
+        data.title = sanitize_text(&data.title);
+        data.artist = sanitize_text(&data.artist);
+        data.album = sanitize_text(&data.album);
+        data.release_date = data
+            .release_date
+            .map(|release_date| sanitize_text(&release_date))
+            .filter(|release_date| !release_date.is_empty());
+
+        let mut metadata = SongMetadata {
+            title: data.title,
+            artist: data.artist,
+            album: data.album,
+        };
+        post_process::post_process(post_processors, &mut metadata);
+        data.title = metadata.title;
+        data.artist = metadata.artist;
+        data.album = metadata.album;
 
         let mut song_builder = Song::builder(
             &Uid::from_prefixed("AudD", data.info_link.trim_start_matches("https://lis.tn/")), // Info link is unique to every song
@@ -62,6 +213,10 @@ impl AudD {
                 playback_links.push(spotify_data.preview_url);
             }
 
+            if let Some(explicit) = spotify_data.explicit {
+                song_builder.explicit(explicit);
+            }
+
             song_builder.external_link(
                 ExternalLinkKey::SpotifyUrl,
                 spotify_data.external_urls.spotify,
@@ -84,7 +239,25 @@ impl AudD {
             );
         }
 
+        if let Some(release) = data
+            .musicbrainz_data
+            .iter()
+            .flatten()
+            .flat_map(|recording| &recording.releases)
+            .find(|release| is_mbid(&release.id))
+        {
+            song_builder.external_link(ExternalLinkKey::MusicbrainzReleaseId, &release.id);
+        }
+
         if let Some(lyrics_data) = data.lyrics_data {
+            if let Some(song_id) = lyrics_data.song_id.filter(|song_id| !song_id.is_empty()) {
+                // Redirects to the page of the song
+                song_builder.external_link(
+                    ExternalLinkKey::GeniusUrl,
+                    format!("https://genius.com/songs/{}", song_id),
+                );
+            }
+
             if !lyrics_data.lyrics.is_empty() {
                 song_builder.lyrics(&lyrics_data.lyrics);
             }
@@ -98,45 +271,38 @@ impl AudD {
             song_builder.playback_link(playback_link);
         }
 
-        Ok(song_builder.build())
+        song_builder.build()
     }
 }
 
 #[async_trait(?Send)]
 impl Provider for AudD {
     async fn recognize(&self, bytes: &[u8]) -> Result<Song, RecognizeError> {
+        self.recognize_candidates(bytes)
+            .await
+            .map(Candidates::into_top)
+    }
+
+    async fn recognize_candidates(&self, bytes: &[u8]) -> Result<Candidates, RecognizeError> {
         let data = json!({
             "api_token": self.api_token,
             "return": "spotify,apple_music,musicbrainz,lyrics",
             "audio": glib::base64_encode(bytes).as_str(),
         });
 
-        let message = soup::Message::new("POST", "https://api.audd.io/").map_err(|err| {
-            RecognizeError::new(
-                RecognizeErrorKind::OtherPermanent,
-                format!("Failed to create POST message: {}", err),
-            )
-        })?;
-        message.set_request_body_from_bytes(None, Some(&glib::Bytes::from_owned(data.to_string())));
-        message.set_priority(soup::MessagePriority::High);
+        let response_bytes = self.send_request(data).await?;
 
-        let response_bytes = Application::get()
-            .session()
-            .send_and_read_future(&message, glib::Priority::default())
-            .await
-            .map_err(|err| {
-                if err.matches(gio::ResolverError::NotFound)
-                    || err.matches(gio::ResolverError::TemporaryFailure)
-                {
-                    RecognizeError::new(RecognizeErrorKind::Connection, err.to_string())
-                } else {
-                    RecognizeError::new(RecognizeErrorKind::OtherPermanent, err.to_string())
-                }
-            })?;
+        Self::build_candidates_from_response_bytes(&response_bytes, &self.post_processors)
+    }
 
-        tracing::trace!(server_response = ?std::str::from_utf8(&response_bytes));
+    async fn validate_credentials(&self) -> Result<(), RecognizeError> {
+        let data = json!({
+            "api_token": self.api_token,
+        });
+
+        let response_bytes = self.send_request(data).await?;
 
-        Self::build_song_from_response_bytes(&response_bytes)
+        Self::check_credentials_from_response_bytes(&response_bytes)
     }
 
     fn listen_duration(&self) -> Duration {
@@ -150,12 +316,60 @@ impl Default for AudD {
     }
 }
 
+/// Whether `id` is a MusicBrainz identifier, i.e., a UUID such as
+/// `3ce8e4e5-8a6c-4afa-b2c6-4be35961fbc9`.
+fn is_mbid(id: &str) -> bool {
+    id.len() == 36
+        && id.char_indices().all(|(index, c)| match index {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use crate::settings::ArtistTitleOrder;
+
     fn parse_response_str(response_str: &'static str) -> Result<Song, RecognizeError> {
-        AudD::build_song_from_response_bytes(response_str.as_bytes())
+        AudD::build_candidates_from_response_bytes(response_str.as_bytes(), &[])
+            .map(Candidates::into_top)
+    }
+
+    fn check_credentials_str(response_str: &'static str) -> Result<(), RecognizeError> {
+        AudD::check_credentials_from_response_bytes(response_str.as_bytes())
+    }
+
+    #[test]
+    fn debug_hides_api_token() {
+        let provider = AudD::new(Some("secret-token"));
+        assert!(!format!("{:?}", provider).contains("secret-token"));
+    }
+
+    #[test]
+    fn check_credentials() {
+        assert!(check_credentials_str("{\"status\":\"success\",\"result\":null}").is_ok());
+
+        // No audio was sent, but the token got through
+        assert!(check_credentials_str("{\"status\":\"error\",\"error\":{\"error_code\":300,\"error_message\":\"Recognition failed: a problem with fingerprints creating.\"}}").is_ok());
+
+        assert_eq!(
+            check_credentials_str("{\"status\":\"error\",\"error\":{\"error_code\":900,\"error_message\":\"Recognition failed: authorization failed: wrong api_token.\"}}")
+                .unwrap_err()
+                .kind(),
+            RecognizeErrorKind::InvalidToken
+        );
+        assert_eq!(
+            check_credentials_str("{\"status\":\"error\",\"error\":{\"error_code\":901,\"error_message\":\"Recognition failed: authorization failed: no api_token passed and the limit was reached.\"}}")
+                .unwrap_err()
+                .kind(),
+            RecognizeErrorKind::TokenLimitReached
+        );
+        assert_eq!(
+            check_credentials_str("").unwrap_err().kind(),
+            RecognizeErrorKind::OtherPermanent
+        );
     }
 
     #[test]
@@ -168,6 +382,22 @@ mod test {
     fn no_matches() {
         let res = parse_response_str("{\"status\":\"success\",\"result\":null}");
         assert_eq!(res.unwrap_err().kind(), RecognizeErrorKind::NoMatches);
+
+        let res = parse_response_str("{\"status\":\"success\",\"result\":[]}");
+        assert_eq!(res.unwrap_err().kind(), RecognizeErrorKind::NoMatches);
+    }
+
+    #[test]
+    fn multiple_results() {
+        let response_str = "{\"status\":\"success\",\"result\":[{\"artist\":\"Public\",\"title\":\"Make You Mine\",\"album\":\"Let's Make It\",\"timecode\":\"00:43\",\"song_link\":\"https://lis.tn/FUYgUV\"},{\"artist\":\"Daniel Boone\",\"title\":\"Beautiful Sunday\",\"album\":\"Pop Legend Vol.1\",\"timecode\":\"00:33\",\"song_link\":\"https://lis.tn/YTuccJ\"}]}";
+
+        let candidates =
+            AudD::build_candidates_from_response_bytes(response_str.as_bytes(), &[]).unwrap();
+        assert_eq!(candidates.top().title(), "Make You Mine");
+        assert_eq!(candidates.top().id(), Uid::from_prefixed("AudD", "FUYgUV"));
+        assert_eq!(candidates.alternates().len(), 1);
+        assert_eq!(candidates.alternates()[0].title(), "Beautiful Sunday");
+        assert_eq!(candidates.alternates()[0].artist(), "Daniel Boone");
     }
 
     #[test]
@@ -304,4 +534,77 @@ mod test {
             Some("https://p.scdn.co/mp3-preview/b2fa24732fe08a251b0c8d44774f37fd55378378?cid=e44e7b8278114c7db211c00ea273ac69")
         );
     }
+
+    #[test]
+    fn genius_link() {
+        let res = parse_response_str("{\"status\":\"success\",\"result\":{\"artist\":\"Aerosmith\",\"title\":\"I Don't Want to Miss a Thing\",\"album\":\"Armageddon\",\"release_date\":\"1998-06-23\",\"label\":\"Columbia\",\"timecode\":\"00:30\",\"song_link\":\"https://lis.tn/IDontWantToMissAThing\",\"lyrics\":{\"song_id\":\"320138\",\"artist_id\":\"533\",\"title\":\"I Don't Want to Miss a Thing\",\"full_title\":\"I Don't Want to Miss a Thing by Aerosmith\",\"artist\":\"Aerosmith\",\"lyrics\":\"I could stay awake just to hear you breathin'\",\"media\":\"[]\"}}}");
+        let song = res.unwrap();
+        assert_eq!(
+            song.external_links()
+                .get(ExternalLinkKey::GeniusUrl)
+                .as_deref(),
+            Some("https://genius.com/songs/320138")
+        );
+
+        let res = parse_response_str("{\"status\":\"success\",\"result\":{\"artist\":\"Public\",\"title\":\"Make You Mine\",\"album\":\"Let's Make It\",\"release_date\":\"2014-10-07\",\"label\":\"PUBLIC\",\"timecode\":\"00:43\",\"song_link\":\"https://lis.tn/FUYgUV\",\"lyrics\":{\"lyrics\":\"\",\"media\":\"[]\"}}}");
+        let song = res.unwrap();
+        assert_eq!(song.external_links().get(ExternalLinkKey::GeniusUrl), None);
+    }
+
+    #[test]
+    fn musicbrainz_release() {
+        let res = parse_response_str("{\"status\":\"success\",\"result\":{\"artist\":\"Aerosmith\",\"title\":\"I Don't Want to Miss a Thing\",\"album\":\"Armageddon\",\"release_date\":\"1998-06-23\",\"label\":\"Columbia\",\"timecode\":\"00:30\",\"song_link\":\"https://lis.tn/IDontWantToMissAThing\",\"musicbrainz\":[{\"id\":\"2e2e66bd-a016-4713-bd7f-dbb4037cc9b8\",\"score\":100,\"title\":\"I Don't Want to Miss a Thing\",\"releases\":[{\"id\":\"not-an-mbid\"},{\"id\":\"3ce8e4e5-8a6c-4afa-b2c6-4be35961fbc9\",\"title\":\"Armageddon: The Album\"}]}]}}");
+        let song = res.unwrap();
+        assert_eq!(
+            song.external_links()
+                .get(ExternalLinkKey::MusicbrainzReleaseId)
+                .as_deref(),
+            Some("3ce8e4e5-8a6c-4afa-b2c6-4be35961fbc9")
+        );
+
+        let res = parse_response_str("{\"status\":\"success\",\"result\":{\"artist\":\"Public\",\"title\":\"Make You Mine\",\"album\":\"Let's Make It\",\"release_date\":\"2014-10-07\",\"label\":\"PUBLIC\",\"timecode\":\"00:43\",\"song_link\":\"https://lis.tn/FUYgUV\",\"musicbrainz\":[{\"id\":\"d245a10e-f39a-4cfe-a98c-803fcf72e924\",\"releases\":[]}]}}");
+        let song = res.unwrap();
+        assert_eq!(
+            song.external_links()
+                .get(ExternalLinkKey::MusicbrainzReleaseId),
+            None
+        );
+    }
+
+    #[test]
+    fn proper_with_invalid_metadata() {
+        let response_bytes: &[u8] = b"{\"status\":\"success\",\"result\":{\"artist\":\"  Sigur R\xc3\xb3s\\u0000 \",\"title\":\"Hopp\\u001bpolla\\n\",\"album\":\"Takk\xff...\",\"release_date\":\"\\t\",\"label\":\"EMI\",\"timecode\":\"00:24\",\"song_link\":\"https://lis.tn/Hoppipolla\"}}";
+
+        let song = AudD::build_candidates_from_response_bytes(response_bytes, &[])
+            .unwrap()
+            .into_top();
+        assert_eq!(song.title(), "Hopppolla");
+        assert_eq!(song.artist(), "Sigur Rós");
+        assert_eq!(song.album(), "Takk...");
+        assert_eq!(song.release_date(), None);
+        assert_eq!(
+            song.copy_term(ArtistTitleOrder::ArtistFirst),
+            "Sigur Rós - Hopppolla"
+        );
+    }
+
+    #[test]
+    fn proper_post_processed() {
+        let response_str = "{\"status\":\"success\",\"result\":{\"artist\":\"5 Seconds Of Summer\",\"title\":\"5 Seconds Of Summer - Amnesia\",\"album\":\"Amnesia - Single\",\"release_date\":\"2014-06-24\",\"label\":\"Universal Music\",\"timecode\":\"01:02\",\"song_link\":\"https://lis.tn/WSKAzD\"}}";
+
+        let song = AudD::build_candidates_from_response_bytes(
+            response_str.as_bytes(),
+            &AudD::default_post_processors(),
+        )
+        .unwrap()
+        .into_top();
+        assert_eq!(song.title(), "Amnesia");
+        assert_eq!(song.artist(), "5 Seconds Of Summer");
+        assert_eq!(song.album(), "Amnesia");
+
+        // Disabled
+        let song = parse_response_str(response_str).unwrap();
+        assert_eq!(song.title(), "5 Seconds Of Summer - Amnesia");
+        assert_eq!(song.album(), "Amnesia - Single");
+    }
 }
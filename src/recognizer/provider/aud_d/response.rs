@@ -4,6 +4,8 @@ use crate::recognizer::provider::error::{RecognizeError, RecognizeErrorKind};
 
 #[derive(Debug, Deserialize)]
 pub struct LyricsData {
+    /// Id of the song on Genius, where the lyrics are from
+    pub song_id: Option<String>,
     pub lyrics: String,
     /// A json object containing `provider` and `url` field
     pub media: String,
@@ -48,6 +50,18 @@ pub struct SpotifyData {
     pub track_number: u32,
     pub preview_url: String,
     pub external_urls: ExternalUrls,
+    pub explicit: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MusicBrainzRelease {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MusicBrainzRecording {
+    #[serde(default)]
+    pub releases: Vec<MusicBrainzRelease>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -68,6 +82,8 @@ pub struct Data {
     pub apple_music_data: Option<AppleMusicData>,
     #[serde(rename(deserialize = "lyrics"))]
     pub lyrics_data: Option<LyricsData>,
+    #[serde(rename(deserialize = "musicbrainz"))]
+    pub musicbrainz_data: Option<Vec<MusicBrainzRecording>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -78,6 +94,15 @@ struct AudDRawError {
     message: String,
 }
 
+/// A single result, or a list of them when there are several possible
+/// matches, from the most to the least likely
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Results {
+    One(Data),
+    Many(Vec<Data>),
+}
+
 /// If `status` is `success` `data` is `Some` and `error` is `None`. On the other hand, if status is
 /// `error` it is the opposite.
 ///
@@ -86,16 +111,26 @@ struct AudDRawError {
 pub struct Response {
     status: String,
     #[serde(rename(deserialize = "result"))]
-    data: Option<Data>,
+    data: Option<Results>,
     error: Option<AudDRawError>,
 }
 
 impl Response {
-    pub fn data(self) -> Result<Data, RecognizeError> {
+    /// Returns the results from the most to the least likely, which is
+    /// never empty.
+    pub fn data(self) -> Result<Vec<Data>, RecognizeError> {
         if self.status == "success" {
-            return self
-                .data
-                .ok_or_else(|| RecognizeError::new(RecognizeErrorKind::NoMatches, None));
+            let data = match self.data {
+                Some(Results::One(data)) => vec![data],
+                Some(Results::Many(data)) => data,
+                None => Vec::new(),
+            };
+
+            if data.is_empty() {
+                return Err(RecognizeError::new(RecognizeErrorKind::NoMatches, None));
+            }
+
+            return Ok(data);
         }
 
         if self.status == "error" {
@@ -0,0 +1,93 @@
+use gtk::glib;
+
+use crate::song::Song;
+
+/// Songs that may match a recording, where the first one is the most likely.
+#[derive(Debug, Clone, PartialEq, Eq, glib::Boxed)]
+#[boxed_type(name = "MsaiCandidates")]
+pub struct Candidates(Vec<Song>);
+
+impl Candidates {
+    pub fn new(top: Song, alternates: Vec<Song>) -> Self {
+        let mut songs = Vec::with_capacity(alternates.len() + 1);
+        songs.push(top);
+        songs.extend(alternates);
+        Self(songs)
+    }
+
+    /// The most likely match
+    pub fn top(&self) -> &Song {
+        &self.0[0]
+    }
+
+    /// Other possible matches, from the most to the least likely
+    pub fn alternates(&self) -> &[Song] {
+        &self.0[1..]
+    }
+
+    pub fn has_alternates(&self) -> bool {
+        self.0.len() > 1
+    }
+
+    /// All candidates, starting with the top match
+    pub fn as_slice(&self) -> &[Song] {
+        &self.0
+    }
+
+    /// Returns the candidate at `index`, discarding the rest, or `None` if
+    /// `index` is out of bounds.
+    pub fn choose(mut self, index: usize) -> Option<Song> {
+        if index >= self.0.len() {
+            return None;
+        }
+
+        Some(self.0.swap_remove(index))
+    }
+
+    /// Returns the top match, discarding the rest.
+    pub fn into_top(self) -> Song {
+        self.choose(0).unwrap()
+    }
+}
+
+impl From<Song> for Candidates {
+    fn from(song: Song) -> Self {
+        Self::new(song, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::uid::Uid;
+
+    fn new_test_song(id: &str) -> Song {
+        Song::builder(&Uid::from(id), id, id, id).build()
+    }
+
+    #[test]
+    fn single() {
+        let song = new_test_song("a");
+        let candidates = Candidates::from(song.clone());
+        assert_eq!(candidates.top(), &song);
+        assert!(candidates.alternates().is_empty());
+        assert!(!candidates.has_alternates());
+        assert_eq!(candidates.into_top(), song);
+    }
+
+    #[test]
+    fn choose() {
+        let songs = [new_test_song("a"), new_test_song("b"), new_test_song("c")];
+        let candidates = Candidates::new(songs[0].clone(), songs[1..].to_vec());
+        assert_eq!(candidates.top(), &songs[0]);
+        assert_eq!(candidates.alternates(), &songs[1..]);
+        assert!(candidates.has_alternates());
+        assert_eq!(candidates.as_slice(), &songs);
+
+        assert_eq!(candidates.clone().choose(2), Some(songs[2].clone()));
+        assert_eq!(candidates.clone().choose(1), Some(songs[1].clone()));
+        assert_eq!(candidates.clone().choose(3), None);
+        assert_eq!(candidates.into_top(), songs[0]);
+    }
+}
@@ -12,6 +12,8 @@ pub enum RecognizeErrorKind {
     TokenLimitReached,
     Connection,
     OtherPermanent,
+    /// Too many requests were sent to the provider
+    RateLimited,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, glib::Boxed)]
@@ -53,6 +55,7 @@ impl RecognizeError {
             RecognizeErrorKind::TokenLimitReached => gettext("Token Limit Reached"),
             RecognizeErrorKind::Connection => gettext("Cannot Connect to the Server"),
             RecognizeErrorKind::OtherPermanent => gettext("Received Other Permanent Error"),
+            RecognizeErrorKind::RateLimited => gettext("Too Many Requests"),
         }
     }
 
@@ -67,7 +70,7 @@ impl RecognizeError {
 
         match self.kind() {
             NoMatches | Fingerprint | OtherPermanent => true,
-            Connection | TokenLimitReached | InvalidToken => false,
+            Connection | TokenLimitReached | InvalidToken | RateLimited => false,
         }
     }
 }
@@ -2,9 +2,8 @@ use async_trait::async_trait;
 
 use std::sync::atomic::{AtomicI32, Ordering};
 
-use super::Song;
 use crate::recognizer::provider::{
-    RecognizeError, RecognizeErrorKind, TestProvider, TestProviderMode,
+    Candidates, RecognizeError, RecognizeErrorKind, TestProvider, TestProviderMode,
 };
 
 // FIXME Store this state to the struct
@@ -19,7 +18,7 @@ impl TestProvider for ErrorTester {
         &self,
         _: &[u8],
         mode: TestProviderMode,
-    ) -> Result<Song, RecognizeError> {
+    ) -> Result<Candidates, RecognizeError> {
         if mode != TestProviderMode::ErrorOnly {
             tracing::warn!("ErrorTester can only handle ErrorOnly mode");
         }
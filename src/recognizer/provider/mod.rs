@@ -1,6 +1,10 @@
 mod aud_d;
+mod candidates;
 mod error;
 mod error_tester;
+mod multi;
+mod post_process;
+mod sanitize;
 mod settings;
 
 use async_trait::async_trait;
@@ -9,19 +13,95 @@ use gtk::glib;
 use std::{fmt, time::Duration};
 
 pub use self::{
+    aud_d::AudD,
+    candidates::Candidates,
     error::{RecognizeError, RecognizeErrorKind},
-    settings::{ProviderSettings, ProviderType, TestProviderMode},
+    multi::recognize_candidates,
+    settings::{ProviderConcurrency, ProviderSettings, ProviderType, TestProviderMode},
 };
 use crate::song::Song;
 
+/// What kind of audio is being recognized
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "MsaiRecognizeMode")]
+pub enum RecognizeMode {
+    /// Recording of the song itself
+    #[default]
+    Music,
+    /// Humming or singing of the song's melody
+    Hum,
+}
+
+glib::bitflags::bitflags! {
+    /// Kinds of audio a provider can recognize
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Capabilities: u32 {
+        const MUSIC = 1 << 0;
+        const HUM = 1 << 1;
+    }
+}
+
+impl Capabilities {
+    /// Returns the combined capabilities of the providers, i.e., what at
+    /// least one of them supports.
+    pub fn aggregate(providers: &[Box<dyn Provider>]) -> Self {
+        providers
+            .iter()
+            .fold(Self::empty(), |acc, provider| acc | provider.capabilities())
+    }
+
+    /// Whether audio of the given mode can be recognized
+    pub fn supports(self, mode: RecognizeMode) -> bool {
+        match mode {
+            RecognizeMode::Music => self.contains(Self::MUSIC),
+            RecognizeMode::Hum => self.contains(Self::HUM),
+        }
+    }
+
+    /// Returns the modes that can be recognized
+    pub fn available_modes(self) -> Vec<RecognizeMode> {
+        [RecognizeMode::Music, RecognizeMode::Hum]
+            .into_iter()
+            .filter(|&mode| self.supports(mode))
+            .collect()
+    }
+}
+
+/// Returns the first provider that can recognize audio of the given mode.
+pub fn select_provider(
+    mode: RecognizeMode,
+    providers: impl IntoIterator<Item = Box<dyn Provider>>,
+) -> Option<Box<dyn Provider>> {
+    providers
+        .into_iter()
+        .find(|provider| provider.capabilities().supports(mode))
+}
+
 #[async_trait(?Send)]
 pub trait Provider: fmt::Debug {
     /// Recognize a song from bytes
     async fn recognize(&self, bytes: &[u8]) -> Result<Song, RecognizeError>;
 
+    /// Like [`Provider::recognize`], but also returns other possible matches
+    /// for providers that support it
+    async fn recognize_candidates(&self, bytes: &[u8]) -> Result<Candidates, RecognizeError> {
+        self.recognize(bytes).await.map(Candidates::from)
+    }
+
+    /// Checks whether the provider accepts the configured credentials
+    /// without recognizing anything
+    async fn validate_credentials(&self) -> Result<(), RecognizeError> {
+        Ok(())
+    }
+
     /// How long to record the audio
     fn listen_duration(&self) -> Duration;
 
+    /// Kinds of audio this can recognize
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::MUSIC
+    }
+
     /// Whether this supports `TestProviderMode`
     fn is_test(&self) -> bool {
         false
@@ -34,7 +114,7 @@ pub trait TestProvider {
         &self,
         bytes: &[u8],
         mode: TestProviderMode,
-    ) -> Result<Song, RecognizeError>;
+    ) -> Result<Candidates, RecognizeError>;
 }
 
 #[async_trait(?Send)]
@@ -43,6 +123,12 @@ where
     T: TestProvider + fmt::Debug,
 {
     async fn recognize(&self, bytes: &[u8]) -> Result<Song, RecognizeError> {
+        self.recognize_candidates(bytes)
+            .await
+            .map(Candidates::into_top)
+    }
+
+    async fn recognize_candidates(&self, bytes: &[u8]) -> Result<Candidates, RecognizeError> {
         let duration = ProviderSettings::lock().test_recognize_duration;
         glib::timeout_future(duration).await;
 
@@ -54,7 +140,125 @@ where
         ProviderSettings::lock().test_listen_duration
     }
 
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::MUSIC | Capabilities::HUM
+    }
+
     fn is_test(&self) -> bool {
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use self::aud_d::{AudD, AudDMock};
+    use crate::uid::Uid;
+
+    fn new_test_song(id: &str) -> Song {
+        Song::builder(&Uid::from(id), id, id, id).build()
+    }
+
+    /// Only implements `recognize`
+    #[derive(Debug)]
+    struct SingleMatch;
+
+    #[async_trait(?Send)]
+    impl Provider for SingleMatch {
+        async fn recognize(&self, _: &[u8]) -> Result<Song, RecognizeError> {
+            Ok(new_test_song("a"))
+        }
+
+        fn listen_duration(&self) -> Duration {
+            Duration::ZERO
+        }
+    }
+
+    #[gtk::test] // Run in serial
+    fn candidates() {
+        let ctx = glib::MainContext::default();
+
+        let mut provider_settings = ProviderSettings::lock();
+        provider_settings.test_mode = TestProviderMode::MultipleMatches;
+        provider_settings.test_recognize_duration = Duration::ZERO;
+        drop(provider_settings);
+
+        let provider = Box::new(AudDMock) as Box<dyn Provider>;
+        let candidates = ctx.block_on(provider.recognize_candidates(&[])).unwrap();
+        ProviderSettings::lock().reset();
+        assert!(candidates.has_alternates());
+        assert_eq!(candidates.top().title(), "Amnesia");
+        assert_eq!(
+            candidates
+                .alternates()
+                .iter()
+                .map(|song| song.title())
+                .collect::<Vec<_>>(),
+            vec!["Scars To Your Beautiful", "Beautiful Sunday"]
+        );
+        assert_eq!(candidates.choose(2).unwrap().title(), "Beautiful Sunday");
+
+        // Providers with a single match have no alternates
+        let provider = Box::new(SingleMatch) as Box<dyn Provider>;
+        let candidates = ctx.block_on(provider.recognize_candidates(&[])).unwrap();
+        assert_eq!(candidates.top().id(), Uid::from("a"));
+        assert!(!candidates.has_alternates());
+    }
+
+    #[test]
+    fn aggregate_capabilities() {
+        let capabilities = Capabilities::aggregate(&[]);
+        assert_eq!(capabilities, Capabilities::empty());
+        assert!(capabilities.available_modes().is_empty());
+
+        let capabilities = Capabilities::aggregate(&[Box::new(AudD::default())]);
+        assert_eq!(capabilities, Capabilities::MUSIC);
+        assert_eq!(capabilities.available_modes(), vec![RecognizeMode::Music]);
+        assert!(!capabilities.supports(RecognizeMode::Hum));
+
+        let capabilities = Capabilities::aggregate(&[
+            Box::new(AudD::default()),
+            Box::new(AudDMock),
+            Box::new(SingleMatch),
+        ]);
+        assert_eq!(capabilities, Capabilities::MUSIC | Capabilities::HUM);
+        assert_eq!(
+            capabilities.available_modes(),
+            vec![RecognizeMode::Music, RecognizeMode::Hum]
+        );
+    }
+
+    #[test]
+    fn select_provider_music() {
+        let provider = select_provider(
+            RecognizeMode::Music,
+            [
+                Box::new(AudD::default()) as Box<dyn Provider>,
+                Box::new(AudDMock),
+            ],
+        )
+        .unwrap();
+        assert!(!provider.is_test());
+    }
+
+    #[test]
+    fn select_provider_hum() {
+        let provider = select_provider(
+            RecognizeMode::Hum,
+            [
+                Box::new(AudD::default()) as Box<dyn Provider>,
+                Box::new(AudDMock),
+            ],
+        )
+        .unwrap();
+        assert!(provider.capabilities().contains(Capabilities::HUM));
+        assert!(provider.is_test());
+
+        assert!(select_provider(
+            RecognizeMode::Hum,
+            [Box::new(AudD::default()) as Box<dyn Provider>]
+        )
+        .is_none());
+    }
+}
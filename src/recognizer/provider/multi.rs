@@ -0,0 +1,252 @@
+use futures_util::future;
+
+use std::{future::Future, pin::Pin, task::Poll};
+
+use super::{Candidates, Provider, ProviderConcurrency, RecognizeError};
+
+/// Recognizes with the providers, given from the highest to the lowest
+/// priority, returning the index of the provider that matched.
+///
+/// [`ProviderConcurrency::Sequential`] only calls the next provider if the
+/// previous one failed, so no request is wasted. [`ProviderConcurrency::Parallel`]
+/// calls all at once and takes the first match, dropping, and thus cancelling,
+/// the requests that are still in flight; matches that are ready at the same
+/// time are resolved by priority.
+///
+/// If all fail, this returns the error of the provider with the highest
+/// priority.
+pub async fn recognize_candidates(
+    providers: &[Box<dyn Provider>],
+    bytes: &[u8],
+    concurrency: ProviderConcurrency,
+) -> Result<(usize, Candidates), (usize, RecognizeError)> {
+    assert!(!providers.is_empty(), "there must be at least one provider");
+
+    match concurrency {
+        ProviderConcurrency::Sequential => {
+            let mut first_err = None;
+
+            for (index, provider) in providers.iter().enumerate() {
+                match provider.recognize_candidates(bytes).await {
+                    Ok(candidates) => return Ok((index, candidates)),
+                    Err(err) => {
+                        tracing::debug!(?provider, ?err, "Provider failed; trying the next one");
+                        first_err.get_or_insert((index, err));
+                    }
+                }
+            }
+
+            Err(first_err.unwrap())
+        }
+        ProviderConcurrency::Parallel => {
+            let mut pending = providers
+                .iter()
+                .map(|provider| Some(provider.recognize_candidates(bytes)))
+                .collect::<Vec<_>>();
+            let mut errs = providers.iter().map(|_| None).collect::<Vec<_>>();
+
+            future::poll_fn(|cx| poll_race(cx, &mut pending, &mut errs)).await
+        }
+    }
+}
+
+type RecognizeFuture<'a> = Pin<Box<dyn Future<Output = Result<Candidates, RecognizeError>> + 'a>>;
+
+/// Polls the pending futures in order of priority, so the first one that is
+/// ready with a match wins.
+fn poll_race(
+    cx: &mut std::task::Context<'_>,
+    pending: &mut [Option<RecognizeFuture<'_>>],
+    errs: &mut [Option<RecognizeError>],
+) -> Poll<Result<(usize, Candidates), (usize, RecognizeError)>> {
+    let mut first_match = None;
+
+    for (index, slot) in pending.iter_mut().enumerate() {
+        let Some(fut) = slot else {
+            continue;
+        };
+
+        if let Poll::Ready(res) = fut.as_mut().poll(cx) {
+            *slot = None;
+
+            match res {
+                Ok(candidates) => {
+                    first_match = Some((index, candidates));
+                    break;
+                }
+                Err(err) => errs[index] = Some(err),
+            }
+        }
+    }
+
+    if let Some((index, candidates)) = first_match {
+        let n_cancelled = pending.iter().flatten().count();
+        tracing::debug!(index, n_cancelled, "Provider matched first");
+        return Poll::Ready(Ok((index, candidates)));
+    }
+
+    if pending.iter().any(Option::is_some) {
+        return Poll::Pending;
+    }
+
+    let (index, err) = errs
+        .iter_mut()
+        .enumerate()
+        .find_map(|(index, err)| err.take().map(|err| (index, err)))
+        .expect("all providers must have failed");
+    Poll::Ready(Err((index, err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use async_trait::async_trait;
+    use gtk::glib;
+
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    use crate::{recognizer::RecognizeErrorKind, song::Song, uid::Uid};
+
+    /// Sets `is_dropped` when dropped
+    struct DropFlag(Rc<Cell<bool>>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    /// Matches a song with its id, or fails if `id` is `None`, after `delay`,
+    /// or right away if it is zero
+    #[derive(Debug)]
+    struct Delayed {
+        id: Option<&'static str>,
+        delay: Duration,
+        n_called: Rc<Cell<u32>>,
+        /// Whether the last call finished
+        is_finished: Rc<Cell<bool>>,
+        /// Whether the last call was dropped, whether it finished or not
+        is_dropped: Rc<Cell<bool>>,
+    }
+
+    impl Delayed {
+        fn new(id: Option<&'static str>, delay_ms: u64) -> Self {
+            Self {
+                id,
+                delay: Duration::from_millis(delay_ms),
+                n_called: Rc::default(),
+                is_finished: Rc::default(),
+                is_dropped: Rc::default(),
+            }
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl Provider for Delayed {
+        async fn recognize(&self, _: &[u8]) -> Result<Song, RecognizeError> {
+            self.n_called.set(self.n_called.get() + 1);
+            self.is_finished.set(false);
+            self.is_dropped.set(false);
+            let _drop_flag = DropFlag(Rc::clone(&self.is_dropped));
+
+            if !self.delay.is_zero() {
+                glib::timeout_future(self.delay).await;
+            }
+            self.is_finished.set(true);
+
+            match self.id {
+                Some(id) => Ok(Song::builder(&Uid::from(id), id, id, id).build()),
+                None => Err(RecognizeError::new(RecognizeErrorKind::NoMatches, None)),
+            }
+        }
+
+        fn listen_duration(&self) -> Duration {
+            Duration::ZERO
+        }
+    }
+
+    fn recognize(
+        providers: &[Box<dyn Provider>],
+        concurrency: ProviderConcurrency,
+    ) -> Result<(usize, Uid), (usize, RecognizeErrorKind)> {
+        glib::MainContext::default()
+            .block_on(recognize_candidates(providers, &[], concurrency))
+            .map(|(index, candidates)| (index, candidates.top().id()))
+            .map_err(|(index, err)| (index, err.kind()))
+    }
+
+    #[gtk::test]
+    fn parallel_first_match() {
+        let slow = Delayed::new(Some("slow"), 300);
+        let slow_is_finished = Rc::clone(&slow.is_finished);
+        let slow_is_dropped = Rc::clone(&slow.is_dropped);
+        let failing = Delayed::new(None, 0);
+        let fast = Delayed::new(Some("fast"), 50);
+
+        let providers: Vec<Box<dyn Provider>> =
+            vec![Box::new(slow), Box::new(failing), Box::new(fast)];
+        assert_eq!(
+            recognize(&providers, ProviderConcurrency::Parallel),
+            Ok((2, Uid::from("fast")))
+        );
+
+        // The slower request is cancelled instead of finishing in the background
+        assert!(slow_is_dropped.get());
+        assert!(!slow_is_finished.get());
+    }
+
+    #[gtk::test]
+    fn parallel_tie_prefers_priority() {
+        let providers: Vec<Box<dyn Provider>> = vec![
+            Box::new(Delayed::new(None, 0)),
+            Box::new(Delayed::new(Some("b"), 0)),
+            Box::new(Delayed::new(Some("c"), 0)),
+        ];
+        assert_eq!(
+            recognize(&providers, ProviderConcurrency::Parallel),
+            Ok((1, Uid::from("b")))
+        );
+    }
+
+    #[gtk::test]
+    fn parallel_all_fail() {
+        let providers: Vec<Box<dyn Provider>> = vec![
+            Box::new(Delayed::new(None, 50)),
+            Box::new(Delayed::new(None, 0)),
+        ];
+        assert_eq!(
+            recognize(&providers, ProviderConcurrency::Parallel),
+            Err((0, RecognizeErrorKind::NoMatches))
+        );
+    }
+
+    #[gtk::test]
+    fn sequential() {
+        let failing = Delayed::new(None, 0);
+        let failing_n_called = Rc::clone(&failing.n_called);
+        let slow = Delayed::new(Some("slow"), 100);
+        let fast = Delayed::new(Some("fast"), 0);
+        let fast_n_called = Rc::clone(&fast.n_called);
+
+        let providers: Vec<Box<dyn Provider>> =
+            vec![Box::new(failing), Box::new(slow), Box::new(fast)];
+        assert_eq!(
+            recognize(&providers, ProviderConcurrency::Sequential),
+            Ok((1, Uid::from("slow")))
+        );
+
+        // Lower priorities are not called once there is a match
+        assert_eq!(failing_n_called.get(), 1);
+        assert_eq!(fast_n_called.get(), 0);
+
+        let providers: Vec<Box<dyn Provider>> = vec![
+            Box::new(Delayed::new(None, 0)),
+            Box::new(Delayed::new(None, 0)),
+        ];
+        assert_eq!(
+            recognize(&providers, ProviderConcurrency::Sequential),
+            Err((0, RecognizeErrorKind::NoMatches))
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+
+/// Metadata of a recognized song before it is turned into a `Song`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SongMetadata {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+}
+
+/// Cleans up a known formatting quirk in the results of a provider.
+///
+/// These must be conservative, i.e., only change metadata that is clearly
+/// not part of the actual title, artist, or album.
+pub trait PostProcessor: fmt::Debug {
+    fn process(&self, metadata: &mut SongMetadata);
+}
+
+/// Runs the post-processors in order.
+pub fn post_process(post_processors: &[Box<dyn PostProcessor>], metadata: &mut SongMetadata) {
+    for post_processor in post_processors {
+        let before = metadata.clone();
+        post_processor.process(metadata);
+
+        if *metadata != before {
+            tracing::debug!(?post_processor, ?before, after = ?metadata, "Post-processed result");
+        }
+    }
+}
+
+/// Removes the release type that some stores append to album names, e.g.,
+/// "Amnesia - Single" becomes "Amnesia".
+#[derive(Debug)]
+pub struct StripReleaseTypeSuffix;
+
+impl StripReleaseTypeSuffix {
+    const SUFFIXES: [&'static str; 2] = [" - Single", " - EP"];
+}
+
+impl PostProcessor for StripReleaseTypeSuffix {
+    fn process(&self, metadata: &mut SongMetadata) {
+        for suffix in Self::SUFFIXES {
+            if let Some(stripped) = metadata.album.strip_suffix(suffix) {
+                if !stripped.trim().is_empty() {
+                    metadata.album = stripped.trim_end().to_string();
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Removes the artist when it is repeated at the start of the title, e.g.,
+/// "Daniel Boone - Beautiful Sunday" by "Daniel Boone" becomes
+/// "Beautiful Sunday".
+#[derive(Debug)]
+pub struct StripArtistFromTitle;
+
+impl PostProcessor for StripArtistFromTitle {
+    fn process(&self, metadata: &mut SongMetadata) {
+        if metadata.artist.is_empty() {
+            return;
+        }
+
+        let Some((prefix, rest)) = metadata.title.split_once(" - ") else {
+            return;
+        };
+
+        if prefix.to_lowercase() == metadata.artist.to_lowercase() && !rest.trim().is_empty() {
+            metadata.title = rest.trim_start().to_string();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(title: &str, artist: &str, album: &str) -> SongMetadata {
+        SongMetadata {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: album.to_string(),
+        }
+    }
+
+    fn processed(post_processor: impl PostProcessor, mut metadata: SongMetadata) -> SongMetadata {
+        post_processor.process(&mut metadata);
+        metadata
+    }
+
+    #[test]
+    fn release_type_suffix() {
+        assert_eq!(
+            processed(
+                StripReleaseTypeSuffix,
+                metadata("Amnesia", "5 Seconds Of Summer", "Amnesia - Single")
+            ),
+            metadata("Amnesia", "5 Seconds Of Summer", "Amnesia")
+        );
+        assert_eq!(
+            processed(
+                StripReleaseTypeSuffix,
+                metadata(
+                    "Scars To Your Beautiful",
+                    "Alessia Cara",
+                    "Four Pink Walls - EP"
+                )
+            ),
+            metadata("Scars To Your Beautiful", "Alessia Cara", "Four Pink Walls")
+        );
+
+        // Part of the actual name
+        for album in [
+            "Single",
+            " - Single",
+            "Singles",
+            "The Single Collection",
+            "Amnesia (Single)",
+            "EP Entertainment",
+        ] {
+            assert_eq!(
+                processed(StripReleaseTypeSuffix, metadata("A", "B", album)).album,
+                album
+            );
+        }
+    }
+
+    #[test]
+    fn artist_in_title() {
+        assert_eq!(
+            processed(
+                StripArtistFromTitle,
+                metadata(
+                    "Daniel Boone - Beautiful Sunday",
+                    "Daniel Boone",
+                    "Pop Legend Vol.1"
+                )
+            ),
+            metadata("Beautiful Sunday", "Daniel Boone", "Pop Legend Vol.1")
+        );
+        assert_eq!(
+            processed(
+                StripArtistFromTitle,
+                metadata("SIGUR RÓS - Hoppípolla", "Sigur Rós", "Takk...")
+            )
+            .title,
+            "Hoppípolla"
+        );
+
+        // Not the artist, or nothing left
+        for title in [
+            "Beautiful Sunday - Remastered",
+            "Daniel Boone -",
+            "Daniel Boone",
+            "Daniel Boones - Beautiful Sunday",
+        ] {
+            assert_eq!(
+                processed(StripArtistFromTitle, metadata(title, "Daniel Boone", "")).title,
+                title
+            );
+        }
+        assert_eq!(
+            processed(StripArtistFromTitle, metadata(" - A", "", "")).title,
+            " - A"
+        );
+    }
+
+    #[test]
+    fn in_order() {
+        let post_processors: Vec<Box<dyn PostProcessor>> = vec![
+            Box::new(StripReleaseTypeSuffix),
+            Box::new(StripArtistFromTitle),
+        ];
+
+        let mut metadata = metadata("Someone - Song", "Someone", "Song - Single");
+        post_process(&post_processors, &mut metadata);
+        assert_eq!(metadata.title, "Song");
+        assert_eq!(metadata.album, "Song");
+
+        let mut unchanged = metadata.clone();
+        post_process(&[], &mut unchanged);
+        assert_eq!(unchanged, metadata);
+    }
+}
@@ -0,0 +1,86 @@
+use std::borrow::Cow;
+
+/// Decodes the bytes as UTF-8, replacing invalid sequences with
+/// `U+FFFD REPLACEMENT CHARACTER`, so a single bad byte does not fail the
+/// whole response.
+pub fn to_valid_utf8(bytes: &[u8]) -> Cow<'_, str> {
+    String::from_utf8_lossy(bytes)
+}
+
+/// Cleans up a single-line metadata string, such as a title or an artist.
+///
+/// This removes control characters, byte order marks, and replacement
+/// characters left from invalid UTF-8, then collapses whitespace runs into a
+/// single space and trims the ends. Other unicode is kept as is.
+pub fn sanitize_text(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut has_pending_space = false;
+
+    for c in text.chars() {
+        if c.is_whitespace() {
+            has_pending_space = true;
+            continue;
+        }
+
+        if c.is_control() || c == '\u{FEFF}' || c == char::REPLACEMENT_CHARACTER {
+            continue;
+        }
+
+        if has_pending_space && !ret.is_empty() {
+            ret.push(' ');
+        }
+        has_pending_space = false;
+
+        ret.push(c);
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_chars() {
+        assert_eq!(sanitize_text("Amne\0sia"), "Amnesia");
+        assert_eq!(sanitize_text("\u{1b}[0mAmnesia\u{7f}"), "[0mAmnesia");
+        assert_eq!(sanitize_text("\u{feff}Amnesia"), "Amnesia");
+        assert_eq!(sanitize_text("Amne\u{fffd}sia"), "Amnesia");
+    }
+
+    #[test]
+    fn whitespace() {
+        assert_eq!(
+            sanitize_text("  5 Seconds\tOf\r\nSummer  "),
+            "5 Seconds Of Summer"
+        );
+        assert_eq!(sanitize_text("A \0 B"), "A B");
+        assert_eq!(sanitize_text("A\u{a0}\u{a0}B"), "A B");
+        assert_eq!(sanitize_text(" \t\n "), "");
+        assert_eq!(sanitize_text(""), "");
+    }
+
+    #[test]
+    fn unicode_kept() {
+        for text in [
+            "Beyoncé",
+            "坂本龍一",
+            "Sigur Rós",
+            "Ева Польна",
+            "👨\u{200d}👩\u{200d}👧",
+            "أم كلثوم",
+        ] {
+            assert_eq!(sanitize_text(text), text);
+        }
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        assert_eq!(to_valid_utf8(b"Amnesia"), "Amnesia");
+
+        let text = to_valid_utf8(b"Amne\xffsia");
+        assert_eq!(text, "Amne\u{fffd}sia");
+        assert_eq!(sanitize_text(&text), "Amnesia");
+    }
+}
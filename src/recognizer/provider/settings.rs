@@ -16,6 +16,8 @@ pub enum TestProviderMode {
     #[default]
     ValidOnly,
     Both,
+    /// Valid responses with other possible matches
+    MultipleMatches,
 }
 
 impl TryFrom<i32> for TestProviderMode {
@@ -26,6 +28,26 @@ impl TryFrom<i32> for TestProviderMode {
     }
 }
 
+/// How to recognize with the active provider and its fallbacks
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
+#[enum_type(name = "MsaiProviderConcurrency")]
+pub enum ProviderConcurrency {
+    /// Only call the next provider if the previous one failed, which saves
+    /// requests
+    #[default]
+    Sequential,
+    /// Call all at once and take the first match, which is faster
+    Parallel,
+}
+
+impl TryFrom<i32> for ProviderConcurrency {
+    type Error = Error;
+
+    fn try_from(val: i32) -> Result<Self> {
+        unsafe { Self::try_from_glib(val) }.map_err(|_| anyhow!("Invalid value `{}`", val))
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
 #[enum_type(name = "MsaiProviderType")]
 pub enum ProviderType {
@@ -45,8 +67,13 @@ impl ProviderType {
         match self {
             Self::AudD => {
                 // FIXME handle this outside
-                let api_token = Application::get().settings().aud_d_api_token();
-                Box::new(AudD::new(Some(&api_token)))
+                let settings = Application::get().settings();
+                let aud_d = AudD::new(Some(&settings.aud_d_api_token()));
+                if settings.clean_up_results() {
+                    Box::new(aud_d)
+                } else {
+                    Box::new(aud_d.with_post_processors(Vec::new()))
+                }
             }
             Self::AudDMock => Box::new(AudDMock),
             Self::ErrorTester => Box::new(ErrorTester),
@@ -65,6 +92,10 @@ impl TryFrom<i32> for ProviderType {
 #[derive(Debug)]
 pub struct ProviderSettings {
     pub active: ProviderType,
+    /// Providers tried after the active one, from the highest to the lowest
+    /// priority
+    pub fallbacks: Vec<ProviderType>,
+    pub concurrency: ProviderConcurrency,
     pub test_mode: TestProviderMode,
     pub test_listen_duration: Duration,
     pub test_recognize_duration: Duration,
@@ -81,6 +112,18 @@ impl ProviderSettings {
             .unwrap()
     }
 
+    /// Returns the active provider followed by the fallbacks, without
+    /// duplicates
+    pub fn by_priority(&self) -> Vec<ProviderType> {
+        let mut providers = vec![self.active];
+        for &fallback in &self.fallbacks {
+            if !providers.contains(&fallback) {
+                providers.push(fallback);
+            }
+        }
+        providers
+    }
+
     /// Reset all fields to their defaults
     pub fn reset(&mut self) {
         *self = Self::default();
@@ -89,6 +132,8 @@ impl ProviderSettings {
     fn default() -> Self {
         Self {
             active: ProviderType::default(),
+            fallbacks: Vec::new(),
+            concurrency: ProviderConcurrency::default(),
             test_mode: TestProviderMode::default(),
             test_listen_duration: Duration::from_secs(1),
             test_recognize_duration: Duration::from_secs(1),
@@ -112,6 +157,30 @@ mod tests {
         assert_eq!(settings.active, ProviderType::default());
     }
 
+    #[gtk::test] // Run in serial
+    fn by_priority() {
+        let mut settings = ProviderSettings::lock();
+        assert_eq!(settings.by_priority(), vec![ProviderType::default()]);
+
+        settings.active = ProviderType::AudDMock;
+        settings.fallbacks = vec![
+            ProviderType::AudD,
+            ProviderType::AudDMock,
+            ProviderType::ErrorTester,
+        ];
+        assert_eq!(
+            settings.by_priority(),
+            vec![
+                ProviderType::AudDMock,
+                ProviderType::AudD,
+                ProviderType::ErrorTester
+            ]
+        );
+
+        settings.reset();
+        assert!(settings.fallbacks.is_empty());
+    }
+
     #[gtk::test] // Run in serial
     fn identity() {
         let mut lock_a = ProviderSettings::lock();
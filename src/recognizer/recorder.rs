@@ -1,70 +1,445 @@
 use anyhow::{anyhow, ensure, Result};
+use futures_util::StreamExt;
+use gettextrs::gettext;
 use gst::{bus::BusWatchGuard, prelude::*};
 use gtk::{
     gio::{self, prelude::*},
     glib::{self, clone},
 };
 
-use std::cell::RefCell;
+use std::{
+    cell::{Cell, RefCell},
+    error, fmt,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
-#[derive(Default)]
+use crate::settings::RecordingFormat;
+
+/// Sample rate of the recording
+pub(super) const SAMPLE_RATE: i32 = 16_000;
+
+/// How long to wait for the end of stream to reach the sink when stopping
+const EOS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Interval between `level` messages
+const LEVEL_INTERVAL: Duration = Duration::from_millis(80);
+
+/// Maximum gain of the pre-amp, in decibels, so boosting weak input does not
+/// turn it into noise
+const MAX_GAIN_DB: f64 = 12.0;
+
+/// How long the peak must stay above the threshold to be considered music
+const MUSIC_START_SUSTAIN: Duration = Duration::from_millis(160);
+
+/// Max time to wait for music, after which everything is recorded
+const MUSIC_START_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Attack/decay smoothing for the recording peaks, so that the meter
+/// rises fast and falls smoothly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeakSmoother {
+    attack_coef: f64,
+    decay_coef: f64,
+    value: f64,
+}
+
+impl PeakSmoother {
+    /// `attack` and `decay` are the time constants for rising and falling
+    /// peaks, respectively. A zero duration disables smoothing in that direction.
+    pub fn new(attack: Duration, decay: Duration) -> Self {
+        Self {
+            attack_coef: smoothing_coef(attack),
+            decay_coef: smoothing_coef(decay),
+            value: 0.0,
+        }
+    }
+
+    /// Passes the peaks as is.
+    #[cfg(test)]
+    pub fn raw() -> Self {
+        Self::new(Duration::ZERO, Duration::ZERO)
+    }
+
+    pub fn process(&mut self, peak: f64) -> f64 {
+        let coef = if peak > self.value {
+            self.attack_coef
+        } else {
+            self.decay_coef
+        };
+        self.value += (peak - self.value) * coef;
+        self.value
+    }
+}
+
+impl Default for PeakSmoother {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(20), Duration::from_millis(300))
+    }
+}
+
+fn smoothing_coef(time_constant: Duration) -> f64 {
+    if time_constant.is_zero() {
+        return 1.0;
+    }
+
+    1.0 - (-LEVEL_INTERVAL.as_secs_f64() / time_constant.as_secs_f64()).exp()
+}
+
+/// Detects when music starts, so that the leading silence or noise can be
+/// discarded from the recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MusicStartDetector {
+    threshold: f64,
+    above_since: Option<Duration>,
+}
+
+impl MusicStartDetector {
+    /// `threshold` is the normalized peak, from 0 to 1, that must be sustained
+    /// for music to be considered started.
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            above_since: None,
+        }
+    }
+
+    /// Processes the raw peak at `elapsed` since the recording started, and
+    /// returns whether the recording should start from this point.
+    ///
+    /// This always returns true once [`MUSIC_START_TIMEOUT`] is reached.
+    pub fn process(&mut self, peak: f64, elapsed: Duration) -> bool {
+        if elapsed >= MUSIC_START_TIMEOUT {
+            return true;
+        }
+
+        if peak < self.threshold {
+            self.above_since = None;
+            return false;
+        }
+
+        let above_since = *self.above_since.get_or_insert(elapsed);
+        elapsed.saturating_sub(above_since) >= MUSIC_START_SUSTAIN
+    }
+}
+
+/// Detects when the input is clipping, i.e., its peak stays at or near full
+/// scale, which makes the recording distorted and harder to recognize.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClippingDetector {
+    threshold: f64,
+    sustain: Duration,
+    above_since: Option<Duration>,
+    has_detected: bool,
+}
+
+impl ClippingDetector {
+    /// `threshold` is the normalized peak, from 0 to 1, at which the input is
+    /// considered clipping, and `sustain` is how long it must stay there.
+    pub fn new(threshold: f64, sustain: Duration) -> Self {
+        Self {
+            threshold,
+            sustain,
+            above_since: None,
+            has_detected: false,
+        }
+    }
+
+    /// Processes the raw peak at `elapsed` since the recording started, and
+    /// returns whether clipping was just detected.
+    ///
+    /// This returns true at most once, so a warning is not repeated for the
+    /// same recording.
+    pub fn process(&mut self, peak: f64, elapsed: Duration) -> bool {
+        if self.has_detected {
+            return false;
+        }
+
+        if peak < self.threshold {
+            self.above_since = None;
+            return false;
+        }
+
+        let above_since = *self.above_since.get_or_insert(elapsed);
+        self.has_detected = elapsed.saturating_sub(above_since) >= self.sustain;
+        self.has_detected
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordErrorKind {
+    /// Another app is holding the device exclusively
+    DeviceBusy,
+    /// Access to the microphone was denied, as looked up before recording
+    PermissionDenied,
+    Other,
+}
+
+/// Error received from the pipeline while starting to record
+#[derive(Debug)]
+pub struct RecordError {
+    kind: RecordErrorKind,
+    source: glib::Error,
+}
+
+impl RecordError {
+    pub fn from_gst_error(source: glib::Error) -> Self {
+        let kind = if source.matches(gst::ResourceError::Busy) {
+            RecordErrorKind::DeviceBusy
+        } else {
+            RecordErrorKind::Other
+        };
+
+        Self { kind, source }
+    }
+
+    /// The stored choice denied access to the microphone, so the pipeline
+    /// was not started
+    pub fn mic_access_denied() -> Self {
+        Self {
+            kind: RecordErrorKind::PermissionDenied,
+            source: glib::Error::new(
+                gio::IOErrorEnum::PermissionDenied,
+                "Microphone access was denied",
+            ),
+        }
+    }
+
+    pub fn kind(&self) -> RecordErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            RecordErrorKind::DeviceBusy => f.write_str(&gettext(
+                "The audio device is busy. Close other apps using it and try again",
+            )),
+            RecordErrorKind::PermissionDenied => f.write_str(&gettext(
+                "Access to the audio device was denied. Check your privacy settings and try again",
+            )),
+            RecordErrorKind::Other => write!(f, "{}", self.source),
+        }
+    }
+}
 
+impl error::Error for RecordError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// What to record from and how to process it while recording.
+#[derive(Debug, Clone)]
+pub struct RecordOptions {
+    /// The device to record from, or the default source if `None`
+    pub device_name: Option<String>,
+    pub format: RecordingFormat,
+    /// The input is amplified by this many decibels, up to [`MAX_GAIN_DB`],
+    /// before it is measured and encoded.
+    pub gain_db: f64,
+    /// If given, audio is discarded until it detects music.
+    pub music_start_detector: Option<MusicStartDetector>,
+    pub clipping_detector: ClippingDetector,
+    pub peak_smoother: PeakSmoother,
+}
+
+#[derive(Default)]
 pub struct Recorder {
     pipeline: RefCell<Option<(gst::Pipeline, BusWatchGuard, gio::MemoryOutputStream)>>,
+    /// When the kept audio started, which is later than the start of the
+    /// pipeline while waiting for music
+    start_time: Rc<Cell<Option<Instant>>>,
+    /// Bytes of the last stopped recording, which are only kept in memory
+    last_recording: RefCell<Option<glib::Bytes>>,
 }
 
 impl Drop for Recorder {
     fn drop(&mut self) {
-        if let Err(err) = self.stop() {
-            tracing::debug!("Failed to stop on dispose: {:?}", err);
-        }
+        self.cancel();
     }
 }
 
 impl Recorder {
+    /// `clipping_callback` is called once the options' clipping detector
+    /// detects clipping, including clipping caused by the gain.
     pub fn start(
         &self,
-        device_name: Option<&str>,
+        options: &RecordOptions,
         peak_callback: impl Fn(f64) + 'static,
+        clipping_callback: impl Fn() + 'static,
     ) -> Result<()> {
         ensure!(
             self.pipeline.borrow().is_none(),
             "there is already a recording in progress"
         );
 
+        self.last_recording.take();
+
         let output_stream = gio::MemoryOutputStream::new_resizable();
-        let pipeline = create_pipeline(&output_stream, device_name)?;
+        let (pipeline, valve) = create_pipeline(
+            &output_stream,
+            options.device_name.as_deref(),
+            options.format,
+            options.gain_db,
+        )?;
+        valve.set_property("drop", options.music_start_detector.is_some());
 
+        let start_time = Instant::now();
+        let waits_for_music = options.music_start_detector.is_some();
+        let kept_start_time = Rc::clone(&self.start_time);
+        let music_start_detector = RefCell::new(options.music_start_detector);
+        let clipping_detector = RefCell::new(options.clipping_detector);
+        let peak_smoother = RefCell::new(options.peak_smoother);
         let bus_watch_guard = pipeline
             .bus()
             .unwrap()
             .add_watch_local(
-                clone!(@weak pipeline => @default-return glib::ControlFlow::Break, move |_, message| {
-                    handle_bus_message(&pipeline, message, &peak_callback)
+                clone!(@weak pipeline, @weak valve => @default-return glib::ControlFlow::Break, move |_, message| {
+                    handle_bus_message(&pipeline, message, &|peak| {
+                        let mut music_start_detector = music_start_detector.borrow_mut();
+                        if music_start_detector
+                            .as_mut()
+                            .is_some_and(|detector| detector.process(peak, start_time.elapsed()))
+                        {
+                            tracing::debug!(elapsed = ?start_time.elapsed(), "Music started; opening valve");
+                            valve.set_property("drop", false);
+                            music_start_detector.take();
+                            kept_start_time.set(Some(Instant::now()));
+                        }
+
+                        if clipping_detector.borrow_mut().process(peak, start_time.elapsed()) {
+                            tracing::debug!(elapsed = ?start_time.elapsed(), "Input is clipping");
+                            clipping_callback();
+                        }
+
+                        let smoothed_peak = peak_smoother.borrow_mut().process(peak);
+                        peak_callback(smoothed_peak);
+                    })
                 }),
             )
             .unwrap();
         self.pipeline
             .replace(Some((pipeline.clone(), bus_watch_guard, output_stream)));
 
-        pipeline.set_state(gst::State::Playing)?;
+        if let Err(err) = play_capture(&pipeline) {
+            self.cancel();
+            return Err(err);
+        }
+
+        if !waits_for_music {
+            self.start_time.set(Some(Instant::now()));
+        }
 
         Ok(())
     }
 
-    pub fn stop(&self) -> Result<glib::Bytes> {
-        let (pipeline, _bus_watch_guard, stream) = self
+    /// Returns the duration of the audio kept so far, which excludes what was
+    /// discarded while waiting for music, or `None` if there is no recording
+    /// in progress or nothing was kept yet.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.start_time.get().map(|start_time| start_time.elapsed())
+    }
+
+    /// Stops the recording and returns its bytes, after waiting for the
+    /// encoder to finish them.
+    pub async fn stop(&self) -> Result<glib::Bytes> {
+        self.start_time.set(None);
+
+        let (pipeline, bus_watch_guard, stream) = self
             .pipeline
             .take()
             .ok_or_else(|| anyhow!("Recording has not been started"))?;
 
+        // The levels are not needed anymore, and the bus can't be streamed
+        // while it is watched
+        drop(bus_watch_guard);
+
+        // Muxers only finalize the file, e.g., patch the WAV header sizes or
+        // write the last Ogg page, on end of stream.
+        if pipeline.current_state() == gst::State::Playing {
+            let flush_start = Instant::now();
+            match flush_eos(&pipeline).await {
+                Ok(()) => tracing::debug!("Flushed end of stream in {:?}", flush_start.elapsed()),
+                Err(err) => tracing::warn!("Failed to flush end of stream: {:?}", err),
+            }
+        }
+
+        self.finish(&pipeline, &stream)
+    }
+
+    /// Stops the recording without waiting for the encoder, e.g., when the
+    /// recognition is cancelled, so its end may be cut.
+    pub fn cancel(&self) {
+        self.start_time.set(None);
+
+        let Some((pipeline, _bus_watch_guard, stream)) = self.pipeline.take() else {
+            return;
+        };
+
+        if let Err(err) = self.finish(&pipeline, &stream) {
+            tracing::debug!("Failed to finish cancelled recording: {:?}", err);
+        }
+    }
+
+    fn finish(
+        &self,
+        pipeline: &gst::Pipeline,
+        stream: &gio::MemoryOutputStream,
+    ) -> Result<glib::Bytes> {
         pipeline.set_state(gst::State::Null)?;
         stream.close(gio::Cancellable::NONE)?;
 
-        Ok(stream.steal_as_bytes())
+        let bytes = stream.steal_as_bytes();
+        self.last_recording.replace(Some(bytes.clone()));
+
+        Ok(bytes)
+    }
+
+    /// Returns the bytes of the last stopped recording, until another one is
+    /// started or it is cleared.
+    pub fn last_recording(&self) -> Option<glib::Bytes> {
+        self.last_recording.borrow().clone()
+    }
+
+    pub fn clear_last_recording(&self) {
+        self.last_recording.take();
     }
 }
 
+/// Sends an end of stream and waits for it to reach the sink, up to
+/// [`EOS_TIMEOUT`].
+///
+/// The bus of the pipeline must not be watched.
+async fn flush_eos(pipeline: &gst::Pipeline) -> Result<()> {
+    // A closed valve would drop the end of stream too
+    if let Some(valve) = pipeline.by_name("valve") {
+        valve.set_property("drop", false);
+    }
+
+    let mut messages = pipeline
+        .bus()
+        .unwrap()
+        .stream_filtered(&[gst::MessageType::Eos, gst::MessageType::Error]);
+
+    ensure!(
+        pipeline.send_event(gst::event::Eos::new()),
+        "Pipeline did not handle end of stream"
+    );
+
+    let message = glib::future_with_timeout(EOS_TIMEOUT, messages.next())
+        .await
+        .map_err(|_| anyhow!("Timed out waiting for end of stream"))?
+        .ok_or_else(|| anyhow!("Bus stream ended before end of stream"))?;
+
+    if let gst::MessageView::Error(e) = message.view() {
+        return Err(anyhow!(e.error()).context("Received error while flushing"));
+    }
+
+    Ok(())
+}
+
 fn handle_bus_message(
     pipeline: &gst::Pipeline,
     message: &gst::Message,
@@ -76,18 +451,8 @@ fn handle_bus_message(
         MessageView::Element(e) => {
             tracing::trace!("Received element message on bus: {:?}", e);
 
-            if let Some(structure) = e.structure() {
-                if structure.has_name("level") {
-                    let peak = structure
-                        .get::<&glib::ValueArray>("peak")
-                        .unwrap()
-                        .first()
-                        .unwrap()
-                        .get::<f64>()
-                        .unwrap();
-                    let normalized_peak = 10_f64.powf(peak / 20.0);
-                    peak_callback(normalized_peak);
-                }
+            if let Some(peak) = level_peak(message) {
+                peak_callback(peak);
             }
 
             glib::ControlFlow::Continue
@@ -141,58 +506,573 @@ fn handle_bus_message(
     }
 }
 
+/// Returns the normalized peak, from 0 to 1, of the first channel if the
+/// message is from a `level` element.
+pub fn level_peak(message: &gst::Message) -> Option<f64> {
+    let gst::MessageView::Element(e) = message.view() else {
+        return None;
+    };
+
+    let structure = e.structure().filter(|s| s.has_name("level"))?;
+    let peak = structure
+        .get::<&glib::ValueArray>("peak")
+        .ok()?
+        .first()?
+        .get::<f64>()
+        .ok()?;
+    Some(10_f64.powf(peak / 20.0))
+}
+
+/// Returns the linear volume that amplifies by `gain_db` decibels, clamped
+/// between unity and [`MAX_GAIN_DB`].
+fn gain_to_volume(gain_db: f64) -> f64 {
+    10_f64.powf(gain_db.clamp(0.0, MAX_GAIN_DB) / 20.0)
+}
+
+/// Returns the pipeline and its `valve`, which drops the audio going to the
+/// encoder while its `drop` property is set.
 fn create_pipeline(
     stream: &gio::MemoryOutputStream,
     device_name: Option<&str>,
-) -> Result<gst::Pipeline> {
+    format: RecordingFormat,
+    gain_db: f64,
+) -> Result<(gst::Pipeline, gst::Element)> {
     let pipeline = gst::Pipeline::new();
 
+    let level = add_capture(&pipeline, device_name, gain_db)?;
+
+    let valve = gst::ElementFactory::make("valve").name("valve").build()?;
+    pipeline.add(&valve)?;
+    level.link(&valve)?;
+    valve.sync_state_with_parent()?;
+
+    link_encoder(&pipeline, &valve, format, stream)?;
+
+    Ok((pipeline, valve))
+}
+
+/// Adds the elements that capture mono audio at [`SAMPLE_RATE`] from the
+/// device, amplify it by `gain_db`, and measure its level every
+/// [`LEVEL_INTERVAL`], returning the `level` element to link consumers to.
+///
+/// If `device_name` is `None`, the default source is captured.
+pub fn add_capture(
+    pipeline: &gst::Pipeline,
+    device_name: Option<&str>,
+    gain_db: f64,
+) -> Result<gst::Element> {
     let pulsesrc = gst::ElementFactory::make("pulsesrc").build()?;
     let audioconvert = gst::ElementFactory::make("audioconvert").build()?;
-    let level = gst::ElementFactory::make("level")
-        .property("interval", gst::ClockTime::from_mseconds(80))
-        .property("peak-ttl", gst::ClockTime::from_mseconds(80))
+    // Samples are saturated when amplified past full scale, and the level
+    // comes after it, so the resulting clipping is still detected
+    let preamp = gst::ElementFactory::make("volume")
+        .name("preamp")
+        .property("volume", gain_to_volume(gain_db))
         .build()?;
-    let opusenc = gst::ElementFactory::make("opusenc")
-        .property("bitrate", 16_000)
-        .build()?;
-    let oggmux = gst::ElementFactory::make("oggmux").build()?;
-    let giostreamsink = gst::ElementFactory::make("giostreamsink")
-        .property("stream", stream)
+    let level = gst::ElementFactory::make("level")
+        .property(
+            "interval",
+            gst::ClockTime::from_nseconds(LEVEL_INTERVAL.as_nanos() as u64),
+        )
+        .property(
+            "peak-ttl",
+            gst::ClockTime::from_nseconds(LEVEL_INTERVAL.as_nanos() as u64),
+        )
         .build()?;
 
     if let Some(device_name) = device_name {
         pulsesrc.set_property("device", device_name);
         tracing::debug!("Using device `{}` for recording", device_name);
     } else {
-        tracing::warn!("Recording without pulsesrc `device` property set");
+        tracing::debug!("Recording without pulsesrc `device` property set");
     }
 
-    let elements = [
-        &pulsesrc,
-        &audioconvert,
-        &level,
-        &opusenc,
-        &oggmux,
-        &giostreamsink,
-    ];
+    let elements = [&pulsesrc, &audioconvert, &preamp, &level];
     pipeline.add_many(elements)?;
 
     pulsesrc.link_filtered(
         &audioconvert,
         &gst::Caps::builder("audio/x-raw")
             .field("channels", 1)
-            .field("rate", 16_000)
+            .field("rate", SAMPLE_RATE)
             .build(),
     )?;
-    audioconvert.link(&level)?;
-    level.link(&opusenc)?;
-    opusenc.link_filtered(&oggmux, &gst::Caps::builder("audio/x-opus").build())?;
-    oggmux.link_filtered(&giostreamsink, &gst::Caps::builder("audio/ogg").build())?;
+    audioconvert.link(&preamp)?;
+    preamp.link(&level)?;
+
+    for e in elements {
+        e.sync_state_with_parent()?;
+    }
+
+    Ok(level)
+}
+
+/// Starts the pipeline made with [`add_capture`], returning a [`RecordError`]
+/// if the device can't be opened, e.g., when it is busy or access is denied.
+///
+/// On failure, the pipeline is left in `Null`.
+pub fn play_capture(pipeline: &gst::Pipeline) -> Result<()> {
+    if let Err(err) = pipeline.set_state(gst::State::Playing) {
+        // Busy or inaccessible devices fail on state change, so check the bus
+        // for the reason. This must be done before stopping, as the bus is
+        // flushed when the pipeline goes to `Null`.
+        let error_message = pipeline
+            .bus()
+            .unwrap()
+            .pop_filtered(&[gst::MessageType::Error]);
+
+        let _ = pipeline.set_state(gst::State::Null);
+
+        if let Some(gst::MessageView::Error(e)) = error_message.as_ref().map(|m| m.view()) {
+            return Err(RecordError::from_gst_error(e.error()).into());
+        }
+
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// Adds the elements that encode the audio from `upstream` in `format` and
+/// write it to `stream`.
+pub(super) fn link_encoder(
+    pipeline: &gst::Pipeline,
+    upstream: &gst::Element,
+    format: RecordingFormat,
+    stream: &gio::MemoryOutputStream,
+) -> Result<()> {
+    let giostreamsink = gst::ElementFactory::make("giostreamsink")
+        .property("stream", stream)
+        .build()?;
+
+    let elements = match format {
+        RecordingFormat::OggOpus => {
+            let opusenc = gst::ElementFactory::make("opusenc")
+                .property("bitrate", 16_000)
+                .build()?;
+            let oggmux = gst::ElementFactory::make("oggmux").build()?;
+
+            pipeline.add_many([&opusenc, &oggmux, &giostreamsink])?;
+
+            upstream.link(&opusenc)?;
+            opusenc.link_filtered(&oggmux, &gst::Caps::builder("audio/x-opus").build())?;
+            oggmux.link_filtered(&giostreamsink, &gst::Caps::builder("audio/ogg").build())?;
+
+            vec![opusenc, oggmux, giostreamsink]
+        }
+        RecordingFormat::Wav => {
+            // This seeks back to the header to write the sizes on end of
+            // stream, which the memory stream supports.
+            let wavenc = gst::ElementFactory::make("wavenc").build()?;
+
+            pipeline.add_many([&wavenc, &giostreamsink])?;
+
+            upstream.link_filtered(
+                &wavenc,
+                &gst::Caps::builder("audio/x-raw")
+                    .field("format", "S16LE")
+                    .build(),
+            )?;
+            wavenc.link_filtered(&giostreamsink, &gst::Caps::builder("audio/x-wav").build())?;
+
+            vec![wavenc, giostreamsink]
+        }
+    };
 
     for e in elements {
         e.sync_state_with_parent()?;
     }
 
-    Ok(pipeline)
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_error_kind() {
+        gst::init().unwrap();
+
+        let err = RecordError::from_gst_error(glib::Error::new(
+            gst::ResourceError::Busy,
+            "Device or resource busy",
+        ));
+        assert_eq!(err.kind(), RecordErrorKind::DeviceBusy);
+        assert_eq!(
+            err.to_string(),
+            "The audio device is busy. Close other apps using it and try again"
+        );
+
+        let err = RecordError::mic_access_denied();
+        assert_eq!(err.kind(), RecordErrorKind::PermissionDenied);
+        assert_eq!(
+            err.to_string(),
+            "Access to the audio device was denied. Check your privacy settings and try again"
+        );
+
+        let err = RecordError::from_gst_error(glib::Error::new(
+            gst::ResourceError::OpenRead,
+            "Failed to connect: Connection refused",
+        ));
+        assert_eq!(err.kind(), RecordErrorKind::Other);
+        assert_eq!(err.to_string(), "Failed to connect: Connection refused");
+    }
+
+    /// Returns the time the detector opens at while feeding it `peaks`, one per
+    /// level interval.
+    fn music_start(threshold: f64, peaks: impl IntoIterator<Item = f64>) -> Option<Duration> {
+        let mut detector = MusicStartDetector::new(threshold);
+        peaks
+            .into_iter()
+            .enumerate()
+            .map(|(i, peak)| (LEVEL_INTERVAL * i as u32, peak))
+            .find(|(elapsed, peak)| detector.process(*peak, *elapsed))
+            .map(|(elapsed, _)| elapsed)
+    }
+
+    /// Returns the times the detector detects clipping at while feeding it
+    /// `peaks`, one per level interval.
+    fn clipping(sustain: Duration, peaks: impl IntoIterator<Item = f64>) -> Vec<Duration> {
+        let mut detector = ClippingDetector::new(0.99, sustain);
+        peaks
+            .into_iter()
+            .enumerate()
+            .map(|(i, peak)| (LEVEL_INTERVAL * i as u32, peak))
+            .filter(|(elapsed, peak)| detector.process(*peak, *elapsed))
+            .map(|(elapsed, _)| elapsed)
+            .collect()
+    }
+
+    /// Returns the body of the first chunk with `id` in a RIFF file.
+    fn riff_chunk<'a>(bytes: &'a [u8], id: &[u8]) -> Option<&'a [u8]> {
+        let mut offset = 12;
+        while offset + 8 <= bytes.len() {
+            let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap());
+            let body_start = offset + 8;
+            let body_end = body_start + size as usize;
+            if &bytes[offset..offset + 4] == id {
+                return bytes.get(body_start..body_end);
+            }
+            // Chunks are padded to an even size
+            offset = body_end + (size as usize % 2);
+        }
+        None
+    }
+
+    #[test]
+    fn wav() {
+        gst::init().unwrap();
+
+        const N_BUFFERS: i32 = 10;
+        const SAMPLES_PER_BUFFER: i32 = 1600;
+
+        let pipeline = gst::Pipeline::new();
+        let src = gst::ElementFactory::make("audiotestsrc")
+            .property("num-buffers", N_BUFFERS)
+            .property("samplesperbuffer", SAMPLES_PER_BUFFER)
+            .build()
+            .unwrap();
+        let capsfilter = gst::ElementFactory::make("capsfilter")
+            .property(
+                "caps",
+                gst::Caps::builder("audio/x-raw")
+                    .field("channels", 1)
+                    .field("rate", SAMPLE_RATE)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        pipeline.add_many([&src, &capsfilter]).unwrap();
+        src.link(&capsfilter).unwrap();
+
+        let stream = gio::MemoryOutputStream::new_resizable();
+        link_encoder(&pipeline, &capsfilter, RecordingFormat::Wav, &stream).unwrap();
+
+        pipeline.set_state(gst::State::Playing).unwrap();
+        let message = pipeline
+            .bus()
+            .unwrap()
+            .timed_pop_filtered(
+                gst::ClockTime::from_seconds(5),
+                &[gst::MessageType::Eos, gst::MessageType::Error],
+            )
+            .unwrap();
+        assert!(matches!(message.view(), gst::MessageView::Eos(_)));
+        pipeline.set_state(gst::State::Null).unwrap();
+        stream.close(gio::Cancellable::NONE).unwrap();
+
+        let bytes = stream.steal_as_bytes();
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[8..12], b"WAVE");
+        assert_eq!(
+            u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize,
+            bytes.len() - 8
+        );
+
+        let fmt = riff_chunk(&bytes, b"fmt ").unwrap();
+        let n_channels = u16::from_le_bytes(fmt[2..4].try_into().unwrap());
+        let sample_rate = u32::from_le_bytes(fmt[4..8].try_into().unwrap());
+        let bits_per_sample = u16::from_le_bytes(fmt[14..16].try_into().unwrap());
+        assert_eq!(n_channels, 1);
+        assert_eq!(sample_rate, SAMPLE_RATE as u32);
+        assert_eq!(bits_per_sample, 16);
+
+        let data = riff_chunk(&bytes, b"data").unwrap();
+        assert_eq!(data.len(), (N_BUFFERS * SAMPLES_PER_BUFFER * 2) as usize);
+    }
+
+    /// Decodes the Ogg Opus `bytes` and returns the total duration of the
+    /// decoded audio.
+    fn decoded_ogg_opus_duration(bytes: glib::Bytes) -> gst::ClockTime {
+        let pipeline = gst::parse_launch(
+            "giostreamsrc name=src ! oggdemux ! opusdec ! fakesink name=sink sync=false",
+        )
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        pipeline
+            .by_name("src")
+            .unwrap()
+            .set_property("stream", gio::MemoryInputStream::from_bytes(&bytes));
+
+        let duration = std::sync::Arc::new(std::sync::Mutex::new(gst::ClockTime::ZERO));
+        let duration_clone = duration.clone();
+        pipeline
+            .by_name("sink")
+            .unwrap()
+            .static_pad("sink")
+            .unwrap()
+            .add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+                if let Some(buffer) = info.buffer() {
+                    *duration_clone.lock().unwrap() += buffer.duration().unwrap_or_default();
+                }
+                gst::PadProbeReturn::Ok
+            });
+
+        pipeline.set_state(gst::State::Playing).unwrap();
+        let message = pipeline
+            .bus()
+            .unwrap()
+            .timed_pop_filtered(
+                gst::ClockTime::from_seconds(5),
+                &[gst::MessageType::Eos, gst::MessageType::Error],
+            )
+            .unwrap();
+        assert!(matches!(message.view(), gst::MessageView::Eos(_)));
+        let decoded_duration = *duration.lock().unwrap();
+        pipeline.set_state(gst::State::Null).unwrap();
+
+        decoded_duration
+    }
+
+    #[test]
+    fn stop_flushes_eos() {
+        gst::init().unwrap();
+
+        let pipeline = gst::Pipeline::new();
+        let src = gst::ElementFactory::make("audiotestsrc")
+            .property("is-live", true)
+            .property("samplesperbuffer", 1600)
+            .build()
+            .unwrap();
+        let capsfilter = gst::ElementFactory::make("capsfilter")
+            .property(
+                "caps",
+                gst::Caps::builder("audio/x-raw")
+                    .field("channels", 1)
+                    .field("rate", SAMPLE_RATE)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        pipeline.add_many([&src, &capsfilter]).unwrap();
+        src.link(&capsfilter).unwrap();
+
+        let stream = gio::MemoryOutputStream::new_resizable();
+        link_encoder(&pipeline, &capsfilter, RecordingFormat::OggOpus, &stream).unwrap();
+
+        pipeline.set_state(gst::State::Playing).unwrap();
+        pipeline.state(gst::ClockTime::from_seconds(5)).0.unwrap();
+
+        let ctx = glib::MainContext::default();
+        ctx.block_on(glib::timeout_future(Duration::from_secs(2)));
+        ctx.block_on(flush_eos(&pipeline)).unwrap();
+        let recorded_duration = pipeline.query_position::<gst::ClockTime>().unwrap();
+        pipeline.set_state(gst::State::Null).unwrap();
+        stream.close(gio::Cancellable::NONE).unwrap();
+
+        // Without flushing, the last pages of up to a second would be missing
+        let decoded_duration = decoded_ogg_opus_duration(stream.steal_as_bytes());
+        let difference = if decoded_duration > recorded_duration {
+            decoded_duration - recorded_duration
+        } else {
+            recorded_duration - decoded_duration
+        };
+        assert!(
+            difference < gst::ClockTime::from_mseconds(200),
+            "decoded {} but recorded {}",
+            decoded_duration,
+            recorded_duration
+        );
+    }
+
+    #[gtk::test]
+    fn last_recording() {
+        gst::init().unwrap();
+
+        let recorder = Recorder::default();
+        assert!(recorder.last_recording().is_none());
+
+        // Same as `start`, but with a test source instead of a device
+        let pipeline = gst::Pipeline::new();
+        let src = gst::ElementFactory::make("audiotestsrc")
+            .property("is-live", true)
+            .build()
+            .unwrap();
+        let capsfilter = gst::ElementFactory::make("capsfilter")
+            .property(
+                "caps",
+                gst::Caps::builder("audio/x-raw")
+                    .field("channels", 1)
+                    .field("rate", SAMPLE_RATE)
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        pipeline.add_many([&src, &capsfilter]).unwrap();
+        src.link(&capsfilter).unwrap();
+
+        let stream = gio::MemoryOutputStream::new_resizable();
+        link_encoder(&pipeline, &capsfilter, RecordingFormat::Wav, &stream).unwrap();
+        let bus_watch_guard = pipeline
+            .bus()
+            .unwrap()
+            .add_watch_local(|_, _| glib::ControlFlow::Continue)
+            .unwrap();
+        recorder
+            .pipeline
+            .replace(Some((pipeline.clone(), bus_watch_guard, stream)));
+
+        pipeline.set_state(gst::State::Playing).unwrap();
+        pipeline.state(gst::ClockTime::from_seconds(5)).0.unwrap();
+
+        let ctx = glib::MainContext::default();
+        ctx.block_on(glib::timeout_future(Duration::from_millis(500)));
+        let bytes = ctx.block_on(recorder.stop()).unwrap();
+        assert!(!bytes.is_empty());
+        assert_eq!(recorder.last_recording(), Some(bytes));
+
+        // Kept when stopping without a recording
+        assert!(ctx.block_on(recorder.stop()).is_err());
+        recorder.cancel();
+        assert!(recorder.last_recording().is_some());
+
+        recorder.clear_last_recording();
+        assert!(recorder.last_recording().is_none());
+    }
+
+    #[test]
+    fn gain_to_volume_clamped() {
+        assert_eq!(gain_to_volume(0.0), 1.0);
+        assert!((gain_to_volume(6.0) - 1.995).abs() < 0.001);
+        assert_eq!(gain_to_volume(-6.0), 1.0);
+        assert_eq!(gain_to_volume(100.0), gain_to_volume(MAX_GAIN_DB));
+    }
+
+    #[test]
+    fn pipeline_preamp() {
+        gst::init().unwrap();
+
+        let stream = gio::MemoryOutputStream::new_resizable();
+
+        let (pipeline, _) = create_pipeline(&stream, None, RecordingFormat::Wav, 0.0).unwrap();
+        let preamp = pipeline.by_name("preamp").unwrap();
+        assert_eq!(preamp.property::<f64>("volume"), 1.0);
+
+        let (pipeline, _) = create_pipeline(&stream, None, RecordingFormat::Wav, 6.0).unwrap();
+        let preamp = pipeline.by_name("preamp").unwrap();
+        assert_eq!(preamp.property::<f64>("volume"), gain_to_volume(6.0));
+    }
+
+    #[test]
+    fn music_start_after_silence() {
+        let silence = std::iter::repeat(0.01).take(20);
+        let tone = std::iter::repeat(0.8).take(10);
+
+        let tone_start = LEVEL_INTERVAL * 20;
+        let start = music_start(0.1, silence.chain(tone)).unwrap();
+        assert!(start >= tone_start);
+        assert!(start - tone_start <= MUSIC_START_SUSTAIN);
+    }
+
+    #[test]
+    fn music_start_ignores_spikes() {
+        let peaks = [0.0, 0.9, 0.0, 0.0, 0.9, 0.0, 0.9, 0.9, 0.9];
+        assert_eq!(music_start(0.1, peaks), Some(LEVEL_INTERVAL * 8));
+    }
+
+    #[test]
+    fn music_start_timeout() {
+        let start = music_start(0.1, std::iter::repeat(0.0).take(100)).unwrap();
+        assert!(start >= MUSIC_START_TIMEOUT);
+        assert!(start - MUSIC_START_TIMEOUT < LEVEL_INTERVAL);
+    }
+
+    #[test]
+    fn clipping_sustained() {
+        let quiet = std::iter::repeat(0.5).take(10);
+        let clipping = std::iter::repeat(1.0).take(20);
+
+        // Detected once, even though it keeps clipping
+        assert_eq!(
+            clipping(LEVEL_INTERVAL * 4, quiet.chain(clipping)),
+            [LEVEL_INTERVAL * 14]
+        );
+    }
+
+    #[test]
+    fn clipping_ignores_spikes() {
+        let peaks = [1.0, 0.5, 1.0, 1.0, 0.5, 0.995, 1.0, 1.0, 0.5];
+        assert_eq!(clipping(LEVEL_INTERVAL * 2, peaks), [LEVEL_INTERVAL * 7]);
+        assert!(clipping(LEVEL_INTERVAL * 3, peaks).is_empty());
+        assert!(clipping(Duration::ZERO, [0.5, 0.98]).is_empty());
+    }
+
+    #[test]
+    fn peak_smoother_raw() {
+        let mut smoother = PeakSmoother::raw();
+        assert_eq!(smoother.process(1.0), 1.0);
+        assert_eq!(smoother.process(0.0), 0.0);
+        assert_eq!(smoother.process(0.5), 0.5);
+    }
+
+    #[test]
+    fn peak_smoother_step() {
+        let mut smoother = PeakSmoother::new(Duration::ZERO, Duration::from_millis(300));
+
+        // Rises immediately with zero attack
+        assert_eq!(smoother.process(1.0), 1.0);
+
+        // Then decays monotonically toward zero
+        let mut prev = 1.0;
+        for _ in 0..50 {
+            let value = smoother.process(0.0);
+            assert!(value < prev, "{} must be less than {}", value, prev);
+            assert!(value >= 0.0);
+            prev = value;
+        }
+        assert!(prev < 0.01);
+    }
+
+    #[test]
+    fn peak_smoother_attack() {
+        let mut smoother = PeakSmoother::default();
+
+        let first = smoother.process(1.0);
+        assert!(first > 0.0 && first < 1.0);
+
+        let second = smoother.process(1.0);
+        assert!(second > first && second <= 1.0);
+    }
 }
@@ -16,7 +16,7 @@ use std::{
 
 use super::Recording;
 use crate::{
-    database::{EnvExt, RECORDINGS_DB_NAME},
+    database::{EnvExt, RawDatabase, RECORDINGS_DB_NAME},
     uid::{Uid, UidCodec},
     utils,
 };
@@ -198,6 +198,11 @@ impl Recordings {
         self.n_items() == 0
     }
 
+    /// Returns the db of the recordings viewed as raw bytes.
+    pub fn raw_database(&self) -> RawDatabase {
+        self.db().1.remap_types()
+    }
+
     fn db(&self) -> &(heed::Env, RecordingDatabase) {
         self.imp().db.get().unwrap()
     }
@@ -0,0 +1,85 @@
+use anyhow::{Context, Result};
+use gst::{bus::BusWatchGuard, prelude::*};
+use gtk::{
+    gio,
+    glib::{self, clone},
+};
+
+use std::cell::RefCell;
+
+/// Plays recorded audio straight from memory, independent of the preview
+/// [`crate::player::Player`].
+#[derive(Default)]
+pub struct RecordingReplayer {
+    active: RefCell<Option<(gst::Pipeline, BusWatchGuard)>>,
+}
+
+impl RecordingReplayer {
+    /// Plays the recording without waiting for it to finish, stopping the
+    /// previous one if it is still playing.
+    pub fn play(&self, bytes: &glib::Bytes) -> Result<()> {
+        self.stop();
+
+        let pipeline = gst::parse_launch(
+            "giostreamsrc name=src ! decodebin ! audioconvert ! audioresample ! autoaudiosink",
+        )
+        .context("Failed to create replay pipeline")?
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+
+        let input_stream = gio::MemoryInputStream::from_bytes(bytes);
+        pipeline
+            .by_name("src")
+            .unwrap()
+            .set_property("stream", &input_stream);
+
+        let bus_watch_guard = pipeline
+            .bus()
+            .unwrap()
+            .add_watch_local(
+                clone!(@weak pipeline => @default-return glib::ControlFlow::Break, move |_, message| {
+                    handle_bus_message(&pipeline, message)
+                }),
+            )
+            .context("Failed to add bus watch")?;
+
+        pipeline
+            .set_state(gst::State::Playing)
+            .context("Failed to play recording")?;
+
+        self.active.replace(Some((pipeline, bus_watch_guard)));
+
+        tracing::debug!(n_bytes = bytes.len(), "Replaying recording");
+
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Some((pipeline, _bus_watch_guard)) = self.active.take() {
+            let _ = pipeline.set_state(gst::State::Null);
+        }
+    }
+}
+
+impl Drop for RecordingReplayer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn handle_bus_message(pipeline: &gst::Pipeline, message: &gst::Message) -> glib::ControlFlow {
+    use gst::MessageView;
+
+    match message.view() {
+        MessageView::Eos(_) => {
+            let _ = pipeline.set_state(gst::State::Null);
+            glib::ControlFlow::Break
+        }
+        MessageView::Error(e) => {
+            tracing::warn!(debug = ?e.debug(), err = ?e.error(), "Received error at replay bus");
+            let _ = pipeline.set_state(gst::State::Null);
+            glib::ControlFlow::Break
+        }
+        _ => glib::ControlFlow::Continue,
+    }
+}
@@ -0,0 +1,342 @@
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use gettextrs::gettext;
+use gst::prelude::*;
+use gtk::{gio, glib};
+
+use std::{
+    error, fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use super::recorder::{self, SAMPLE_RATE};
+use crate::settings::RecordingFormat;
+
+/// Schemes of the links that audio may be decoded from
+const ALLOWED_SCHEMES: [&str; 3] = ["file", "http", "https"];
+
+/// How much longer than the audio decoding it may take, e.g., on a slow
+/// connection, before giving up
+const DECODE_TIMEOUT_MARGIN: Duration = Duration::from_secs(20);
+
+/// Name of the message posted once enough audio is decoded
+const DURATION_REACHED_MESSAGE_NAME: &str = "mousai-duration-reached";
+
+/// Why audio could not be decoded from a link
+#[derive(Debug)]
+pub enum DecodeUriError {
+    /// The link is not one that audio is decoded from
+    UnsupportedScheme(String),
+    /// The link does not point to audio that can be decoded, e.g., a web page
+    /// or a stream protected by DRM
+    Unsupported(glib::Error),
+    /// The link points to a stream without audio
+    NoAudio,
+    /// Loading the audio took too long
+    TimedOut,
+    Other(glib::Error),
+}
+
+impl DecodeUriError {
+    fn from_gst_error(source: glib::Error) -> Self {
+        if source.matches(gst::StreamError::TypeNotFound)
+            || source.matches(gst::StreamError::WrongType)
+            || source.matches(gst::StreamError::CodecNotFound)
+            || source.matches(gst::StreamError::Decrypt)
+            || source.matches(gst::StreamError::DecryptNokey)
+            || source.matches(gst::CoreError::MissingPlugin)
+        {
+            Self::Unsupported(source)
+        } else {
+            Self::Other(source)
+        }
+    }
+}
+
+impl fmt::Display for DecodeUriError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedScheme(_) => f.write_str(&gettext(
+                "Only web links and files can be recognized",
+            )),
+            Self::Unsupported(_) => f.write_str(&gettext(
+                "The link does not point to playable audio. Protected streams and web pages are not supported",
+            )),
+            Self::NoAudio => f.write_str(&gettext("The link has no audio")),
+            Self::TimedOut => f.write_str(&gettext("Loading the audio from the link took too long")),
+            Self::Other(source) => write!(f, "{}", source),
+        }
+    }
+}
+
+impl error::Error for DecodeUriError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Unsupported(source) | Self::Other(source) => Some(source),
+            Self::UnsupportedScheme(_) | Self::NoAudio | Self::TimedOut => None,
+        }
+    }
+}
+
+/// Decodes up to `max_duration` of the audio at `uri`, only downloading what
+/// is needed for that, and encodes it in `format` as if it was recorded.
+pub async fn decode(
+    uri: &str,
+    max_duration: Duration,
+    format: RecordingFormat,
+) -> Result<glib::Bytes> {
+    let scheme = glib::Uri::peek_scheme(uri).unwrap_or_default();
+    if !ALLOWED_SCHEMES.contains(&scheme.as_str()) {
+        return Err(DecodeUriError::UnsupportedScheme(scheme.to_string()).into());
+    }
+
+    let pipeline = gst::Pipeline::new();
+    let uridecodebin = gst::ElementFactory::make("uridecodebin")
+        .property("uri", uri)
+        .build()?;
+    let audioconvert = gst::ElementFactory::make("audioconvert").build()?;
+    let audioresample = gst::ElementFactory::make("audioresample").build()?;
+    let capsfilter = gst::ElementFactory::make("capsfilter")
+        .property(
+            "caps",
+            gst::Caps::builder("audio/x-raw")
+                .field("channels", 1)
+                .field("rate", SAMPLE_RATE)
+                .build(),
+        )
+        .build()?;
+    pipeline.add_many([&uridecodebin, &audioconvert, &audioresample, &capsfilter])?;
+    gst::Element::link_many([&audioconvert, &audioresample, &capsfilter])?;
+
+    let stream = gio::MemoryOutputStream::new_resizable();
+    recorder::link_encoder(&pipeline, &capsfilter, format, &stream)?;
+
+    // Only the first audio stream is decoded, e.g., of a video
+    let audioconvert_sink_pad = audioconvert.static_pad("sink").unwrap();
+    uridecodebin.connect_pad_added(move |_, pad| {
+        if audioconvert_sink_pad.is_linked() {
+            return;
+        }
+
+        let is_audio = pad
+            .current_caps()
+            .unwrap_or_else(|| pad.query_caps(None))
+            .structure(0)
+            .is_some_and(|structure| structure.name().starts_with("audio/"));
+        if !is_audio {
+            return;
+        }
+
+        if let Err(err) = pad.link(&audioconvert_sink_pad) {
+            tracing::warn!("Failed to link decoded audio pad: {:?}", err);
+        }
+    });
+
+    let decoded_nanos = Arc::new(AtomicU64::new(0));
+    let is_duration_reached = Arc::new(AtomicBool::new(false));
+    let max_nanos = max_duration.as_nanos() as u64;
+    capsfilter.static_pad("src").unwrap().add_probe(
+        gst::PadProbeType::BUFFER,
+        glib::clone!(@strong decoded_nanos => move |pad, info| {
+            if is_duration_reached.load(Ordering::Relaxed) {
+                return gst::PadProbeReturn::Drop;
+            }
+
+            let Some(buffer) = info.buffer() else {
+                return gst::PadProbeReturn::Ok;
+            };
+            let buffer_nanos = buffer.duration().map_or(0, gst::ClockTime::nseconds);
+            let decoded = decoded_nanos.fetch_add(buffer_nanos, Ordering::Relaxed) + buffer_nanos;
+
+            if decoded >= max_nanos {
+                is_duration_reached.store(true, Ordering::Relaxed);
+
+                // The end of stream is sent from the main context, as it
+                // can't be sent from the streaming thread.
+                if let Some(element) = pad.parent_element() {
+                    let message = gst::message::Application::builder(gst::Structure::new_empty(
+                        DURATION_REACHED_MESSAGE_NAME,
+                    ))
+                    .src(&element)
+                    .build();
+                    let _ = element.post_message(message);
+                }
+            }
+
+            gst::PadProbeReturn::Ok
+        }),
+    );
+
+    let mut messages = pipeline.bus().unwrap().stream();
+
+    let res = glib::future_with_timeout(max_duration + DECODE_TIMEOUT_MARGIN, async {
+        pipeline
+            .set_state(gst::State::Playing)
+            .map_err(|_| DecodeUriError::NoAudio)?;
+
+        while let Some(message) = messages.next().await {
+            use gst::MessageView;
+
+            match message.view() {
+                MessageView::Eos(_) => break,
+                MessageView::Error(e) => {
+                    tracing::debug!(debug = ?e.debug(), "Received error while decoding link");
+                    return Err(DecodeUriError::from_gst_error(e.error()));
+                }
+                MessageView::Application(a)
+                    if a.structure()
+                        .is_some_and(|s| s.name() == DURATION_REACHED_MESSAGE_NAME) =>
+                {
+                    tracing::debug!("Decoded enough audio from link");
+                    pipeline.send_event(gst::event::Eos::new());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    })
+    .await;
+
+    let _ = pipeline.set_state(gst::State::Null);
+
+    res.map_err(|_| DecodeUriError::TimedOut)??;
+
+    if decoded_nanos.load(Ordering::Relaxed) == 0 {
+        return Err(DecodeUriError::NoAudio.into());
+    }
+
+    stream
+        .close(gio::Cancellable::NONE)
+        .context("Failed to close stream")?;
+
+    Ok(stream.steal_as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `duration` of a test tone to a WAV file in `dir`, returning its
+    /// uri.
+    fn new_wav_file(dir: &std::path::Path, duration: Duration) -> String {
+        let path = dir.join("tone.wav");
+        let n_buffers = duration.as_millis() as i32 / 100;
+
+        let pipeline = gst::parse_launch(&format!(
+            "audiotestsrc num-buffers={} samplesperbuffer=4410 ! audio/x-raw,rate=44100 ! wavenc ! filesink location={}",
+            n_buffers,
+            path.display()
+        ))
+        .unwrap();
+        pipeline.set_state(gst::State::Playing).unwrap();
+        pipeline
+            .bus()
+            .unwrap()
+            .timed_pop_filtered(gst::ClockTime::from_seconds(5), &[gst::MessageType::Eos])
+            .unwrap();
+        pipeline.set_state(gst::State::Null).unwrap();
+
+        glib::filename_to_uri(path, None).unwrap().to_string()
+    }
+
+    /// Returns the duration of the decoded WAV `bytes`.
+    fn wav_duration(bytes: &[u8]) -> Duration {
+        // 16-bit mono samples after the 44-byte header
+        let n_samples = (bytes.len() - 44) / 2;
+        Duration::from_secs_f64(n_samples as f64 / SAMPLE_RATE as f64)
+    }
+
+    #[gtk::test]
+    fn file_capped() {
+        gst::init().unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let uri = new_wav_file(dir.path(), Duration::from_secs(3));
+
+        let bytes = glib::MainContext::default()
+            .block_on(decode(&uri, Duration::from_secs(1), RecordingFormat::Wav))
+            .unwrap();
+        assert_eq!(&bytes[0..4], b"RIFF");
+
+        // Decoding stops within a buffer past the maximum
+        let duration = wav_duration(&bytes);
+        assert!(duration >= Duration::from_secs(1), "{:?}", duration);
+        assert!(duration < Duration::from_millis(1500), "{:?}", duration);
+    }
+
+    #[gtk::test]
+    fn file_shorter() {
+        gst::init().unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let uri = new_wav_file(dir.path(), Duration::from_secs(1));
+
+        let bytes = glib::MainContext::default()
+            .block_on(decode(&uri, Duration::from_secs(5), RecordingFormat::Wav))
+            .unwrap();
+        let duration = wav_duration(&bytes);
+        assert!(duration >= Duration::from_millis(900), "{:?}", duration);
+        assert!(duration <= Duration::from_millis(1100), "{:?}", duration);
+    }
+
+    #[gtk::test]
+    fn recognize_file() {
+        use crate::{
+            recognizer::{ProviderSettings, ProviderType, Recognizer, TestProviderMode},
+            song::RecognitionSource,
+        };
+
+        gst::init().unwrap();
+
+        let mut provider_settings = ProviderSettings::lock();
+        provider_settings.active = ProviderType::AudDMock;
+        provider_settings.test_mode = TestProviderMode::ValidOnly;
+        provider_settings.test_listen_duration = Duration::from_secs(1);
+        provider_settings.test_recognize_duration = Duration::ZERO;
+        drop(provider_settings);
+
+        let dir = tempfile::tempdir().unwrap();
+        let uri = new_wav_file(dir.path(), Duration::from_secs(2));
+
+        let song = glib::MainContext::default()
+            .block_on(Recognizer::new().recognize_uri(&uri, RecordingFormat::OggOpus))
+            .unwrap();
+        assert_eq!(song.source(), RecognitionSource::Link);
+
+        ProviderSettings::lock().reset();
+    }
+
+    #[gtk::test]
+    fn unsupported() {
+        gst::init().unwrap();
+
+        let err = glib::MainContext::default()
+            .block_on(decode(
+                "javascript:alert(1)",
+                Duration::from_secs(1),
+                RecordingFormat::Wav,
+            ))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DecodeUriError>(),
+            Some(DecodeUriError::UnsupportedScheme(_))
+        ));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.html");
+        std::fs::write(&path, "<html><body>Not audio</body></html>").unwrap();
+        let uri = glib::filename_to_uri(path, None).unwrap();
+        let err = glib::MainContext::default()
+            .block_on(decode(&uri, Duration::from_secs(1), RecordingFormat::Wav))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DecodeUriError>(),
+            Some(DecodeUriError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+use gettextrs::gettext;
+use gtk::glib;
+
+use std::fmt;
+
+/// Release date parsed from the arbitrary string given by providers, as
+/// precise as it was given.
+///
+/// Dates are ordered chronologically, with less precise dates ordered before
+/// more precise ones within the same period, e.g., `2020` before `2020-01`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ReleaseDate {
+    year: i32,
+    /// From 1 to 12
+    month: Option<u8>,
+    /// From 1 to 31, only set with a month
+    day: Option<u8>,
+}
+
+impl ReleaseDate {
+    /// Parses `YYYY`, `YYYY-MM`, and `YYYY-MM-DD`, also separated by `/` or
+    /// `.`, ignoring any time after the date.
+    ///
+    /// Returns `None` if the string is in another format or is not a valid
+    /// date.
+    pub fn parse(string: &str) -> Option<Self> {
+        let string = string.trim();
+
+        // E.g., `2020-01-01T00:00:00Z` or `2020-01-01 00:00`
+        let date = string
+            .split_once(['T', ' '])
+            .map_or(string, |(date, _)| date);
+
+        let mut parts = date.split(['-', '/', '.']);
+
+        let year = parse_part(parts.next()?, 4)?;
+        let month = parts.next().map(|part| parse_part(part, 2)).transpose()?;
+        let day = parts.next().map(|part| parse_part(part, 2)).transpose()?;
+
+        if parts.next().is_some() || year == 0 {
+            return None;
+        }
+
+        let month = month.map(u8::try_from).transpose().ok()?;
+        let day = day.map(u8::try_from).transpose().ok()?;
+
+        // Also validates the day within the month, e.g., for leap years
+        glib::DateTime::from_utc(
+            year,
+            month.unwrap_or(1).into(),
+            day.unwrap_or(1).into(),
+            0,
+            0,
+            0.0,
+        )
+        .ok()?;
+
+        Some(Self { year, month, day })
+    }
+
+    /// Returns the date in the locale's format, as precise as it was given.
+    pub fn to_display(&self) -> String {
+        let Some(month) = self.month else {
+            return self.year.to_string();
+        };
+
+        let date_time = glib::DateTime::from_utc(
+            self.year,
+            month.into(),
+            self.day.unwrap_or(1).into(),
+            0,
+            0,
+            0.0,
+        )
+        .expect("date must have been validated");
+
+        let format = if self.day.is_some() {
+            // Locale's date representation (e.g., `12/31/99`)
+            "%x".to_string()
+        } else {
+            // Translators: Release date with only the month and year. `%OB` will be replaced with the month name (e.g., `January`), and `%Y` with the year
+            gettext("%OB %Y")
+        };
+
+        date_time
+            .format(&format)
+            .expect("format must be correct")
+            .to_string()
+    }
+}
+
+/// Formats the date normalized as ISO 8601, as precise as it was given.
+impl fmt::Display for ReleaseDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}", self.year)?;
+
+        if let Some(month) = self.month {
+            write!(f, "-{:02}", month)?;
+        }
+
+        if let Some(day) = self.day {
+            write!(f, "-{:02}", day)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a part of the date with exactly `n_digits` digits, except for
+/// months and days, which may have a single digit.
+fn parse_part(part: &str, n_digits: usize) -> Option<i32> {
+    let is_valid_len = part.len() == n_digits || (n_digits == 2 && part.len() == 1);
+
+    if !is_valid_len || !part.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    part.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: Option<u8>, day: Option<u8>) -> ReleaseDate {
+        ReleaseDate { year, month, day }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(ReleaseDate::parse("2020"), Some(date(2020, None, None)));
+        assert_eq!(
+            ReleaseDate::parse("2020-03"),
+            Some(date(2020, Some(3), None))
+        );
+        assert_eq!(
+            ReleaseDate::parse("2020-03-04"),
+            Some(date(2020, Some(3), Some(4)))
+        );
+        assert_eq!(
+            ReleaseDate::parse("2020/3/4"),
+            Some(date(2020, Some(3), Some(4)))
+        );
+        assert_eq!(
+            ReleaseDate::parse("2020.12.31"),
+            Some(date(2020, Some(12), Some(31)))
+        );
+        assert_eq!(
+            ReleaseDate::parse(" 2020-03-04T05:06:07Z "),
+            Some(date(2020, Some(3), Some(4)))
+        );
+        assert_eq!(
+            ReleaseDate::parse("2020-02-29"),
+            Some(date(2020, Some(2), Some(29)))
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(ReleaseDate::parse(""), None);
+        assert_eq!(ReleaseDate::parse("some value"), None);
+        assert_eq!(ReleaseDate::parse("00-00-0000"), None);
+        assert_eq!(ReleaseDate::parse("0000"), None);
+        assert_eq!(ReleaseDate::parse("20"), None);
+        assert_eq!(ReleaseDate::parse("2020-13"), None);
+        assert_eq!(ReleaseDate::parse("2020-00-01"), None);
+        assert_eq!(ReleaseDate::parse("2021-02-29"), None);
+        assert_eq!(ReleaseDate::parse("2020-01-01-01"), None);
+        assert_eq!(ReleaseDate::parse("2020-+1"), None);
+        assert_eq!(ReleaseDate::parse("2020-"), None);
+    }
+
+    #[test]
+    fn normalized() {
+        assert_eq!(ReleaseDate::parse("2020").unwrap().to_string(), "2020");
+        assert_eq!(ReleaseDate::parse("2020/3").unwrap().to_string(), "2020-03");
+        assert_eq!(
+            ReleaseDate::parse("2020.3.4").unwrap().to_string(),
+            "2020-03-04"
+        );
+    }
+
+    #[test]
+    fn chronological() {
+        let mut dates = ["2021-01-01", "2020-06", "2020", "2020-06-15", "1999"]
+            .into_iter()
+            .map(|string| ReleaseDate::parse(string).unwrap())
+            .collect::<Vec<_>>();
+        dates.sort();
+
+        assert_eq!(
+            dates
+                .iter()
+                .map(|date| date.to_string())
+                .collect::<Vec<_>>(),
+            ["1999", "2020", "2020-06", "2020-06-15", "2021-01-01"]
+        );
+    }
+
+    fn format_utc(year: i32, month: i32, day: i32, format: &str) -> String {
+        glib::DateTime::from_utc(year, month, day, 0, 0, 0.0)
+            .unwrap()
+            .format(format)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn to_display() {
+        assert_eq!(ReleaseDate::parse("2020").unwrap().to_display(), "2020");
+        assert_eq!(
+            ReleaseDate::parse("2020-03").unwrap().to_display(),
+            format_utc(2020, 3, 1, "%OB %Y")
+        );
+        assert_eq!(
+            ReleaseDate::parse("2020-03-04").unwrap().to_display(),
+            format_utc(2020, 3, 4, "%x")
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use anyhow::{Context, Result};
+use gst::{bus::BusWatchGuard, prelude::*};
+use gtk::glib::{self, clone};
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::recognizer;
+
+/// Sample rate of the ring, which is enough for recognition
+const SAMPLE_RATE: u32 = 16_000;
+
+/// Minimum audio in the ring for a snapshot, as less can't be recognized
+const MIN_SNAPSHOT_DURATION: Duration = Duration::from_secs(3);
+
+/// Bounded buffer of the most recent mono 16-bit samples, where new samples
+/// overwrite the oldest ones.
+#[derive(Debug)]
+pub struct RingBuffer {
+    samples: VecDeque<i16>,
+    capacity: usize,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Returns a buffer that holds up to `duration` of samples.
+    pub fn with_duration(duration: Duration) -> Self {
+        Self::new(n_samples(duration))
+    }
+
+    pub fn push(&mut self, samples: &[i16]) {
+        let samples = &samples[samples.len().saturating_sub(self.capacity)..];
+
+        let n_overflowing = (self.samples.len() + samples.len()).saturating_sub(self.capacity);
+        self.samples.drain(..n_overflowing);
+        self.samples.extend(samples);
+    }
+
+    /// Returns the duration of the samples in the buffer.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples.len() as f64 / SAMPLE_RATE as f64)
+    }
+
+    /// Returns the samples in the buffer, from the oldest.
+    pub fn snapshot(&self) -> Vec<i16> {
+        self.samples.iter().copied().collect()
+    }
+}
+
+/// Returns the number of samples in `duration`.
+fn n_samples(duration: Duration) -> usize {
+    (duration.as_secs_f64() * SAMPLE_RATE as f64) as usize
+}
+
+/// Encodes mono 16-bit `samples` as WAV.
+fn encode_wav(samples: &[i16]) -> Vec<u8> {
+    const N_CHANNELS: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+    const BLOCK_ALIGN: u16 = N_CHANNELS * BITS_PER_SAMPLE / 8;
+
+    let data_len = (samples.len() * BLOCK_ALIGN as usize) as u32;
+
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16_u32.to_le_bytes());
+    bytes.extend_from_slice(&1_u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&N_CHANNELS.to_le_bytes());
+    bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(SAMPLE_RATE * BLOCK_ALIGN as u32).to_le_bytes());
+    bytes.extend_from_slice(&BLOCK_ALIGN.to_le_bytes());
+    bytes.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    bytes
+}
+
+type ActiveRing = (gst::Pipeline, BusWatchGuard, Arc<Mutex<RingBuffer>>);
+
+/// Continuously records a device into a [`RingBuffer`], so the last seconds
+/// can be recognized on demand without waiting.
+///
+/// The audio is only kept in memory, and is dropped when stopped.
+#[derive(Default)]
+pub struct RingRecorder {
+    /// Cleared when the pipeline fails, so it can be started again
+    active: Rc<RefCell<Option<ActiveRing>>>,
+}
+
+impl RingRecorder {
+    pub fn is_active(&self) -> bool {
+        self.active.borrow().is_some()
+    }
+
+    /// Starts recording the device, amplified by `gain_db`, keeping the last
+    /// `duration` of audio.
+    ///
+    /// If `device_name` is `None`, the default source is recorded.
+    pub fn start(&self, device_name: Option<&str>, gain_db: f64, duration: Duration) -> Result<()> {
+        self.stop();
+
+        let pipeline = gst::Pipeline::new();
+        let level = recognizer::add_capture(&pipeline, device_name, gain_db)
+            .context("Failed to create ring pipeline")?;
+        let audioconvert = gst::ElementFactory::make("audioconvert").build()?;
+        let sink = gst::ElementFactory::make("fakesink")
+            .property("sync", false)
+            .build()?;
+        pipeline.add_many([&audioconvert, &sink])?;
+        level.link(&audioconvert)?;
+        audioconvert.link_filtered(
+            &sink,
+            &gst::Caps::builder("audio/x-raw")
+                .field("format", "S16LE")
+                .field("channels", 1)
+                .field("rate", SAMPLE_RATE as i32)
+                .build(),
+        )?;
+
+        let ring = Arc::new(Mutex::new(RingBuffer::with_duration(duration)));
+
+        sink.static_pad("sink").unwrap().add_probe(
+            gst::PadProbeType::BUFFER,
+            clone!(@strong ring => move |_, info| {
+                let Some(map) = info.buffer().and_then(|buffer| buffer.map_readable().ok()) else {
+                    return gst::PadProbeReturn::Ok;
+                };
+
+                let samples = map
+                    .chunks_exact(2)
+                    .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+                    .collect::<Vec<_>>();
+                ring.lock().unwrap().push(&samples);
+
+                gst::PadProbeReturn::Ok
+            }),
+        );
+
+        let active = Rc::downgrade(&self.active);
+        let bus_watch_guard = pipeline
+            .bus()
+            .unwrap()
+            .add_watch_local(
+                clone!(@weak pipeline => @default-return glib::ControlFlow::Break, move |_, message| {
+                    if let gst::MessageView::Error(e) = message.view() {
+                        tracing::warn!(debug = ?e.debug(), err = ?e.error(), "Received error at ring bus");
+                        let _ = pipeline.set_state(gst::State::Null);
+
+                        // The watch can't be removed while it is dispatched
+                        let active = active.clone();
+                        glib::idle_add_local_once(move || {
+                            let Some(active) = active.upgrade() else {
+                                return;
+                            };
+
+                            if active
+                                .borrow()
+                                .as_ref()
+                                .is_some_and(|(active_pipeline, _, _)| *active_pipeline == pipeline)
+                            {
+                                active.take();
+                                tracing::debug!("Stopped recording to ring after error");
+                            }
+                        });
+
+                        return glib::ControlFlow::Break;
+                    }
+
+                    glib::ControlFlow::Continue
+                }),
+            )
+            .context("Failed to add bus watch")?;
+
+        recognizer::play_capture(&pipeline).context("Failed to start ring pipeline")?;
+
+        self.active.replace(Some((pipeline, bus_watch_guard, ring)));
+
+        tracing::debug!(?duration, "Started recording to ring");
+
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Some((pipeline, _bus_watch_guard, _ring)) = self.active.take() {
+            let _ = pipeline.set_state(gst::State::Null);
+            tracing::debug!("Stopped recording to ring");
+        }
+    }
+
+    /// Returns the audio currently in the ring encoded as WAV, or `None` if
+    /// not recording or too little was recorded yet.
+    pub fn snapshot(&self) -> Option<glib::Bytes> {
+        let active = self.active.borrow();
+        let (_, _, ring) = active.as_ref()?;
+
+        let ring = ring.lock().unwrap();
+        let duration = ring.duration();
+        if duration < MIN_SNAPSHOT_DURATION {
+            tracing::debug!(?duration, "Too little in ring to snapshot");
+            return None;
+        }
+
+        tracing::debug!(?duration, "Took snapshot of ring");
+
+        Some(glib::Bytes::from_owned(encode_wav(&ring.snapshot())))
+    }
+}
+
+impl Drop for RingRecorder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_bounded() {
+        let mut ring = RingBuffer::new(5);
+        assert!(ring.snapshot().is_empty());
+
+        ring.push(&[1, 2, 3]);
+        assert_eq!(ring.snapshot(), [1, 2, 3]);
+
+        // Oldest are overwritten
+        ring.push(&[4, 5, 6, 7]);
+        assert_eq!(ring.snapshot(), [3, 4, 5, 6, 7]);
+
+        // Only the last fit
+        ring.push(&[8, 9, 10, 11, 12, 13, 14]);
+        assert_eq!(ring.snapshot(), [10, 11, 12, 13, 14]);
+    }
+
+    #[test]
+    fn ring_buffer_snapshot_window() {
+        let mut ring = RingBuffer::with_duration(Duration::from_secs(2));
+
+        // 5 seconds of a known ramp, pushed in buffers like from the pipeline
+        let pattern = (0..n_samples(Duration::from_secs(5)))
+            .map(|i| (i % i16::MAX as usize) as i16)
+            .collect::<Vec<_>>();
+        for chunk in pattern.chunks(1600) {
+            ring.push(chunk);
+        }
+
+        assert_eq!(ring.duration(), Duration::from_secs(2));
+
+        let snapshot = ring.snapshot();
+        assert_eq!(snapshot.len(), n_samples(Duration::from_secs(2)));
+        assert_eq!(snapshot, pattern[pattern.len() - snapshot.len()..]);
+    }
+
+    #[test]
+    fn wav() {
+        let samples = [0, 1, -1, i16::MAX, i16::MIN];
+        let bytes = encode_wav(&samples);
+
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(
+            u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize,
+            bytes.len() - 8
+        );
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(
+            u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+            SAMPLE_RATE
+        );
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(
+            u32::from_le_bytes(bytes[40..44].try_into().unwrap()) as usize,
+            samples.len() * 2
+        );
+        assert_eq!(&bytes[44..46], &0_i16.to_le_bytes());
+        assert_eq!(&bytes[50..52], &i16::MAX.to_le_bytes());
+    }
+}
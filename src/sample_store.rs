@@ -0,0 +1,532 @@
+use anyhow::{anyhow, bail, Context, Result};
+use gst::prelude::*;
+use gtk::{gio, glib, prelude::*};
+
+use std::{
+    cell::Cell,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{settings::RecordingFormat, song::Song, uid::Uid};
+
+/// Bitrate of the re-encoded Ogg Opus samples, same as when recording
+const TAGGED_OPUS_BITRATE: i32 = 16_000;
+
+/// Recognized metadata written into the tags of a saved sample
+#[derive(Debug, Clone)]
+pub struct SampleTags {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// Encoded image, e.g., PNG
+    pub album_art: Option<glib::Bytes>,
+}
+
+impl SampleTags {
+    /// The album art is only included if it is already loaded, so this
+    /// never waits for a download.
+    pub fn for_song(song: &Song) -> Self {
+        Self {
+            title: song.title(),
+            artist: song.artist(),
+            album: song.album(),
+            album_art: song
+                .album_art()
+                .and_then(|album_art| album_art.loaded_texture().map(|t| t.save_to_png_bytes())),
+        }
+    }
+
+    fn to_tag_list(&self) -> gst::TagList {
+        let mut tag_list = gst::TagList::new();
+
+        {
+            let tag_list = tag_list.get_mut().unwrap();
+            tag_list.add::<gst::tags::Title>(&self.title.as_str(), gst::TagMergeMode::Replace);
+            tag_list.add::<gst::tags::Artist>(&self.artist.as_str(), gst::TagMergeMode::Replace);
+            if !self.album.is_empty() {
+                tag_list.add::<gst::tags::Album>(&self.album.as_str(), gst::TagMergeMode::Replace);
+            }
+            if let Some(ref album_art) = self.album_art {
+                let sample = gst::Sample::builder()
+                    .buffer(&gst::Buffer::from_slice(album_art.clone()))
+                    .caps(&gst::Caps::builder("image/png").build())
+                    .build();
+                tag_list.add::<gst::tags::Image>(&sample, gst::TagMergeMode::Replace);
+            }
+        }
+
+        tag_list
+    }
+}
+
+/// Returns the format of the sample, or `None` if it is not one that we
+/// record in.
+fn detect_format(bytes: &[u8]) -> Option<RecordingFormat> {
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WAVE") {
+        return Some(RecordingFormat::Wav);
+    }
+
+    // The first page only has the identification header
+    if bytes.starts_with(b"OggS") && bytes.get(28..36) == Some(b"OpusHead") {
+        return Some(RecordingFormat::OggOpus);
+    }
+
+    None
+}
+
+/// Returns the sample with the tags written into it, or `None` if its format
+/// can't carry tags.
+///
+/// Ogg Opus samples are re-encoded, as the tags are part of the stream
+/// headers. WAV samples can't carry the album art.
+///
+/// This blocks until the sample is processed, so it should be run on a
+/// separate thread.
+pub fn embed_tags(bytes: &[u8], tags: &SampleTags) -> Result<Option<glib::Bytes>> {
+    let description = match detect_format(bytes) {
+        Some(RecordingFormat::OggOpus) => format!(
+            "giostreamsrc name=src ! oggdemux ! opusdec ! opusenc name=tagger bitrate={} ! oggmux ! giostreamsink name=sink",
+            TAGGED_OPUS_BITRATE
+        ),
+        Some(RecordingFormat::Wav) => {
+            "giostreamsrc name=src ! wavparse ! wavenc name=tagger ! giostreamsink name=sink"
+                .to_string()
+        }
+        None => return Ok(None),
+    };
+
+    let pipeline = gst::parse_launch(&description)
+        .context("Failed to create tagging pipeline")?
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+
+    let input_stream = gio::MemoryInputStream::from_bytes(&glib::Bytes::from(bytes));
+    pipeline
+        .by_name("src")
+        .unwrap()
+        .set_property("stream", &input_stream);
+
+    // This seeks back to write the WAV header sizes, which the memory stream
+    // supports.
+    let output_stream = gio::MemoryOutputStream::new_resizable();
+    pipeline
+        .by_name("sink")
+        .unwrap()
+        .set_property("stream", &output_stream);
+
+    pipeline
+        .by_name("tagger")
+        .unwrap()
+        .dynamic_cast::<gst::TagSetter>()
+        .map_err(|_| anyhow!("Encoder does not support setting tags"))?
+        .merge_tags(&tags.to_tag_list(), gst::TagMergeMode::Replace);
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .context("Failed to start tagging pipeline")?;
+    let message = pipeline.bus().unwrap().timed_pop_filtered(
+        gst::ClockTime::from_seconds(10),
+        &[gst::MessageType::Eos, gst::MessageType::Error],
+    );
+    pipeline
+        .set_state(gst::State::Null)
+        .context("Failed to stop tagging pipeline")?;
+
+    match message.as_ref().map(|message| message.view()) {
+        Some(gst::MessageView::Eos(_)) => {}
+        Some(gst::MessageView::Error(err)) => {
+            return Err(err.error()).context("Failed to write tags");
+        }
+        _ => bail!("Timed out writing tags"),
+    }
+
+    output_stream
+        .close(gio::Cancellable::NONE)
+        .context("Failed to close output stream")?;
+
+    Ok(Some(output_stream.steal_as_bytes()))
+}
+
+/// Stores the recorded audio that each song was recognized from, so it can be
+/// replayed when checking mismatches.
+///
+/// The oldest samples are removed when the total size exceeds the limit.
+pub struct SampleStore {
+    dir: PathBuf,
+    max_total_size: Cell<u64>,
+}
+
+impl SampleStore {
+    pub fn new(dir: PathBuf, max_total_size: u64) -> Self {
+        Self {
+            dir,
+            max_total_size: Cell::new(max_total_size),
+        }
+    }
+
+    /// Sets the total size in bytes that the samples may take. This applies
+    /// on the next save.
+    pub fn set_max_total_size(&self, max_total_size: u64) {
+        self.max_total_size.set(max_total_size);
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns where the sample of the song is stored, whether it exists or not.
+    pub fn path(&self, song_id: &Uid) -> PathBuf {
+        path_in(&self.dir, song_id)
+    }
+
+    pub fn contains(&self, song_id: &Uid) -> bool {
+        self.path(song_id).is_file()
+    }
+
+    /// Stores `bytes` as the sample of the song, replacing the previous one,
+    /// then prunes the store. If `tags` are given, they are written into the
+    /// sample first, falling back to the untagged sample if that is not
+    /// possible.
+    pub async fn save(
+        &self,
+        song_id: &Uid,
+        bytes: glib::Bytes,
+        tags: Option<SampleTags>,
+    ) -> Result<()> {
+        let dir = self.dir.clone();
+        let path = self.path(song_id);
+        let max_total_size = self.max_total_size.get();
+
+        gio::spawn_blocking(move || {
+            let bytes = match tags {
+                Some(tags) => tagged_or_untagged(bytes, &tags),
+                None => bytes,
+            };
+            save_to(&dir, &path, &bytes, max_total_size)
+        })
+        .await
+        .map_err(|err| anyhow!("Failed to spawn blocking task: {:?}", err))
+        .and_then(|res| res)
+    }
+
+    /// Removes the sample of the song, returning whether there was one.
+    pub fn remove(&self, song_id: &Uid) -> Result<bool> {
+        let path = self.path(song_id);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => {
+                Err(err).with_context(|| format!("Failed to remove sample at {}", path.display()))
+            }
+        }
+    }
+}
+
+/// Returns where the sample of the song is stored in `dir`, whether it exists
+/// or not.
+///
+/// This is for checking samples without going through the application, e.g.,
+/// from widgets that are also used in tests.
+pub fn path_in(dir: &Path, song_id: &Uid) -> PathBuf {
+    dir.join(glib::Uri::escape_string(song_id.as_str(), None, false).as_str())
+}
+
+fn tagged_or_untagged(bytes: glib::Bytes, tags: &SampleTags) -> glib::Bytes {
+    match embed_tags(&bytes, tags) {
+        Ok(Some(tagged_bytes)) => tagged_bytes,
+        Ok(None) => {
+            tracing::debug!("Recorded sample format can't carry tags; saving it untagged");
+            bytes
+        }
+        Err(err) => {
+            tracing::warn!("Failed to tag recorded sample: {:?}", err);
+            bytes
+        }
+    }
+}
+
+fn save_to(dir: &Path, path: &Path, bytes: &[u8], max_total_size: u64) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create dir at {}", dir.display()))?;
+
+    fs::write(path, bytes)
+        .with_context(|| format!("Failed to write sample at {}", path.display()))?;
+
+    prune(dir, max_total_size)?;
+
+    Ok(())
+}
+
+/// Removes the oldest samples in `dir` until the total size is within
+/// `max_total_size`, returning the number of removed samples.
+fn prune(dir: &Path, max_total_size: u64) -> Result<u32> {
+    let mut entries = entries(dir)?;
+    entries.sort_by_key(|(_, _, modified)| *modified);
+
+    let mut total_size = entries.iter().map(|(_, size, _)| size).sum::<u64>();
+    let mut n_removed = 0;
+
+    for (path, size, _) in entries {
+        if total_size <= max_total_size {
+            break;
+        }
+
+        fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove sample at {}", path.display()))?;
+        total_size -= size;
+        n_removed += 1;
+    }
+
+    if n_removed > 0 {
+        tracing::debug!(total_size, "Pruned {} samples", n_removed);
+    }
+
+    Ok(n_removed)
+}
+
+/// Returns the path, size, and modified time of all samples in `dir`.
+fn entries(dir: &Path) -> Result<Vec<(PathBuf, u64, SystemTime)>> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read dir at {}", dir.display()))
+        }
+    };
+
+    let mut entries = Vec::new();
+    for entry in read_dir {
+        let entry = entry.context("Failed to read dir entry")?;
+        let metadata = entry.metadata().context("Failed to get metadata")?;
+
+        if metadata.is_file() {
+            entries.push((
+                entry.path(),
+                metadata.len(),
+                metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            ));
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gtk::gdk;
+
+    use std::time::Duration;
+
+    fn new_test_sample(format: RecordingFormat) -> glib::Bytes {
+        let encoder = match format {
+            RecordingFormat::OggOpus => "opusenc ! oggmux",
+            RecordingFormat::Wav => "wavenc",
+        };
+        let pipeline = gst::parse_launch(&format!(
+            "audiotestsrc num-buffers=10 ! audioconvert ! {} ! giostreamsink name=sink",
+            encoder
+        ))
+        .unwrap()
+        .downcast::<gst::Pipeline>()
+        .unwrap();
+        let stream = gio::MemoryOutputStream::new_resizable();
+        pipeline
+            .by_name("sink")
+            .unwrap()
+            .set_property("stream", &stream);
+
+        pipeline.set_state(gst::State::Playing).unwrap();
+        pipeline
+            .bus()
+            .unwrap()
+            .timed_pop_filtered(gst::ClockTime::from_seconds(5), &[gst::MessageType::Eos])
+            .unwrap();
+        pipeline.set_state(gst::State::Null).unwrap();
+        stream.close(gio::Cancellable::NONE).unwrap();
+
+        stream.steal_as_bytes()
+    }
+
+    /// Decodes the sample and returns all tags found in it
+    fn read_tags(bytes: &glib::Bytes) -> gst::TagList {
+        let pipeline = gst::parse_launch("giostreamsrc name=src ! decodebin ! fakesink sync=false")
+            .unwrap()
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+        pipeline
+            .by_name("src")
+            .unwrap()
+            .set_property("stream", gio::MemoryInputStream::from_bytes(bytes));
+
+        let mut tags = gst::TagList::new();
+        let bus = pipeline.bus().unwrap();
+        pipeline.set_state(gst::State::Playing).unwrap();
+        loop {
+            let message = bus
+                .timed_pop_filtered(
+                    gst::ClockTime::from_seconds(5),
+                    &[
+                        gst::MessageType::Tag,
+                        gst::MessageType::Eos,
+                        gst::MessageType::Error,
+                    ],
+                )
+                .unwrap();
+            match message.view() {
+                gst::MessageView::Tag(tag) => {
+                    tags.make_mut()
+                        .insert(&tag.tags(), gst::TagMergeMode::Append);
+                }
+                gst::MessageView::Eos(_) => break,
+                other => panic!("unexpected message: {:?}", other),
+            }
+        }
+        pipeline.set_state(gst::State::Null).unwrap();
+
+        tags
+    }
+
+    fn new_test_tags() -> SampleTags {
+        let texture = gdk::MemoryTexture::new(
+            1,
+            1,
+            gdk::MemoryFormat::R8g8b8a8,
+            &glib::Bytes::from_static(&[0, 0, 0, 255]),
+            4,
+        );
+        SampleTags {
+            title: "Hopppolla".to_string(),
+            artist: "Sigur Rós".to_string(),
+            album: "Takk...".to_string(),
+            album_art: Some(texture.save_to_png_bytes()),
+        }
+    }
+
+    #[test]
+    fn embed_tags_ogg_opus() {
+        gst::init().unwrap();
+
+        let sample = new_test_sample(RecordingFormat::OggOpus);
+        assert_eq!(detect_format(&sample), Some(RecordingFormat::OggOpus));
+
+        let tagged = embed_tags(&sample, &new_test_tags()).unwrap().unwrap();
+        assert_eq!(detect_format(&tagged), Some(RecordingFormat::OggOpus));
+
+        let tags = read_tags(&tagged);
+        assert_eq!(tags.get::<gst::tags::Title>().unwrap().get(), "Hopppolla");
+        assert_eq!(tags.get::<gst::tags::Artist>().unwrap().get(), "Sigur Rós");
+        assert_eq!(tags.get::<gst::tags::Album>().unwrap().get(), "Takk...");
+        assert!(tags.get::<gst::tags::Image>().is_some());
+    }
+
+    #[test]
+    fn embed_tags_wav() {
+        gst::init().unwrap();
+
+        let sample = new_test_sample(RecordingFormat::Wav);
+        assert_eq!(detect_format(&sample), Some(RecordingFormat::Wav));
+
+        let tagged = embed_tags(&sample, &new_test_tags()).unwrap().unwrap();
+        assert_eq!(detect_format(&tagged), Some(RecordingFormat::Wav));
+
+        let tags = read_tags(&tagged);
+        assert_eq!(tags.get::<gst::tags::Title>().unwrap().get(), "Hopppolla");
+        assert_eq!(tags.get::<gst::tags::Artist>().unwrap().get(), "Sigur Rós");
+    }
+
+    #[test]
+    fn embed_tags_unsupported() {
+        gst::init().unwrap();
+
+        assert_eq!(detect_format(b"sample"), None);
+        assert!(embed_tags(b"sample", &new_test_tags()).unwrap().is_none());
+    }
+
+    #[test]
+    fn save_and_remove() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let store = SampleStore::new(tempdir.path().join("samples"), u64::MAX);
+
+        let song_id = Uid::from("AudD-some/id");
+        assert!(!store.contains(&song_id));
+        assert!(!store.remove(&song_id).unwrap());
+
+        glib::MainContext::new()
+            .block_on(store.save(&song_id, glib::Bytes::from_static(b"sample"), None))
+            .unwrap();
+        assert!(store.contains(&song_id));
+        assert_eq!(fs::read(store.path(&song_id)).unwrap(), b"sample");
+        assert_eq!(store.path(&song_id).parent(), Some(store.dir()));
+
+        assert!(store.remove(&song_id).unwrap());
+        assert!(!store.contains(&song_id));
+    }
+
+    #[test]
+    fn save_tagged() {
+        gst::init().unwrap();
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let store = SampleStore::new(tempdir.path().to_path_buf(), u64::MAX);
+
+        let song_id = Uid::from("a");
+        glib::MainContext::new()
+            .block_on(store.save(
+                &song_id,
+                new_test_sample(RecordingFormat::Wav),
+                Some(new_test_tags()),
+            ))
+            .unwrap();
+
+        let tags = read_tags(&glib::Bytes::from_owned(
+            fs::read(store.path(&song_id)).unwrap(),
+        ));
+        assert_eq!(tags.get::<gst::tags::Title>().unwrap().get(), "Hopppolla");
+
+        // Untagged formats are saved as is
+        let other_id = Uid::from("b");
+        glib::MainContext::new()
+            .block_on(store.save(
+                &other_id,
+                glib::Bytes::from_static(b"sample"),
+                Some(new_test_tags()),
+            ))
+            .unwrap();
+        assert_eq!(fs::read(store.path(&other_id)).unwrap(), b"sample");
+    }
+
+    #[test]
+    fn prune() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let store = SampleStore::new(tempdir.path().to_path_buf(), 10);
+        let ctx = glib::MainContext::new();
+        let save = |song_id: &str| {
+            ctx.block_on(store.save(
+                &Uid::from(song_id),
+                glib::Bytes::from_static(b"12345"),
+                None,
+            ))
+            .unwrap();
+        };
+        // So the modified times are ordered
+        let wait = || ctx.block_on(glib::timeout_future(Duration::from_millis(20)));
+
+        save("a");
+        wait();
+        save("b");
+        assert!(store.contains(&Uid::from("a")));
+
+        wait();
+        save("c");
+        assert!(!store.contains(&Uid::from("a")));
+        assert!(store.contains(&Uid::from("b")));
+        assert!(store.contains(&Uid::from("c")));
+
+        assert_eq!(super::prune(store.dir(), 0).unwrap(), 2);
+        assert!(!store.contains(&Uid::from("c")));
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
+
+use crate::{song::Song, song_filter::normalize_search_term, uid::Uid};
+
+/// Maximum number of searches whose results are kept
+const MAX_ENTRIES: usize = 16;
+
+/// What the results of a search depend on, besides the songs. Other filters
+/// are applied on top of the results, so toggling them still hits the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SearchKey {
+    /// Normalized query
+    query: String,
+    search_lyrics: bool,
+}
+
+/// Songs that fuzzy matched a query, ranked from the best match
+#[derive(Debug, Default)]
+pub struct SearchResults {
+    ranks: HashMap<Uid, u32>,
+}
+
+impl SearchResults {
+    fn compute<'a>(
+        songs: impl IntoIterator<Item = &'a Song>,
+        query: &str,
+        search_lyrics: bool,
+    ) -> Self {
+        let mut scored = songs
+            .into_iter()
+            .filter_map(|song| {
+                song.fuzzy_match(query, search_lyrics)
+                    .map(|score| (song.id(), score))
+            })
+            .collect::<Vec<_>>();
+        // Stable, so equal scores keep the order of the songs
+        scored.sort_by(|(_, score_1), (_, score_2)| score_2.cmp(score_1));
+
+        let ranks = scored
+            .into_iter()
+            .enumerate()
+            .map(|(rank, (id, _))| (id, rank as u32))
+            .collect();
+        Self { ranks }
+    }
+
+    /// Returns the position of the song among the matches, from 0 for the
+    /// best match, or `None` if it did not match.
+    pub fn rank(&self, song_id: &Uid) -> Option<u32> {
+        self.ranks.get(song_id).copied()
+    }
+}
+
+/// Bounded LRU cache of the results of fuzzy searches, so the same query is
+/// not matched against every song again, e.g., as the filters are toggled.
+///
+/// The results are only valid for the songs they were computed from, so this
+/// must be cleared whenever those change.
+#[derive(Debug, Default)]
+pub struct SearchCache {
+    /// Most recently used first
+    entries: RefCell<VecDeque<(SearchKey, Rc<SearchResults>)>>,
+    /// Number of searches computed instead of taken from the cache
+    n_computed: Cell<u32>,
+}
+
+impl SearchCache {
+    /// Returns the cached results of the query, or computes them from the
+    /// songs returned by `songs` if there are none.
+    pub fn get_or_compute<'a, I>(
+        &self,
+        query: &str,
+        search_lyrics: bool,
+        songs: impl FnOnce() -> I,
+    ) -> Rc<SearchResults>
+    where
+        I: IntoIterator<Item = &'a Song>,
+    {
+        let key = SearchKey {
+            query: normalize_search_term(query),
+            search_lyrics,
+        };
+
+        let mut entries = self.entries.borrow_mut();
+
+        if let Some(index) = entries.iter().position(|(k, _)| *k == key) {
+            let entry = entries.remove(index).unwrap();
+            let results = Rc::clone(&entry.1);
+            entries.push_front(entry);
+            return results;
+        }
+
+        let results = Rc::new(SearchResults::compute(
+            songs(),
+            &key.query,
+            key.search_lyrics,
+        ));
+
+        let n_computed = self.n_computed.get() + 1;
+        self.n_computed.set(n_computed);
+        tracing::trace!(query = key.query, n_computed, "Computed search results");
+
+        entries.push_front((key, Rc::clone(&results)));
+        entries.truncate(MAX_ENTRIES);
+
+        results
+    }
+
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    #[cfg(test)]
+    pub fn n_computed(&self) -> u32 {
+        self.n_computed.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_test_songs() -> Vec<Song> {
+        vec![
+            Song::builder(&Uid::from("0"), "Blue Monday", "New Order", "Power").build(),
+            Song::builder(&Uid::from("1"), "Ceremony", "New Order", "Substance").build(),
+            Song::builder(&Uid::from("2"), "Blue", "Eiffel 65", "Europop").build(),
+        ]
+    }
+
+    #[test]
+    fn repeated_query_hits() {
+        let songs = new_test_songs();
+        let cache = SearchCache::default();
+
+        let results = cache.get_or_compute("blue", false, || &songs);
+        assert_eq!(cache.n_computed(), 1);
+        assert!(results.rank(&Uid::from("0")).is_some());
+        assert!(results.rank(&Uid::from("1")).is_none());
+        assert!(results.rank(&Uid::from("2")).is_some());
+
+        // Normalized to the same query
+        let results_again = cache.get_or_compute("BLUE", false, || &songs);
+        assert_eq!(cache.n_computed(), 1);
+        assert!(Rc::ptr_eq(&results, &results_again));
+
+        cache.get_or_compute("blue", true, || &songs);
+        assert_eq!(cache.n_computed(), 2);
+
+        cache.clear();
+        cache.get_or_compute("blue", false, || &songs);
+        assert_eq!(cache.n_computed(), 3);
+    }
+
+    #[test]
+    fn ranked_by_score() {
+        let songs = new_test_songs();
+        let cache = SearchCache::default();
+
+        let results = cache.get_or_compute("ceremony", false, || &songs);
+        assert_eq!(results.rank(&Uid::from("1")), Some(0));
+    }
+
+    #[test]
+    fn bounded() {
+        let songs = new_test_songs();
+        let cache = SearchCache::default();
+
+        cache.get_or_compute("query 0", false, || &songs);
+        for i in 1..MAX_ENTRIES {
+            cache.get_or_compute(&format!("query {}", i), false, || &songs);
+        }
+        assert_eq!(cache.n_computed(), MAX_ENTRIES as u32);
+
+        // Using it makes it the most recent, so the next oldest is evicted
+        cache.get_or_compute("query 0", false, || &songs);
+        cache.get_or_compute("query new", false, || &songs);
+        assert_eq!(cache.entries.borrow().len(), MAX_ENTRIES);
+        assert_eq!(cache.n_computed(), MAX_ENTRIES as u32 + 1);
+
+        cache.get_or_compute("query 0", false, || &songs);
+        assert_eq!(cache.n_computed(), MAX_ENTRIES as u32 + 1);
+
+        cache.get_or_compute("query 1", false, || &songs);
+        assert_eq!(cache.n_computed(), MAX_ENTRIES as u32 + 2);
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::{Context, Result};
+use gtk::{cairo, gdk, glib, prelude::*};
+
+use crate::{album_art, settings::ArtistTitleOrder, song::Song};
+
+/// Size of the card, the one recommended for OpenGraph images
+const CARD_WIDTH: i32 = 1200;
+const CARD_HEIGHT: i32 = 630;
+
+const PADDING: f64 = 80.0;
+const COVER_SIZE: f64 = CARD_HEIGHT as f64 - 2.0 * PADDING;
+const COVER_CORNER_RADIUS: f64 = 24.0;
+
+const PRIMARY_FONT_SIZE: f64 = 56.0;
+const SECONDARY_FONT_SIZE: f64 = 36.0;
+const BRANDING_FONT_SIZE: f64 = 28.0;
+
+/// Used as background when there is no album art, and as the cover in
+/// its place
+const FALLBACK_BACKGROUND: (f64, f64, f64) = (0.13, 0.13, 0.16);
+const FALLBACK_COVER: (f64, f64, f64) = (0.24, 0.24, 0.28);
+
+/// How much of the album art's dominant color is kept in the background, so
+/// the white text stays readable
+const BACKGROUND_COLOR_FACTOR: f64 = 0.45;
+
+/// Renders a card of the song for sharing, with its album art, name, and the
+/// app's name, returning it as PNG.
+///
+/// If `album_art` is `None`, a solid background and cover are drawn instead.
+pub fn render(
+    song: &Song,
+    order: ArtistTitleOrder,
+    album_art: Option<&gdk::Texture>,
+) -> Result<glib::Bytes> {
+    let mut surface = cairo::ImageSurface::create(cairo::Format::ARgb32, CARD_WIDTH, CARD_HEIGHT)
+        .context("Failed to create surface")?;
+
+    {
+        let ctx = cairo::Context::new(&surface).context("Failed to create context")?;
+        draw(&ctx, song, order, album_art)?;
+    }
+
+    surface.flush();
+    let stride = surface.stride() as usize;
+    let data = surface.data().context("Failed to get surface data")?;
+
+    // Cairo's ARGB32 is stored in native endianness
+    let format = if cfg!(target_endian = "little") {
+        gdk::MemoryFormat::B8g8r8a8Premultiplied
+    } else {
+        gdk::MemoryFormat::A8r8g8b8Premultiplied
+    };
+    let texture = gdk::MemoryTexture::new(
+        CARD_WIDTH,
+        CARD_HEIGHT,
+        format,
+        &glib::Bytes::from(&*data),
+        stride,
+    );
+
+    Ok(texture.save_to_png_bytes())
+}
+
+fn draw(
+    ctx: &cairo::Context,
+    song: &Song,
+    order: ArtistTitleOrder,
+    album_art: Option<&gdk::Texture>,
+) -> Result<()> {
+    let (r, g, b) =
+        album_art
+            .and_then(album_art::dominant_color)
+            .map_or(FALLBACK_BACKGROUND, |color| {
+                (
+                    color.red() as f64 * BACKGROUND_COLOR_FACTOR,
+                    color.green() as f64 * BACKGROUND_COLOR_FACTOR,
+                    color.blue() as f64 * BACKGROUND_COLOR_FACTOR,
+                )
+            });
+    ctx.set_source_rgb(r, g, b);
+    ctx.paint()?;
+
+    ctx.save()?;
+    rounded_rectangle(
+        ctx,
+        PADDING,
+        PADDING,
+        COVER_SIZE,
+        COVER_SIZE,
+        COVER_CORNER_RADIUS,
+    );
+    ctx.clip();
+    if let Some(album_art) = album_art {
+        let art_surface = texture_surface(album_art)?;
+        ctx.translate(PADDING, PADDING);
+        ctx.scale(
+            COVER_SIZE / album_art.width() as f64,
+            COVER_SIZE / album_art.height() as f64,
+        );
+        let pattern = cairo::SurfacePattern::create(&art_surface);
+        pattern.set_filter(cairo::Filter::Good);
+        ctx.set_source(&pattern)?;
+    } else {
+        let (r, g, b) = FALLBACK_COVER;
+        ctx.set_source_rgb(r, g, b);
+    }
+    ctx.paint()?;
+    ctx.restore()?;
+
+    let (primary, secondary) = if order.is_title_first() {
+        (song.title(), song.artist())
+    } else {
+        (song.artist(), song.title())
+    };
+
+    let text_x = 2.0 * PADDING + COVER_SIZE;
+    let text_width = CARD_WIDTH as f64 - text_x - PADDING;
+    let center_y = CARD_HEIGHT as f64 / 2.0;
+
+    ctx.set_source_rgb(1.0, 1.0, 1.0);
+    ctx.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Bold);
+    ctx.set_font_size(PRIMARY_FONT_SIZE);
+    ctx.move_to(text_x, center_y - 12.0);
+    ctx.show_text(&ellipsize(ctx, &primary, text_width)?)?;
+
+    ctx.set_source_rgba(1.0, 1.0, 1.0, 0.8);
+    ctx.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Normal);
+    ctx.set_font_size(SECONDARY_FONT_SIZE);
+    ctx.move_to(text_x, center_y + SECONDARY_FONT_SIZE + 12.0);
+    ctx.show_text(&ellipsize(ctx, &secondary, text_width)?)?;
+
+    ctx.set_source_rgba(1.0, 1.0, 1.0, 0.6);
+    ctx.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Bold);
+    ctx.set_font_size(BRANDING_FONT_SIZE);
+    ctx.move_to(text_x, CARD_HEIGHT as f64 - PADDING);
+    ctx.show_text("Mousai")?;
+
+    Ok(())
+}
+
+/// Returns the texture's pixels as a surface that can be drawn with Cairo.
+fn texture_surface(texture: &gdk::Texture) -> Result<cairo::ImageSurface> {
+    let width = texture.width();
+    let height = texture.height();
+    let stride = cairo::Format::ARgb32
+        .stride_for_width(width as u32)
+        .context("Invalid album art width")?;
+
+    // Downloaded textures are in the same format as Cairo's ARGB32
+    let mut data = vec![0; stride as usize * height as usize];
+    texture.download(&mut data, stride as usize);
+
+    cairo::ImageSurface::create_for_data(data, cairo::Format::ARgb32, width, height, stride)
+        .context("Failed to create album art surface")
+}
+
+fn rounded_rectangle(ctx: &cairo::Context, x: f64, y: f64, width: f64, height: f64, radius: f64) {
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    ctx.new_sub_path();
+    ctx.arc(x + width - radius, y + radius, radius, -FRAC_PI_2, 0.0);
+    ctx.arc(
+        x + width - radius,
+        y + height - radius,
+        radius,
+        0.0,
+        FRAC_PI_2,
+    );
+    ctx.arc(x + radius, y + height - radius, radius, FRAC_PI_2, PI);
+    ctx.arc(x + radius, y + radius, radius, PI, PI + FRAC_PI_2);
+    ctx.close_path();
+}
+
+/// Returns `text` with its end replaced by an ellipsis if it is wider than
+/// `max_width` with the current font of `ctx`.
+fn ellipsize(ctx: &cairo::Context, text: &str, max_width: f64) -> Result<String> {
+    if ctx.text_extents(text)?.x_advance() <= max_width {
+        return Ok(text.to_string());
+    }
+
+    let mut chars = text.chars().collect::<Vec<_>>();
+    while chars.pop().is_some() {
+        let candidate = format!("{}…", chars.iter().collect::<String>().trim_end());
+        if ctx.text_extents(&candidate)?.x_advance() <= max_width {
+            return Ok(candidate);
+        }
+    }
+
+    Ok("…".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::uid::Uid;
+
+    /// PNG file signature
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    #[gtk::test]
+    fn render_with_album_art() {
+        let song = Song::builder(
+            &Uid::from("0"),
+            "A Very Long Title That Does Not Fit In A Single Line Of The Card At All",
+            "Artist",
+            "Album",
+        )
+        .build();
+        let album_art = album_art::new_solid_texture(64, 48, [200, 40, 40, 255]);
+
+        let bytes = render(&song, ArtistTitleOrder::TitleFirst, Some(&album_art)).unwrap();
+        assert!(bytes.starts_with(PNG_SIGNATURE));
+
+        let texture = gdk::Texture::from_bytes(&bytes).unwrap();
+        assert_eq!(texture.width(), CARD_WIDTH);
+        assert_eq!(texture.height(), CARD_HEIGHT);
+
+        // The background is the darkened dominant color of the album art
+        let dominant_color = album_art::dominant_color(&album_art).unwrap();
+        let mut downloader = gdk::TextureDownloader::new(&texture);
+        downloader.set_format(gdk::MemoryFormat::R8g8b8a8);
+        let (pixels, _) = downloader.download_bytes();
+        let expected_red = dominant_color.red() as f64 * BACKGROUND_COLOR_FACTOR * 255.0;
+        assert!((pixels[0] as f64 - expected_red).abs() <= 1.0);
+    }
+
+    #[gtk::test]
+    fn render_without_album_art() {
+        let song = Song::builder(&Uid::from("0"), "Title", "Artist", "").build();
+
+        let bytes = render(&song, ArtistTitleOrder::ArtistFirst, None).unwrap();
+        assert!(bytes.starts_with(PNG_SIGNATURE));
+
+        let texture = gdk::Texture::from_bytes(&bytes).unwrap();
+        assert_eq!(texture.width(), CARD_WIDTH);
+        assert_eq!(texture.height(), CARD_HEIGHT);
+    }
+
+    #[test]
+    fn ellipsize_long() {
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1).unwrap();
+        let ctx = cairo::Context::new(&surface).unwrap();
+        ctx.set_font_size(20.0);
+
+        assert_eq!(ellipsize(&ctx, "Short", 1000.0).unwrap(), "Short");
+
+        let text = "Some long text that does not fit";
+        let max_width = ctx.text_extents("Some long").unwrap().x_advance() + 1.0;
+        let ellipsized = ellipsize(&ctx, text, max_width).unwrap();
+        assert!(ellipsized.ends_with('…'), "{}", ellipsized);
+        assert!(text.starts_with(ellipsized.trim_end_matches('…')));
+        assert!(ctx.text_extents(&ellipsized).unwrap().x_advance() <= max_width);
+
+        assert_eq!(ellipsize(&ctx, text, 0.0).unwrap(), "…");
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::Result;
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use gettextrs::gettext;
 use gtk::{
     glib::{self, once_cell::sync::Lazy},
     prelude::*,
@@ -16,11 +17,63 @@ use crate::{
     album_art::AlbumArt,
     date_time::DateTime,
     external_links::{ExternalLinkKey, ExternalLinks},
+    release_date::ReleaseDate,
     serde_helpers,
+    settings::ArtistTitleOrder,
     uid::Uid,
     Application,
 };
 
+/// Prefix of the ids of songs created by [`Song::new_placeholder`]
+const PLACEHOLDER_ID_PREFIX: &str = "placeholder";
+
+/// Weights of the fields in [`Song::fuzzy_match`], so that matches on the
+/// title and artist rank above matches on the other fields
+const TITLE_ARTIST_MATCH_WEIGHT: i64 = 4;
+const ALBUM_MATCH_WEIGHT: i64 = 2;
+const LYRICS_MATCH_WEIGHT: i64 = 1;
+
+/// How the audio that a song was recognized from was recorded
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecognitionSource {
+    /// Music recorded from a microphone. This is also assumed for songs
+    /// saved before the source was stored.
+    #[default]
+    Microphone,
+    /// Music recorded from the desktop audio
+    DesktopAudio,
+    /// Humming recorded from a microphone
+    Hum,
+    /// Audio decoded from a link
+    Link,
+}
+
+impl RecognitionSource {
+    pub fn display_name(self) -> String {
+        match self {
+            Self::Microphone => gettext("Microphone"),
+            Self::DesktopAudio => gettext("Desktop Audio"),
+            Self::Hum => gettext("Humming"),
+            Self::Link => gettext("Link"),
+        }
+    }
+}
+
+impl ArtistTitleOrder {
+    /// Whether the title comes before the artist. For
+    /// [`ArtistTitleOrder::Locale`], this is the order chosen by translators.
+    pub fn is_title_first(self) -> bool {
+        match self {
+            Self::Locale => {
+                // Translators: Set this to "title-first" if songs are usually written as "Title - Artist" in your language. Do NOT translate it into anything else.
+                gettext("artist-first") == "title-first"
+            }
+            Self::ArtistFirst => false,
+            Self::TitleFirst => true,
+        }
+    }
+}
+
 mod imp {
     use super::*;
 
@@ -32,15 +85,19 @@ mod imp {
         #[serde(with = "serde_helpers::once_cell")]
         pub(super) id: OnceCell<Uid>,
         /// Title of the song
-        #[property(get, set, construct_only)]
+        ///
+        /// This is only changed by the user, e.g., when annotating a placeholder.
+        #[property(get, set = Self::set_title, explicit_notify)]
         pub(super) title: RefCell<String>,
         /// Artist of the song
-        #[property(get, set, construct_only)]
+        ///
+        /// This is only changed by the user, e.g., when annotating a placeholder.
+        #[property(get, set = Self::set_artist, explicit_notify)]
         pub(super) artist: RefCell<String>,
         /// Album where the song was from
         #[property(get, set, construct_only)]
         pub(super) album: RefCell<String>,
-        /// Arbitrary string for release date
+        /// Release date as given by the provider, in an arbitrary format
         #[property(get, set, construct_only)]
         pub(super) release_date: RefCell<Option<String>>,
         /// Links relevant to the song
@@ -61,6 +118,20 @@ mod imp {
         /// Whether the song was heard for the first time
         #[property(get, set = Self::set_is_newly_heard, explicit_notify)]
         pub(super) is_newly_heard: Cell<bool>,
+        /// Whether the song was marked as favorite by the user
+        #[property(get, set = Self::set_is_favorite, explicit_notify)]
+        #[serde(default)]
+        pub(super) is_favorite: Cell<bool>,
+        /// Whether the song is the explicit version, if the provider reported it
+        #[serde(default)]
+        pub(super) is_explicit: Cell<Option<bool>>,
+        /// How the song was recognized
+        #[serde(default)]
+        pub(super) source: Cell<RecognitionSource>,
+        /// Release date normalized as ISO 8601, or `None` if it is in an
+        /// unknown format
+        #[serde(default)]
+        pub(super) normalized_release_date: RefCell<Option<String>>,
     }
 
     #[glib::object_subclass]
@@ -70,9 +141,44 @@ mod imp {
     }
 
     #[glib::derived_properties]
-    impl ObjectImpl for Song {}
+    impl ObjectImpl for Song {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            let normalized_release_date = self
+                .release_date
+                .borrow()
+                .as_deref()
+                .and_then(ReleaseDate::parse)
+                .map(|release_date| release_date.to_string());
+            self.normalized_release_date
+                .replace(normalized_release_date);
+        }
+    }
 
     impl Song {
+        fn set_title(&self, title: &str) {
+            let obj = self.obj();
+
+            if title == obj.title() {
+                return;
+            }
+
+            self.title.replace(title.to_string());
+            obj.notify_title();
+        }
+
+        fn set_artist(&self, artist: &str) {
+            let obj = self.obj();
+
+            if artist == obj.artist() {
+                return;
+            }
+
+            self.artist.replace(artist.to_string());
+            obj.notify_artist();
+        }
+
         fn set_last_heard(&self, last_heard: Option<DateTime>) {
             let obj = self.obj();
 
@@ -94,6 +200,17 @@ mod imp {
             self.is_newly_heard.set(is_newly_heard);
             obj.notify_is_newly_heard();
         }
+
+        fn set_is_favorite(&self, is_favorite: bool) {
+            let obj = self.obj();
+
+            if is_favorite == obj.is_favorite() {
+                return;
+            }
+
+            self.is_favorite.set(is_favorite);
+            obj.notify_is_favorite();
+        }
     }
 }
 
@@ -112,17 +229,186 @@ impl Song {
         SongBuilder::new(id, title, artist, album)
     }
 
+    /// Creates a song standing for a recognition without matches, so that it
+    /// can be annotated later. It is last heard at `recorded_time`.
+    pub fn new_placeholder(recorded_time: &DateTime) -> Self {
+        let song = Self::builder(
+            &Uid::generate_prefixed(PLACEHOLDER_ID_PREFIX),
+            &gettext("Unknown Song"),
+            "",
+            "",
+        )
+        .build();
+        song.set_last_heard(Some(recorded_time.clone()));
+        song
+    }
+
+    /// Sets the link of the album art, e.g., when it was looked up after the
+    /// song was recognized.
+    pub fn replace_album_art_link(&self, album_art_link: &str) {
+        self.imp()
+            .album_art_link
+            .replace(Some(album_art_link.to_string()));
+        self.notify_album_art_link();
+    }
+
+    /// Returns a copy of self with the metadata, e.g., title and links, of
+    /// `other`, such as when the song was recognized again.
+    ///
+    /// The id and the fields set by the user or the history, like whether it
+    /// is a favorite and when it was last heard, are kept.
+    pub fn with_metadata_of(&self, other: &Song) -> Song {
+        // Copy, so changing the links of either doesn't change the other
+        let external_links = ExternalLinks::new();
+        external_links.merge(&other.external_links());
+
+        let song = glib::Object::builder::<Song>()
+            .property("id", self.id())
+            .property("title", other.title())
+            .property("artist", other.artist())
+            .property("album", other.album())
+            .property("release-date", other.release_date())
+            .property("external-links", external_links)
+            .property("album-art-link", other.album_art_link())
+            .property("playback-link", other.playback_link())
+            .property("lyrics", other.lyrics())
+            .property("last-heard", self.last_heard())
+            .property("is-newly-heard", self.is_newly_heard())
+            .property("is-favorite", self.is_favorite())
+            .build();
+        song.imp().is_explicit.set(other.is_explicit());
+        song.imp().source.set(self.source());
+        song
+    }
+
+    /// How the song was recognized
+    pub fn source(&self) -> RecognitionSource {
+        self.imp().source.get()
+    }
+
+    /// Sets how the song was recognized. This must be called before the song
+    /// is added to a list, as it is not written to the db otherwise.
+    pub fn set_source(&self, source: RecognitionSource) {
+        self.imp().source.set(source);
+    }
+
+    /// Whether this is the explicit (`Some(true)`) or the clean (`Some(false)`)
+    /// version, or `None` if unknown.
+    pub fn is_explicit(&self) -> Option<bool> {
+        self.imp().is_explicit.get()
+    }
+
+    /// Whether this was created by [`Song::new_placeholder`]
+    pub fn is_placeholder(&self) -> bool {
+        self.id_ref().has_prefix(PLACEHOLDER_ID_PREFIX)
+    }
+
     /// Returns the score of song against the pattern.
-    pub fn fuzzy_match(&self, pattern: &str) -> Option<i64> {
+    ///
+    /// Each field is matched separately and the best weighted score is
+    /// returned. Lyrics are only matched if `include_lyrics` is true, as they
+    /// are much longer than the other fields.
+    pub fn fuzzy_match(&self, pattern: &str, include_lyrics: bool) -> Option<i64> {
         static FUZZY_MATCHER: Lazy<SkimMatcherV2> = Lazy::new(SkimMatcherV2::default);
 
-        let choice = format!("{} {}", self.artist(), self.title());
-        FUZZY_MATCHER.fuzzy_match(&choice, pattern)
+        let weighted_score = |choice: &str, weight: i64| {
+            FUZZY_MATCHER
+                .fuzzy_match(choice, pattern)
+                .map(|score| score * weight)
+        };
+
+        let title_artist_score = weighted_score(
+            &format!("{} {}", self.artist(), self.title()),
+            TITLE_ARTIST_MATCH_WEIGHT,
+        );
+        let album_score = weighted_score(&self.album(), ALBUM_MATCH_WEIGHT);
+        let lyrics_score = include_lyrics
+            .then(|| self.lyrics())
+            .flatten()
+            .and_then(|lyrics| weighted_score(&lyrics, LYRICS_MATCH_WEIGHT));
+
+        [title_artist_score, album_score, lyrics_score]
+            .into_iter()
+            .flatten()
+            .max()
+    }
+
+    /// Merges what the user has of `other` into self, e.g., when both are the
+    /// same track.
+    ///
+    /// The external links are united, the most recent last heard time is
+    /// kept, and self becomes a favorite if either is.
+    pub fn merge_user_data(&self, other: &Song) {
+        self.external_links().merge(&other.external_links());
+        if other.last_heard() > self.last_heard() {
+            self.set_last_heard(other.last_heard());
+        }
+        if other.is_favorite() && !self.is_favorite() {
+            self.set_is_favorite(true);
+        }
+    }
+
+    /// Returns a copy of self with the user data of `others` merged into it,
+    /// leaving self untouched. See [`Song::merge_user_data`].
+    pub fn merged_with(&self, others: &[Song]) -> Song {
+        let merged = self.with_metadata_of(self);
+        for other in others {
+            merged.merge_user_data(other);
+        }
+        merged
+    }
+
+    /// String copied to clipboard when copying self, with the artist and the
+    /// title in the given order.
+    pub fn copy_term(&self, order: ArtistTitleOrder) -> String {
+        if order.is_title_first() {
+            format!("{} - {}", self.title(), self.artist())
+        } else {
+            format!("{} - {}", self.artist(), self.title())
+        }
+    }
+
+    /// Returns the most relevant web link of the song, if there is one.
+    pub fn web_link(&self) -> Option<String> {
+        self.external_links()
+            .first_of(&[
+                ExternalLinkKey::SpotifyUrl,
+                ExternalLinkKey::AppleMusicUrl,
+                ExternalLinkKey::AudDUrl,
+            ])
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the uri of the link to listen to this song on `preferred`, or
+    /// on the other services if there is none.
+    pub fn listen_uri(&self, preferred: ExternalLinkKey) -> Option<String> {
+        self.external_links()
+            .first_of(&ExternalLinkKey::listen_priority(preferred))
+            .map(|(key, value)| key.to_uri(&value))
+    }
+
+    /// Returns the release date normalized as ISO 8601, or `None` if there is
+    /// none or it is in an unknown format.
+    pub fn normalized_release_date(&self) -> Option<String> {
+        self.imp().normalized_release_date.borrow().clone()
     }
 
-    /// String copied to clipboard when copying self.
-    pub fn copy_term(&self) -> String {
-        format!("{} - {}", self.artist(), self.title())
+    /// Returns the release date parsed into a structured form, or `None` if
+    /// there is none or it is in an unknown format.
+    pub fn parsed_release_date(&self) -> Option<ReleaseDate> {
+        self.imp()
+            .normalized_release_date
+            .borrow()
+            .as_deref()
+            .and_then(ReleaseDate::parse)
+    }
+
+    /// Returns the release date in the locale's format, or as given if it is
+    /// in an unknown format.
+    pub fn release_date_display(&self) -> Option<String> {
+        self.parsed_release_date()
+            .map(|release_date| release_date.to_display())
+            .or_else(|| self.release_date())
     }
 
     /// Get a reference to the Uid instead of cloning it like in `Self::id()`
@@ -130,15 +416,55 @@ impl Song {
         self.imp().id.get().unwrap()
     }
 
-    /// Returns a result of album art for the corresponding album art link if it exists
+    /// Returns the links where the album art can be downloaded, the
+    /// preferred first.
+    ///
+    /// If `prefers_cover_art_archive` is set and the song has a MusicBrainz
+    /// release, its front cover on the Cover Art Archive comes before the
+    /// album art link from the provider.
+    pub fn album_art_links(&self, prefers_cover_art_archive: bool) -> Vec<String> {
+        let mut links = Vec::new();
+
+        if prefers_cover_art_archive {
+            if let Some(release_id) = self
+                .external_links()
+                .get(ExternalLinkKey::MusicbrainzReleaseId)
+            {
+                links.push(format!(
+                    "https://coverartarchive.org/release/{}/front-500",
+                    release_id
+                ));
+            }
+        }
+
+        links.extend(self.album_art_link());
+        links
+    }
+
+    /// Returns a result of album art for the corresponding album art link if it exists,
+    /// falling back to the next link if it fails to load
     pub fn album_art(&self) -> Option<Rc<AlbumArt>> {
-        let album_art_link = self.album_art_link()?;
+        // Songs without any link don't need the application to be running
+        if self.album_art_link().is_none()
+            && self
+                .external_links()
+                .get(ExternalLinkKey::MusicbrainzReleaseId)
+                .is_none()
+        {
+            return None;
+        }
 
-        Some(
-            Application::get()
-                .album_art_store()
-                .get_or_init(&album_art_link),
-        )
+        let app = Application::get();
+        let store = app.album_art_store();
+
+        match self
+            .album_art_links(app.settings().prefer_cover_art_archive())
+            .as_slice()
+        {
+            [] => None,
+            [link] => Some(store.get_or_init(link)),
+            [link, fallback, ..] => Some(store.get_or_init_with_fallback(link, fallback)),
+        }
     }
 }
 
@@ -151,7 +477,7 @@ impl Serialize for Song {
 impl<'de> Deserialize<'de> for Song {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
         let deserialized_imp = imp::Song::deserialize(deserializer)?;
-        Ok(glib::Object::builder()
+        let song = glib::Object::builder::<Song>()
             .property(
                 "id",
                 deserialized_imp
@@ -178,7 +504,15 @@ impl<'de> Deserialize<'de> for Song {
                 "is-newly-heard",
                 deserialized_imp.is_newly_heard.into_inner(),
             )
-            .build())
+            .property("is-favorite", deserialized_imp.is_favorite.into_inner())
+            .build();
+        song.imp()
+            .is_explicit
+            .set(deserialized_imp.is_explicit.into_inner());
+        song.imp().source.set(deserialized_imp.source.into_inner());
+        // The normalized release date is not read back, as it is derived from
+        // the raw one on construction, e.g., for rows saved before it was.
+        Ok(song)
     }
 }
 
@@ -186,6 +520,7 @@ impl<'de> Deserialize<'de> for Song {
 pub struct SongBuilder {
     properties: Vec<(&'static str, glib::Value)>,
     external_links: ExternalLinks,
+    is_explicit: Option<bool>,
 }
 
 impl SongBuilder {
@@ -198,6 +533,7 @@ impl SongBuilder {
                 ("album", album.into()),
             ],
             external_links: ExternalLinks::default(),
+            is_explicit: None,
         }
     }
 
@@ -206,6 +542,16 @@ impl SongBuilder {
         self
     }
 
+    pub fn favorite(&mut self, value: bool) -> &mut Self {
+        self.properties.push(("is-favorite", value.into()));
+        self
+    }
+
+    pub fn explicit(&mut self, value: bool) -> &mut Self {
+        self.is_explicit = Some(value);
+        self
+    }
+
     pub fn release_date(&mut self, value: &str) -> &mut Self {
         self.properties.push(("release-date", value.into()));
         self
@@ -235,9 +581,11 @@ impl SongBuilder {
     pub fn build(&mut self) -> Song {
         self.properties
             .push(("external-links", self.external_links.to_value()));
-        glib::Object::with_mut_values(Song::static_type(), &mut self.properties)
-            .downcast()
-            .unwrap()
+        let song = glib::Object::with_mut_values(Song::static_type(), &mut self.properties)
+            .downcast::<Song>()
+            .unwrap();
+        song.imp().is_explicit.set(self.is_explicit);
+        song
     }
 }
 
@@ -259,6 +607,67 @@ mod test {
         assert_eq!(&song.id(), song.id_ref());
     }
 
+    #[test]
+    fn copy_term() {
+        let song = Song::builder(&Uid::from("a"), "Some song", "Someone", "SomeAlbum").build();
+        assert_eq!(
+            song.copy_term(ArtistTitleOrder::ArtistFirst),
+            "Someone - Some song"
+        );
+        assert_eq!(
+            song.copy_term(ArtistTitleOrder::TitleFirst),
+            "Some song - Someone"
+        );
+        // Untranslated, so the default of artist first is used
+        assert_eq!(
+            song.copy_term(ArtistTitleOrder::Locale),
+            "Someone - Some song"
+        );
+    }
+
+    #[test]
+    fn album_art_links() {
+        let song = Song::builder(&Uid::from("a"), "A", "B", "C")
+            .album_art_link("https://provider.test/a.jpg")
+            .external_link(
+                ExternalLinkKey::MusicbrainzReleaseId,
+                "3ce8e4e5-8a6c-4afa-b2c6-4be35961fbc9",
+            )
+            .build();
+        assert_eq!(
+            song.album_art_links(true),
+            [
+                "https://coverartarchive.org/release/3ce8e4e5-8a6c-4afa-b2c6-4be35961fbc9/front-500",
+                "https://provider.test/a.jpg"
+            ]
+        );
+        assert_eq!(song.album_art_links(false), ["https://provider.test/a.jpg"]);
+
+        let song = Song::builder(&Uid::from("b"), "A", "B", "C")
+            .album_art_link("https://provider.test/b.jpg")
+            .build();
+        assert_eq!(song.album_art_links(true), ["https://provider.test/b.jpg"]);
+
+        let song = Song::builder(&Uid::from("c"), "A", "B", "C").build();
+        assert!(song.album_art_links(true).is_empty());
+    }
+
+    #[test]
+    fn placeholder() {
+        let recorded_time = DateTime::from_iso8601("2022-01-01T00:00:00Z").unwrap();
+        let placeholder = Song::new_placeholder(&recorded_time);
+        assert!(placeholder.is_placeholder());
+        assert!(placeholder.artist().is_empty());
+        assert_eq!(placeholder.last_heard(), Some(recorded_time));
+        assert_ne!(
+            placeholder.id(),
+            Song::new_placeholder(&DateTime::now_utc()).id()
+        );
+
+        let song = Song::builder(&Uid::from("placeholder"), "A", "B", "C").build();
+        assert!(!song.is_placeholder());
+    }
+
     #[test]
     fn properties() {
         let song = Song::builder(
@@ -272,6 +681,8 @@ mod test {
         .playback_link("https://test.mp3")
         .lyrics("Some song lyrics")
         .newly_heard(true)
+        .favorite(true)
+        .explicit(true)
         .build();
 
         assert_eq!(song.title(), "Some song");
@@ -282,6 +693,32 @@ mod test {
         assert_eq!(song.playback_link().as_deref(), Some("https://test.mp3"));
         assert_eq!(song.lyrics().as_deref(), Some("Some song lyrics"));
         assert!(song.is_newly_heard());
+        assert!(song.is_favorite());
+        assert_eq!(song.is_explicit(), Some(true));
+    }
+
+    #[test]
+    fn release_date_display() {
+        let song = Song::builder(&Uid::from("a"), "A", "B", "C")
+            .release_date("2020")
+            .build();
+        assert_eq!(song.normalized_release_date().as_deref(), Some("2020"));
+        assert_eq!(song.parsed_release_date(), ReleaseDate::parse("2020"));
+        assert_eq!(song.release_date_display().as_deref(), Some("2020"));
+
+        // Shown as is if it can't be parsed
+        let song = Song::builder(&Uid::from("b"), "A", "B", "C")
+            .release_date("Summer of '69")
+            .build();
+        assert_eq!(song.normalized_release_date(), None);
+        assert_eq!(song.parsed_release_date(), None);
+        assert_eq!(
+            song.release_date_display().as_deref(),
+            Some("Summer of '69")
+        );
+
+        let song = Song::builder(&Uid::from("c"), "A", "B", "C").build();
+        assert_eq!(song.release_date_display(), None);
     }
 
     fn assert_song_eq(v1: &Song, v2: &Song) {
@@ -290,6 +727,7 @@ mod test {
         assert_eq!(v1.artist(), v2.artist());
         assert_eq!(v1.album(), v2.album());
         assert_eq!(v1.release_date(), v2.release_date());
+        assert_eq!(v1.normalized_release_date(), v2.normalized_release_date());
 
         assert_eq!(v1.external_links().n_items(), v2.external_links().n_items());
         for (v1_item, v2_item) in v1
@@ -308,6 +746,9 @@ mod test {
         assert_eq!(v1.lyrics(), v2.lyrics());
         assert_eq!(v1.last_heard(), v2.last_heard());
         assert_eq!(v1.is_newly_heard(), v2.is_newly_heard());
+        assert_eq!(v1.is_favorite(), v2.is_favorite());
+        assert_eq!(v1.is_explicit(), v2.is_explicit());
+        assert_eq!(v1.source(), v2.source());
     }
 
     #[test]
@@ -324,6 +765,27 @@ mod test {
         let de_val = bincode::deserialize::<Song>(&bytes).unwrap();
         assert_song_eq(&val, &de_val);
 
+        let val = SongBuilder::new(&Uid::from("b"), "B Title", "B Artist", "B Album")
+            .favorite(true)
+            .build();
+        let bytes = bincode::serialize(&val).unwrap();
+        let de_val = bincode::deserialize::<Song>(&bytes).unwrap();
+        assert_song_eq(&val, &de_val);
+
+        let val = SongBuilder::new(&Uid::from("b"), "B Title", "B Artist", "B Album")
+            .explicit(false)
+            .build();
+        let bytes = bincode::serialize(&val).unwrap();
+        let de_val = bincode::deserialize::<Song>(&bytes).unwrap();
+        assert_song_eq(&val, &de_val);
+
+        let val = SongBuilder::new(&Uid::from("b"), "B Title", "B Artist", "B Album").build();
+        val.set_source(RecognitionSource::DesktopAudio);
+        let bytes = bincode::serialize(&val).unwrap();
+        let de_val = bincode::deserialize::<Song>(&bytes).unwrap();
+        assert_eq!(de_val.source(), RecognitionSource::DesktopAudio);
+        assert_song_eq(&val, &de_val);
+
         let val = SongBuilder::new(&Uid::from("c"), "C Title", "C Artist", "C Album")
             .release_date("some value")
             .album_art_link("some value")
@@ -374,5 +836,102 @@ mod test {
             "2022-05-14T10:15:37.798479+08"
         );
         assert!(song.is_newly_heard());
+        assert!(!song.is_favorite());
+        assert_eq!(song.source(), RecognitionSource::Microphone);
+    }
+
+    #[test]
+    fn legacy_bincode_source() {
+        let val = SongBuilder::new(&Uid::from("a"), "A Title", "A Artist", "A Album").build();
+        val.set_source(RecognitionSource::Hum);
+        let mut bytes = bincode::serialize(&val).unwrap();
+
+        // Rows saved before the source was stored lack the fields from it on,
+        // and the migrations append the variant index of the default source
+        // and no normalized release date.
+        bytes.truncate(bytes.len() - 4 - 1);
+        bytes.extend_from_slice(&0_u32.to_le_bytes());
+        bytes.extend_from_slice(&bincode::serialize(&None::<String>).unwrap());
+        let de_val = bincode::deserialize::<Song>(&bytes).unwrap();
+        assert_eq!(de_val.source(), RecognitionSource::Microphone);
+        assert_eq!(de_val.title(), "A Title");
+    }
+
+    #[test]
+    fn legacy_bincode_normalized_release_date() {
+        let val = SongBuilder::new(&Uid::from("a"), "A Title", "A Artist", "A Album")
+            .release_date("2020/3/4")
+            .build();
+        assert_eq!(val.normalized_release_date().as_deref(), Some("2020-03-04"));
+        let mut bytes = bincode::serialize(&val).unwrap();
+
+        // Rows saved before the normalized release date was stored lack the
+        // last field, and the migration appends `None`, which is normalized
+        // again from the raw value.
+        let normalized_len = bincode::serialize(&val.normalized_release_date())
+            .unwrap()
+            .len();
+        bytes.truncate(bytes.len() - normalized_len);
+        bytes.extend_from_slice(&bincode::serialize(&None::<String>).unwrap());
+        let de_val = bincode::deserialize::<Song>(&bytes).unwrap();
+        assert_eq!(de_val.release_date().as_deref(), Some("2020/3/4"));
+        assert_eq!(
+            de_val.normalized_release_date().as_deref(),
+            Some("2020-03-04")
+        );
+    }
+
+    #[test]
+    fn fuzzy_match_weighted() {
+        let title_match =
+            Song::builder(&Uid::from("a"), "Amnesia", "5 Seconds Of Summer", "LIVESOS")
+                .lyrics("I drove by all the places we used to hang out getting wasted")
+                .build();
+        let album_match = Song::builder(&Uid::from("b"), "Lost Boy", "Ruth B.", "Amnesia").build();
+
+        // Album-only query finds the song
+        assert!(title_match.fuzzy_match("livesos", false).is_some());
+        assert!(album_match.fuzzy_match("livesos", false).is_none());
+
+        // Title matches rank above album matches
+        let title_score = title_match.fuzzy_match("amnesia", false).unwrap();
+        let album_score = album_match.fuzzy_match("amnesia", false).unwrap();
+        assert!(title_score > album_score);
+
+        // Lyrics are only matched when included, and rank the lowest
+        assert!(title_match.fuzzy_match("places we used", false).is_none());
+        let lyrics_score = title_match.fuzzy_match("places we used", true).unwrap();
+        let other_title_match =
+            Song::builder(&Uid::from("c"), "Places We Used", "Someone", "").build();
+        assert!(
+            other_title_match
+                .fuzzy_match("places we used", true)
+                .unwrap()
+                > lyrics_score
+        );
+        assert!(album_match.fuzzy_match("places we used", true).is_none());
+    }
+
+    #[test]
+    fn merged_with() {
+        let song = Song::builder(&Uid::from("a"), "A", "A", "A")
+            .external_link(ExternalLinkKey::SpotifyUrl, "spotify")
+            .build();
+        let other = Song::builder(&Uid::from("b"), "A", "A", "A")
+            .external_link(ExternalLinkKey::YoutubeSearchTerm, "youtube")
+            .build();
+        other.set_last_heard(DateTime::now_utc());
+        other.set_is_favorite(true);
+
+        let merged = song.merged_with(&[other.clone()]);
+        assert_eq!(merged.id_ref(), song.id_ref());
+        assert_eq!(merged.external_links().n_items(), 2);
+        assert_eq!(merged.last_heard(), other.last_heard());
+        assert!(merged.is_favorite());
+
+        // Self is left untouched
+        assert_eq!(song.external_links().n_items(), 1);
+        assert_eq!(song.last_heard(), None);
+        assert!(!song.is_favorite());
     }
 }
@@ -2,11 +2,15 @@
 // SPDX-FileCopyrightText: 2023 Dave Patrick Caberto
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
-use gtk::{glib, prelude::*, subclass::prelude::*};
+use gtk::{
+    glib::{self, WeakRef},
+    prelude::*,
+    subclass::prelude::*,
+};
 
-use crate::song::Song;
+use crate::{song::Song, song_list::SongList};
 
 mod imp {
     use super::*;
@@ -19,6 +23,14 @@ mod imp {
         /// If search is empty, the filter will match all songs.
         #[property(get, set = Self::set_search, explicit_notify)]
         pub(super) search: RefCell<String>,
+        /// Whether the search also matches the lyrics
+        #[property(get, set = Self::set_search_lyrics, explicit_notify)]
+        pub(super) search_lyrics: Cell<bool>,
+
+        pub(super) predicate: RefCell<Option<SongPredicate>>,
+        /// List whose cached search results are used instead of matching
+        /// every song
+        pub(super) song_list: WeakRef<SongList>,
     }
 
     #[glib::object_subclass]
@@ -33,7 +45,7 @@ mod imp {
 
     impl FilterImpl for SongFilter {
         fn strictness(&self) -> gtk::FilterMatch {
-            if self.search.borrow().is_empty() {
+            if self.search.borrow().is_empty() && self.predicate.borrow().is_none() {
                 gtk::FilterMatch::All
             } else {
                 gtk::FilterMatch::Some
@@ -43,12 +55,29 @@ mod imp {
         fn match_(&self, song: &glib::Object) -> bool {
             let song = song.downcast_ref::<Song>().unwrap();
 
+            if self
+                .predicate
+                .borrow()
+                .as_ref()
+                .is_some_and(|predicate| !predicate.matches(song))
+            {
+                return false;
+            }
+
             let search = self.search.borrow();
 
             if search.is_empty() {
-                true
-            } else {
-                song.fuzzy_match(&search).is_some()
+                return true;
+            }
+
+            match self.song_list.upgrade() {
+                Some(song_list) => song_list
+                    .search(&search, self.search_lyrics.get())
+                    .rank(song.id_ref())
+                    .is_some(),
+                None => song
+                    .fuzzy_match(&search, self.search_lyrics.get())
+                    .is_some(),
             }
         }
     }
@@ -57,7 +86,7 @@ mod imp {
         fn set_search(&self, search: &str) {
             let obj = self.obj();
             let old_search = obj.search();
-            let search = search.to_lowercase();
+            let search = normalize_search_term(search);
 
             if old_search == search {
                 return;
@@ -77,6 +106,26 @@ mod imp {
             obj.changed(change);
             obj.notify_search();
         }
+
+        fn set_search_lyrics(&self, search_lyrics: bool) {
+            let obj = self.obj();
+
+            if search_lyrics == obj.search_lyrics() {
+                return;
+            }
+
+            self.search_lyrics.set(search_lyrics);
+
+            if !self.search.borrow().is_empty() {
+                obj.changed(if search_lyrics {
+                    gtk::FilterChange::LessStrict
+                } else {
+                    gtk::FilterChange::MoreStrict
+                });
+            }
+
+            obj.notify_search_lyrics();
+        }
     }
 }
 
@@ -90,6 +139,34 @@ impl SongFilter {
     pub fn new() -> Self {
         glib::Object::new()
     }
+
+    /// Sets the predicate that songs must match, in addition to the search.
+    pub fn set_predicate(&self, predicate: Option<SongPredicate>) {
+        let imp = self.imp();
+
+        if *imp.predicate.borrow() == predicate {
+            return;
+        }
+
+        let change = if predicate.is_none() {
+            gtk::FilterChange::LessStrict
+        } else {
+            gtk::FilterChange::Different
+        };
+
+        imp.predicate.replace(predicate);
+        self.changed(change);
+    }
+
+    pub fn predicate(&self) -> Option<SongPredicate> {
+        self.imp().predicate.borrow().clone()
+    }
+
+    /// Sets the list of the filtered songs, so its cached search results are
+    /// used. Songs not in the list never match a search.
+    pub fn set_song_list(&self, song_list: &SongList) {
+        self.imp().song_list.set(Some(song_list));
+    }
 }
 
 impl Default for SongFilter {
@@ -98,6 +175,51 @@ impl Default for SongFilter {
     }
 }
 
+/// Condition that songs must satisfy to match a [`SongFilter`]
+///
+/// Predicates can be combined with [`SongPredicate::And`] and
+/// [`SongPredicate::Or`]. All of them are evaluated in memory, as the fuzzy
+/// search cannot be answered by the database anyway.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SongPredicate {
+    /// Song is marked as favorite
+    Favorite,
+    /// Song is by the artist, ignoring case
+    Artist(String),
+    /// Song is from the album, ignoring case
+    Album(String),
+    /// Song fuzzy matches the term
+    Search(String),
+    /// All predicates match, or there are none
+    And(Vec<SongPredicate>),
+    /// Any predicate matches
+    Or(Vec<SongPredicate>),
+    /// The predicate does not match
+    Not(Box<SongPredicate>),
+}
+
+impl SongPredicate {
+    pub fn matches(&self, song: &Song) -> bool {
+        match self {
+            Self::Favorite => song.is_favorite(),
+            Self::Artist(artist) => song.artist().to_lowercase() == artist.to_lowercase(),
+            Self::Album(album) => song.album().to_lowercase() == album.to_lowercase(),
+            Self::Search(term) => {
+                let term = normalize_search_term(term);
+                term.is_empty() || song.fuzzy_match(&term, false).is_some()
+            }
+            Self::And(predicates) => predicates.iter().all(|predicate| predicate.matches(song)),
+            Self::Or(predicates) => predicates.iter().any(|predicate| predicate.matches(song)),
+            Self::Not(predicate) => !predicate.matches(song),
+        }
+    }
+}
+
+/// Returns the term in the form it is matched against songs.
+pub fn normalize_search_term(term: &str) -> String {
+    term.to_lowercase()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -169,5 +291,62 @@ mod tests {
 
         filter.set_search("");
         assert_eq!(calls_output.take(), &[gtk::FilterChange::LessStrict]);
+
+        filter.set_predicate(Some(SongPredicate::Favorite));
+        assert_eq!(calls_output.take(), &[gtk::FilterChange::Different]);
+
+        filter.set_predicate(Some(SongPredicate::Favorite));
+        assert!(calls_output.take().is_empty());
+
+        filter.set_predicate(None);
+        assert_eq!(calls_output.take(), &[gtk::FilterChange::LessStrict]);
+    }
+
+    #[gtk::test]
+    fn predicate() {
+        let songs = [
+            Song::builder(&Uid::from("0"), "Blue", "Eiffel 65", "Europop").build(),
+            Song::builder(&Uid::from("1"), "Move Your Body", "Eiffel 65", "Europop").build(),
+            Song::builder(&Uid::from("2"), "Blue Monday", "New Order", "Power").build(),
+            Song::builder(&Uid::from("3"), "Ceremony", "New Order", "Substance").build(),
+        ];
+        songs[0].set_is_favorite(true);
+        songs[2].set_is_favorite(true);
+        songs[3].set_is_favorite(true);
+
+        let filter = SongFilter::new();
+        filter.set_predicate(Some(SongPredicate::And(vec![
+            SongPredicate::Favorite,
+            SongPredicate::Or(vec![
+                SongPredicate::Album("europop".into()),
+                SongPredicate::Artist("NEW ORDER".into()),
+            ]),
+        ])));
+        assert_eq!(filter.strictness(), gtk::FilterMatch::Some);
+
+        let matched_ids = |filter: &SongFilter| {
+            songs
+                .iter()
+                .filter(|song| filter.match_(*song))
+                .map(|song| song.id())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            matched_ids(&filter),
+            [Uid::from("0"), Uid::from("2"), Uid::from("3")]
+        );
+
+        filter.set_search("blue");
+        assert_eq!(matched_ids(&filter), [Uid::from("0"), Uid::from("2")]);
+
+        filter.set_predicate(Some(SongPredicate::Not(Box::new(SongPredicate::Favorite))));
+        assert_eq!(matched_ids(&filter), Vec::<Uid>::new());
+
+        filter.set_search("");
+        assert_eq!(matched_ids(&filter), [Uid::from("1")]);
+
+        assert!(SongPredicate::And(Vec::new()).matches(&songs[1]));
+        assert!(!SongPredicate::Or(Vec::new()).matches(&songs[1]));
+        assert!(SongPredicate::Search("move".into()).matches(&songs[1]));
     }
 }
@@ -1,7 +1,7 @@
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
 use gtk::{
     gio,
-    glib::{self, clone},
+    glib::{self, clone, closure_local},
     prelude::*,
     subclass::prelude::*,
 };
@@ -9,13 +9,16 @@ use heed::types::SerdeBincode;
 use indexmap::IndexMap;
 
 use std::{
-    cell::{OnceCell, RefCell},
+    cell::{Cell, OnceCell, RefCell},
     collections::{BTreeSet, HashMap, HashSet},
+    rc::Rc,
     time::Instant,
 };
 
 use crate::{
-    database::{EnvExt, SONG_LIST_DB_NAME},
+    database::{EnvExt, RawDatabase, SONG_LIST_DB_NAME},
+    date_time::DateTime,
+    search_cache::{SearchCache, SearchResults},
     song::Song,
     uid::{Uid, UidCodec},
     utils,
@@ -27,10 +30,13 @@ type SongDatabase = heed::Database<UidCodec, SerdeBincode<Song>>;
 
 mod imp {
     use super::*;
+    use glib::{once_cell::sync::Lazy, subclass::Signal};
 
     #[derive(Default)]
     pub struct SongList {
         pub(super) list: RefCell<IndexMap<Uid, Song>>,
+        pub(super) distinguishes_explicit: Cell<bool>,
+        pub(super) search_cache: SearchCache,
 
         pub(super) db: OnceCell<(heed::Env, SongDatabase)>,
     }
@@ -42,7 +48,34 @@ mod imp {
         type Interfaces = (gio::ListModel,);
     }
 
-    impl ObjectImpl for SongList {}
+    impl ObjectImpl for SongList {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                vec![
+                    Signal::builder("write-failed")
+                        .param_types([String::static_type()])
+                        .build(),
+                    Signal::builder("song-removed")
+                        .param_types([Song::static_type()])
+                        .build(),
+                    Signal::builder("favorites-changed").build(),
+                ]
+            });
+
+            SIGNALS.as_ref()
+        }
+
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            // Searched fields other than the title and artist can't change
+            // without the song being replaced, so the results are otherwise
+            // only outdated when the items change.
+            self.obj().connect_items_changed(|obj, _, _, _| {
+                obj.imp().search_cache.clear();
+            });
+        }
+    }
 
     impl ListModelImpl for SongList {
         fn item_type(&self) -> glib::Type {
@@ -69,6 +102,53 @@ glib::wrapper! {
 }
 
 impl SongList {
+    /// Emitted when a change of a song's property can't be written to the db.
+    /// In that case, the property is reverted to its persisted value.
+    pub fn connect_write_failed<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self, &str) + 'static,
+    {
+        self.connect_closure(
+            "write-failed",
+            true,
+            closure_local!(|obj: &Self, message: &str| {
+                f(obj, message);
+            }),
+        )
+    }
+
+    /// Emitted for every song removed from the list, after the list is updated.
+    pub fn connect_song_removed<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self, &Song) + 'static,
+    {
+        self.connect_closure(
+            "song-removed",
+            true,
+            closure_local!(|obj: &Self, song: &Song| {
+                f(obj, song);
+            }),
+        )
+    }
+
+    /// Emitted when a song in the list is marked or unmarked as favorite.
+    pub fn connect_favorites_changed<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self) + 'static,
+    {
+        self.connect_closure(
+            "favorites-changed",
+            true,
+            closure_local!(|obj: &Self| {
+                f(obj);
+            }),
+        )
+    }
+
+    fn emit_write_failed(&self, err: &anyhow::Error) {
+        self.emit_by_name::<()>("write-failed", &[&format!("{:?}", err)]);
+    }
+
     /// Load from the `songs` table in the database
     pub fn load_from_env(env: heed::Env) -> Result<Self> {
         let db_load_start_time = Instant::now();
@@ -141,8 +221,8 @@ impl SongList {
     ///
     /// This returns the number of songs that were appended at the end, i.e., not updated.
     ///
-    /// This is more efficient than [`SongList::insert`] since it emits `items-changed`
-    /// only once for all appended songs.
+    /// This is more efficient than [`SongList::insert`] since it writes all songs in a
+    /// single transaction and emits `items-changed` only once for all appended songs.
     pub fn insert_many(&self, songs: Vec<Song>) -> Result<u32> {
         let (env, db) = self.db();
         env.with_write_txn(|wtxn| {
@@ -195,8 +275,6 @@ impl SongList {
     }
 
     pub fn remove_many(&self, song_ids: &[&Uid]) -> Result<Vec<Song>> {
-        let imp = self.imp();
-
         let (env, db) = self.db();
         env.with_write_txn(|wtxn| {
             for song_id in song_ids {
@@ -206,45 +284,362 @@ impl SongList {
             Ok(())
         })?;
 
-        let to_remove_indices = {
-            let list = imp.list.borrow();
-            song_ids
-                .iter()
-                .filter_map(|&song_id| list.get_index_of(song_id))
-                .collect::<BTreeSet<_>>()
-        };
+        Ok(self.remove_from_list(song_ids))
+    }
 
-        let removed = {
-            let mut ret = Vec::with_capacity(to_remove_indices.len());
+    /// Writes the current state of `song` to the database.
+    ///
+    /// This is only needed for changes that don't emit a notify on the song,
+    /// like modifying its external links in place.
+    pub fn update(&self, song: &Song) -> Result<()> {
+        debug_assert!(self.contains(song.id_ref()), "song must be in the list");
 
-            // Reverse the iterations so we don't shift the indices
-            for &(first, count) in utils::consecutive_groups(&to_remove_indices).iter().rev() {
-                {
-                    let mut list = imp.list.borrow_mut();
+        let (env, db) = self.db();
+        env.with_write_txn(|wtxn| {
+            db.put(wtxn, song.id_ref(), song)
+                .context("Failed to put song to db")?;
+            Ok(())
+        })
+    }
+
+    /// Applies `f` to the song with the given id and writes the result in a
+    /// single transaction, instead of once for every property that `f` changes.
+    ///
+    /// If the write fails, all changes made by `f` are reverted.
+    pub fn update_with(&self, song_id: &Uid, f: impl FnOnce(&Song)) -> Result<Song> {
+        let song = self
+            .get(song_id)
+            .with_context(|| format!("Song `{}` is not in the list", song_id.as_str()))?;
+
+        let (_, db) = self.db();
+        self.apply_and_write(&song, f, |wtxn, song| {
+            db.put(wtxn, song.id_ref(), song)
+                .context("Failed to put song to db")?;
+            Ok(())
+        })?;
+
+        Ok(song)
+    }
+
+    /// Merges the user data of `song` into `existing`, e.g., when importing
+    /// or recognizing the same track again.
+    ///
+    /// Unlike other updates, this keeps a more recent last heard that is
+    /// already in the db.
+    fn merge_user_data_into(&self, existing: &Song, song: &Song) -> Result<()> {
+        self.apply_and_write(
+            existing,
+            |existing| existing.merge_user_data(song),
+            |wtxn, existing| self.put_reconciled(wtxn, existing),
+        )
+    }
 
-                    for index in (first..first + count).rev() {
-                        let (_, song) =
-                            list.shift_remove_index(index).expect("index must be valid");
-                        unbind_song_from_db(&song);
-                        ret.push(song);
+    /// Applies `f` to `song` and writes it with `put` in a single transaction,
+    /// reverting all changes made by `f` if the write fails.
+    fn apply_and_write(
+        &self,
+        song: &Song,
+        f: impl FnOnce(&Song),
+        put: impl FnOnce(&mut heed::RwTxn<'_>, &Song) -> Result<()>,
+    ) -> Result<()> {
+        let was_favorite = song.is_favorite();
+        with_db_binding_blocked(song, || f(song))?;
+
+        let (env, _) = self.db();
+        if let Err(err) = env.with_write_txn(|wtxn| put(wtxn, song)) {
+            for pspec in song.list_properties().iter() {
+                let flags = pspec.flags();
+                if flags.contains(glib::ParamFlags::WRITABLE)
+                    && !flags.contains(glib::ParamFlags::CONSTRUCT_ONLY)
+                {
+                    if let Err(err) = self.restore_persisted_property(song, pspec) {
+                        tracing::error!("Failed to restore song property: {:?}", err);
                     }
                 }
+            }
+            return Err(err);
+        }
+
+        if song.is_favorite() != was_favorite {
+            self.emit_by_name::<()>("favorites-changed", &[]);
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the metadata of the song with the given id with the one of
+    /// `recognized`, keeping its id and user fields, e.g., when it was
+    /// recognized again.
+    ///
+    /// This returns the updated song, which replaces the previous one in the list.
+    pub fn replace_metadata(&self, song_id: &Uid, recognized: &Song) -> Result<Song> {
+        let existing = self
+            .get(song_id)
+            .with_context(|| format!("Song `{}` is not in the list", song_id.as_str()))?;
+
+        let updated = existing.with_metadata_of(recognized);
+        self.insert(updated.clone())?;
+
+        Ok(updated)
+    }
+
+    /// Finds a song in the list that is the same track as `song` but has a different
+    /// [`Uid`], and merges `song` into it, i.e., its last heard is updated and missing
+    /// external links are added.
+    ///
+    /// This returns the song that `song` was merged into, or `None` if there is no
+    /// equivalent song. In that case, the song must be inserted instead.
+    ///
+    /// Placeholders are never merged.
+    pub fn merge_into_equivalent(&self, song: &Song) -> Result<Option<Song>> {
+        let Some(existing) = self.find_equivalent(song) else {
+            return Ok(None);
+        };
+
+        tracing::debug!(
+            id = ?song.id_ref(),
+            existing_id = ?existing.id_ref(),
+            "Merging song into equivalent song"
+        );
+
+        self.merge_user_data_into(&existing, song)?;
+
+        Ok(Some(existing))
+    }
+
+    /// Like [`SongList::merge_into_equivalent`], but also merges into a song
+    /// with the same [`Uid`], e.g., when restoring a backup of the history.
+    pub fn merge_into_existing(&self, song: &Song) -> Result<Option<Song>> {
+        let Some(existing) = self.get(song.id_ref()) else {
+            return self.merge_into_equivalent(song);
+        };
+
+        if existing == *song {
+            return Ok(Some(existing));
+        }
+
+        self.merge_user_data_into(&existing, song)?;
+
+        Ok(Some(existing))
+    }
+
+    /// Sets whether explicit versions are considered a different track from
+    /// clean or unknown versions, when merging and deduplicating songs.
+    ///
+    /// By default, they are considered the same track.
+    pub fn set_distinguishes_explicit(&self, distinguishes_explicit: bool) {
+        self.imp()
+            .distinguishes_explicit
+            .set(distinguishes_explicit);
+    }
+
+    /// Returns the key identifying the track of `song`, taking
+    /// [`SongList::set_distinguishes_explicit`] into account.
+    pub fn dedup_key(&self, song: &Song) -> (String, String, bool) {
+        let (title, artist) = normalized_song_key(song);
+        let is_explicit =
+            self.imp().distinguishes_explicit.get() && song.is_explicit() == Some(true);
+        (title, artist, is_explicit)
+    }
+
+    /// Returns the groups of songs that are the same track, without modifying
+    /// the list. See [`SongList::apply_dedup`].
+    ///
+    /// The favorite, or else the most recently heard song of each group is
+    /// proposed to be kept. Placeholders are never considered duplicates.
+    pub fn plan_dedup(&self) -> DedupPlan {
+        let mut groups = IndexMap::<(String, String, bool), Vec<Song>>::new();
+        for song in self
+            .imp()
+            .list
+            .borrow()
+            .values()
+            .filter(|song| !song.is_placeholder())
+        {
+            groups
+                .entry(self.dedup_key(song))
+                .or_default()
+                .push(song.clone());
+        }
+
+        let groups = groups
+            .into_values()
+            .filter(|songs| songs.len() > 1)
+            .map(|mut songs| {
+                // Stable, so the earlier song is kept on ties
+                songs
+                    .sort_by_key(|song| std::cmp::Reverse((song.is_favorite(), song.last_heard())));
+                let survivor = songs.remove(0);
+                DedupGroup {
+                    survivor,
+                    duplicates: songs,
+                }
+            })
+            .collect();
 
-                self.items_changed(first as u32, count as u32, 0);
+        DedupPlan { groups }
+    }
+
+    /// Merges the duplicates of each group into its survivor and removes them,
+    /// writing all changes in a single transaction.
+    ///
+    /// Songs that are no longer in the list are skipped. This returns the
+    /// number of removed songs.
+    pub fn apply_dedup(&self, plan: &DedupPlan) -> Result<u32> {
+        let groups = plan
+            .groups
+            .iter()
+            .filter_map(|group| {
+                let survivor = self.get(group.survivor.id_ref())?;
+                let duplicates = group
+                    .duplicates
+                    .iter()
+                    .filter(|song| song.id_ref() != survivor.id_ref())
+                    .filter_map(|song| self.get(song.id_ref()))
+                    .collect::<Vec<_>>();
+                (!duplicates.is_empty()).then_some((survivor, duplicates))
+            })
+            .collect::<Vec<_>>();
+
+        if groups.is_empty() {
+            return Ok(0);
+        }
+
+        // Write merged copies, so the survivors are left untouched if the txn
+        // fails
+        let (env, db) = self.db();
+        env.with_write_txn(|wtxn| {
+            for (survivor, duplicates) in &groups {
+                let merged = survivor.merged_with(duplicates);
+                db.put(wtxn, merged.id_ref(), &merged)
+                    .context("Failed to put song to db")?;
+                for duplicate in duplicates {
+                    db.delete(wtxn, duplicate.id_ref())
+                        .context("Failed to delete song from db")?;
+                }
             }
+            Ok(())
+        })?;
+
+        // The merged values are already in the db, so don't write them again
+        for (survivor, duplicates) in &groups {
+            with_db_binding_blocked(survivor, || {
+                for duplicate in duplicates {
+                    survivor.merge_user_data(duplicate);
+                }
+            })?;
+        }
 
-            debug_assert_eq!(ret.len(), to_remove_indices.len());
+        let to_remove_ids = groups
+            .iter()
+            .flat_map(|(_, duplicates)| duplicates.iter().map(|song| song.id_ref()))
+            .collect::<Vec<_>>();
+        let removed = self.remove_from_list(&to_remove_ids);
 
-            ret
+        Ok(removed.len() as u32)
+    }
+
+    /// Merges the song with `other_id` into the one with `survivor_id` and
+    /// removes it in a single transaction, e.g., when the user sees two songs
+    /// are the same track but they were not deduplicated.
+    ///
+    /// Like [`SongList::apply_dedup`], the metadata of the survivor is kept
+    /// and the user data of both is merged. This returns the survivor.
+    pub fn merge(&self, survivor_id: &Uid, other_id: &Uid) -> Result<Song> {
+        ensure!(survivor_id != other_id, "Can't merge a song into itself");
+
+        let survivor = self
+            .get(survivor_id)
+            .with_context(|| format!("Song `{}` is not in the list", survivor_id.as_str()))?;
+        let other = self
+            .get(other_id)
+            .with_context(|| format!("Song `{}` is not in the list", other_id.as_str()))?;
+
+        let plan = DedupPlan {
+            groups: vec![DedupGroup {
+                survivor: survivor.clone(),
+                duplicates: vec![other],
+            }],
         };
+        self.apply_dedup(&plan)?;
+
+        Ok(survivor)
+    }
+
+    /// Whether a song with the same [`Uid`] or the same normalized title and
+    /// artist is already in the list.
+    pub fn contains_equivalent(&self, song: &Song) -> bool {
+        self.contains(song.id_ref()) || self.find_equivalent(song).is_some()
+    }
 
-        Ok(removed)
+    /// Returns a song with a different [`Uid`] but with the same normalized
+    /// title and artist as `song`.
+    fn find_equivalent(&self, song: &Song) -> Option<Song> {
+        if song.is_placeholder() {
+            return None;
+        }
+
+        let key = self.dedup_key(song);
+        self.imp()
+            .list
+            .borrow()
+            .values()
+            .find(|other| {
+                other.id_ref() != song.id_ref()
+                    && !other.is_placeholder()
+                    && self.dedup_key(other) == key
+            })
+            .cloned()
+    }
+
+    /// Returns the songs that fuzzy match the query, reusing the results of
+    /// recent searches while the list is unchanged.
+    pub fn search(&self, query: &str, search_lyrics: bool) -> Rc<SearchResults> {
+        let imp = self.imp();
+        let list = imp.list.borrow();
+        imp.search_cache
+            .get_or_compute(query, search_lyrics, || list.values())
     }
 
     pub fn get(&self, song_id: &Uid) -> Option<Song> {
         self.imp().list.borrow().get(song_id).cloned()
     }
 
+    /// Returns up to `max_n` favorite songs, most recently heard first.
+    pub fn favorites(&self, max_n: usize) -> Vec<Song> {
+        let mut favorites = self
+            .imp()
+            .list
+            .borrow()
+            .values()
+            .filter(|song| song.is_favorite())
+            .cloned()
+            .collect::<Vec<_>>();
+        favorites.sort_by_key(|song| std::cmp::Reverse(song.last_heard()));
+        favorites.truncate(max_n);
+        favorites
+    }
+
+    /// Returns the songs last heard on the same local day as `day`, earliest
+    /// first.
+    pub fn heard_on(&self, day: &DateTime) -> Vec<Song> {
+        let ymd = day.to_local().ymd();
+
+        let mut songs = self
+            .imp()
+            .list
+            .borrow()
+            .values()
+            .filter(|song| {
+                song.last_heard()
+                    .is_some_and(|last_heard| last_heard.to_local().ymd() == ymd)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        songs.sort_by_key(|song| song.last_heard());
+        songs
+    }
+
     pub fn contains(&self, song_id: &Uid) -> bool {
         self.imp().list.borrow().contains_key(song_id)
     }
@@ -253,6 +648,135 @@ impl SongList {
         self.n_items() == 0
     }
 
+    /// Removes the songs that were last heard before `cutoff` in a single
+    /// transaction, except the favorites. Returns the number of removed songs.
+    pub fn remove_heard_before(&self, cutoff: &DateTime) -> Result<u32> {
+        let to_remove = self
+            .imp()
+            .list
+            .borrow()
+            .values()
+            .filter(|song| {
+                !song.is_favorite()
+                    && song
+                        .last_heard()
+                        .is_some_and(|last_heard| &last_heard < cutoff)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if to_remove.is_empty() {
+            return Ok(0);
+        }
+
+        let to_remove_ids = to_remove
+            .iter()
+            .map(|song| song.id_ref())
+            .collect::<Vec<_>>();
+        let removed = self.remove_many(&to_remove_ids)?;
+
+        Ok(removed.len() as u32)
+    }
+
+    /// Removes the least recently heard songs in a single transaction until
+    /// there are at most `max_n_items`, except the favorites. Songs that were
+    /// never heard are removed first. Returns the removed songs.
+    pub fn remove_oldest_beyond(&self, max_n_items: u32) -> Result<Vec<Song>> {
+        let n_excess = self.n_items().saturating_sub(max_n_items) as usize;
+
+        if n_excess == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut candidates = self
+            .imp()
+            .list
+            .borrow()
+            .values()
+            .filter(|song| !song.is_favorite())
+            .cloned()
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|song| song.last_heard());
+        candidates.truncate(n_excess);
+
+        if candidates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let to_remove_ids = candidates
+            .iter()
+            .map(|song| song.id_ref())
+            .collect::<Vec<_>>();
+        self.remove_many(&to_remove_ids)
+    }
+
+    /// Groups the songs by artist, with the most recently heard artist first.
+    pub fn group_by_artist(&self) -> Vec<ArtistSongs> {
+        let mut groups = IndexMap::<String, Vec<Song>>::new();
+        for song in self.imp().list.borrow().values() {
+            groups
+                .entry(normalized_artist_key(&song.artist()))
+                .or_default()
+                .push(song.clone());
+        }
+
+        let mut ret = groups
+            .into_values()
+            .map(|mut songs| {
+                songs.sort_by_key(|song| std::cmp::Reverse(song.last_heard()));
+                ArtistSongs {
+                    name: songs[0].artist(),
+                    songs,
+                }
+            })
+            .collect::<Vec<_>>();
+        ret.sort_by_key(|artist| std::cmp::Reverse(artist.last_heard()));
+        ret
+    }
+
+    /// Removes the songs from the list only, without touching the db.
+    fn remove_from_list(&self, song_ids: &[&Uid]) -> Vec<Song> {
+        let imp = self.imp();
+
+        let to_remove_indices = {
+            let list = imp.list.borrow();
+            song_ids
+                .iter()
+                .filter_map(|&song_id| list.get_index_of(song_id))
+                .collect::<BTreeSet<_>>()
+        };
+
+        let mut ret = Vec::with_capacity(to_remove_indices.len());
+
+        // Reverse the iterations so we don't shift the indices
+        for &(first, count) in utils::consecutive_groups(&to_remove_indices).iter().rev() {
+            {
+                let mut list = imp.list.borrow_mut();
+
+                for index in (first..first + count).rev() {
+                    let (_, song) = list.shift_remove_index(index).expect("index must be valid");
+                    unbind_song_from_db(&song);
+                    ret.push(song);
+                }
+            }
+
+            self.items_changed(first as u32, count as u32, 0);
+        }
+
+        debug_assert_eq!(ret.len(), to_remove_indices.len());
+
+        for song in &ret {
+            self.emit_by_name::<()>("song-removed", &[song]);
+        }
+
+        ret
+    }
+
+    /// Returns the db of the songs viewed as raw bytes.
+    pub fn raw_database(&self) -> RawDatabase {
+        self.db().1.remap_types()
+    }
+
     fn db(&self) -> &(heed::Env, SongDatabase) {
         self.imp().db.get().unwrap()
     }
@@ -264,25 +788,209 @@ impl SongList {
                 clone!(@weak self as obj => move |song, pspec| {
                     tracing::debug!("Song property `{}` notified", pspec.name());
 
-                    let (env, db) = obj.db();
-                    if let Err(err) = env.with_write_txn(|wtxn| {
-                        debug_assert!(
-                            db.get(wtxn, song.id_ref()).unwrap().is_some(),
-                            "song must exist in the db"
-                        );
-
-                        db.put(wtxn, song.id_ref(), song)
-                            .context("Failed to put song to db")?;
+                    if matches!(pspec.name(), "title" | "artist") {
+                        obj.imp().search_cache.clear();
+                    }
 
-                        Ok(())
-                    }) {
+                    if let Err(err) = obj.write_song(song) {
                         tracing::error!("Failed to update song in database: {:?}", err);
-                    };
+
+                        // Keep the in-memory value consistent with the db
+                        if let Err(err) = obj.restore_persisted_property(song, pspec) {
+                            tracing::error!("Failed to restore song property: {:?}", err);
+                        }
+
+                        obj.emit_write_failed(&err);
+                    }
+
+                    if pspec.name() == "is-favorite" {
+                        obj.emit_by_name::<()>("favorites-changed", &[]);
+                    }
                 }),
             );
             song.set_data(SONG_NOTIFY_HANDLER_ID_KEY, handler_id);
         }
     }
+
+    fn write_song(&self, song: &Song) -> Result<()> {
+        let (env, db) = self.db();
+        env.with_write_txn(|wtxn| {
+            debug_assert!(
+                matches!(db.get(wtxn, song.id_ref()), Ok(Some(_))),
+                "song must exist in the db"
+            );
+
+            db.put(wtxn, song.id_ref(), song)
+                .context("Failed to put song to db")?;
+
+            Ok(())
+        })
+    }
+
+    /// Puts `song` to the db, re-reading the persisted song first so that a
+    /// more recent last heard, e.g., from a recognition that was written in
+    /// between, is kept instead of being overwritten with an older one.
+    fn put_reconciled(&self, wtxn: &mut heed::RwTxn<'_>, song: &Song) -> Result<()> {
+        let (_, db) = self.db();
+
+        let persisted = db
+            .get(wtxn, song.id_ref())
+            .context("Failed to get song from db")?;
+        if let Some(persisted) = persisted {
+            if persisted.last_heard() > song.last_heard() {
+                tracing::debug!(
+                    "Keeping more recent persisted last heard of song `{}`",
+                    song.id_ref().as_str()
+                );
+                with_db_binding_blocked(song, || song.set_last_heard(persisted.last_heard()))?;
+            }
+        }
+
+        db.put(wtxn, song.id_ref(), song)
+            .context("Failed to put song to db")?;
+
+        Ok(())
+    }
+
+    /// Sets the property of `song` back to the value stored in the db.
+    fn restore_persisted_property(&self, song: &Song, pspec: &glib::ParamSpec) -> Result<()> {
+        let flags = pspec.flags();
+        anyhow::ensure!(
+            flags.contains(glib::ParamFlags::WRITABLE)
+                && !flags.contains(glib::ParamFlags::CONSTRUCT_ONLY),
+            "Property `{}` is not writable",
+            pspec.name()
+        );
+
+        let (env, db) = self.db();
+        let rtxn = env.read_txn().context("Failed to create read txn")?;
+        let persisted = db
+            .get(&rtxn, song.id_ref())
+            .context("Failed to get song from db")?
+            .context("Song is not in the db")?;
+        drop(rtxn);
+
+        let value = persisted.property_value(pspec.name());
+
+        // Don't try to write the restored value again
+        with_db_binding_blocked(song, || song.set_property_from_value(pspec.name(), &value))?;
+
+        Ok(())
+    }
+}
+
+/// Songs that are the same track, where the duplicates are merged into the
+/// survivor and then removed.
+#[derive(Debug, Clone)]
+pub struct DedupGroup {
+    pub survivor: Song,
+    pub duplicates: Vec<Song>,
+}
+
+impl DedupGroup {
+    /// Keeps the song with `song_id` instead, making the previous survivor
+    /// a duplicate. Returns false if the song is not in this group.
+    pub fn set_survivor(&mut self, song_id: &Uid) -> bool {
+        if self.survivor.id_ref() == song_id {
+            return true;
+        }
+
+        let Some(index) = self
+            .duplicates
+            .iter()
+            .position(|song| song.id_ref() == song_id)
+        else {
+            return false;
+        };
+
+        let new_survivor = self.duplicates.remove(index);
+        let prev_survivor = std::mem::replace(&mut self.survivor, new_survivor);
+        self.duplicates.insert(index, prev_survivor);
+        true
+    }
+}
+
+/// Proposed changes of [`SongList::apply_dedup`], which can be shown and
+/// adjusted before applying.
+#[derive(Debug, Default, Clone)]
+pub struct DedupPlan {
+    pub groups: Vec<DedupGroup>,
+}
+
+impl DedupPlan {
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Number of songs that would be removed
+    pub fn n_duplicates(&self) -> usize {
+        self.groups.iter().map(|group| group.duplicates.len()).sum()
+    }
+}
+
+/// Songs of a single artist, where case and diacritic variants of the
+/// artist's name are considered the same artist.
+#[derive(Debug, Clone)]
+pub struct ArtistSongs {
+    /// Name of the artist as in the most recently heard song
+    pub name: String,
+    /// Songs sorted by last heard, most recent first
+    pub songs: Vec<Song>,
+}
+
+impl ArtistSongs {
+    pub fn last_heard(&self) -> Option<DateTime> {
+        self.songs.first().and_then(|song| song.last_heard())
+    }
+}
+
+/// Returns the case folded artist without diacritics and with collapsed
+/// whitespaces.
+fn normalized_artist_key(artist: &str) -> String {
+    let decomposed = glib::normalize(artist, glib::NormalizeMode::All);
+    let without_diacritics = decomposed
+        .chars()
+        .filter(|c| !('\u{0300}'..='\u{036f}').contains(c))
+        .collect::<String>();
+    glib::casefold(
+        without_diacritics
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+    .to_string()
+}
+
+/// Returns the lowercase title and artist with collapsed whitespaces, used
+/// to identify the same track recognized with different ids.
+pub fn normalized_song_key(song: &Song) -> (String, String) {
+    normalized_title_artist_key(&song.title(), &song.artist())
+}
+
+/// Like [`normalized_song_key`], but for a title and artist that are not
+/// from a song, e.g., search results.
+pub fn normalized_title_artist_key(title: &str, artist: &str) -> (String, String) {
+    fn normalize(s: &str) -> String {
+        s.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+
+    (normalize(title), normalize(artist))
+}
+
+/// Calls `f` without writing the changes it makes to `song` to the db.
+fn with_db_binding_blocked<T>(song: &Song, f: impl FnOnce() -> T) -> Result<T> {
+    unsafe {
+        let handler_id = song
+            .data::<glib::SignalHandlerId>(SONG_NOTIFY_HANDLER_ID_KEY)
+            .context("Song is not bound to the db")?;
+        song.block_signal(handler_id.as_ref());
+        let ret = f();
+        song.unblock_signal(handler_id.as_ref());
+        Ok(ret)
+    }
 }
 
 fn unbind_song_from_db(song: &Song) {
@@ -296,7 +1004,7 @@ fn unbind_song_from_db(song: &Song) {
 
 /// Migrate from the old memory list of Mousai v0.6.6 and earlier.
 fn migrate_from_memory_list(song_list: &SongList) -> Result<()> {
-    use crate::{date_time::DateTime, external_links::ExternalLinkKey, settings::Settings};
+    use crate::{external_links::ExternalLinkKey, settings::Settings};
 
     let settings = Settings::default();
     let memory_list = settings.memory_list();
@@ -439,9 +1147,10 @@ mod test {
     use std::{
         cell::{Cell, RefCell},
         rc::Rc,
+        time::Duration,
     };
 
-    use crate::database;
+    use crate::{database, date_time::DateTime, external_links::ExternalLinkKey};
 
     fn new_test_song(id: &str) -> Song {
         Song::builder(&Uid::from(id), id, id, id).build()
@@ -609,10 +1318,16 @@ mod test {
         assert_eq!(song_list.get(song_2.id_ref()), Some(song_2.clone()));
         assert_n_items_and_db_count_eq(&song_list, 2);
 
+        let removed_signals = Rc::new(RefCell::new(Vec::new()));
+        song_list.connect_song_removed(clone!(@strong removed_signals => move |_, song| {
+            removed_signals.borrow_mut().push(song.clone());
+        }));
+
         let removed = song_list
             .remove_many(&[song_1.id_ref(), song_2.id_ref()])
             .unwrap();
         assert_eq!(removed, vec![song_2.clone(), song_1.clone()]);
+        assert_eq!(*removed_signals.borrow(), removed);
         assert_eq!(song_list.get(song_1.id_ref()), None);
         assert_eq!(song_list.get(song_2.id_ref()), None);
         assert_n_items_and_db_count_eq(&song_list, 0);
@@ -670,6 +1385,591 @@ mod test {
         assert_n_items_and_db_count_eq(&song_list, 3);
     }
 
+    #[test]
+    fn remove_heard_before() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let new_song = |id: &str, last_heard: Option<&str>, is_favorite: bool| {
+            let song = Song::builder(&Uid::from(id), id, id, id)
+                .favorite(is_favorite)
+                .build();
+            song.set_last_heard(last_heard.map(|s| DateTime::from_iso8601(s).unwrap()));
+            song
+        };
+        song_list
+            .insert_many(vec![
+                new_song("old", Some("2020-01-01T00:00:00Z"), false),
+                new_song("old_favorite", Some("2020-01-01T00:00:00Z"), true),
+                new_song("recent", Some("2023-01-01T00:00:00Z"), false),
+                new_song("recent_favorite", Some("2023-01-01T00:00:00Z"), true),
+                new_song("never_heard", None, false),
+            ])
+            .unwrap();
+        assert_n_items_and_db_count_eq(&song_list, 5);
+
+        let cutoff = DateTime::from_iso8601("2022-01-01T00:00:00Z").unwrap();
+        assert_eq!(song_list.remove_heard_before(&cutoff).unwrap(), 1);
+        assert_n_items_and_db_count_eq(&song_list, 4);
+        assert!(!song_list.contains(&Uid::from("old")));
+        assert!(song_list.contains(&Uid::from("old_favorite")));
+        assert!(song_list.contains(&Uid::from("recent")));
+        assert!(song_list.contains(&Uid::from("recent_favorite")));
+        assert!(song_list.contains(&Uid::from("never_heard")));
+
+        assert_eq!(song_list.remove_heard_before(&cutoff).unwrap(), 0);
+        assert_n_items_and_db_count_eq(&song_list, 4);
+    }
+
+    #[test]
+    fn remove_oldest_beyond() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let new_song = |id: &str, last_heard: &str, is_favorite: bool| {
+            let song = Song::builder(&Uid::from(id), id, id, id)
+                .favorite(is_favorite)
+                .build();
+            song.set_last_heard(Some(DateTime::from_iso8601(last_heard).unwrap()));
+            song
+        };
+        song_list
+            .insert_many(vec![
+                new_song("oldest_favorite", "2019-01-01T00:00:00Z", true),
+                new_song("oldest", "2020-01-01T00:00:00Z", false),
+                new_song("old", "2021-01-01T00:00:00Z", false),
+                new_song("recent", "2022-01-01T00:00:00Z", false),
+            ])
+            .unwrap();
+
+        // Within the cap
+        assert!(song_list.remove_oldest_beyond(4).unwrap().is_empty());
+        assert_n_items_and_db_count_eq(&song_list, 4);
+
+        // Exceeding the cap after a new save
+        song_list
+            .insert(new_song("newest", "2023-01-01T00:00:00Z", false))
+            .unwrap();
+        song_list
+            .insert(new_song("newest_favorite", "2023-02-01T00:00:00Z", true))
+            .unwrap();
+        let removed = song_list.remove_oldest_beyond(4).unwrap();
+        let mut removed_ids = removed
+            .iter()
+            .map(|song| song.id_ref().as_str())
+            .collect::<Vec<_>>();
+        removed_ids.sort_unstable();
+        assert_eq!(removed_ids, ["old", "oldest"]);
+        assert_n_items_and_db_count_eq(&song_list, 4);
+        assert!(song_list.contains(&Uid::from("oldest_favorite")));
+        assert!(song_list.contains(&Uid::from("recent")));
+        assert!(song_list.contains(&Uid::from("newest")));
+        assert!(song_list.contains(&Uid::from("newest_favorite")));
+
+        // Favorites are kept even if there are more than the cap
+        let removed = song_list.remove_oldest_beyond(1).unwrap();
+        assert_eq!(removed.len(), 2);
+        assert_n_items_and_db_count_eq(&song_list, 2);
+        assert!(song_list.contains(&Uid::from("oldest_favorite")));
+        assert!(song_list.contains(&Uid::from("newest_favorite")));
+        assert!(song_list.remove_oldest_beyond(1).unwrap().is_empty());
+    }
+
+    #[gtk::test]
+    fn favorites() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+        assert!(song_list.favorites(10).is_empty());
+
+        let new_song = |id: &str, last_heard: &str, is_favorite: bool| {
+            let song = Song::builder(&Uid::from(id), id, id, id)
+                .favorite(is_favorite)
+                .build();
+            song.set_last_heard(Some(DateTime::from_iso8601(last_heard).unwrap()));
+            song
+        };
+        song_list
+            .insert_many(vec![
+                new_song("old_favorite", "2020-01-01T00:00:00Z", true),
+                new_song("not_favorite", "2021-01-01T00:00:00Z", false),
+                new_song("new_favorite", "2022-01-01T00:00:00Z", true),
+            ])
+            .unwrap();
+
+        let n_changed = Rc::new(Cell::new(0));
+        song_list.connect_favorites_changed(clone!(@strong n_changed => move |_| {
+            n_changed.set(n_changed.get() + 1);
+        }));
+
+        let ids = |songs: Vec<Song>| songs.iter().map(|song| song.id()).collect::<Vec<_>>();
+        assert_eq!(
+            ids(song_list.favorites(10)),
+            [Uid::from("new_favorite"), Uid::from("old_favorite")]
+        );
+        assert_eq!(ids(song_list.favorites(1)), [Uid::from("new_favorite")]);
+
+        song_list
+            .get(&Uid::from("not_favorite"))
+            .unwrap()
+            .set_is_favorite(true);
+        assert_eq!(n_changed.get(), 1);
+        assert_eq!(song_list.favorites(10).len(), 3);
+
+        // Other properties don't count
+        song_list
+            .get(&Uid::from("not_favorite"))
+            .unwrap()
+            .set_is_newly_heard(true);
+        assert_eq!(n_changed.get(), 1);
+    }
+
+    #[test]
+    fn artist_key() {
+        assert_eq!(normalized_artist_key("Beyoncé"), "beyonce");
+        assert_eq!(normalized_artist_key("BEYONCE"), "beyonce");
+        assert_eq!(normalized_artist_key(" Daft   Punk "), "daft punk");
+        assert_ne!(
+            normalized_artist_key("Daft Punk"),
+            normalized_artist_key("Daft")
+        );
+    }
+
+    #[test]
+    fn group_by_artist() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let new_song = |id: &str, artist: &str, last_heard: &str| {
+            let song = Song::builder(&Uid::from(id), id, artist, id).build();
+            song.set_last_heard(Some(DateTime::from_iso8601(last_heard).unwrap()));
+            song
+        };
+        song_list
+            .insert_many(vec![
+                new_song("a", "Beyonce", "2022-01-01T00:00:00Z"),
+                new_song("b", "Daft Punk", "2022-02-01T00:00:00Z"),
+                new_song("c", "Beyoncé", "2022-03-01T00:00:00Z"),
+                new_song("d", "daft  punk", "2021-01-01T00:00:00Z"),
+                new_song("e", "BEYONCÉ", "2020-01-01T00:00:00Z"),
+                new_song("f", "Someone", "2021-06-01T00:00:00Z"),
+            ])
+            .unwrap();
+
+        let artists = song_list.group_by_artist();
+        assert_eq!(
+            artists
+                .iter()
+                .map(|artist| (artist.name.as_str(), artist.songs.len()))
+                .collect::<Vec<_>>(),
+            vec![("Beyoncé", 3), ("Daft Punk", 2), ("Someone", 1)]
+        );
+        assert_eq!(
+            artists[0]
+                .songs
+                .iter()
+                .map(|song| song.id())
+                .collect::<Vec<_>>(),
+            vec![Uid::from("c"), Uid::from("a"), Uid::from("e")]
+        );
+        assert_eq!(
+            artists[0].last_heard(),
+            Some(DateTime::from_iso8601("2022-03-01T00:00:00Z").unwrap())
+        );
+    }
+
+    #[test]
+    fn dedup() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let new_song = |id: &str, title: &str, last_heard: &str, is_favorite: bool| {
+            let song = Song::builder(&Uid::from(id), title, "Someone", "Album")
+                .external_link(ExternalLinkKey::AudDUrl, format!("https://aud_d.link/{id}"))
+                .favorite(is_favorite)
+                .build();
+            song.set_last_heard(Some(DateTime::from_iso8601(last_heard).unwrap()));
+            song
+        };
+        song_list
+            .insert_many(vec![
+                new_song("a", "Some Song", "2022-01-01T00:00:00Z", false),
+                new_song("b", "some  song", "2023-01-01T00:00:00Z", false),
+                new_song("c", "Other Song", "2021-01-01T00:00:00Z", true),
+                new_song("d", "Unique", "2021-01-01T00:00:00Z", false),
+                new_song("e", "OTHER SONG", "2024-01-01T00:00:00Z", false),
+                new_song("f", "Some Song", "2020-01-01T00:00:00Z", true),
+            ])
+            .unwrap();
+
+        let mut plan = song_list.plan_dedup();
+        assert_eq!(plan.groups.len(), 2);
+        assert_eq!(plan.n_duplicates(), 3);
+
+        // Favorites are kept first, then the most recently heard
+        assert_eq!(plan.groups[0].survivor.id(), Uid::from("f"));
+        assert_eq!(
+            plan.groups[0]
+                .duplicates
+                .iter()
+                .map(|song| song.id())
+                .collect::<Vec<_>>(),
+            vec![Uid::from("b"), Uid::from("a")]
+        );
+        assert_eq!(plan.groups[1].survivor.id(), Uid::from("c"));
+        assert_eq!(
+            plan.groups[1]
+                .duplicates
+                .iter()
+                .map(|song| song.id())
+                .collect::<Vec<_>>(),
+            vec![Uid::from("e")]
+        );
+
+        // Planning must not touch the list nor the db
+        assert_n_items_and_db_count_eq(&song_list, 6);
+        assert_eq!(
+            song_list.get(&Uid::from("f")).unwrap().last_heard(),
+            Some(DateTime::from_iso8601("2020-01-01T00:00:00Z").unwrap())
+        );
+
+        assert!(plan.groups[0].set_survivor(&Uid::from("b")));
+        assert!(!plan.groups[0].set_survivor(&Uid::from("d")));
+        assert_eq!(plan.groups[0].survivor.id(), Uid::from("b"));
+
+        assert_eq!(song_list.apply_dedup(&plan).unwrap(), 3);
+        assert_n_items_and_db_count_eq(&song_list, 3);
+        assert!(song_list.contains(&Uid::from("b")));
+        assert!(song_list.contains(&Uid::from("c")));
+        assert!(song_list.contains(&Uid::from("d")));
+
+        let (env, db) = song_list.db();
+        let rtxn = env.read_txn().unwrap();
+        let db_song = db.get(&rtxn, &Uid::from("b")).unwrap().unwrap();
+        assert!(db_song.is_favorite());
+        assert_eq!(
+            db_song.last_heard(),
+            Some(DateTime::from_iso8601("2023-01-01T00:00:00Z").unwrap())
+        );
+        let db_song = db.get(&rtxn, &Uid::from("c")).unwrap().unwrap();
+        assert_eq!(
+            db_song.last_heard(),
+            Some(DateTime::from_iso8601("2024-01-01T00:00:00Z").unwrap())
+        );
+        drop(rtxn);
+
+        // Survivors must still be synced to the db afterwards
+        song_list
+            .get(&Uid::from("c"))
+            .unwrap()
+            .set_is_favorite(false);
+        let rtxn = env.read_txn().unwrap();
+        assert!(!db
+            .get(&rtxn, &Uid::from("c"))
+            .unwrap()
+            .unwrap()
+            .is_favorite());
+        drop(rtxn);
+
+        // Applying a stale plan does nothing
+        assert_eq!(song_list.apply_dedup(&plan).unwrap(), 0);
+        assert!(song_list.plan_dedup().is_empty());
+
+        // Placeholders all look the same, but are never duplicates
+        let recorded_time = DateTime::from_iso8601("2022-01-01T00:00:00Z").unwrap();
+        let placeholder = Song::new_placeholder(&recorded_time);
+        song_list
+            .insert_many(vec![
+                placeholder.clone(),
+                Song::new_placeholder(&recorded_time),
+            ])
+            .unwrap();
+        assert!(song_list.plan_dedup().is_empty());
+        assert!(song_list.find_equivalent(&placeholder).is_none());
+    }
+
+    #[test]
+    fn dedup_explicit() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let explicit = Song::builder(&Uid::from("a"), "Some Song", "Someone", "Album")
+            .explicit(true)
+            .build();
+        let clean = Song::builder(&Uid::from("b"), "Some Song", "Someone", "Album")
+            .explicit(false)
+            .build();
+        song_list
+            .insert_many(vec![explicit.clone(), clean.clone()])
+            .unwrap();
+
+        // Merged by default
+        let plan = song_list.plan_dedup();
+        assert_eq!(plan.groups.len(), 1);
+        assert_eq!(plan.n_duplicates(), 1);
+        assert_eq!(
+            song_list.find_equivalent(&explicit).map(|song| song.id()),
+            Some(Uid::from("b"))
+        );
+
+        song_list.set_distinguishes_explicit(true);
+        assert!(song_list.plan_dedup().is_empty());
+        assert!(song_list.find_equivalent(&explicit).is_none());
+        assert!(song_list.find_equivalent(&clean).is_none());
+
+        song_list.set_distinguishes_explicit(false);
+        assert_eq!(song_list.plan_dedup().n_duplicates(), 1);
+    }
+
+    #[test]
+    fn write_failed() {
+        // Small enough that a second copy of the song can't fit
+        let tempdir = tempfile::tempdir().unwrap();
+        let env = heed::EnvOpenOptions::new()
+            .map_size(256 * 1024)
+            .max_dbs(1)
+            .open(&tempdir)
+            .unwrap();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let song = Song::builder(&Uid::from("a"), "a", "a", "a")
+            .lyrics(&"a".repeat(150 * 1024))
+            .build();
+        assert!(song_list.insert(song.clone()).unwrap());
+
+        let failed_messages = Rc::new(RefCell::new(Vec::new()));
+        song_list.connect_write_failed(clone!(@strong failed_messages => move |_, message| {
+            failed_messages.borrow_mut().push(message.to_string());
+        }));
+
+        // Must not panic
+        song.set_is_newly_heard(true);
+
+        assert_eq!(failed_messages.borrow().len(), 1);
+        assert!(!song.is_newly_heard());
+
+        let (env, db) = song_list.db();
+        let rtxn = env.read_txn().unwrap();
+        let db_song = db.get(&rtxn, &Uid::from("a")).unwrap().unwrap();
+        assert_eq!(db_song.is_newly_heard(), song.is_newly_heard());
+        drop(rtxn);
+
+        // Still bound to the db afterwards
+        song.set_is_newly_heard(true);
+        assert_eq!(failed_messages.borrow().len(), 2);
+        assert!(!song.is_newly_heard());
+    }
+
+    #[test]
+    fn search_cached() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+        song_list
+            .insert_many(vec![new_test_song("blue"), new_test_song("red")])
+            .unwrap();
+        let n_computed = || song_list.imp().search_cache.n_computed();
+
+        let results = song_list.search("blue", false);
+        assert!(results.rank(&Uid::from("blue")).is_some());
+        assert!(results.rank(&Uid::from("red")).is_none());
+        assert_eq!(n_computed(), 1);
+
+        song_list.search("blue", false);
+        assert_eq!(n_computed(), 1);
+
+        // Mutations invalidate the results
+        song_list.insert(new_test_song("blue 2")).unwrap();
+        let results = song_list.search("blue", false);
+        assert!(results.rank(&Uid::from("blue 2")).is_some());
+        assert_eq!(n_computed(), 2);
+
+        song_list.remove_many(&[&Uid::from("blue")]).unwrap();
+        let results = song_list.search("blue", false);
+        assert!(results.rank(&Uid::from("blue")).is_none());
+        assert_eq!(n_computed(), 3);
+
+        // So do changes to the searched fields
+        let red = song_list.get(&Uid::from("red")).unwrap();
+        red.set_title("Blue 3");
+        let results = song_list.search("blue", false);
+        assert!(results.rank(&Uid::from("red")).is_some());
+        assert_eq!(n_computed(), 4);
+    }
+
+    #[test]
+    fn merge() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let survivor = Song::builder(&Uid::from("a"), "Some Song", "Someone", "Album")
+            .external_link(ExternalLinkKey::AudDUrl, "https://aud_d.link/a")
+            .build();
+        survivor.set_last_heard(Some(
+            DateTime::from_iso8601("2022-01-01T00:00:00Z").unwrap(),
+        ));
+        assert!(song_list.insert(survivor.clone()).unwrap());
+
+        // Not detected as the same track, but the user knows better
+        let other = Song::builder(&Uid::from("b"), "Some Song (Live)", "Someone", "Live")
+            .external_link(ExternalLinkKey::AudDUrl, "https://aud_d.link/b")
+            .external_link(ExternalLinkKey::SpotifyUrl, "https://spotify.link")
+            .favorite(true)
+            .build();
+        let last_heard = DateTime::from_iso8601("2023-01-01T00:00:00Z").unwrap();
+        other.set_last_heard(Some(last_heard.clone()));
+        assert!(song_list.insert(other.clone()).unwrap());
+        assert!(song_list.plan_dedup().is_empty());
+
+        assert!(song_list.merge(&Uid::from("a"), &Uid::from("a")).is_err());
+        assert!(song_list.merge(&Uid::from("a"), &Uid::from("c")).is_err());
+        assert_n_items_and_db_count_eq(&song_list, 2);
+
+        let merged = song_list.merge(&Uid::from("a"), &Uid::from("b")).unwrap();
+        assert_eq!(merged, survivor);
+        assert_n_items_and_db_count_eq(&song_list, 1);
+        assert!(!song_list.contains(&Uid::from("b")));
+
+        let (env, db) = song_list.db();
+        let rtxn = env.read_txn().unwrap();
+        let db_song = db.get(&rtxn, &Uid::from("a")).unwrap().unwrap();
+        drop(rtxn);
+
+        for song in [&merged, &db_song] {
+            // The metadata of the survivor is kept
+            assert_eq!(song.title(), "Some Song");
+            assert_eq!(song.album(), "Album");
+            assert_eq!(
+                song.external_links()
+                    .get(ExternalLinkKey::AudDUrl)
+                    .as_deref(),
+                Some("https://aud_d.link/a")
+            );
+
+            // The user data of both is combined
+            assert_eq!(
+                song.external_links()
+                    .get(ExternalLinkKey::SpotifyUrl)
+                    .as_deref(),
+                Some("https://spotify.link")
+            );
+            assert_eq!(song.last_heard(), Some(last_heard.clone()));
+            assert!(song.is_favorite());
+        }
+    }
+
+    #[test]
+    fn merge_into_equivalent() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let first = Song::builder(&Uid::from("a"), "Some Song", "Someone", "Album")
+            .external_link(ExternalLinkKey::AudDUrl, "https://aud_d.link/a")
+            .build();
+        first.set_last_heard(Some(
+            DateTime::from_iso8601("2022-01-01T00:00:00Z").unwrap(),
+        ));
+        assert!(song_list.insert(first.clone()).unwrap());
+
+        // Same track, but recognized with a different id
+        let second = Song::builder(&Uid::from("b"), "some  song ", "SOMEONE", "Album")
+            .external_link(ExternalLinkKey::AudDUrl, "https://aud_d.link/b")
+            .external_link(ExternalLinkKey::SpotifyUrl, "https://spotify.link")
+            .build();
+        let last_heard = DateTime::from_iso8601("2023-01-01T00:00:00Z").unwrap();
+        second.set_last_heard(Some(last_heard.clone()));
+
+        let merged = song_list.merge_into_equivalent(&second).unwrap().unwrap();
+        assert_eq!(merged, first);
+        assert_n_items_and_db_count_eq(&song_list, 1);
+
+        let (env, db) = song_list.db();
+        let rtxn = env.read_txn().unwrap();
+        let db_song = db.get(&rtxn, &Uid::from("a")).unwrap().unwrap();
+        assert_eq!(db_song.last_heard(), Some(last_heard));
+        assert_eq!(
+            db_song
+                .external_links()
+                .get(ExternalLinkKey::AudDUrl)
+                .as_deref(),
+            Some("https://aud_d.link/a")
+        );
+        assert_eq!(
+            db_song
+                .external_links()
+                .get(ExternalLinkKey::SpotifyUrl)
+                .as_deref(),
+            Some("https://spotify.link")
+        );
+        drop(rtxn);
+
+        let different = new_test_song("c");
+        assert!(song_list
+            .merge_into_equivalent(&different)
+            .unwrap()
+            .is_none());
+        assert!(song_list
+            .merge_into_equivalent(&song_list.get(&Uid::from("a")).unwrap())
+            .unwrap()
+            .is_none());
+    }
+
+    #[gtk::test]
+    fn concurrent_updates() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let song = new_test_song("a");
+        assert!(song_list.insert(song.clone()).unwrap());
+
+        let n_favorites_changed = Rc::new(Cell::new(0));
+        song_list.connect_favorites_changed(clone!(@strong n_favorites_changed => move |_| {
+            n_favorites_changed.set(n_favorites_changed.get() + 1);
+        }));
+
+        let older = DateTime::from_iso8601("2023-01-01T00:00:00Z").unwrap();
+        let newer = DateTime::from_iso8601("2023-01-02T00:00:00Z").unwrap();
+
+        // The recognition finishes while an import that was started earlier,
+        // with an older last heard, is still pending.
+        let ctx = glib::MainContext::default();
+        let recognition = ctx.spawn_local(clone!(@strong song, @strong newer => async move {
+            glib::timeout_future(Duration::from_millis(10)).await;
+            song.set_last_heard(Some(newer));
+        }));
+        let import = ctx.spawn_local(clone!(@strong song_list, @strong older => async move {
+            glib::timeout_future(Duration::from_millis(20)).await;
+            let imported = Song::builder(&Uid::from("a"), "a", "a", "a")
+                .favorite(true)
+                .build();
+            imported.set_last_heard(Some(older));
+            song_list.merge_into_existing(&imported).unwrap();
+        }));
+        ctx.block_on(async move {
+            recognition.await.unwrap();
+            import.await.unwrap();
+        });
+
+        assert_eq!(song.last_heard(), Some(newer.clone()));
+        assert!(song.is_favorite());
+        assert_eq!(n_favorites_changed.get(), 1);
+
+        let (env, db) = song_list.db();
+        let rtxn = env.read_txn().unwrap();
+        let db_song = db.get(&rtxn, &Uid::from("a")).unwrap().unwrap();
+        assert_eq!(db_song.last_heard(), Some(newer));
+        assert!(db_song.is_favorite());
+        drop(rtxn);
+
+        // Other updates are written as is
+        song_list
+            .update_with(&Uid::from("a"), |song| {
+                song.set_last_heard(Some(older.clone()));
+            })
+            .unwrap();
+        let rtxn = env.read_txn().unwrap();
+        let db_song = db.get(&rtxn, &Uid::from("a")).unwrap().unwrap();
+        assert_eq!(db_song.last_heard(), Some(older));
+    }
+
     #[test]
     fn items_changed_insert() {
         let (env, _tempdir) = database::new_test_env();
@@ -736,6 +2036,29 @@ mod test {
             .unwrap();
     }
 
+    #[test]
+    fn items_changed_insert_many_large() {
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let calls_output = Rc::new(RefCell::new(Vec::new()));
+        let calls_output_clone = Rc::clone(&calls_output);
+        song_list.connect_items_changed(move |_, index, removed, added| {
+            calls_output_clone
+                .borrow_mut()
+                .push((index, removed, added));
+        });
+
+        let songs = (0..100)
+            .map(|i| new_test_song(&i.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(song_list.insert_many(songs).unwrap(), 100);
+
+        assert_eq!(*calls_output.borrow(), [(0, 0, 100)]);
+        assert_n_items_and_db_count_eq(&song_list, 100);
+        assert_synced_to_db(&song_list);
+    }
+
     #[test]
     fn items_changed_insert_many_with_duplicates() {
         let (env, _tempdir) = database::new_test_env();
@@ -2,11 +2,15 @@
 // SPDX-FileCopyrightText: 2023 Dave Patrick Caberto
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
-use gtk::{glib, prelude::*, subclass::prelude::*};
+use gtk::{
+    glib::{self, WeakRef},
+    prelude::*,
+    subclass::prelude::*,
+};
 
-use crate::song::Song;
+use crate::{song::Song, song_list::SongList};
 
 mod imp {
     use super::*;
@@ -21,6 +25,12 @@ mod imp {
         /// on Song's search term
         #[property(get, set = Self::set_search, explicit_notify)]
         pub(super) search: RefCell<String>,
+        /// Whether the fuzzy match score includes the lyrics
+        #[property(get, set = Self::set_search_lyrics, explicit_notify)]
+        pub(super) search_lyrics: Cell<bool>,
+        /// List whose cached search results are used instead of matching
+        /// every song
+        pub(super) song_list: WeakRef<SongList>,
     }
 
     #[glib::object_subclass]
@@ -41,10 +51,20 @@ mod imp {
             let search = self.search.borrow();
 
             if search.is_empty() {
-                song_2.last_heard().cmp(&song_1.last_heard()).into()
+                return song_2.last_heard().cmp(&song_1.last_heard()).into();
+            }
+
+            let search_lyrics = self.search_lyrics.get();
+
+            if let Some(song_list) = self.song_list.upgrade() {
+                let results = song_list.search(&search, search_lyrics);
+                // Songs that don't match are sorted last
+                let rank_1 = results.rank(song_1.id_ref()).unwrap_or(u32::MAX);
+                let rank_2 = results.rank(song_2.id_ref()).unwrap_or(u32::MAX);
+                rank_1.cmp(&rank_2).into()
             } else {
-                let score_1 = song_1.fuzzy_match(&search);
-                let score_2 = song_2.fuzzy_match(&search);
+                let score_1 = song_1.fuzzy_match(&search, search_lyrics);
+                let score_2 = song_2.fuzzy_match(&search, search_lyrics);
                 score_2.cmp(&score_1).into()
             }
         }
@@ -66,6 +86,22 @@ mod imp {
             obj.changed(gtk::SorterChange::Different);
             obj.notify_search();
         }
+
+        fn set_search_lyrics(&self, search_lyrics: bool) {
+            let obj = self.obj();
+
+            if search_lyrics == obj.search_lyrics() {
+                return;
+            }
+
+            self.search_lyrics.set(search_lyrics);
+
+            if !self.search.borrow().is_empty() {
+                obj.changed(gtk::SorterChange::Different);
+            }
+
+            obj.notify_search_lyrics();
+        }
     }
 }
 
@@ -79,6 +115,12 @@ impl SongSorter {
     pub fn new() -> Self {
         glib::Object::new()
     }
+
+    /// Sets the list of the sorted songs, so its cached search results are
+    /// used.
+    pub fn set_song_list(&self, song_list: &SongList) {
+        self.imp().song_list.set(Some(song_list));
+    }
 }
 
 impl Default for SongSorter {
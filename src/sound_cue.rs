@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures_channel::oneshot;
+use gst::{bus::BusWatchGuard, prelude::*};
+use gtk::glib::{self, clone};
+
+use std::{cell::RefCell, time::Duration};
+
+use crate::recognizer::{RecognitionFailure, RecognizeOutcome};
+
+/// Number of samples in a second of the cue
+const SAMPLE_RATE: u32 = 44_100;
+
+/// Max time to wait for a cue to finish, in case the sink never reaches EOS
+const MAX_WAIT_DURATION: Duration = Duration::from_secs(2);
+
+/// Short synthesized tone played around recognitions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundCue {
+    /// Listening is about to start
+    ListenStart,
+    /// A song was recognized
+    Match,
+    /// The recognition finished without a song, e.g., no matches or the
+    /// recording was saved for later
+    NoMatch,
+}
+
+impl SoundCue {
+    /// Returns the cue for the result of `Recognizer::toggle_recognize`, or
+    /// `None` if no cue should be played, e.g., the recognition was cancelled.
+    pub fn for_result(res: &Result<Option<RecognizeOutcome>, RecognitionFailure>) -> Option<Self> {
+        match res {
+            Ok(Some(RecognizeOutcome::Recognized)) => Some(Self::Match),
+            Ok(Some(RecognizeOutcome::Saved)) | Err(_) => Some(Self::NoMatch),
+            Ok(None) => None,
+        }
+    }
+
+    /// A quick tick on listen start, a higher and shorter tone on match, and
+    /// a lower and longer one otherwise.
+    fn frequency_and_n_samples(self) -> (f64, u32) {
+        match self {
+            Self::ListenStart => (1320.0, SAMPLE_RATE / 25),
+            Self::Match => (880.0, SAMPLE_RATE / 8),
+            Self::NoMatch => (330.0, SAMPLE_RATE / 4),
+        }
+    }
+
+    fn pipeline_description(self) -> String {
+        let (frequency, n_samples) = self.frequency_and_n_samples();
+        format!(
+            "audiotestsrc wave=sine freq={} volume=0.3 samplesperbuffer={} num-buffers=1 \
+            ! audio/x-raw,rate={} ! audioconvert ! autoaudiosink",
+            frequency, n_samples, SAMPLE_RATE
+        )
+    }
+}
+
+/// Something that can play a [`SoundCue`] to the end.
+#[async_trait(?Send)]
+pub trait PlaySoundCue {
+    /// Plays the cue and resolves once it finished playing.
+    async fn play_to_end(&self, cue: SoundCue) -> Result<()>;
+}
+
+/// Plays cues on a tiny pipeline independent of the preview [`crate::player::Player`].
+#[derive(Default)]
+pub struct SoundCuePlayer {
+    active: RefCell<Option<(gst::Pipeline, BusWatchGuard)>>,
+}
+
+impl SoundCuePlayer {
+    /// Plays the cue without waiting for it to finish, stopping the previous
+    /// one if it is still playing.
+    pub fn play(&self, cue: SoundCue) -> Result<()> {
+        self.start(cue).map(|_| ())
+    }
+
+    pub fn stop(&self) {
+        if let Some((pipeline, _bus_watch_guard)) = self.active.take() {
+            let _ = pipeline.set_state(gst::State::Null);
+        }
+    }
+
+    /// Returns a receiver that is resolved once the cue is done playing.
+    fn start(&self, cue: SoundCue) -> Result<oneshot::Receiver<()>> {
+        self.stop();
+
+        let pipeline = gst::parse_launch(&cue.pipeline_description())
+            .context("Failed to create cue pipeline")?
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+
+        let (done_tx, done_rx) = oneshot::channel();
+        let done_tx = RefCell::new(Some(done_tx));
+        let bus_watch_guard = pipeline
+            .bus()
+            .unwrap()
+            .add_watch_local(
+                clone!(@weak pipeline => @default-return glib::ControlFlow::Break, move |_, message| {
+                    let control_flow = handle_bus_message(&pipeline, message);
+                    if control_flow == glib::ControlFlow::Break {
+                        if let Some(done_tx) = done_tx.take() {
+                            let _ = done_tx.send(());
+                        }
+                    }
+                    control_flow
+                }),
+            )
+            .context("Failed to add bus watch")?;
+
+        pipeline
+            .set_state(gst::State::Playing)
+            .context("Failed to play cue")?;
+
+        self.active.replace(Some((pipeline, bus_watch_guard)));
+
+        tracing::debug!(?cue, "Playing sound cue");
+
+        Ok(done_rx)
+    }
+}
+
+#[async_trait(?Send)]
+impl PlaySoundCue for SoundCuePlayer {
+    async fn play_to_end(&self, cue: SoundCue) -> Result<()> {
+        let done_rx = self.start(cue)?;
+
+        if glib::future_with_timeout(MAX_WAIT_DURATION, done_rx)
+            .await
+            .is_err()
+        {
+            tracing::warn!("Sound cue did not finish in time");
+            self.stop();
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for SoundCuePlayer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn handle_bus_message(pipeline: &gst::Pipeline, message: &gst::Message) -> glib::ControlFlow {
+    use gst::MessageView;
+
+    match message.view() {
+        MessageView::Eos(_) => {
+            let _ = pipeline.set_state(gst::State::Null);
+            glib::ControlFlow::Break
+        }
+        MessageView::Error(e) => {
+            tracing::warn!(debug = ?e.debug(), err = ?e.error(), "Received error at cue bus");
+            let _ = pipeline.set_state(gst::State::Null);
+            glib::ControlFlow::Break
+        }
+        _ => glib::ControlFlow::Continue,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::anyhow;
+
+    use crate::recognizer::{RecognizeError, RecognizeErrorKind};
+
+    #[test]
+    fn for_result() {
+        assert_eq!(
+            SoundCue::for_result(&Ok(Some(RecognizeOutcome::Recognized))),
+            Some(SoundCue::Match)
+        );
+        assert_eq!(
+            SoundCue::for_result(&Ok(Some(RecognizeOutcome::Saved))),
+            Some(SoundCue::NoMatch)
+        );
+        assert_eq!(
+            SoundCue::for_result(&Err(anyhow::Error::new(RecognizeError::new(
+                RecognizeErrorKind::NoMatches,
+                "no matches".to_string(),
+            ))
+            .into())),
+            Some(SoundCue::NoMatch)
+        );
+        assert_eq!(
+            SoundCue::for_result(&Err(anyhow!("Failed to record").into())),
+            Some(SoundCue::NoMatch)
+        );
+        assert_eq!(SoundCue::for_result(&Ok(None)), None);
+    }
+
+    #[test]
+    fn distinct_cues() {
+        let descriptions = [SoundCue::ListenStart, SoundCue::Match, SoundCue::NoMatch]
+            .map(SoundCue::pipeline_description);
+        assert_ne!(descriptions[0], descriptions[1]);
+        assert_ne!(descriptions[0], descriptions[2]);
+        assert_ne!(descriptions[1], descriptions[2]);
+    }
+}
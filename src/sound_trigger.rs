@@ -0,0 +1,250 @@
+use anyhow::{Context, Result};
+use gst::{bus::BusWatchGuard, prelude::*};
+use gtk::glib::{self, clone};
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::recognizer;
+
+/// Interval between `level` messages of the capture
+#[cfg(test)]
+const LEVEL_INTERVAL: Duration = Duration::from_millis(80);
+
+/// How long it must be quiet before a loud sound counts as a trigger, so
+/// that continuous music or noise does not trigger it
+const QUIET_DURATION: Duration = Duration::from_secs(1);
+
+/// How long the peak must stay above the threshold to trigger
+const LOUD_DURATION: Duration = Duration::from_millis(160);
+
+/// Minimum time between two triggers
+const COOLDOWN: Duration = Duration::from_secs(5);
+
+/// Detects a short loud sound, e.g., a clap or a shout, after a quiet period.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnergyTrigger {
+    threshold: f64,
+    quiet_since: Option<Duration>,
+    loud_since: Option<Duration>,
+    last_triggered: Option<Duration>,
+}
+
+impl EnergyTrigger {
+    /// `threshold` is the normalized peak, from 0 to 1, that must be reached
+    /// to trigger.
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            quiet_since: None,
+            loud_since: None,
+            last_triggered: None,
+        }
+    }
+
+    /// Processes the peak at `elapsed` since listening started, and returns
+    /// whether it triggered.
+    pub fn process(&mut self, peak: f64, elapsed: Duration) -> bool {
+        if peak < self.threshold {
+            if self.loud_since.take().is_some() || self.quiet_since.is_none() {
+                self.quiet_since = Some(elapsed);
+            }
+            return false;
+        }
+
+        let Some(quiet_since) = self.quiet_since else {
+            return false;
+        };
+        let loud_since = *self.loud_since.get_or_insert(elapsed);
+
+        if loud_since.saturating_sub(quiet_since) < QUIET_DURATION
+            || elapsed.saturating_sub(loud_since) < LOUD_DURATION
+        {
+            return false;
+        }
+
+        // Either way, it must be quiet again before the next trigger.
+        self.quiet_since = None;
+        self.loud_since = None;
+
+        if self
+            .last_triggered
+            .is_some_and(|last_triggered| elapsed.saturating_sub(last_triggered) < COOLDOWN)
+        {
+            return false;
+        }
+
+        self.last_triggered = Some(elapsed);
+
+        true
+    }
+}
+
+/// Listens to the default microphone on a separate pipeline and calls back
+/// when an [`EnergyTrigger`] fires.
+///
+/// The audio is only measured and is never stored nor sent anywhere.
+#[derive(Default)]
+pub struct SoundTrigger {
+    /// Cleared when the pipeline fails, so it can be started again
+    active: Rc<RefCell<Option<(gst::Pipeline, BusWatchGuard)>>>,
+}
+
+impl SoundTrigger {
+    pub fn is_active(&self) -> bool {
+        self.active.borrow().is_some()
+    }
+
+    pub fn start(&self, threshold: f64, callback: impl Fn() + 'static) -> Result<()> {
+        self.stop();
+
+        let pipeline = gst::Pipeline::new();
+        let level = recognizer::add_capture(&pipeline, None, 0.0)
+            .context("Failed to create trigger pipeline")?;
+        let fakesink = gst::ElementFactory::make("fakesink")
+            .property("sync", false)
+            .build()?;
+        pipeline.add(&fakesink)?;
+        level.link(&fakesink)?;
+
+        let start_time = Instant::now();
+        let trigger = RefCell::new(EnergyTrigger::new(threshold));
+        let active = Rc::downgrade(&self.active);
+        let bus_watch_guard = pipeline
+            .bus()
+            .unwrap()
+            .add_watch_local(
+                clone!(@weak pipeline => @default-return glib::ControlFlow::Break, move |_, message| {
+                    if let Some(peak) = recognizer::level_peak(message) {
+                        if trigger.borrow_mut().process(peak, start_time.elapsed()) {
+                            tracing::debug!("Sound trigger fired");
+                            callback();
+                        }
+                    } else if let gst::MessageView::Error(e) = message.view() {
+                        tracing::warn!(debug = ?e.debug(), err = ?e.error(), "Received error at trigger bus");
+                        let _ = pipeline.set_state(gst::State::Null);
+
+                        // The watch can't be removed while it is dispatched
+                        let active = active.clone();
+                        glib::idle_add_local_once(move || {
+                            let Some(active) = active.upgrade() else {
+                                return;
+                            };
+
+                            if active
+                                .borrow()
+                                .as_ref()
+                                .is_some_and(|(active_pipeline, _)| *active_pipeline == pipeline)
+                            {
+                                active.take();
+                                tracing::debug!("Stopped listening for sound trigger after error");
+                            }
+                        });
+
+                        return glib::ControlFlow::Break;
+                    }
+
+                    glib::ControlFlow::Continue
+                }),
+            )
+            .context("Failed to add bus watch")?;
+
+        recognizer::play_capture(&pipeline).context("Failed to start trigger pipeline")?;
+
+        self.active.replace(Some((pipeline, bus_watch_guard)));
+
+        tracing::debug!(threshold, "Started listening for sound trigger");
+
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Some((pipeline, _bus_watch_guard)) = self.active.take() {
+            let _ = pipeline.set_state(gst::State::Null);
+            tracing::debug!("Stopped listening for sound trigger");
+        }
+    }
+}
+
+impl Drop for SoundTrigger {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the times the trigger fired while feeding it `peaks`, one per
+    /// level interval.
+    fn triggered_at(threshold: f64, peaks: impl IntoIterator<Item = f64>) -> Vec<Duration> {
+        let mut trigger = EnergyTrigger::new(threshold);
+        peaks
+            .into_iter()
+            .enumerate()
+            .map(|(i, peak)| (LEVEL_INTERVAL * i as u32, peak))
+            .filter(|(elapsed, peak)| trigger.process(*peak, *elapsed))
+            .map(|(elapsed, _)| elapsed)
+            .collect()
+    }
+
+    fn frames(peak: f64, duration: Duration) -> impl Iterator<Item = f64> {
+        std::iter::repeat(peak).take((duration.as_millis() / LEVEL_INTERVAL.as_millis()) as usize)
+    }
+
+    #[test]
+    fn burst_after_quiet() {
+        let peaks = frames(0.05, Duration::from_secs(2))
+            .chain(frames(0.9, Duration::from_millis(400)))
+            .chain(frames(0.05, Duration::from_secs(1)));
+        let loud_start = Duration::from_millis(2000);
+
+        let triggered_at = triggered_at(0.5, peaks);
+        assert_eq!(triggered_at.len(), 1);
+        assert!(triggered_at[0] >= loud_start + LOUD_DURATION);
+        assert!(triggered_at[0] < loud_start + LOUD_DURATION + LEVEL_INTERVAL);
+    }
+
+    #[test]
+    fn below_threshold() {
+        let peaks = frames(0.05, Duration::from_secs(2)).chain(frames(0.4, Duration::from_secs(1)));
+        assert!(triggered_at(0.5, peaks).is_empty());
+    }
+
+    #[test]
+    fn continuous_loudness() {
+        assert!(triggered_at(0.5, frames(0.9, Duration::from_secs(10))).is_empty());
+
+        // Not quiet for long enough before getting loud
+        let peaks =
+            frames(0.05, Duration::from_millis(400)).chain(frames(0.9, Duration::from_secs(1)));
+        assert!(triggered_at(0.5, peaks).is_empty());
+    }
+
+    #[test]
+    fn short_spike() {
+        let peaks = frames(0.05, Duration::from_secs(2))
+            .chain(frames(0.9, LEVEL_INTERVAL))
+            .chain(frames(0.05, Duration::from_secs(1)));
+        assert!(triggered_at(0.5, peaks).is_empty());
+    }
+
+    #[test]
+    fn cooldown() {
+        let burst = || {
+            frames(0.05, Duration::from_secs(1) + LEVEL_INTERVAL)
+                .chain(frames(0.9, Duration::from_millis(400)))
+        };
+
+        // Second burst is within the cooldown, the third is not
+        let peaks = burst()
+            .chain(burst())
+            .chain(frames(0.05, Duration::from_secs(3)))
+            .chain(burst());
+        assert_eq!(triggered_at(0.5, peaks).len(), 2);
+    }
+}
@@ -31,6 +31,23 @@ impl Uid {
     pub fn generate() -> Self {
         Self::from(format!("{:x}-{:x}", glib::real_time(), glib::random_int()))
     }
+
+    /// Generate a new id like in [`Uid::generate`], prefixed with `prefix`.
+    pub fn generate_prefixed(prefix: &str) -> Self {
+        Self::from_prefixed(prefix, &Self::generate().0)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns whether this was created with the given `prefix`, as in
+    /// [`Uid::from_prefixed`].
+    pub fn has_prefix(&self, prefix: &str) -> bool {
+        self.0
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('-'))
+    }
 }
 
 pub struct UidCodec;
@@ -77,6 +94,15 @@ mod test {
         assert_ne!(Uid::from("A"), Uid::from("B"));
     }
 
+    #[test]
+    fn prefix() {
+        assert!(Uid::from_prefixed("a", "b").has_prefix("a"));
+        assert!(Uid::generate_prefixed("a").has_prefix("a"));
+        assert!(!Uid::from_prefixed("ab", "c").has_prefix("a"));
+        assert!(!Uid::from("a").has_prefix("a"));
+        assert_ne!(Uid::generate_prefixed("a"), Uid::generate_prefixed("a"));
+    }
+
     #[test]
     fn serde_bincode() {
         let val = Uid::from("some unique str");
@@ -1,6 +1,9 @@
+use gettextrs::gettext;
+use gtk::glib;
+
 use std::{collections::BTreeSet, future::Future};
 
-use gtk::glib;
+use crate::i18n::ngettext_f;
 
 /// Spawns a future in the default [`glib::MainContext`]
 pub fn spawn<R, F>(priority: glib::Priority, fut: F) -> glib::JoinHandle<R>
@@ -50,6 +53,50 @@ pub fn consecutive_groups(ordered_set: &BTreeSet<usize>) -> Vec<(usize, usize)>
     ret
 }
 
+/// What is counted in a label returned by [`count_label`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Countable {
+    /// Songs in the history
+    Songs,
+    /// Songs selected in the history
+    SelectedSongs,
+    /// Songs matching the search
+    SearchResults,
+}
+
+/// Returns a label of `n` items of `countable`, pluralized for the locale, or
+/// a friendly text when there are none.
+pub fn count_label(countable: Countable, n: u32) -> String {
+    let n_string = n.to_string();
+
+    match (countable, n) {
+        (Countable::Songs, 0) => gettext("No songs yet"),
+        (Countable::Songs, _) => ngettext_f(
+            // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+            "{n_songs} song",
+            "{n_songs} songs",
+            n,
+            &[("n_songs", &n_string)],
+        ),
+        (Countable::SelectedSongs, 0) => gettext("Select items"),
+        (Countable::SelectedSongs, _) => ngettext_f(
+            // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+            "Selected {selection_size} song",
+            "Selected {selection_size} songs",
+            n,
+            &[("selection_size", &n_string)],
+        ),
+        (Countable::SearchResults, 0) => gettext("No Results"),
+        (Countable::SearchResults, _) => ngettext_f(
+            // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+            "{n_results} result",
+            "{n_results} results",
+            n,
+            &[("n_results", &n_string)],
+        ),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -165,4 +212,22 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn count_label_forms() {
+        // No catalog is loaded in tests, so these are the untranslated forms,
+        // pluralized with the fallback English rules.
+        assert_eq!(count_label(Countable::Songs, 0), "No songs yet");
+        assert_eq!(count_label(Countable::Songs, 1), "1 song");
+        assert_eq!(count_label(Countable::Songs, 2), "2 songs");
+        assert_eq!(count_label(Countable::Songs, 21), "21 songs");
+
+        assert_eq!(count_label(Countable::SelectedSongs, 0), "Select items");
+        assert_eq!(count_label(Countable::SelectedSongs, 1), "Selected 1 song");
+        assert_eq!(count_label(Countable::SelectedSongs, 3), "Selected 3 songs");
+
+        assert_eq!(count_label(Countable::SearchResults, 0), "No Results");
+        assert_eq!(count_label(Countable::SearchResults, 1), "1 result");
+        assert_eq!(count_label(Countable::SearchResults, 100), "100 results");
+    }
 }
@@ -5,9 +5,12 @@ use gtk::{
     subclass::prelude::*,
 };
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
-use crate::{song::Song, utils};
+use crate::{
+    album_art::{AlbumArtRequest, LoadKind},
+    song::Song,
+};
 
 const DEFAULT_ENABLE_CROSSFADE: bool = true;
 
@@ -23,6 +26,9 @@ mod imp {
         pub(super) pixel_size: PhantomData<i32>,
         #[property(get = Self::enables_crossfade, set = Self::set_enables_crossfade, default = DEFAULT_ENABLE_CROSSFADE, explicit_notify)]
         pub(super) enables_crossfade: PhantomData<bool>,
+        /// Album art that is shown, if it is loaded
+        #[property(get)]
+        pub(super) texture: RefCell<Option<gdk::Texture>>,
 
         #[template_child]
         pub(super) stack: TemplateChild<gtk::Stack>,
@@ -32,8 +38,19 @@ mod imp {
         pub(super) image_b: TemplateChild<gtk::Image>,
         #[template_child]
         pub(super) placeholder: TemplateChild<gtk::Image>,
+        #[template_child]
+        pub(super) retry_button: TemplateChild<gtk::Button>,
 
-        pub(super) join_handle: RefCell<Option<glib::JoinHandle<()>>>,
+        pub(super) song: RefCell<Option<Song>>,
+        /// Pending album art load, cancelled when the song changes or when
+        /// unmapped
+        pub(super) request: RefCell<Option<AlbumArtRequest>>,
+        /// Whether the pending load was cancelled on unmap, or deferred while
+        /// out of view, so it has to be started again
+        pub(super) needs_reload: Cell<bool>,
+        /// Whether this is outside of the visible area of a list and its
+        /// over-scan, where album art that is not loaded yet is not loaded
+        pub(super) is_out_of_view: Cell<bool>,
     }
 
     #[glib::object_subclass]
@@ -58,7 +75,33 @@ mod imp {
         fn constructed(&self) {
             self.parent_constructed();
 
-            self.obj().set_enables_crossfade(DEFAULT_ENABLE_CROSSFADE);
+            let obj = self.obj();
+
+            obj.set_enables_crossfade(DEFAULT_ENABLE_CROSSFADE);
+
+            // Allow explicitly loading the album art when it is not downloaded
+            // automatically
+            let gesture = gtk::GestureClick::new();
+            gesture.connect_released(clone!(@weak obj => move |gesture, _, _, _| {
+                let imp = obj.imp();
+
+                if imp.stack.visible_child().as_ref() != Some(imp.placeholder.upcast_ref()) {
+                    return;
+                }
+
+                let song = imp.song.borrow().clone();
+                if song.as_ref().and_then(|song| song.album_art()).is_some() {
+                    gesture.set_state(gtk::EventSequenceState::Claimed);
+                    obj.load(song.as_ref(), LoadKind::Force);
+                }
+            }));
+            obj.add_controller(gesture);
+
+            self.retry_button
+                .connect_clicked(clone!(@weak obj => move |_| {
+                    let song = obj.imp().song.borrow().clone();
+                    obj.load(song.as_ref(), LoadKind::Retry);
+                }));
         }
 
         fn dispose(&self) {
@@ -66,7 +109,29 @@ mod imp {
         }
     }
 
-    impl WidgetImpl for AlbumCover {}
+    impl WidgetImpl for AlbumCover {
+        fn map(&self) {
+            self.parent_map();
+
+            if self.needs_reload.get() {
+                self.needs_reload.set(false);
+
+                let obj = self.obj();
+                let song = self.song.borrow().clone();
+                obj.load(song.as_ref(), LoadKind::Auto);
+            }
+        }
+
+        fn unmap(&self) {
+            // Don't waste bandwidth on album covers that are not shown
+            if let Some(request) = self.request.take() {
+                request.cancel();
+                self.needs_reload.set(true);
+            }
+
+            self.parent_unmap();
+        }
+    }
 
     impl AlbumCover {
         fn pixel_size(&self) -> i32 {
@@ -113,51 +178,101 @@ impl AlbumCover {
     }
 
     pub fn set_song(&self, song: Option<&Song>) {
+        self.imp().song.replace(song.cloned());
+        self.load(song, LoadKind::Auto);
+    }
+
+    /// Defers loading album art that is not loaded yet until this is back in
+    /// view, so fast scrolling through a large list doesn't load the covers
+    /// of every tile passed.
+    pub fn set_is_out_of_view(&self, is_out_of_view: bool) {
+        let imp = self.imp();
+
+        if is_out_of_view == imp.is_out_of_view.get() {
+            return;
+        }
+
+        imp.is_out_of_view.set(is_out_of_view);
+
+        if is_out_of_view {
+            if let Some(request) = imp.request.take() {
+                request.cancel();
+                imp.needs_reload.set(true);
+            }
+        } else if imp.needs_reload.get() && self.is_mapped() {
+            imp.needs_reload.set(false);
+
+            let song = imp.song.borrow().clone();
+            self.load(song.as_ref(), LoadKind::Auto);
+        }
+    }
+
+    /// Loads the album art of the song. If `kind` is [`LoadKind::Auto`], this
+    /// won't download the album art if automatic downloading is disabled.
+    fn load(&self, song: Option<&Song>, kind: LoadKind) {
         let imp = self.imp();
 
-        if let Some(join_handle) = imp.join_handle.take() {
-            join_handle.abort();
+        if let Some(request) = imp.request.take() {
+            request.cancel();
         }
+        imp.needs_reload.set(false);
 
         if let Some(album_art) = song.as_ref().and_then(|song| song.album_art()) {
             if !album_art.is_loaded() {
-                self.set_paintable(gdk::Paintable::NONE);
+                self.set_texture(None);
+
+                if imp.is_out_of_view.get() && kind == LoadKind::Auto {
+                    imp.needs_reload.set(true);
+                    return;
+                }
             }
 
-            let join_handle = utils::spawn(
-                glib::Priority::LOW,
-                clone!(@weak self as obj, @weak album_art => async move {
-                    match album_art.texture().await {
+            let request = album_art.load(
+                kind,
+                clone!(@weak self as obj, @weak album_art => move |res| {
+                    let imp = obj.imp();
+                    imp.request.take();
+
+                    match res {
                         Ok(texture) => {
-                            obj.set_paintable(Some(texture));
+                            obj.set_texture(texture.as_ref());
                         }
                         Err(err) => {
                             tracing::warn!("Failed to load texture: {:?}", err);
-                            obj.set_paintable(gdk::Paintable::NONE);
+
+                            obj.set_texture(None);
+                            if album_art.is_failed() {
+                                imp.stack.set_visible_child(&imp.retry_button.get());
+                            }
                         }
                     }
                 }),
             );
-            imp.join_handle.replace(Some(join_handle));
+            imp.request.replace(Some(request));
         } else {
-            self.set_paintable(gdk::Paintable::NONE);
+            self.set_texture(None);
         }
     }
 
-    fn set_paintable(&self, paintable: Option<&impl IsA<gdk::Paintable>>) {
+    fn set_texture(&self, texture: Option<&gdk::Texture>) {
         let imp = self.imp();
 
-        if let Some(paintable) = paintable {
+        if let Some(texture) = texture {
             if imp.stack.visible_child().as_ref() == Some(imp.image_a.upcast_ref()) {
-                imp.image_b.set_paintable(Some(paintable));
+                imp.image_b.set_paintable(Some(texture));
                 imp.stack.set_visible_child(&imp.image_b.get());
             } else {
-                imp.image_a.set_paintable(Some(paintable));
+                imp.image_a.set_paintable(Some(texture));
                 imp.stack.set_visible_child(&imp.image_a.get());
             }
         } else {
             imp.stack.set_visible_child(&imp.placeholder.get());
         }
+
+        if imp.texture.borrow().as_ref() != texture {
+            imp.texture.replace(texture.cloned());
+            self.notify_texture();
+        }
     }
 }
 
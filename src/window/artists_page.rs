@@ -0,0 +1,149 @@
+use adw::{
+    prelude::*,
+    subclass::{navigation_page::NavigationPageImpl, prelude::*},
+};
+use gtk::glib::{self, clone, closure_local};
+
+use super::album_cover::AlbumCover;
+use crate::{
+    i18n::ngettext_f,
+    song::Song,
+    song_list::{ArtistSongs, SongList},
+};
+
+const ARTIST_COVER_PIXEL_SIZE: i32 = 48;
+
+mod imp {
+    use super::*;
+    use glib::{once_cell::sync::Lazy, subclass::Signal};
+
+    #[derive(Default, gtk::CompositeTemplate)]
+    #[template(resource = "/io/github/seadve/Mousai/ui/artists-page.ui")]
+    pub struct ArtistsPage {
+        #[template_child]
+        pub(super) stack: TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub(super) main_page: TemplateChild<gtk::ScrolledWindow>,
+        #[template_child]
+        pub(super) list_box: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub(super) empty_page: TemplateChild<adw::StatusPage>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ArtistsPage {
+        const NAME: &'static str = "MsaiArtistsPage";
+        type Type = super::ArtistsPage;
+        type ParentType = adw::NavigationPage;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for ArtistsPage {
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                vec![Signal::builder("song-activated")
+                    .param_types([Song::static_type()])
+                    .build()]
+            });
+
+            SIGNALS.as_ref()
+        }
+    }
+
+    impl WidgetImpl for ArtistsPage {}
+    impl NavigationPageImpl for ArtistsPage {}
+}
+
+glib::wrapper! {
+     pub struct ArtistsPage(ObjectSubclass<imp::ArtistsPage>)
+        @extends gtk::Widget, adw::NavigationPage;
+}
+
+impl ArtistsPage {
+    pub fn new() -> Self {
+        glib::Object::new()
+    }
+
+    pub fn connect_song_activated<F>(&self, f: F) -> glib::SignalHandlerId
+    where
+        F: Fn(&Self, &Song) + 'static,
+    {
+        self.connect_closure(
+            "song-activated",
+            true,
+            closure_local!(|obj: &Self, song: &Song| {
+                f(obj, song);
+            }),
+        )
+    }
+
+    /// Rebuilds the rows from the current songs in the list.
+    ///
+    /// This only takes a snapshot, so it must be called again to reflect
+    /// later changes in the list.
+    pub fn bind_song_list(&self, song_list: &SongList) {
+        let imp = self.imp();
+
+        imp.list_box.remove_all();
+
+        let artists = song_list.group_by_artist();
+        for artist in &artists {
+            imp.list_box.append(&self.create_artist_row(artist));
+        }
+
+        if artists.is_empty() {
+            imp.stack.set_visible_child(&imp.empty_page.get());
+        } else {
+            imp.stack.set_visible_child(&imp.main_page.get());
+        }
+    }
+
+    fn create_artist_row(&self, artist: &ArtistSongs) -> adw::ExpanderRow {
+        let n_songs = artist.songs.len();
+
+        let row = adw::ExpanderRow::builder()
+            .title(glib::markup_escape_text(&artist.name))
+            .subtitle(ngettext_f(
+                // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                "{n_songs} Song",
+                "{n_songs} Songs",
+                n_songs as u32,
+                &[("n_songs", &n_songs.to_string())],
+            ))
+            .build();
+
+        // The most recently heard song represents the artist
+        let cover = AlbumCover::new();
+        cover.set_pixel_size(ARTIST_COVER_PIXEL_SIZE);
+        cover.set_valign(gtk::Align::Center);
+        cover.set_song(artist.songs.first());
+        row.add_prefix(&cover);
+
+        for song in &artist.songs {
+            let song_row = adw::ActionRow::builder()
+                .title(glib::markup_escape_text(&song.title()))
+                .subtitle(glib::markup_escape_text(&song.album()))
+                .activatable(true)
+                .build();
+            song_row.connect_activated(clone!(@weak self as obj, @weak song => move |_| {
+                obj.emit_by_name::<()>("song-activated", &[&song]);
+            }));
+            row.add_row(&song_row);
+        }
+
+        row
+    }
+}
+
+impl Default for ArtistsPage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
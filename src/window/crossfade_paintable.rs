@@ -211,9 +211,9 @@ impl CrossfadePaintable {
             let join_handle = utils::spawn(
                 glib::Priority::LOW,
                 clone!(@weak self as obj, @weak album_art => async move {
-                    match album_art.texture().await {
+                    match album_art.auto_texture().await {
                         Ok(texture) => {
-                            obj.set_paintable(Some(texture.upcast_ref::<gdk::Paintable>()));
+                            obj.set_paintable(texture.map(|texture| texture.upcast_ref::<gdk::Paintable>()));
                         }
                         Err(err) => {
                             tracing::warn!("Failed to load texture: {:?}", err);
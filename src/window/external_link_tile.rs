@@ -65,6 +65,16 @@ mod imp {
                     obj.set_tooltip_text(Some(&gettext("Browse on AudD")));
                     obj.add_css_class("audd");
                 }
+                ExternalLinkKey::GeniusUrl => {
+                    self.label.set_label(&gettext("Genius"));
+                    obj.set_tooltip_text(Some(&gettext("Read on Genius")));
+                    obj.add_css_class("genius");
+                }
+                ExternalLinkKey::MusicbrainzReleaseId => {
+                    self.label.set_label(&gettext("MusicBrainz"));
+                    obj.set_tooltip_text(Some(&gettext("Browse on MusicBrainz")));
+                    obj.add_css_class("musicbrainz");
+                }
                 ExternalLinkKey::SpotifyUrl => {
                     self.label.set_label(&gettext("Spotify"));
                     obj.set_tooltip_text(Some(&gettext("Listen on Spotify")));
@@ -115,17 +125,7 @@ impl ExternalLinkTile {
             unreachable!("external link tile with an unhandleable key `{}` should not have been constructed and thus activated", raw_key);
         };
 
-        let uri = match key {
-            ExternalLinkKey::AppleMusicUrl
-            | ExternalLinkKey::AudDUrl
-            | ExternalLinkKey::SpotifyUrl => raw_value.to_string(),
-            ExternalLinkKey::YoutubeSearchTerm => {
-                format!(
-                    "https://www.youtube.com/results?search_query={}",
-                    glib::Uri::escape_string(raw_value, None, true)
-                )
-            }
-        };
+        let uri = key.to_uri(raw_value);
 
         if let Err(err) = glib::Uri::is_valid(&uri, glib::UriFlags::ENCODED) {
             tracing::warn!("Trying to launch an invalid Uri: {:?}", err);
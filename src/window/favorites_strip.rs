@@ -0,0 +1,255 @@
+use gtk::{
+    glib::{self, clone, WeakRef},
+    prelude::*,
+    subclass::prelude::*,
+};
+
+use std::cell::{OnceCell, RefCell};
+
+use super::album_cover::AlbumCover;
+use crate::{i18n::gettext_f, player::Player, song::Song, song_list::SongList};
+
+/// Maximum number of songs shown in the strip
+const MAX_N_SONGS: usize = 12;
+
+const COVER_PIXEL_SIZE: i32 = 56;
+
+mod imp {
+    use super::*;
+
+    #[derive(Default)]
+    pub struct FavoritesStrip {
+        pub(super) scrolled_window: OnceCell<gtk::ScrolledWindow>,
+        pub(super) box_: OnceCell<gtk::Box>,
+
+        pub(super) player: OnceCell<WeakRef<Player>>,
+        pub(super) song_list: OnceCell<WeakRef<SongList>>,
+        /// Songs of the shown buttons, in order
+        pub(super) songs: RefCell<Vec<Song>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for FavoritesStrip {
+        const NAME: &'static str = "MsaiFavoritesStrip";
+        type Type = super::FavoritesStrip;
+        type ParentType = gtk::Widget;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.set_css_name("favoritesstrip");
+            klass.set_layout_manager_type::<gtk::BinLayout>();
+        }
+    }
+
+    impl ObjectImpl for FavoritesStrip {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            let obj = self.obj();
+
+            let box_ = gtk::Box::builder()
+                .orientation(gtk::Orientation::Horizontal)
+                .spacing(6)
+                .margin_start(12)
+                .margin_end(12)
+                .margin_top(6)
+                .margin_bottom(6)
+                .build();
+            let scrolled_window = gtk::ScrolledWindow::builder()
+                .hscrollbar_policy(gtk::PolicyType::Automatic)
+                .vscrollbar_policy(gtk::PolicyType::Never)
+                .child(&box_)
+                .build();
+            scrolled_window.set_parent(&*obj);
+
+            self.box_.set(box_).unwrap();
+            self.scrolled_window.set(scrolled_window).unwrap();
+
+            obj.update_visibility();
+        }
+
+        fn dispose(&self) {
+            if let Some(scrolled_window) = self.scrolled_window.get() {
+                scrolled_window.unparent();
+            }
+        }
+    }
+
+    impl WidgetImpl for FavoritesStrip {}
+}
+
+glib::wrapper! {
+    /// Horizontal strip of the covers of favorite songs, which play their
+    /// preview when clicked.
+    pub struct FavoritesStrip(ObjectSubclass<imp::FavoritesStrip>)
+        @extends gtk::Widget;
+}
+
+impl FavoritesStrip {
+    pub fn new() -> Self {
+        glib::Object::new()
+    }
+
+    /// Must only be called once
+    pub fn bind_player(&self, player: &Player) {
+        self.imp().player.set(player.downgrade()).unwrap();
+    }
+
+    /// Must only be called once
+    pub fn bind_song_list(&self, song_list: &SongList) {
+        song_list.connect_items_changed(clone!(@weak self as obj => move |_, _, _, _| {
+            obj.update_songs();
+        }));
+        song_list.connect_favorites_changed(clone!(@weak self as obj => move |_| {
+            obj.update_songs();
+        }));
+
+        self.imp().song_list.set(song_list.downgrade()).unwrap();
+
+        self.update_songs();
+    }
+
+    fn update_songs(&self) {
+        let imp = self.imp();
+
+        let Some(song_list) = imp
+            .song_list
+            .get()
+            .and_then(|song_list| song_list.upgrade())
+        else {
+            return;
+        };
+
+        // Only songs with a preview can be played from here
+        let songs = song_list
+            .favorites(usize::MAX)
+            .into_iter()
+            .filter(|song| song.playback_link().is_some())
+            .take(MAX_N_SONGS)
+            .collect::<Vec<_>>();
+
+        if *imp.songs.borrow() == songs {
+            return;
+        }
+
+        let box_ = imp.box_.get().unwrap();
+        while let Some(child) = box_.first_child() {
+            box_.remove(&child);
+        }
+        for song in &songs {
+            box_.append(&self.new_button(song));
+        }
+
+        imp.songs.replace(songs);
+        self.update_visibility();
+    }
+
+    fn new_button(&self, song: &Song) -> gtk::Button {
+        let cover = AlbumCover::new();
+        cover.set_pixel_size(COVER_PIXEL_SIZE);
+        cover.set_song(Some(song));
+
+        let button = gtk::Button::builder()
+            .child(&cover)
+            .tooltip_text(gettext_f(
+                // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                "Play “{title}” by {artist}",
+                &[("title", &song.title()), ("artist", &song.artist())],
+            ))
+            .css_classes(["flat", "favorites-strip-button"])
+            .build();
+        button.connect_clicked(clone!(@weak self as obj, @strong song => move |_| {
+            obj.play(&song);
+        }));
+
+        button
+    }
+
+    fn play(&self, song: &Song) {
+        let Some(player) = self.imp().player.get().and_then(|player| player.upgrade()) else {
+            tracing::warn!("Player is not bound");
+            return;
+        };
+
+        player.set_song(Some(song.clone()));
+        player.play();
+    }
+
+    fn update_visibility(&self) {
+        self.set_visible(!self.imp().songs.borrow().is_empty());
+    }
+
+    #[cfg(test)]
+    fn n_buttons(&self) -> usize {
+        let box_ = self.imp().box_.get().unwrap();
+        let mut n_buttons = 0;
+        let mut child = box_.first_child();
+        while let Some(widget) = child {
+            n_buttons += 1;
+            child = widget.next_sibling();
+        }
+        n_buttons
+    }
+}
+
+impl Default for FavoritesStrip {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{database, uid::Uid, window::init_gresources};
+
+    fn new_test_song(id: &str, is_favorite: bool) -> Song {
+        Song::builder(&Uid::from(id), id, id, id)
+            .favorite(is_favorite)
+            .playback_link(&format!("file:///{}.ogg", id))
+            .build()
+    }
+
+    #[gtk::test]
+    fn populate_and_hide() {
+        init_gresources();
+
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let strip = FavoritesStrip::new();
+        strip.bind_song_list(&song_list);
+        assert!(!strip.is_visible());
+        assert_eq!(strip.n_buttons(), 0);
+
+        song_list
+            .insert_many(vec![
+                new_test_song("a", true),
+                new_test_song("b", false),
+                new_test_song("c", true),
+                // Can't be previewed
+                Song::builder(&Uid::from("d"), "d", "d", "d")
+                    .favorite(true)
+                    .build(),
+            ])
+            .unwrap();
+        assert!(strip.is_visible());
+        assert_eq!(strip.n_buttons(), 2);
+
+        // Updates when favorites change
+        song_list
+            .get(&Uid::from("b"))
+            .unwrap()
+            .set_is_favorite(true);
+        assert_eq!(strip.n_buttons(), 3);
+
+        for id in ["a", "b", "c"] {
+            song_list
+                .get(&Uid::from(id))
+                .unwrap()
+                .set_is_favorite(false);
+        }
+        assert!(!strip.is_visible());
+        assert_eq!(strip.n_buttons(), 0);
+    }
+}
@@ -2,32 +2,60 @@ use adw::prelude::*;
 use anyhow::{Context, Result};
 use gettextrs::{gettext, ngettext};
 use gtk::{
+    gdk, gio,
     glib::{self, clone, closure},
     subclass::prelude::*,
 };
 
-use std::cell::{Cell, OnceCell, RefCell};
+use std::{
+    cell::{Cell, OnceCell, RefCell},
+    time::{Duration, Instant},
+};
 
 use super::{
-    recognized_page::RecognizedPage, recognizer_status::RecognizerStatus, song_page::SongPage,
-    song_tile::SongTile, AdaptiveMode,
+    artists_page::ArtistsPage,
+    favorites_strip::FavoritesStrip,
+    quick_jump::{self, QuickJump},
+    recognized_page::RecognizedPage,
+    recognizer_status::RecognizerStatus,
+    song_page::SongPage,
+    song_tile::SongTile,
+    AdaptiveMode,
 };
 use crate::{
-    config::APP_ID, i18n::ngettext_f, player::Player, recognizer::Recognizer, song::Song,
-    song_filter::SongFilter, song_list::SongList, song_sorter::SongSorter, uid::Uid, Application,
+    config::APP_ID,
+    export,
+    i18n::{gettext_f, ngettext_f},
+    player::Player,
+    recognizer::Recognizer,
+    song::Song,
+    song_filter::{SongFilter, SongPredicate},
+    song_list::SongList,
+    song_sorter::SongSorter,
+    uid::Uid,
+    utils::{self, Countable},
+    Application,
 };
 
 // FIXME Missing global navigation shortcuts
 
+/// How long a new song stays highlighted after it is revealed
+const NEW_SONG_HIGHLIGHT_DURATION: Duration = Duration::from_millis(1500);
+
 const SONG_PAGE_SONG_REMOVE_REQUEST_HANDLER_ID_KEY: &str =
     "mousai-song-page-song-remove-request-handler-id";
 const SONG_PAGE_ADAPTIVE_MODE_BINDING_KEY: &str = "mousai-song-page-adaptive-mode-binding";
+const SONG_PAGE_SHOWS_ALBUM_ART_ACCENT_BINDING_KEY: &str =
+    "mousai-song-page-shows-album-art-accent-binding";
 
 const RECOGNIZED_PAGE_SONG_ACTIVATED_HANDLER_ID_KEY: &str =
     "mousai-recognized-page-song-activated-handler-id";
 const RECOGNIZED_PAGE_ADAPTIVE_MODE_BINDING_KEY: &str =
     "mousai-recognized-page-adaptive-mode-binding";
 
+const ARTISTS_PAGE_SONG_ACTIVATED_HANDLER_ID_KEY: &str =
+    "mousai-artists-page-song-activated-handler-id";
+
 const GRID_LIST_ITEM_BINDINGS_KEY: &str = "mousai-grid-list-item-bindings";
 const GRID_LIST_ITEM_EXPRESSION_WATCHES_KEY: &str = "mousai-grid-list-item-expression-watches";
 
@@ -45,6 +73,19 @@ mod imp {
         /// Current adaptive mode
         #[property(get, set = Self::set_adaptive_mode, explicit_notify, builder(AdaptiveMode::default()))]
         pub(super) adaptive_mode: Cell<AdaptiveMode>,
+        /// Whether to show extra large song tiles
+        #[property(get, set)]
+        pub(super) is_large_tiles: Cell<bool>,
+        /// Number of rows beyond the visible ones whose album covers are
+        /// loaded
+        #[property(get, set)]
+        pub(super) overscan_rows: Cell<u32>,
+        /// Whether to tint song pages with the album art's color
+        #[property(get, set)]
+        pub(super) shows_album_art_accent: Cell<bool>,
+        /// Whether the search also matches the lyrics of songs
+        #[property(get, set)]
+        pub(super) search_lyrics: Cell<bool>,
 
         #[template_child]
         pub(super) navigation_view: TemplateChild<adw::NavigationView>,
@@ -69,14 +110,20 @@ mod imp {
         #[template_child]
         pub(super) search_bar: TemplateChild<gtk::SearchBar>,
         #[template_child]
+        pub(super) favorites_strip: TemplateChild<FavoritesStrip>,
+        #[template_child]
         pub(super) search_entry: TemplateChild<gtk::SearchEntry>,
         #[template_child]
+        pub(super) favorites_only_button: TemplateChild<gtk::ToggleButton>,
+        #[template_child]
         pub(super) content_stack: TemplateChild<gtk::Stack>,
         #[template_child]
         pub(super) content_main_page: TemplateChild<gtk::ScrolledWindow>,
         #[template_child]
         pub(super) grid: TemplateChild<gtk::GridView>,
         #[template_child]
+        pub(super) main_title: TemplateChild<adw::WindowTitle>,
+        #[template_child]
         pub(super) content_empty_page: TemplateChild<adw::StatusPage>,
         #[template_child]
         pub(super) content_empty_search_result_page: TemplateChild<adw::StatusPage>,
@@ -90,6 +137,18 @@ mod imp {
         pub(super) undo_remove_song_toast: RefCell<Option<adw::Toast>>,
 
         pub(super) navigation_forward_stack: RefCell<Vec<adw::NavigationPage>>,
+
+        pub(super) quick_jump: RefCell<QuickJump>,
+
+        /// Tiles created by the grid factory, which only creates enough to
+        /// fill the view and recycles them as it scrolls
+        pub(super) song_tiles: RefCell<Vec<WeakRef<SongTile>>>,
+        pub(super) is_tile_view_update_queued: Cell<bool>,
+
+        /// Newly recognized song whose tile is highlighted, until the
+        /// timeout clears it
+        pub(super) highlighted_song: RefCell<Option<Song>>,
+        pub(super) new_song_highlight_timeout: RefCell<Option<glib::SourceId>>,
     }
 
     #[glib::object_subclass]
@@ -120,18 +179,15 @@ mod imp {
             });
 
             klass.install_action("history-view.copy-selected-song", None, |obj, _, _| {
-                let selected_songs = obj.snapshot_selected_songs();
-
-                debug_assert!(
-                    !selected_songs.is_empty(),
-                    "copying must only be allowed if there is atleast one selected"
-                );
-
-                let text = selected_songs
-                    .iter()
-                    .map(|song| song.copy_term())
-                    .collect::<Vec<_>>()
-                    .join("\n");
+                let settings = Application::get().settings();
+                let Some(text) = export::copy_text(
+                    settings.copy_format(),
+                    settings.artist_title_order(),
+                    &obj.snapshot_selected_songs(),
+                ) else {
+                    tracing::warn!("Tried to copy without selected songs");
+                    return;
+                };
                 obj.display().clipboard().set_text(&text);
 
                 Application::get()
@@ -139,6 +195,32 @@ mod imp {
                     .add_message_toast(&gettext("Copied to clipboard"));
             });
 
+            klass.install_action_async(
+                "history-view.export-shown-songs",
+                None,
+                |obj, _, _| async move {
+                    let songs = obj.snapshot_shown_songs();
+                    Application::get().window().export_songs(songs).await;
+                },
+            );
+
+            klass.install_action_async(
+                "history-view.export-selected-songs",
+                None,
+                |obj, _, _| async move {
+                    let songs = obj.snapshot_selected_songs();
+                    Application::get().window().export_songs(songs).await;
+                },
+            );
+
+            klass.install_action("history-view.show-artists", None, |obj, _, _| {
+                obj.push_artists_page();
+            });
+
+            klass.install_action("history-view.play-all", None, |obj, _, _| {
+                obj.play_all();
+            });
+
             klass.install_action("history-view.remove-selected-songs", None, |obj, _, _| {
                 let selected_songs = obj.snapshot_selected_songs();
                 let song_ids = selected_songs
@@ -156,6 +238,31 @@ mod imp {
 
                 obj.show_undo_remove_song_toast();
             });
+
+            klass.install_action_async(
+                "history-view.merge-selected-songs",
+                None,
+                |obj, _, _| async move {
+                    obj.merge_selected_songs().await;
+                },
+            );
+
+            klass.install_action("history-view.open-listen-link", None, |obj, _, _| {
+                obj.open_listen_link();
+            });
+
+            klass.add_binding_action(
+                gdk::Key::Return,
+                gdk::ModifierType::CONTROL_MASK,
+                "history-view.open-listen-link",
+                None,
+            );
+            klass.add_binding_action(
+                gdk::Key::KP_Enter,
+                gdk::ModifierType::CONTROL_MASK,
+                "history-view.open-listen-link",
+                None,
+            );
         }
 
         fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
@@ -170,6 +277,17 @@ mod imp {
 
             let obj = self.obj();
 
+            // The entry is not the direct child of the search bar
+            self.search_bar.connect_entry(&*self.search_entry);
+            // Nothing would show that songs are hidden otherwise
+            self.search_bar.connect_search_mode_enabled_notify(
+                clone!(@weak obj => move |search_bar| {
+                    if !search_bar.is_search_mode() {
+                        obj.imp().favorites_only_button.set_active(false);
+                    }
+                }),
+            );
+
             self.navigation_view
                 .connect_pushed(clone!(@weak obj => move |view| {
                     let imp = obj.imp();
@@ -214,6 +332,16 @@ mod imp {
             self.content_empty_page.set_icon_name(Some(APP_ID));
             obj.setup_grid();
 
+            // This is on the grid, so it is handled before the search bar's
+            // key capture on the window
+            let key_controller = gtk::EventControllerKey::new();
+            key_controller.connect_key_pressed(
+                clone!(@weak obj => @default-return glib::Propagation::Proceed, move |_, keyval, _, state| {
+                    obj.handle_quick_jump_key(keyval, state)
+                }),
+            );
+            self.grid.add_controller(key_controller);
+
             obj.update_selection_actions();
             obj.update_selection_mode_ui();
 
@@ -259,6 +387,32 @@ impl HistoryView {
         self.imp().search_bar.get()
     }
 
+    pub fn search_text(&self) -> glib::GString {
+        self.imp().search_entry.text()
+    }
+
+    /// Whether the search or another filter hides some songs
+    fn is_filtering(&self) -> bool {
+        let imp = self.imp();
+        !imp.search_entry.text().is_empty() || imp.favorites_only_button.is_active()
+    }
+
+    /// Shows the search bar with the text, or hides it if the text is empty.
+    pub fn set_search_text(&self, text: &str) {
+        let imp = self.imp();
+        imp.search_entry.set_text(text);
+        imp.search_bar.set_search_mode(!text.is_empty());
+    }
+
+    /// Returns the song of the visible page, if it is a `SongPage`.
+    pub fn visible_song(&self) -> Option<Song> {
+        self.imp()
+            .navigation_view
+            .visible_page()
+            .and_downcast::<SongPage>()
+            .and_then(|song_page| song_page.song())
+    }
+
     pub fn is_on_navigation_main_page(&self) -> bool {
         let imp = self.imp();
         imp.navigation_view.visible_page().as_ref() == Some(imp.navigation_main_page.upcast_ref())
@@ -301,6 +455,33 @@ impl HistoryView {
         imp.navigation_view.push(&recognized_page);
     }
 
+    /// Pushes an `ArtistsPage` to the navigation stack.
+    pub fn push_artists_page(&self) {
+        let imp = self.imp();
+
+        if imp
+            .navigation_view
+            .visible_page()
+            .is_some_and(|page| page.is::<ArtistsPage>())
+        {
+            return;
+        }
+
+        let artists_page = ArtistsPage::new();
+        artists_page.bind_song_list(&self.song_list());
+
+        unsafe {
+            artists_page.set_data(
+                ARTISTS_PAGE_SONG_ACTIVATED_HANDLER_ID_KEY,
+                artists_page.connect_song_activated(clone!(@weak self as obj => move |_, song| {
+                    obj.push_song_page(song);
+                })),
+            );
+        }
+
+        imp.navigation_view.push(&artists_page);
+    }
+
     /// Pushes a `SongPage` for the given song to the navigation stack.
     pub fn push_song_page(&self, song: &Song) {
         let imp = self.imp();
@@ -310,6 +491,9 @@ impl HistoryView {
             if let Some(song_page) = visible_page.downcast_ref::<SongPage>() {
                 if let Some(song_page_song) = song_page.song() {
                     if song_page_song.id_ref() == song.id_ref() {
+                        // The song may have been replaced with the same id,
+                        // e.g., when it was recognized again.
+                        song_page.set_song(song);
                         return;
                     }
                 }
@@ -342,6 +526,16 @@ impl HistoryView {
                     .sync_create()
                     .build(),
             );
+            song_page.set_data(
+                SONG_PAGE_SHOWS_ALBUM_ART_ACCENT_BINDING_KEY,
+                self.bind_property(
+                    "shows-album-art-accent",
+                    &song_page,
+                    "shows-album-art-accent",
+                )
+                .sync_create()
+                .build(),
+            );
         }
 
         imp.navigation_view.push(&song_page);
@@ -357,7 +551,9 @@ impl HistoryView {
 
     /// Must only be called once
     pub fn bind_player(&self, player: &Player) {
-        self.imp().player.set(player.downgrade()).unwrap();
+        let imp = self.imp();
+        imp.favorites_strip.bind_player(player);
+        imp.player.set(player.downgrade()).unwrap();
     }
 
     /// Must only be called once
@@ -368,8 +564,18 @@ impl HistoryView {
             obj.update_content_stack_visible_child();
         }));
 
+        imp.favorites_strip.bind_song_list(song_list);
+
         let filter = SongFilter::new();
+        filter.set_song_list(song_list);
         let sorter = SongSorter::new();
+        sorter.set_song_list(song_list);
+        self.bind_property("search-lyrics", &filter, "search-lyrics")
+            .sync_create()
+            .build();
+        self.bind_property("search-lyrics", &sorter, "search-lyrics")
+            .sync_create()
+            .build();
 
         let filter_model = gtk::FilterListModel::new(Some(song_list.clone()), Some(filter.clone()));
         filter_model.connect_items_changed(clone!(@weak self as obj => move |_, _, _, _| {
@@ -384,6 +590,12 @@ impl HistoryView {
                 obj.update_content_stack_visible_child();
             }),
         );
+        imp.favorites_only_button.connect_active_notify(
+            clone!(@weak self as obj, @weak filter => move |button| {
+                filter.set_predicate(button.is_active().then_some(SongPredicate::Favorite));
+                obj.update_content_stack_visible_child();
+            }),
+        );
 
         let sort_model = gtk::SortListModel::new(Some(filter_model.clone()), Some(sorter));
 
@@ -449,12 +661,141 @@ impl HistoryView {
         );
     }
 
+    /// Scrolls to and focuses the song in the grid, then opens its page.
+    pub fn reveal_song(&self, song: &Song) {
+        if let Some(position) = self.song_position(song) {
+            self.imp()
+                .grid
+                .scroll_to(position, gtk::ListScrollFlags::FOCUS, None);
+        } else {
+            tracing::debug!("Song to reveal is filtered out");
+        }
+
+        self.push_song_page(song);
+    }
+
+    /// Scrolls to the newly recognized song in the grid and briefly
+    /// highlights its tile, so it is not lost among the others.
+    ///
+    /// If the search hides the song, the search is cleared if `clears_search`
+    /// is set, otherwise nothing is done.
+    pub fn reveal_new_song(&self, song: &Song, clears_search: bool) {
+        let imp = self.imp();
+
+        let Some(position) = self.new_song_position(song, clears_search) else {
+            tracing::debug!("New song is filtered out; not revealing it");
+            return;
+        };
+
+        imp.grid
+            .scroll_to(position, gtk::ListScrollFlags::FOCUS, None);
+
+        // The tile is only bound to the song on the next layout, so let the
+        // tiles view update pick it up
+        imp.highlighted_song.replace(Some(song.clone()));
+        self.queue_update_tiles_view();
+
+        let source_id = glib::timeout_add_local_once(
+            NEW_SONG_HIGHLIGHT_DURATION,
+            clone!(@weak self as obj => move || {
+                let imp = obj.imp();
+                imp.new_song_highlight_timeout.take();
+                imp.highlighted_song.take();
+                obj.update_tiles_view();
+            }),
+        );
+        if let Some(prev_source_id) = imp.new_song_highlight_timeout.replace(Some(source_id)) {
+            prev_source_id.remove();
+        }
+    }
+
+    /// Returns the position of the new song in the grid, clearing the search
+    /// first if it hides the song and `clears_search` is set.
+    fn new_song_position(&self, song: &Song, clears_search: bool) -> Option<u32> {
+        let position = self.song_position(song);
+
+        if position.is_some() || !clears_search || !self.is_filtering() {
+            return position;
+        }
+
+        // Clearing the search filters the songs right away, unlike typing.
+        // This also hides the search bar, which turns off the other filters.
+        tracing::debug!("New song is hidden by the search; clearing it");
+        self.set_search_text("");
+        self.song_position(song)
+    }
+
+    /// Returns the position of the song in the grid, or `None` if it is
+    /// filtered out.
+    fn song_position(&self, song: &Song) -> Option<u32> {
+        let selection_model = self.imp().selection_model.get()?.upgrade()?;
+
+        (0..selection_model.n_items()).find(|position| {
+            selection_model
+                .item(*position)
+                .and_downcast::<Song>()
+                .is_some_and(|item| item.id_ref() == song.id_ref())
+        })
+    }
+
     pub fn scroll_to_top(&self) -> bool {
         self.imp()
             .content_main_page
             .emit_scroll_child(gtk::ScrollType::Start, false)
     }
 
+    /// Scrolls to the first shown song whose title or artist starts with the
+    /// typed prefix.
+    fn handle_quick_jump_key(
+        &self,
+        keyval: gdk::Key,
+        state: gdk::ModifierType,
+    ) -> glib::Propagation {
+        let imp = self.imp();
+
+        if imp.search_bar.is_search_mode()
+            || self.is_selection_mode_active()
+            || state.intersects(
+                gdk::ModifierType::CONTROL_MASK
+                    | gdk::ModifierType::ALT_MASK
+                    | gdk::ModifierType::SUPER_MASK,
+            )
+        {
+            imp.quick_jump.borrow_mut().reset();
+            return glib::Propagation::Proceed;
+        }
+
+        let Some(c) = keyval.to_unicode().filter(|c| !c.is_control()) else {
+            return glib::Propagation::Proceed;
+        };
+
+        let now = Instant::now();
+
+        // Let space activate the focused song unless it is part of the prefix
+        if c.is_whitespace() && !imp.quick_jump.borrow().is_typing(now) {
+            return glib::Propagation::Proceed;
+        }
+
+        let Some(selection_model) = imp.selection_model.get().and_then(|model| model.upgrade())
+        else {
+            return glib::Propagation::Proceed;
+        };
+
+        let mut quick_jump = imp.quick_jump.borrow_mut();
+        let prefix = quick_jump.push(c, now);
+
+        let songs = (0..selection_model.n_items())
+            .filter_map(|position| selection_model.item(position).and_downcast::<Song>());
+        if let Some(position) = quick_jump::find_target(songs, prefix) {
+            imp.grid
+                .scroll_to(position as u32, gtk::ListScrollFlags::FOCUS, None);
+        } else {
+            tracing::debug!("No song to jump to with the typed prefix");
+        }
+
+        glib::Propagation::Stop
+    }
+
     fn player(&self) -> Player {
         self.imp()
             .player
@@ -464,6 +805,60 @@ impl HistoryView {
             .expect("player must not be dropped")
     }
 
+    /// Returns the song of the visible song page, or else the only selected
+    /// song, which follows the keyboard focus in the grid.
+    fn active_song(&self) -> Option<Song> {
+        let imp = self.imp();
+
+        if let Some(song_page) = imp
+            .navigation_view
+            .visible_page()
+            .and_downcast::<SongPage>()
+        {
+            return song_page.song();
+        }
+
+        let selection_model = imp.selection_model.get()?.upgrade()?;
+        let selection = selection_model.selection();
+        if selection.size() != 1 {
+            return None;
+        }
+
+        selection_model
+            .item(selection.minimum())
+            .and_downcast::<Song>()
+    }
+
+    fn open_listen_link(&self) {
+        let Some(song) = self.active_song() else {
+            return;
+        };
+
+        let preferred = Application::get().settings().preferred_streaming_service();
+        let Some(uri) = song.listen_uri(preferred.into()) else {
+            tracing::debug!(
+                song_id = song.id_ref().as_str(),
+                "Song has no link to listen to"
+            );
+            return;
+        };
+
+        gtk::UriLauncher::new(&uri).launch(
+            self.root().and_downcast::<gtk::Window>().as_ref(),
+            gio::Cancellable::NONE,
+            move |res| {
+                if let Err(err) = res {
+                    tracing::warn!("Failed to launch default for uri `{}`: {:?}", uri, err);
+                    Application::get().window().add_message_toast(&gettext_f(
+                        // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                        "Failed to open {uri}",
+                        &[("uri", &uri)],
+                    ));
+                }
+            },
+        );
+    }
+
     fn song_list(&self) -> SongList {
         self.imp()
             .song_list
@@ -510,6 +905,49 @@ impl HistoryView {
             })
     }
 
+    /// Returns all the songs currently shown, in display order.
+    fn snapshot_shown_songs(&self) -> Vec<Song> {
+        self.imp()
+            .selection_model
+            .get()
+            .and_then(|model| model.upgrade())
+            .map_or(Vec::new(), |selection_model| {
+                selection_model
+                    .iter::<Song>()
+                    .map(|song| song.unwrap())
+                    .collect::<Vec<_>>()
+            })
+    }
+
+    /// Queues and plays the shown songs. The queue does not follow changes
+    /// on the filter after this is called.
+    fn play_all(&self) {
+        let songs = self.snapshot_shown_songs();
+        let n_songs = songs.len();
+
+        let player = self.player();
+        let n_skipped = player.set_queue(songs);
+
+        if n_skipped == n_songs {
+            Application::get()
+                .window()
+                .add_message_toast(&gettext("No songs with playable previews"));
+            return;
+        }
+
+        player.play();
+
+        if n_skipped != 0 {
+            Application::get().window().add_message_toast(&ngettext_f(
+                // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                "Skipped {n_skipped} song without preview",
+                "Skipped {n_skipped} songs without preview",
+                n_skipped as u32,
+                &[("n_skipped", &n_skipped.to_string())],
+            ));
+        }
+    }
+
     fn select_all(&self) {
         let selection_model = self
             .imp()
@@ -520,6 +958,63 @@ impl HistoryView {
         selection_model.select_all();
     }
 
+    /// Merges the two selected songs, keeping the metadata of the one the user
+    /// chooses. This refuses unless exactly two songs are selected.
+    async fn merge_selected_songs(&self) {
+        const CANCEL_RESPONSE_ID: &str = "cancel";
+        const KEEP_FIRST_RESPONSE_ID: &str = "keep-first";
+        const KEEP_SECOND_RESPONSE_ID: &str = "keep-second";
+
+        let window = Application::get().window();
+
+        let Ok([first, second]) = <[Song; 2]>::try_from(self.snapshot_selected_songs()) else {
+            tracing::warn!("Tried to merge without exactly two selected songs");
+            window.add_message_toast(&gettext("Select exactly two songs to merge"));
+            return;
+        };
+
+        let keep_label = |song: &Song| {
+            gettext_f(
+                // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                "Keep “{title}” by {artist}",
+                &[
+                    ("title", &song.title().replace('_', "__")),
+                    ("artist", &song.artist().replace('_', "__")),
+                ],
+            )
+        };
+
+        let dialog = adw::MessageDialog::builder()
+            .transient_for(&window)
+            .modal(true)
+            .heading(gettext("Merge Songs?"))
+            .body(gettext(
+                "Only the details of the kept song remain. The links, favorite state, and last heard time of both are combined.",
+            ))
+            .default_response(CANCEL_RESPONSE_ID)
+            .close_response(CANCEL_RESPONSE_ID)
+            .build();
+        dialog.add_response(CANCEL_RESPONSE_ID, &gettext("_Cancel"));
+        dialog.add_response(KEEP_FIRST_RESPONSE_ID, &keep_label(&first));
+        dialog.add_response(KEEP_SECOND_RESPONSE_ID, &keep_label(&second));
+
+        let (survivor, other) = match dialog.choose_future().await.as_str() {
+            KEEP_FIRST_RESPONSE_ID => (first, second),
+            KEEP_SECOND_RESPONSE_ID => (second, first),
+            _ => return,
+        };
+
+        if let Err(err) = self.song_list().merge(survivor.id_ref(), other.id_ref()) {
+            tracing::error!("Failed to merge songs: {:?}", err);
+            window.add_message_toast(&gettext("Failed to merge songs"));
+            return;
+        }
+
+        self.unselect_all();
+        self.set_selection_mode_active(false);
+        window.add_message_toast(&gettext("Merged songs"));
+    }
+
     fn unselect_all(&self) {
         let selection_model = self
             .imp()
@@ -630,6 +1125,14 @@ impl HistoryView {
             {
                 song.set_is_newly_heard(true);
             }
+
+            // Keep the favorite state of the song in the history.
+            if song_list
+                .get(song.id_ref())
+                .is_some_and(|prev| prev.is_favorite())
+            {
+                song.set_is_favorite(true);
+            }
         }
 
         song_list
@@ -665,14 +1168,14 @@ impl HistoryView {
     fn update_content_stack_visible_child(&self) {
         let imp = self.imp();
 
-        let search_text = imp.search_entry.text();
+        let is_filtering = self.is_filtering();
 
         if imp
             .filter_model
             .get()
             .and_then(|filter_model| filter_model.upgrade())
             .map_or(true, |filter_model| filter_model.n_items() == 0)
-            && !search_text.is_empty()
+            && is_filtering
         {
             imp.content_stack
                 .set_visible_child(&imp.content_empty_search_result_page.get());
@@ -681,7 +1184,7 @@ impl HistoryView {
             .get()
             .and_then(|song_list| song_list.upgrade())
             .map_or(true, |song_list| song_list.n_items() == 0)
-            && search_text.is_empty()
+            && !is_filtering
         {
             imp.content_stack
                 .set_visible_child(&imp.content_empty_page.get());
@@ -689,6 +1192,31 @@ impl HistoryView {
             imp.content_stack
                 .set_visible_child(&imp.content_main_page.get());
         }
+
+        self.update_main_title_subtitle();
+    }
+
+    /// Shows the number of songs in the history, or of results while
+    /// searching.
+    fn update_main_title_subtitle(&self) {
+        let imp = self.imp();
+
+        let subtitle = if !self.is_filtering() {
+            let n_songs = imp
+                .song_list
+                .get()
+                .and_then(|song_list| song_list.upgrade())
+                .map_or(0, |song_list| song_list.n_items());
+            utils::count_label(Countable::Songs, n_songs)
+        } else {
+            let n_results = imp
+                .filter_model
+                .get()
+                .and_then(|filter_model| filter_model.upgrade())
+                .map_or(0, |filter_model| filter_model.n_items());
+            utils::count_label(Countable::SearchResults, n_results)
+        };
+        imp.main_title.set_subtitle(&subtitle);
     }
 
     fn update_selection_actions(&self) {
@@ -701,20 +1229,13 @@ impl HistoryView {
 
         self.action_set_enabled("history-view.copy-selected-song", selection_size != 0);
         self.action_set_enabled("history-view.remove-selected-songs", selection_size != 0);
+        self.action_set_enabled("history-view.merge-selected-songs", selection_size == 2);
 
         imp.selection_mode_menu_button
-            .set_label(&match selection_size {
-                0 => gettext("Select items"),
-                1.. => {
-                    ngettext_f(
-                        // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
-                        "Selected {selection_size} song",
-                        "Selected {selection_size} songs",
-                        selection_size as u32,
-                        &[("selection_size", &selection_size.to_string())],
-                    )
-                }
-            });
+            .set_label(&utils::count_label(
+                Countable::SelectedSongs,
+                selection_size as u32,
+            ));
 
         imp.copy_selected_songs_button
             .set_tooltip_text(Some(&ngettext(
@@ -748,6 +1269,10 @@ impl HistoryView {
                 .bind_property("adaptive-mode", &song_tile, "adaptive-mode")
                 .sync_create()
                 .build();
+            let is_large_tiles_binding = obj
+                .bind_property("is-large-tiles", &song_tile, "is-large")
+                .sync_create()
+                .build();
 
             song_tile.connect_is_active_notify(clone!(@weak obj, @weak list_item => move |tile| {
                 let selection_model = obj
@@ -786,7 +1311,11 @@ impl HistoryView {
             unsafe {
                 list_item.set_data(
                     GRID_LIST_ITEM_BINDINGS_KEY,
-                    vec![selection_mode_active_binding, adaptive_mode_binding],
+                    vec![
+                        selection_mode_active_binding,
+                        adaptive_mode_binding,
+                        is_large_tiles_binding,
+                    ],
                 );
                 list_item.set_data(
                     GRID_LIST_ITEM_EXPRESSION_WATCHES_KEY,
@@ -794,11 +1323,24 @@ impl HistoryView {
                 );
             }
 
+            obj.imp().song_tiles.borrow_mut().push(song_tile.downgrade());
+            obj.queue_update_tiles_view();
+
             list_item.set_child(Some(&song_tile));
         }));
-        factory.connect_teardown(|_, list_item| {
+        factory.connect_bind(clone!(@weak self as obj => move |_, _| {
+            // Recycled tiles are moved to their new position on the next layout
+            obj.queue_update_tiles_view();
+        }));
+        factory.connect_teardown(clone!(@weak self as obj => move |_, list_item| {
             let list_item = list_item.downcast_ref::<gtk::ListItem>().unwrap();
 
+            let child = list_item.child();
+            obj.imp().song_tiles.borrow_mut().retain(|tile| {
+                tile.upgrade()
+                    .is_some_and(|tile| child.as_ref() != Some(tile.upcast_ref()))
+            });
+
             unsafe {
                 let bindings = list_item
                     .steal_data::<Vec<glib::Binding>>(GRID_LIST_ITEM_BINDINGS_KEY)
@@ -814,12 +1356,84 @@ impl HistoryView {
                     watch.unwatch();
                 }
             }
+        }));
+
+        let imp = self.imp();
+        imp.grid.set_factory(Some(&factory));
+
+        let vadjustment = imp.content_main_page.vadjustment();
+        vadjustment.connect_value_changed(clone!(@weak self as obj => move |_| {
+            obj.queue_update_tiles_view();
+        }));
+        vadjustment.connect_page_size_notify(clone!(@weak self as obj => move |_| {
+            obj.queue_update_tiles_view();
+        }));
+        self.connect_overscan_rows_notify(|obj| {
+            obj.queue_update_tiles_view();
         });
+    }
 
-        self.imp().grid.set_factory(Some(&factory));
+    /// Updates which tiles are in view once the grid is laid out, as tiles are
+    /// only moved on the layout after scrolling.
+    fn queue_update_tiles_view(&self) {
+        let imp = self.imp();
+
+        if imp.is_tile_view_update_queued.get() {
+            return;
+        }
+
+        imp.is_tile_view_update_queued.set(true);
+        glib::idle_add_local_once(clone!(@weak self as obj => move || {
+            obj.imp().is_tile_view_update_queued.set(false);
+            obj.update_tiles_view();
+        }));
+    }
+
+    fn update_tiles_view(&self) {
+        let imp = self.imp();
+
+        let overscan_rows = self.overscan_rows();
+        let viewport_height = imp.content_main_page.vadjustment().page_size();
+        let highlighted_song = imp.highlighted_song.borrow();
+
+        for tile in imp
+            .song_tiles
+            .borrow()
+            .iter()
+            .filter_map(|tile| tile.upgrade())
+        {
+            // Bounds are relative to the scrolled window, so they are already
+            // offset by the scroll position.
+            let is_in_view = tile
+                .compute_bounds(&*imp.content_main_page)
+                .is_some_and(|bounds| {
+                    is_within_overscan(
+                        bounds.y() as f64,
+                        bounds.height() as f64,
+                        viewport_height,
+                        overscan_rows,
+                    )
+                });
+            tile.set_is_out_of_view(!is_in_view);
+
+            // Recycled tiles may have shown the highlighted song before
+            if highlighted_song.is_some() && tile.song() == *highlighted_song {
+                tile.add_css_class("highlighted");
+            } else {
+                tile.remove_css_class("highlighted");
+            }
+        }
     }
 }
 
+/// Returns whether a tile at `top` with `height`, relative to the top of a
+/// viewport with `viewport_height`, is within the viewport or `overscan_rows`
+/// rows of the tile's height beyond it.
+fn is_within_overscan(top: f64, height: f64, viewport_height: f64, overscan_rows: u32) -> bool {
+    let overscan = height * overscan_rows as f64;
+    top + height > -overscan && top < viewport_height + overscan
+}
+
 fn unbind_page(page: &adw::NavigationPage) {
     if let Some(song_page) = page.downcast_ref::<SongPage>() {
         unsafe {
@@ -832,6 +1446,11 @@ fn unbind_page(page: &adw::NavigationPage) {
                 .steal_data::<glib::Binding>(SONG_PAGE_ADAPTIVE_MODE_BINDING_KEY)
                 .unwrap();
             binding.unbind();
+
+            let binding = song_page
+                .steal_data::<glib::Binding>(SONG_PAGE_SHOWS_ALBUM_ART_ACCENT_BINDING_KEY)
+                .unwrap();
+            binding.unbind();
         }
         song_page.unbind_player();
         song_page.unbind_song_list();
@@ -848,6 +1467,13 @@ fn unbind_page(page: &adw::NavigationPage) {
             adaptive_mode_binding.unbind();
         }
         recognized_page.unbind_player();
+    } else if let Some(artists_page) = page.downcast_ref::<ArtistsPage>() {
+        unsafe {
+            let song_activated_handler_id = artists_page
+                .steal_data::<glib::SignalHandlerId>(ARTISTS_PAGE_SONG_ACTIVATED_HANDLER_ID_KEY)
+                .unwrap();
+            artists_page.disconnect(song_activated_handler_id);
+        }
     } else {
         unreachable!(
             "tried to unbind unknown navigation page type `{}`",
@@ -866,20 +1492,7 @@ impl Default for HistoryView {
 mod test {
     use super::*;
 
-    use gtk::gio;
-
-    use std::sync::Once;
-
-    use crate::{database, RESOURCES_FILE};
-
-    static GRESOURCES_INIT: Once = Once::new();
-
-    fn init_gresources() {
-        GRESOURCES_INIT.call_once(|| {
-            let res = gio::Resource::load(RESOURCES_FILE).unwrap();
-            gio::resources_register(&res);
-        });
-    }
+    use crate::{database, date_time::DateTime, window::init_gresources};
 
     fn new_test_song(id: &str) -> Song {
         Song::builder(&Uid::from(id), id, id, id).build()
@@ -1000,6 +1613,56 @@ mod test {
         assert!(view.is_on_navigation_main_page());
     }
 
+    #[gtk::test]
+    fn new_song_position() {
+        init_gresources();
+        gst::init().unwrap(); // For Player
+
+        let player = Player::new();
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let old_song = new_test_song("old");
+        old_song.set_last_heard(Some(
+            DateTime::from_iso8601("2022-01-01T00:00:00Z").unwrap(),
+        ));
+        let new_song = new_test_song("new");
+        new_song.set_last_heard(Some(
+            DateTime::from_iso8601("2023-01-01T00:00:00Z").unwrap(),
+        ));
+        song_list
+            .insert_many(vec![old_song.clone(), new_song.clone()])
+            .unwrap();
+
+        let view = HistoryView::new();
+        view.bind_player(&player);
+        view.bind_song_list(&song_list);
+
+        // Most recently heard first
+        assert_eq!(view.new_song_position(&new_song, false), Some(0));
+        assert_eq!(view.new_song_position(&old_song, false), Some(1));
+
+        // Typed searches are applied after a delay
+        view.set_search_text("old");
+        view.imp()
+            .search_entry
+            .emit_by_name::<()>("search-changed", &[]);
+        assert_eq!(view.new_song_position(&new_song, false), None);
+        assert_eq!(view.search_text(), "old");
+
+        assert_eq!(view.new_song_position(&new_song, true), Some(0));
+        assert!(view.search_text().is_empty());
+
+        old_song.set_is_favorite(true);
+        view.imp().search_bar.set_search_mode(true);
+        view.imp().favorites_only_button.set_active(true);
+        assert_eq!(view.new_song_position(&old_song, false), Some(0));
+        assert_eq!(view.new_song_position(&new_song, false), None);
+
+        assert_eq!(view.new_song_position(&new_song, true), Some(0));
+        assert!(!view.imp().favorites_only_button.is_active());
+    }
+
     #[gtk::test]
     fn navigation_pop_and_push() {
         init_gresources();
@@ -1251,4 +1914,29 @@ mod test {
         view.push_recognized_page(&[]);
         view.push_recognized_page(&[]);
     }
+
+    #[test]
+    fn overscan() {
+        const TILE_HEIGHT: f64 = 200.0;
+        const VIEWPORT_HEIGHT: f64 = 600.0;
+        const SCROLL_POSITION: f64 = 1000.0;
+
+        let n_rows_in_view = |overscan_rows| {
+            (0..10_000)
+                .map(|row| row as f64 * TILE_HEIGHT - SCROLL_POSITION)
+                .filter(|&top| is_within_overscan(top, TILE_HEIGHT, VIEWPORT_HEIGHT, overscan_rows))
+                .count()
+        };
+
+        // Only the visible rows and the over-scan on both sides load covers,
+        // regardless of the size of the history
+        assert_eq!(n_rows_in_view(0), 3);
+        assert_eq!(n_rows_in_view(2), 3 + 2 * 2);
+
+        // Partially visible rows are in view
+        assert!(is_within_overscan(-199.0, TILE_HEIGHT, VIEWPORT_HEIGHT, 0));
+        assert!(!is_within_overscan(-200.0, TILE_HEIGHT, VIEWPORT_HEIGHT, 0));
+        assert!(is_within_overscan(599.0, TILE_HEIGHT, VIEWPORT_HEIGHT, 0));
+        assert!(!is_within_overscan(600.0, TILE_HEIGHT, VIEWPORT_HEIGHT, 0));
+    }
 }
@@ -1,10 +1,13 @@
 mod album_cover;
+mod artists_page;
 mod crossfade_paintable;
 mod external_link_tile;
+mod favorites_strip;
 mod history_view;
 mod information_row;
 mod playback_button;
 mod progress_icon;
+mod quick_jump;
 mod recognized_page;
 mod recognized_page_tile;
 mod recognizer_status;
@@ -15,26 +18,79 @@ mod song_tile;
 mod waveform;
 
 use adw::{prelude::*, subclass::prelude::*};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use gettextrs::gettext;
 use gtk::{
     gdk, gio,
     glib::{self, clone},
 };
 
-use std::cell::OnceCell;
+use std::{
+    cell::{Cell, OnceCell, RefCell},
+    rc::Rc,
+    time::Duration,
+};
 
-use self::{history_view::HistoryView, recognizer_view::RecognizerView, song_bar::SongBar};
+use self::{
+    album_cover::AlbumCover, history_view::HistoryView, recognizer_view::RecognizerView,
+    song_bar::SongBar,
+};
 use crate::{
+    audio_device::{self, AudioDeviceClass},
+    cancelled::Cancelled,
     config::PROFILE,
-    player::{Player, PlayerState},
+    database,
+    date_time::DateTime,
+    diagnostics,
+    export::{self, ExportFormat},
+    i18n::{gettext_f, ngettext_f},
+    import::{self, ImportFormat},
+    link_resolver::{self, DEFAULT_RESOLVE_INTERVAL},
+    mic_permission,
+    player::{PlaybackHold, Player, PlayerState},
     preferences_window::PreferencesWindow,
-    recognizer::{RecognizeError, RecognizeErrorKind, Recognizer, RecognizerState, Recordings},
+    recognizer::{
+        self, Candidates, RecognitionFailure, RecognitionFailureKind, RecognizeError,
+        RecognizeErrorKind, RecognizeMode, RecognizeOutcome, Recognizer, RecognizerState,
+        RecordError, Recordings,
+    },
+    ring_recorder::RingRecorder,
+    sample_store::SampleTags,
+    settings::{PreferredAudioSource, StartupAction},
     song::Song,
     song_list::SongList,
-    Application,
+    sound_cue::{SoundCue, SoundCuePlayer},
+    sound_trigger::SoundTrigger,
+    uid::Uid,
+    utils, Application,
 };
 
+const CANDIDATE_COVER_PIXEL_SIZE: i32 = 48;
+
+/// What the window shows or does once presented at startup
+#[derive(Debug, PartialEq, Eq)]
+enum StartupDispatch {
+    ShowHistory,
+    RestoreView {
+        search: String,
+        song_id: Option<Uid>,
+    },
+    StartRecognizing,
+}
+
+impl StartupDispatch {
+    fn new(action: StartupAction, last_search: &str, last_shown_song: &str) -> Self {
+        match action {
+            StartupAction::ShowHistory => Self::ShowHistory,
+            StartupAction::RestoreLastView => Self::RestoreView {
+                search: last_search.to_string(),
+                song_id: (!last_shown_song.is_empty()).then(|| Uid::from(last_shown_song)),
+            },
+            StartupAction::StartRecognizing => Self::StartRecognizing,
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, glib::Enum)]
 #[enum_type(name = "MsaiAdaptiveMode")]
 pub enum AdaptiveMode {
@@ -64,7 +120,17 @@ mod imp {
 
         pub(super) player: Player,
         pub(super) recognizer: Recognizer,
+        pub(super) sound_cue_player: SoundCuePlayer,
+        pub(super) sound_trigger: SoundTrigger,
+        pub(super) ring_recorder: RingRecorder,
         pub(super) song_history: OnceCell<SongList>,
+        pub(super) re_resolve_cancellable: RefCell<Option<gio::Cancellable>>,
+        /// Song whose metadata is replaced by the next recognized song
+        pub(super) rerecognize_target: RefCell<Option<Uid>>,
+        /// Keeps the playback paused while recording the desktop audio
+        pub(super) playback_hold: RefCell<Option<PlaybackHold>>,
+        pub(super) is_write_failed_message_shown: Cell<bool>,
+        pub(super) narrow_breakpoint: OnceCell<adw::Breakpoint>,
     }
 
     #[glib::object_subclass]
@@ -88,21 +154,72 @@ mod imp {
             });
 
             klass.install_action_async("win.toggle-recognize", None, |obj, _, _| async move {
-                let imp = obj.imp();
-
-                imp.player.set_song(Song::NONE);
+                obj.toggle_recognize(RecognizeMode::Music).await;
+            });
 
-                if let Err(err) = imp.recognizer.toggle_recognize().await {
-                    tracing::error!("{:?} (dbg: {:#?})", err, err);
+            klass.install_action_async(
+                "win.toggle-hum-recognize",
+                None,
+                |obj, _, _| async move {
+                    obj.toggle_recognize(RecognizeMode::Hum).await;
+                },
+            );
+
+            klass.install_action_async(
+                "win.recognize-clipboard-link",
+                None,
+                |obj, _, _| async move {
+                    obj.action_set_enabled("win.recognize-clipboard-link", false);
+                    obj.recognize_clipboard_link().await;
+                    obj.action_set_enabled("win.recognize-clipboard-link", true);
+                },
+            );
+
+            klass.install_action_async("win.recognize-ring", None, |obj, _, _| async move {
+                obj.action_set_enabled("win.recognize-ring", false);
+                obj.recognize_ring().await;
+                obj.update_ring_recorder();
+            });
 
-                    if let Some(recognize_error) = err.downcast_ref::<RecognizeError>() {
-                        obj.present_recognize_error(recognize_error);
-                    } else {
-                        obj.add_message_toast(&err.to_string());
-                    }
+            klass.install_action("win.play-last-recording", None, |obj, _, _| {
+                if let Err(err) = obj.imp().recognizer.play_last_recording() {
+                    tracing::warn!("Failed to play last recording: {:?}", err);
+                    obj.add_message_toast(&gettext("Failed to play last recording"));
                 }
             });
 
+            klass.install_action_async(
+                "win.re-resolve-external-links",
+                None,
+                |obj, _, _| async move {
+                    obj.re_resolve_external_links().await;
+                },
+            );
+
+            klass.install_action_async("win.import-history", None, |obj, _, _| async move {
+                obj.import_history().await;
+            });
+
+            klass.install_action_async("win.merge-duplicates", None, |obj, _, _| async move {
+                obj.merge_duplicates().await;
+            });
+
+            klass.install_action_async("win.show-stats", None, |obj, _, _| async move {
+                obj.action_set_enabled("win.show-stats", false);
+                obj.show_stats().await;
+                obj.action_set_enabled("win.show-stats", true);
+            });
+
+            klass.install_action_async("win.check-database", None, |obj, _, _| async move {
+                obj.action_set_enabled("win.check-database", false);
+                obj.check_database().await;
+                obj.action_set_enabled("win.check-database", true);
+            });
+
+            klass.install_action("win.optimize-database", None, |obj, _, _| {
+                obj.optimize_database();
+            });
+
             klass.install_action("win.toggle-search", None, |obj, _, _| {
                 let search_bar = obj.imp().main_view.search_bar();
                 search_bar.set_search_mode(!search_bar.is_search_mode());
@@ -131,6 +248,70 @@ mod imp {
                 .search_bar()
                 .set_key_capture_widget(Some(obj.as_ref()));
 
+            let settings = Application::get().settings();
+            let narrow_breakpoint =
+                new_narrow_breakpoint(settings.narrow_breakpoint(), &self.main_view);
+            obj.add_breakpoint(narrow_breakpoint.clone());
+            self.narrow_breakpoint.set(narrow_breakpoint).unwrap();
+            settings.connect_narrow_breakpoint_changed(clone!(@weak obj => move |_| {
+                obj.update_narrow_breakpoint();
+            }));
+            settings.connect_narrow_on_small_screens_only_changed(clone!(@weak obj => move |_| {
+                obj.update_narrow_breakpoint();
+            }));
+            obj.connect_realize(|obj| {
+                obj.update_narrow_breakpoint();
+                obj.surface()
+                    .connect_enter_monitor(clone!(@weak obj => move |_, _| {
+                        obj.update_narrow_breakpoint();
+                    }));
+            });
+
+            Application::get()
+                .settings()
+                .bind_large_tiles(&self.main_view.get(), "is-large-tiles")
+                .get_only()
+                .build();
+            Application::get()
+                .settings()
+                .bind_history_overscan_rows(&self.main_view.get(), "overscan-rows")
+                .get_only()
+                .build();
+            Application::get()
+                .settings()
+                .bind_album_art_accent(&self.main_view.get(), "shows-album-art-accent")
+                .get_only()
+                .build();
+            Application::get()
+                .settings()
+                .bind_search_lyrics(&self.main_view.get(), "search-lyrics")
+                .get_only()
+                .build();
+            Application::get()
+                .settings()
+                .bind_normalize_loudness(&self.player, "normalizes-loudness")
+                .get_only()
+                .build();
+            Application::get()
+                .settings()
+                .bind_offline_mode(&self.recognizer, "is-forced-offline")
+                .get_only()
+                .build();
+            Application::get()
+                .settings()
+                .bind_recognize_cooldown(&self.recognizer, "cooldown-duration")
+                .get_only()
+                .build();
+            Application::get()
+                .settings()
+                .bind_crossfade_duration(&self.player, "crossfade-duration")
+                .get_only()
+                .build();
+            Application::get()
+                .settings()
+                .bind_loop_previews(&self.player, "loops")
+                .build();
+
             obj.setup_signals();
 
             obj.load_window_size();
@@ -151,6 +332,12 @@ mod imp {
                 tracing::warn!("Failed to save window state, {:?}", &err);
             }
 
+            if let Err(err) = obj.save_last_view() {
+                tracing::warn!("Failed to save last view, {:?}", &err);
+            }
+
+            self.recognizer.clear_last_recording();
+
             self.parent_close_request()
         }
     }
@@ -180,6 +367,17 @@ impl Window {
             .expect("song history must be bound only once");
 
         imp.main_view.bind_song_list(song_history);
+        song_history.connect_write_failed(clone!(@weak self as obj => move |_, message| {
+            obj.present_write_failed_message(message);
+        }));
+
+        let settings = Application::get().settings();
+        song_history.set_distinguishes_explicit(settings.distinguish_explicit_songs());
+        settings.connect_distinguish_explicit_songs_changed(
+            clone!(@weak song_history => move |settings| {
+                song_history.set_distinguishes_explicit(settings.distinguish_explicit_songs());
+            }),
+        );
         imp.recognizer.bind_saved_recordings(recordings);
 
         // Recognizer must have saved recordings first
@@ -189,35 +387,505 @@ impl Window {
         imp.recognizer
             .connect_state_notify(clone!(@weak self as obj => move |_| {
                 obj.update_stack();
+                obj.update_recognize_actions();
+                obj.update_play_last_recording_action();
+                obj.update_sound_trigger();
+                obj.update_ring_recorder();
             }));
-        imp.recognizer
-            .connect_song_recognized(clone!(@weak self as obj => move |_, song| {
-                let history = obj.song_history();
-
-                // If the song is not found in the history, set it as newly heard
-                // (That's why an always true value is used after `or`). If it is in the
-                // history and it was newly heard, pass that state to the new value.
-                if history
-                    .get(song.id_ref())
-                    .map_or(true, |prev| prev.is_newly_heard())
-                {
-                    song.set_is_newly_heard(true);
-                }
+        imp.recognizer.connect_is_recording_desktop_audio_notify(
+            clone!(@weak self as obj => move |recognizer| {
+                let imp = obj.imp();
 
-                if let Err(err) = history.insert(song.clone()) {
-                    tracing::error!("Failed to insert song to history: {:?}", err);
-                    obj.add_message_toast(&gettext("Failed to insert song to history"));
-                    return;
+                if recognizer.is_recording_desktop_audio()
+                    && Application::get()
+                        .settings()
+                        .pause_playback_while_recording_desktop_audio()
+                {
+                    imp.playback_hold.replace(Some(imp.player.hold()));
+                } else {
+                    imp.playback_hold.take();
                 }
+            }),
+        );
 
-                let main_view = obj.imp().main_view.get();
-                main_view.push_song_page(song);
-                main_view.scroll_to_top();
-            }));
+        self.update_recognize_actions();
+        self.update_play_last_recording_action();
+        self.update_sound_trigger();
+        self.update_ring_recorder();
+        imp.recognizer.connect_song_recognized(
+            clone!(@weak self as obj => move |_, song, recording_bytes| {
+                let song = song.clone();
+                let recording_bytes = recording_bytes.clone();
+                utils::spawn(glib::Priority::default(), async move {
+                    obj.add_recognized_song(&song, Some(&recording_bytes)).await;
+                });
+            }),
+        );
+        imp.recognizer.connect_candidates_recognized(
+            clone!(@weak self as obj => move |_, candidates, recording_bytes| {
+                let candidates = candidates.clone();
+                let recording_bytes = recording_bytes.clone();
+                utils::spawn(glib::Priority::default(), async move {
+                    let song = obj.choose_candidate(candidates).await;
+                    obj.add_recognized_song(&song, Some(&recording_bytes)).await;
+                });
+            }),
+        );
         imp.recognizer
             .connect_recording_saved(clone!(@weak self as obj => move |_, cause| {
                 obj.present_recording_saved_message(cause);
             }));
+        imp.recognizer
+            .connect_recognize_ignored(clone!(@weak self as obj => move |_| {
+                obj.error_bell();
+            }));
+        imp.recognizer
+            .connect_recording_clipped(clone!(@weak self as obj => move |_| {
+                let message = if Application::get().settings().recording_gain() > 0.0 {
+                    gettext("The input is too loud. Lower the input boost for better results")
+                } else {
+                    gettext("The input is too loud. Lower the input volume for better results")
+                };
+                obj.add_message_toast(&message);
+            }));
+    }
+
+    /// Disables recognizing modes that no enabled provider supports.
+    fn update_recognize_actions(&self) {
+        let capabilities = self.imp().recognizer.capabilities();
+        self.action_set_enabled(
+            "win.toggle-recognize",
+            capabilities.supports(RecognizeMode::Music),
+        );
+        self.action_set_enabled(
+            "win.toggle-hum-recognize",
+            capabilities.supports(RecognizeMode::Hum),
+        );
+    }
+
+    fn update_play_last_recording_action(&self) {
+        let recognizer = &self.imp().recognizer;
+        self.action_set_enabled(
+            "win.play-last-recording",
+            recognizer.state() == RecognizerState::Null && recognizer.has_last_recording(),
+        );
+    }
+
+    /// Starts recognizing in the given mode, unless already recognizing or
+    /// the mode is not supported.
+    pub fn start_recognize(&self, mode: RecognizeMode) {
+        if self.imp().recognizer.state() != RecognizerState::Null {
+            tracing::debug!("Already recognizing, ignoring request to start");
+            return;
+        }
+
+        let action_name = match mode {
+            RecognizeMode::Music => "win.toggle-recognize",
+            RecognizeMode::Hum => "win.toggle-hum-recognize",
+        };
+        if let Err(err) = WidgetExt::activate_action(self, action_name, None) {
+            tracing::debug!("Failed to activate `{}`: {:?}", action_name, err);
+        }
+    }
+
+    /// Does what the user chose to do on launch. This must only be called
+    /// once, after the window is first presented.
+    pub fn run_startup_action(&self) {
+        let settings = Application::get().settings();
+        let dispatch = StartupDispatch::new(
+            settings.startup_action(),
+            &settings.last_search(),
+            &settings.last_shown_song(),
+        );
+        tracing::debug!(?dispatch, "Running startup action");
+
+        match dispatch {
+            StartupDispatch::ShowHistory => {}
+            StartupDispatch::RestoreView { search, song_id } => {
+                let main_view = self.imp().main_view.get();
+                main_view.set_search_text(&search);
+
+                if let Some(song_id) = song_id {
+                    match self.song_history().get(&song_id) {
+                        Some(song) => main_view.push_song_page(&song),
+                        None => tracing::debug!(?song_id, "Last shown song is not in history"),
+                    }
+                }
+            }
+            // This goes through the same permission and device checks as
+            // starting from the button.
+            StartupDispatch::StartRecognizing => self.start_recognize(RecognizeMode::Music),
+        }
+    }
+
+    /// Listens for the sound trigger only while enabled and idle, so it
+    /// doesn't compete with the recording for the device.
+    fn update_sound_trigger(&self) {
+        let imp = self.imp();
+        let settings = Application::get().settings();
+
+        if !settings.sound_trigger() || imp.recognizer.state() != RecognizerState::Null {
+            imp.sound_trigger.stop();
+            return;
+        }
+
+        if imp.sound_trigger.is_active() {
+            return;
+        }
+
+        let res = imp.sound_trigger.start(
+            settings.sound_trigger_threshold(),
+            clone!(@weak self as obj => move || {
+                if let Err(err) = WidgetExt::activate_action(&obj, "win.toggle-recognize", None) {
+                    tracing::debug!("Failed to activate recognize on trigger: {:?}", err);
+                }
+            }),
+        );
+
+        if let Err(err) = res {
+            tracing::error!("Failed to listen for sound trigger: {:?}", err);
+            self.add_message_toast(&gettext("Failed to listen for sound trigger"));
+        }
+    }
+
+    /// Whether the last seconds should be kept, which is only while enabled
+    /// and idle, as the recording already has the device otherwise
+    fn should_ring_record(&self) -> bool {
+        Application::get().settings().ring_recording()
+            && self.imp().recognizer.state() == RecognizerState::Null
+    }
+
+    fn update_ring_recorder(&self) {
+        let imp = self.imp();
+
+        if !self.should_ring_record() {
+            imp.ring_recorder.stop();
+        } else if !imp.ring_recorder.is_active() {
+            utils::spawn(
+                glib::Priority::default(),
+                clone!(@weak self as obj => async move {
+                    obj.start_ring_recorder().await;
+                }),
+            );
+        }
+
+        self.action_set_enabled("win.recognize-ring", imp.ring_recorder.is_active());
+    }
+
+    /// Records the same device as recognizing would, so permission and busy
+    /// errors are reported the same way.
+    async fn start_ring_recorder(&self) {
+        let imp = self.imp();
+        let settings = Application::get().settings();
+
+        let res = audio_device::find_default_name(match settings.preferred_audio_source() {
+            PreferredAudioSource::Microphone => AudioDeviceClass::Source,
+            PreferredAudioSource::DesktopAudio => AudioDeviceClass::Sink,
+        })
+        .await;
+
+        // It may have changed or already been started while finding the device
+        if !self.should_ring_record() || imp.ring_recorder.is_active() {
+            return;
+        }
+
+        let res = res
+            .context("Failed to find default device name")
+            .and_then(|device_name| {
+                imp.ring_recorder.start(
+                    Some(&device_name),
+                    settings.recording_gain(),
+                    Duration::from_secs(settings.ring_recording_duration().into()),
+                )
+            });
+
+        if let Err(err) = res {
+            tracing::error!("Failed to keep listening: {:?}", err);
+            let err = RecognitionFailure::from(err.context(gettext("Failed to keep listening")));
+            self.add_recognition_failure_toast(&err, None);
+        }
+
+        self.action_set_enabled("win.recognize-ring", imp.ring_recorder.is_active());
+    }
+
+    async fn recognize_ring(&self) {
+        let imp = self.imp();
+
+        let Some(bytes) = imp.ring_recorder.snapshot() else {
+            self.add_message_toast(&gettext("Nothing was heard yet"));
+            return;
+        };
+
+        let heard_time = DateTime::now_utc();
+        match imp.recognizer.recognize_sample(&bytes).await {
+            Ok(song) => {
+                song.set_last_heard(Some(heard_time));
+                self.add_recognized_song(&song, None).await;
+            }
+            Err(err) => {
+                tracing::error!("Failed to recognize last seconds: {:?}", err.inner());
+                self.add_recognition_failure_toast(&err, None);
+            }
+        }
+    }
+
+    /// Adds the song to the history and shows it. If given, `sample` is
+    /// saved as the sample of the song that it ends up in, before showing it.
+    async fn add_recognized_song(&self, song: &Song, sample: Option<&glib::Bytes>) {
+        if let Some(target_id) = self.imp().rerecognize_target.take() {
+            if let Some(sample) = sample {
+                save_recognized_sample(&target_id, song, sample).await;
+            }
+
+            self.replace_song_metadata(&target_id, song);
+            return;
+        }
+
+        let history = self.song_history();
+
+        let merged_song = if Application::get().settings().merge_recognized_songs() {
+            match history.merge_into_equivalent(song) {
+                Ok(merged_song) => merged_song,
+                Err(err) => {
+                    tracing::error!("Failed to merge song to history: {:?}", err);
+                    self.add_message_toast(&gettext("Failed to insert song to history"));
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+
+        // The merged song keeps its own newly heard state.
+        let song = if let Some(ref merged_song) = merged_song {
+            merged_song
+        } else {
+            // If the song is not found in the history, set it as newly heard
+            // (That's why an always true value is used after `or`). If it is in the
+            // history and it was newly heard, pass that state to the new value.
+            if history
+                .get(song.id_ref())
+                .map_or(true, |prev| prev.is_newly_heard())
+            {
+                song.set_is_newly_heard(true);
+            }
+
+            // Keep the favorite state of the song in the history.
+            if history
+                .get(song.id_ref())
+                .is_some_and(|prev| prev.is_favorite())
+            {
+                song.set_is_favorite(true);
+            }
+
+            if let Err(err) = history.insert(song.clone()) {
+                tracing::error!("Failed to insert song to history: {:?}", err);
+                self.add_message_toast(&gettext("Failed to insert song to history"));
+                return;
+            }
+
+            Application::get().enforce_max_history_size(history);
+
+            song
+        };
+
+        if let Some(sample) = sample {
+            save_recognized_sample(song.id_ref(), song, sample).await;
+        }
+
+        if let Some(album_art) = song.album_art() {
+            Application::get()
+                .album_art_store()
+                .prefetch(album_art.download_url());
+        }
+
+        let settings = Application::get().settings();
+        let main_view = self.imp().main_view.get();
+        if settings.reveal_new_songs() {
+            main_view.reveal_new_song(song, settings.clear_search_for_new_songs());
+        } else {
+            main_view.push_song_page(song);
+            main_view.scroll_to_top();
+        }
+    }
+
+    /// Recognizes the song again from its stored sample, or from a new
+    /// recording if there is none, then replaces its metadata with the
+    /// result.
+    pub async fn rerecognize_song(&self, song: &Song) {
+        const CANCEL_RESPONSE_ID: &str = "cancel";
+        const RECORD_RESPONSE_ID: &str = "record";
+
+        let imp = self.imp();
+
+        let sample_path = Application::get().sample_store().path(song.id_ref());
+        let sample_bytes = match gio::File::for_path(&sample_path)
+            .load_contents_future()
+            .await
+        {
+            Ok((bytes, _)) => Some(bytes),
+            Err(err) if err.matches(gio::IOErrorEnum::NotFound) => None,
+            Err(err) => {
+                tracing::error!("Failed to load sample: {:?}", err);
+                self.add_message_toast(&gettext("Failed to load recorded sample"));
+                return;
+            }
+        };
+
+        let Some(sample_bytes) = sample_bytes else {
+            let dialog = adw::MessageDialog::builder()
+                .transient_for(self)
+                .modal(true)
+                .heading(gettext("Record Again?"))
+                .body(gettext(
+                    "There is no recorded sample of this song. Play it again to recognize it from a new recording.",
+                ))
+                .default_response(RECORD_RESPONSE_ID)
+                .close_response(CANCEL_RESPONSE_ID)
+                .build();
+            dialog.add_response(CANCEL_RESPONSE_ID, &gettext("_Cancel"));
+            dialog.add_response(RECORD_RESPONSE_ID, &gettext("_Record"));
+            dialog.set_response_appearance(RECORD_RESPONSE_ID, adw::ResponseAppearance::Suggested);
+
+            if dialog.choose_future().await != RECORD_RESPONSE_ID {
+                return;
+            }
+
+            if imp.recognizer.state() != RecognizerState::Null {
+                self.add_message_toast(&gettext("Already recognizing a song"));
+                return;
+            }
+
+            imp.rerecognize_target.replace(Some(song.id()));
+            self.toggle_recognize(RecognizeMode::Music).await;
+            return;
+        };
+
+        match imp.recognizer.recognize_sample(&sample_bytes).await {
+            Ok(recognized) => self.replace_song_metadata(song.id_ref(), &recognized),
+            Err(err) => {
+                tracing::error!("Failed to re-recognize song: {:?}", err.inner());
+                self.add_recognition_failure_toast(&err, None);
+            }
+        }
+    }
+
+    /// Recognizes the audio at the link in the clipboard, e.g., a stream
+    /// playing elsewhere.
+    async fn recognize_clipboard_link(&self) {
+        let text = match self.display().clipboard().read_text_future().await {
+            Ok(Some(text)) => text,
+            Ok(None) => {
+                self.add_message_toast(&gettext("There is no link in the clipboard"));
+                return;
+            }
+            Err(err) => {
+                tracing::warn!("Failed to read clipboard: {:?}", err);
+                self.add_message_toast(&gettext("There is no link in the clipboard"));
+                return;
+            }
+        };
+
+        let format = Application::get().settings().recording_format();
+        match self
+            .imp()
+            .recognizer
+            .recognize_uri(text.trim(), format)
+            .await
+        {
+            Ok(song) => self.add_recognized_song(&song, None).await,
+            Err(err) => {
+                tracing::error!("Failed to recognize link: {:?}", err.inner());
+                self.add_recognition_failure_toast(&err, None);
+            }
+        }
+    }
+
+    fn replace_song_metadata(&self, song_id: &Uid, recognized: &Song) {
+        match self.song_history().replace_metadata(song_id, recognized) {
+            Ok(updated) => {
+                if let Some(album_art) = updated.album_art() {
+                    Application::get()
+                        .album_art_store()
+                        .prefetch(album_art.download_url());
+                }
+
+                self.add_message_toast(&gettext("Updated song info"));
+                self.imp().main_view.push_song_page(&updated);
+            }
+            Err(err) => {
+                tracing::error!("Failed to replace song metadata: {:?}", err);
+                self.add_message_toast(&gettext("Failed to update song info"));
+            }
+        }
+    }
+
+    /// Lets the user choose which of the candidates to keep, discarding the
+    /// rest. This returns the top match if the dialog is dismissed.
+    async fn choose_candidate(&self, candidates: Candidates) -> Song {
+        const USE_TOP_RESPONSE_ID: &str = "use-top";
+
+        let list_box = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .css_classes(["boxed-list"])
+            .build();
+        for song in candidates.as_slice() {
+            let row = adw::ActionRow::builder()
+                .title(glib::markup_escape_text(&song.title()))
+                .subtitle(glib::markup_escape_text(&song.artist()))
+                .activatable(true)
+                .build();
+
+            let cover = AlbumCover::new();
+            cover.set_pixel_size(CANDIDATE_COVER_PIXEL_SIZE);
+            cover.set_valign(gtk::Align::Center);
+            cover.set_song(Some(song));
+            row.add_prefix(&cover);
+
+            list_box.append(&row);
+        }
+
+        let dialog = adw::MessageDialog::builder()
+            .transient_for(self)
+            .modal(true)
+            .heading(gettext("Multiple Matches Found"))
+            .body(gettext("Choose the song that was playing"))
+            .extra_child(&list_box)
+            .default_response(USE_TOP_RESPONSE_ID)
+            .close_response(USE_TOP_RESPONSE_ID)
+            .build();
+        dialog.add_response(USE_TOP_RESPONSE_ID, &gettext("_Use Best Match"));
+
+        let chosen_index = Rc::new(Cell::new(0));
+        list_box.connect_row_activated(
+            clone!(@weak dialog, @strong chosen_index => move |_, row| {
+                chosen_index.set(row.index() as usize);
+                dialog.close();
+            }),
+        );
+
+        dialog.choose_future().await;
+
+        let index = chosen_index.get();
+        tracing::debug!(index, "Chose from multiple candidates");
+        candidates
+            .clone()
+            .choose(index)
+            .unwrap_or_else(|| candidates.into_top())
+    }
+
+    /// Shows the song with the id in the history, e.g., when opening its
+    /// deep link.
+    pub fn reveal_song(&self, song_id: &Uid) {
+        self.present();
+
+        let Some(song) = self.song_history().get(song_id) else {
+            tracing::debug!(?song_id, "Song to reveal is not in history");
+            self.add_message_toast(&gettext("Song not found in history"));
+            return;
+        };
+
+        self.imp().main_view.reveal_song(&song);
     }
 
     pub fn add_toast(&self, toast: adw::Toast) {
@@ -229,6 +897,20 @@ impl Window {
         self.add_toast(toast);
     }
 
+    /// Copies a journal entry of the songs heard on the same day as `day`.
+    pub fn copy_journal_entry(&self, day: &DateTime) {
+        let songs = self.song_history().heard_on(day);
+        let template = Application::get().settings().journal_entry_template();
+
+        let Some(text) = export::journal_entry(&template, &songs) else {
+            self.add_message_toast(&gettext("No songs heard on that day"));
+            return;
+        };
+
+        self.display().clipboard().set_text(&text);
+        self.add_message_toast(&gettext("Copied journal entry to clipboard"));
+    }
+
     fn song_history(&self) -> &SongList {
         self.imp()
             .song_history
@@ -236,7 +918,525 @@ impl Window {
             .expect("song history must be bound")
     }
 
-    fn present_recognize_error(&self, err: &RecognizeError) {
+    async fn import_history(&self) {
+        let filter = gtk::FileFilter::new();
+        filter.set_name(Some(&gettext("Shazam, SoundHound, or Mousai Export")));
+        filter.add_suffix("csv");
+        filter.add_suffix("json");
+
+        let filters = gio::ListStore::new::<gtk::FileFilter>();
+        filters.append(&filter);
+
+        let dialog = gtk::FileDialog::builder()
+            .title(gettext("Import History"))
+            .modal(true)
+            .filters(&filters)
+            .build();
+
+        let file = match dialog.open_future(Some(self)).await {
+            Ok(file) => file,
+            Err(err) => {
+                tracing::debug!("Import file dialog closed: {:?}", err);
+                return;
+            }
+        };
+
+        match self.import_history_from_file(&file).await {
+            Ok((n_imported, n_skipped)) => {
+                self.add_message_toast(&ngettext_f(
+                    // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                    "Imported {n_imported} song",
+                    "Imported {n_imported} songs",
+                    n_imported,
+                    &[("n_imported", &n_imported.to_string())],
+                ));
+
+                if n_skipped > 0 {
+                    self.add_message_toast(&ngettext_f(
+                        // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                        "{n_skipped} entry could not be imported",
+                        "{n_skipped} entries could not be imported",
+                        n_skipped,
+                        &[("n_skipped", &n_skipped.to_string())],
+                    ));
+                }
+            }
+            Err(err) => {
+                tracing::error!("Failed to import history: {:?}", err);
+                self.add_message_toast(&gettext("Failed to import history"));
+            }
+        }
+    }
+
+    /// Returns the number of imported songs and skipped entries.
+    async fn import_history_from_file(&self, file: &gio::File) -> Result<(u32, u32)> {
+        let file_name = file
+            .basename()
+            .context("File has no name")?
+            .to_string_lossy()
+            .to_string();
+
+        let (bytes, _) = file
+            .load_contents_future()
+            .await
+            .context("Failed to load file")?;
+        let text = std::str::from_utf8(&bytes).context("File is not valid UTF-8")?;
+
+        let format = ImportFormat::detect(&file_name, text).context("Unsupported file type")?;
+
+        let parsed = import::parse(format, text)?;
+        for skipped in &parsed.skipped {
+            tracing::warn!(
+                position = skipped.position,
+                "Skipped import entry: {}",
+                skipped.reason
+            );
+        }
+
+        let song_history = self.song_history();
+        let reconciled = import::reconcile_with(song_history, parsed.songs)?;
+        tracing::debug!(
+            n_merged = reconciled.n_merged,
+            n_duplicates = reconciled.n_duplicates,
+            "Reconciled imported songs with history"
+        );
+
+        let n_imported = song_history
+            .insert_many(reconciled.new_songs)
+            .context("Failed to insert imported songs")?;
+
+        Application::get().enforce_max_history_size(song_history);
+
+        Ok((n_imported, parsed.skipped.len() as u32))
+    }
+
+    /// Asks where to save the `songs`, then exports them in the format
+    /// matching the chosen file name's extension.
+    pub async fn export_songs(&self, songs: Vec<Song>) {
+        if songs.is_empty() {
+            self.add_message_toast(&gettext("There are no songs to export"));
+            return;
+        }
+
+        let filters = gio::ListStore::new::<gtk::FileFilter>();
+        for (name, format) in [
+            (gettext("CSV"), ExportFormat::Csv),
+            (gettext("M3U Playlist"), ExportFormat::M3u),
+            (gettext("JSON"), ExportFormat::Json),
+        ] {
+            let filter = gtk::FileFilter::new();
+            filter.set_name(Some(&name));
+            filter.add_suffix(format.extension());
+            filters.append(&filter);
+        }
+
+        let dialog = gtk::FileDialog::builder()
+            .title(gettext("Export Songs"))
+            .modal(true)
+            .filters(&filters)
+            .initial_name(format!("mousai-history.{}", ExportFormat::Csv.extension()))
+            .build();
+
+        let file = match dialog.save_future(Some(self)).await {
+            Ok(file) => file,
+            Err(err) => {
+                tracing::debug!("Export file dialog closed: {:?}", err);
+                return;
+            }
+        };
+
+        match export_songs_to_file(&songs, &file).await {
+            Ok(()) => {
+                self.add_message_toast(&ngettext_f(
+                    // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                    "Exported {n_songs} song",
+                    "Exported {n_songs} songs",
+                    songs.len() as u32,
+                    &[("n_songs", &songs.len().to_string())],
+                ));
+            }
+            Err(err) => {
+                tracing::error!("Failed to export songs: {:?}", err);
+                self.add_message_toast(&gettext("Failed to export songs"));
+            }
+        }
+    }
+
+    /// Shows the songs that would be merged and only merges them once confirmed.
+    async fn merge_duplicates(&self) {
+        const CANCEL_RESPONSE_ID: &str = "cancel";
+        const MERGE_RESPONSE_ID: &str = "merge";
+
+        let song_history = self.song_history();
+
+        let plan = song_history.plan_dedup();
+        if plan.is_empty() {
+            self.add_message_toast(&gettext("No duplicate songs found"));
+            return;
+        }
+
+        let preview = plan
+            .groups
+            .iter()
+            .map(|group| {
+                ngettext_f(
+                    // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                    "Keep “{title}” by {artist} and merge {n_duplicates} duplicate",
+                    "Keep “{title}” by {artist} and merge {n_duplicates} duplicates",
+                    group.duplicates.len() as u32,
+                    &[
+                        ("title", &group.survivor.title()),
+                        ("artist", &group.survivor.artist()),
+                        ("n_duplicates", &group.duplicates.len().to_string()),
+                    ],
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let dialog = adw::MessageDialog::builder()
+            .transient_for(self)
+            .modal(true)
+            .heading(gettext("Merge Duplicate Songs?"))
+            .body(preview)
+            .default_response(CANCEL_RESPONSE_ID)
+            .close_response(CANCEL_RESPONSE_ID)
+            .build();
+        dialog.add_response(CANCEL_RESPONSE_ID, &gettext("_Cancel"));
+        dialog.add_response(MERGE_RESPONSE_ID, &gettext("_Merge"));
+        dialog.set_response_appearance(MERGE_RESPONSE_ID, adw::ResponseAppearance::Destructive);
+
+        if dialog.choose_future().await != MERGE_RESPONSE_ID {
+            return;
+        }
+
+        match song_history.apply_dedup(&plan) {
+            Ok(n_removed) => {
+                self.add_message_toast(&ngettext_f(
+                    // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                    "Merged {n_removed} duplicate song",
+                    "Merged {n_removed} duplicate songs",
+                    n_removed,
+                    &[("n_removed", &n_removed.to_string())],
+                ));
+            }
+            Err(err) => {
+                tracing::error!("Failed to merge duplicate songs: {:?}", err);
+                self.add_message_toast(&gettext("Failed to merge duplicate songs"));
+            }
+        }
+    }
+
+    async fn show_stats(&self) {
+        let env = Application::get().env().clone();
+        let stats = match gio::spawn_blocking(move || database::compute_stats(&env))
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to spawn blocking task: {:?}", err))
+            .and_then(|res| res)
+        {
+            Ok(stats) => stats,
+            Err(err) => {
+                tracing::error!("Failed to compute stats: {:?}", err);
+                self.add_message_toast(&gettext("Failed to compute statistics"));
+                return;
+            }
+        };
+
+        let window = stats_window(&stats);
+        window.set_transient_for(Some(self));
+        window.present();
+    }
+
+    async fn check_database(&self) {
+        const CLOSE_RESPONSE_ID: &str = "close";
+        const REPAIR_RESPONSE_ID: &str = "repair";
+
+        let app = Application::get();
+        let env = app.env().clone();
+        let databases = app.raw_databases();
+        let dbs = databases.clone();
+        let raw_entries = match gio::spawn_blocking(move || database::read_entries(&env, &dbs))
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to spawn blocking task: {:?}", err))
+            .and_then(|res| res)
+        {
+            Ok(raw_entries) => raw_entries,
+            Err(err) => {
+                tracing::error!("Failed to check database: {:?}", err);
+
+                let dialog = adw::MessageDialog::builder()
+                    .transient_for(self)
+                    .modal(true)
+                    .heading(gettext("Database Is Unreadable"))
+                    .body(gettext(
+                        "Some of your history may be lost. Consider backing up your data directory",
+                    ))
+                    .extra_child(&report_details_expander(&err))
+                    .build();
+                dialog.add_response(CLOSE_RESPONSE_ID, &gettext("Ok, Got It"));
+                dialog.set_default_response(Some(CLOSE_RESPONSE_ID));
+                dialog.present();
+                return;
+            }
+        };
+
+        let report = database::check_integrity(&raw_entries);
+
+        if report.is_ok() {
+            self.add_message_toast(&ngettext_f(
+                // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                "Database is intact, checked {n_entries} entry",
+                "Database is intact, checked {n_entries} entries",
+                report.n_checked as u32,
+                &[("n_entries", &report.n_checked.to_string())],
+            ));
+            return;
+        }
+
+        let details = report
+            .corrupt
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let dialog = adw::MessageDialog::builder()
+            .transient_for(self)
+            .modal(true)
+            .heading(gettext("Database Has Damaged Entries"))
+            .body(ngettext_f(
+                // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                "{n_corrupt} entry cannot be read. Repairing removes it from the database",
+                "{n_corrupt} entries cannot be read. Repairing removes them from the database",
+                report.corrupt.len() as u32,
+                &[("n_corrupt", &report.corrupt.len().to_string())],
+            ))
+            .extra_child(&report_details_expander(&anyhow::anyhow!("{}", details)))
+            .default_response(CLOSE_RESPONSE_ID)
+            .close_response(CLOSE_RESPONSE_ID)
+            .build();
+        dialog.add_response(CLOSE_RESPONSE_ID, &gettext("_Close"));
+        dialog.add_response(REPAIR_RESPONSE_ID, &gettext("_Repair"));
+        dialog.set_response_appearance(REPAIR_RESPONSE_ID, adw::ResponseAppearance::Destructive);
+
+        if dialog.choose_future().await != REPAIR_RESPONSE_ID {
+            return;
+        }
+
+        match database::repair_integrity(app.env(), &databases, &report) {
+            Ok(n_deleted) => {
+                tracing::info!("Removed {} corrupt database entries", n_deleted);
+                self.add_message_toast(&gettext("Database repaired"));
+            }
+            Err(err) => {
+                tracing::error!("Failed to repair database: {:?}", err);
+                self.add_message_toast(&gettext("Failed to repair database"));
+            }
+        }
+    }
+
+    /// Schedules the db to be compacted on next start, as it can't be while
+    /// it is open.
+    fn optimize_database(&self) {
+        let app = Application::get();
+        let env = app.env();
+
+        let res = env.real_disk_size().and_then(|real_disk_size| {
+            env.non_free_pages_size()
+                .map(|non_free_pages_size| real_disk_size.saturating_sub(non_free_pages_size))
+        });
+        let n_reclaimable = match res {
+            Ok(n_reclaimable) => n_reclaimable,
+            Err(err) => {
+                tracing::error!("Failed to get db size: {:?}", err);
+                self.add_message_toast(&gettext("Failed to get database size"));
+                return;
+            }
+        };
+        tracing::debug!(n_reclaimable, "Scheduled db compaction");
+
+        app.settings().set_database_compaction_pending(true);
+
+        self.add_message_toast(&gettext_f(
+            // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+            "The database will be optimized on next start, freeing about {size}",
+            &[("size", &glib::format_size(n_reclaimable))],
+        ));
+    }
+
+    /// Fills in missing external links of all songs in the history. If this is
+    /// already running, this cancels it instead.
+    async fn re_resolve_external_links(&self) {
+        let imp = self.imp();
+
+        if let Some(cancellable) = imp.re_resolve_cancellable.take() {
+            cancellable.cancel();
+            return;
+        }
+
+        if Application::get().settings().offline_mode() {
+            self.add_message_toast(&gettext("External links can't be updated in offline mode"));
+            return;
+        }
+
+        let cancellable = gio::Cancellable::new();
+        imp.re_resolve_cancellable.replace(Some(cancellable.clone()));
+
+        let toast = adw::Toast::builder()
+            .title(gettext("Updating External Links…"))
+            .button_label(gettext("Cancel"))
+            .timeout(0)
+            .build();
+        toast.connect_button_clicked(clone!(@weak cancellable => move |_| {
+            cancellable.cancel();
+        }));
+        self.add_toast(toast.clone());
+
+        let res = link_resolver::re_resolve_external_links(
+            self.song_history(),
+            &link_resolver::default_resolvers(),
+            DEFAULT_RESOLVE_INTERVAL,
+            &cancellable,
+            |n_processed, total| {
+                toast.set_title(&gettext_f(
+                    // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                    "Updating External Links ({n_processed}/{total})…",
+                    &[
+                        ("n_processed", &n_processed.to_string()),
+                        ("total", &total.to_string()),
+                    ],
+                ));
+            },
+        )
+        .await;
+
+        toast.dismiss();
+        imp.re_resolve_cancellable.replace(None);
+
+        match res {
+            Ok(summary) => {
+                self.add_message_toast(&ngettext_f(
+                    // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                    "Updated {n_updated} song",
+                    "Updated {n_updated} songs",
+                    summary.n_updated,
+                    &[("n_updated", &summary.n_updated.to_string())],
+                ));
+            }
+            Err(err) if err.is::<Cancelled>() => {
+                tracing::debug!("{:?}", err);
+            }
+            Err(err) => {
+                tracing::error!("Failed to update external links: {:?}", err);
+                self.add_message_toast(&gettext("Failed to update external links"));
+            }
+        }
+    }
+
+    async fn toggle_recognize(&self, mode: RecognizeMode) {
+        let imp = self.imp();
+
+        imp.player.set_song(Song::NONE);
+
+        let res = imp.recognizer.toggle_recognize(mode).await;
+
+        if let Some(cue) = SoundCue::for_result(&res) {
+            self.play_completion_cue(cue);
+        }
+
+        // A recognized song takes the re-recognized one's place when added,
+        // so the target is only left otherwise.
+        let rerecognize_target = if matches!(res, Ok(Some(RecognizeOutcome::Recognized))) {
+            None
+        } else {
+            imp.rerecognize_target.take()
+        };
+
+        if let Err(err) = res {
+            tracing::error!("{:?} (dbg: {:#?})", err.inner(), err.inner());
+
+            if let Some(placeholder) = recognizer::no_match_placeholder(
+                &err,
+                Application::get().settings().save_no_match_placeholders()
+                    && rerecognize_target.is_none(),
+            ) {
+                if let Err(err) = self.song_history().insert(placeholder) {
+                    tracing::error!("Failed to insert placeholder to history: {:?}", err);
+                    self.add_message_toast(&gettext("Failed to insert song to history"));
+                }
+            }
+
+            let retry_action_name = match mode {
+                RecognizeMode::Music => "win.toggle-recognize",
+                RecognizeMode::Hum => "win.toggle-hum-recognize",
+            };
+
+            // Non permanent provider errors, e.g., connection errors, are only
+            // returned when they can't be saved, and can be retried instead
+            if let Some(recognize_error) = err
+                .inner()
+                .downcast_ref::<RecognizeError>()
+                .filter(|recognize_error| recognize_error.is_permanent())
+            {
+                self.present_recognize_error(recognize_error, err.inner());
+            } else if err.kind() == RecognitionFailureKind::PermissionDenied {
+                self.present_mic_access_denied(retry_action_name);
+            } else {
+                self.add_recognition_failure_toast(&err, Some(retry_action_name));
+            }
+        }
+    }
+
+    /// Shows the error in a toast, with a button to resolve it if the kind of
+    /// error allows, e.g., retrying on network errors.
+    fn add_recognition_failure_toast(
+        &self,
+        err: &RecognitionFailure,
+        retry_action_name: Option<&str>,
+    ) {
+        let message = if let Some(record_error) = err.inner().downcast_ref::<RecordError>() {
+            record_error.to_string()
+        } else if let Some(recognize_error) = err.inner().downcast_ref::<RecognizeError>() {
+            recognize_error.to_string()
+        } else {
+            err.to_string()
+        };
+
+        let toast = adw::Toast::new(&message);
+
+        if err.is_retryable() {
+            if let Some(retry_action_name) = retry_action_name {
+                toast.set_button_label(Some(&gettext("_Retry")));
+                toast.set_action_name(Some(retry_action_name));
+            }
+        } else if err.kind() == RecognitionFailureKind::ProviderAuth {
+            toast.set_button_label(Some(&gettext("_Preferences")));
+            toast.set_action_name(Some("app.show-preferences"));
+        }
+
+        self.add_toast(toast);
+    }
+
+    fn play_completion_cue(&self, cue: SoundCue) {
+        let imp = self.imp();
+
+        if !Application::get().settings().completion_cue() {
+            return;
+        }
+
+        // Don't play over an active preview
+        if imp.player.state() == PlayerState::Playing {
+            tracing::debug!("Skipped completion cue as the player is playing");
+            return;
+        }
+
+        if let Err(err) = imp.sound_cue_player.play(cue) {
+            tracing::warn!("Failed to play completion cue: {:?}", err);
+        }
+    }
+
+    /// `full_err` is the error where `err` is from, which is used to build the report details.
+    fn present_recognize_error(&self, err: &RecognizeError, full_err: &anyhow::Error) {
         debug_assert!(
             err.is_permanent(),
             "non permanent errors must be saved instead"
@@ -246,6 +1446,7 @@ impl Window {
             .transient_for(self)
             .modal(true)
             .heading(err.title())
+            .extra_child(&report_details_expander(full_err))
             .build();
 
         match err.kind() {
@@ -303,14 +1504,21 @@ impl Window {
                     clone!(@weak self as obj => move |_, id| {
                         debug_assert_eq!(id, TRY_AGAIN_RESPONSE_ID);
 
-                        debug_assert_eq!(obj.imp().recognizer.state(), RecognizerState::Null);
-                        WidgetExt::activate_action(&obj, "win.toggle-recognize", None).unwrap();
+                        let recognizer = &obj.imp().recognizer;
+                        debug_assert_eq!(recognizer.state(), RecognizerState::Null);
+
+                        let action_name = match recognizer.mode() {
+                            RecognizeMode::Music => "win.toggle-recognize",
+                            RecognizeMode::Hum => "win.toggle-hum-recognize",
+                        };
+                        WidgetExt::activate_action(&obj, action_name, None).unwrap();
                     }),
                 );
             }
             RecognizeErrorKind::Connection
             | RecognizeErrorKind::InvalidToken
-            | RecognizeErrorKind::TokenLimitReached => {
+            | RecognizeErrorKind::TokenLimitReached
+            | RecognizeErrorKind::RateLimited => {
                 unreachable!("recording with non permanent errors must be saved instead")
             }
         }
@@ -318,6 +1526,86 @@ impl Window {
         dialog.present();
     }
 
+    /// Explains how to grant access to the microphone, retrying with
+    /// `retry_action_name` once the user did.
+    fn present_mic_access_denied(&self, retry_action_name: &'static str) {
+        const CANCEL_RESPONSE_ID: &str = "cancel";
+        const OPEN_SETTINGS_RESPONSE_ID: &str = "open-settings";
+        const TRY_AGAIN_RESPONSE_ID: &str = "try-again";
+
+        let dialog = adw::MessageDialog::builder()
+            .transient_for(self)
+            .modal(true)
+            .heading(gettext("Microphone Access Denied"))
+            .body(gettext(
+                "Allow Mousai to use the microphone in the privacy settings, then try again",
+            ))
+            .default_response(TRY_AGAIN_RESPONSE_ID)
+            .close_response(CANCEL_RESPONSE_ID)
+            .build();
+        dialog.add_response(CANCEL_RESPONSE_ID, &gettext("_Cancel"));
+        dialog.add_response(OPEN_SETTINGS_RESPONSE_ID, &gettext("_Open Settings"));
+        dialog.add_response(TRY_AGAIN_RESPONSE_ID, &gettext("_Try Again"));
+        dialog.set_response_appearance(TRY_AGAIN_RESPONSE_ID, adw::ResponseAppearance::Suggested);
+
+        dialog.connect_response(
+            Some(OPEN_SETTINGS_RESPONSE_ID),
+            clone!(@weak self as obj => move |_, _| {
+                utils::spawn(glib::Priority::default(), async move {
+                    if let Err(err) = mic_permission::open_settings(Some(&obj)).await {
+                        tracing::warn!("Failed to open microphone settings: {:?}", err);
+                    }
+
+                    let toast = adw::Toast::builder()
+                        .title(gettext("Allow microphone access, then try again"))
+                        .button_label(gettext("_Try Again"))
+                        .action_name(retry_action_name)
+                        .build();
+                    obj.add_toast(toast);
+                });
+            }),
+        );
+        dialog.connect_response(
+            Some(TRY_AGAIN_RESPONSE_ID),
+            clone!(@weak self as obj => move |_, _| {
+                WidgetExt::activate_action(&obj, retry_action_name, None).unwrap();
+            }),
+        );
+
+        dialog.present();
+    }
+
+    /// Only one of this is shown at a time, as the following writes likely
+    /// fail for the same reason, e.g., the disk is full.
+    fn present_write_failed_message(&self, message: &str) {
+        let imp = self.imp();
+
+        if imp.is_write_failed_message_shown.get() {
+            return;
+        }
+
+        let dialog = adw::MessageDialog::builder()
+            .transient_for(self)
+            .modal(true)
+            .heading(gettext("Failed to Save Changes"))
+            .body(gettext(
+                "The change was reverted. Make sure there is enough free disk space and try again",
+            ))
+            .extra_child(&report_details_expander(&anyhow::anyhow!("{}", message)))
+            .build();
+        dialog.add_response("ok", &gettext("Ok, Got It"));
+        dialog.set_default_response(Some("ok"));
+        dialog.connect_response(
+            None,
+            clone!(@weak self as obj => move |_, _| {
+                obj.imp().is_write_failed_message_shown.set(false);
+            }),
+        );
+
+        imp.is_write_failed_message_shown.set(true);
+        dialog.present();
+    }
+
     fn present_recording_saved_message(&self, cause: &RecognizeError) {
         debug_assert!(!cause.is_permanent(), "permanent errors must not be saved");
 
@@ -338,6 +1626,16 @@ impl Window {
                 dialog.add_response(OK_RESPONSE_ID, &gettext("Ok, Got It"));
                 dialog.set_default_response(Some(OK_RESPONSE_ID));
             }
+            RecognizeErrorKind::RateLimited => {
+                const OK_RESPONSE_ID: &str = "ok";
+
+                dialog.set_body(&gettext(
+                    "The result will be available when the server accepts requests again",
+                ));
+
+                dialog.add_response(OK_RESPONSE_ID, &gettext("Ok, Got It"));
+                dialog.set_default_response(Some(OK_RESPONSE_ID));
+            }
             RecognizeErrorKind::TokenLimitReached | RecognizeErrorKind::InvalidToken => {
                 const NO_RESPONSE_ID: &str = "no";
                 const OPEN_RESPONSE_ID: &str = "open";
@@ -414,6 +1712,21 @@ impl Window {
         Ok(())
     }
 
+    fn save_last_view(&self) -> Result<()> {
+        let main_view = self.imp().main_view.get();
+        let settings = Application::get().settings();
+
+        settings.try_set_last_search(main_view.search_text().trim())?;
+        settings.try_set_last_shown_song(
+            main_view
+                .visible_song()
+                .as_ref()
+                .map_or("", |song| song.id_ref().as_str()),
+        )?;
+
+        Ok(())
+    }
+
     fn update_toggle_playback_action(&self) {
         self.action_set_enabled("win.toggle-playback", self.imp().player.song().is_some());
     }
@@ -425,6 +1738,32 @@ impl Window {
         self.action_set_enabled("win.toggle-search", is_main_page_visible);
     }
 
+    fn update_narrow_breakpoint(&self) {
+        let imp = self.imp();
+        let settings = Application::get().settings();
+
+        let screen_size = if self.is_realized() {
+            let surface = self.surface();
+            surface
+                .display()
+                .monitor_at_surface(&surface)
+                .and_then(|monitor| ScreenSize::of(&monitor))
+        } else {
+            None
+        };
+        let max_width = narrow_max_width(
+            settings.narrow_breakpoint(),
+            screen_size,
+            settings.narrow_on_small_screens_only(),
+        );
+        tracing::debug!(?screen_size, max_width, "Updated narrow breakpoint");
+
+        imp.narrow_breakpoint
+            .get()
+            .unwrap()
+            .set_condition(Some(&narrow_condition(max_width)));
+    }
+
     fn update_stack(&self) {
         let imp = self.imp();
 
@@ -447,8 +1786,8 @@ impl Window {
                 obj.update_song_bar_revealer();
             }));
         imp.player
-            .connect_error(clone!(@weak self as obj => move |_, _| {
-                obj.add_message_toast(&gettext("An error occurred in the player"));
+            .connect_error(clone!(@weak self as obj => move |_, error| {
+                obj.add_message_toast(&error.to_string());
             }));
 
         imp.song_bar
@@ -466,6 +1805,30 @@ impl Window {
             .connect_visible_child_notify(clone!(@weak self as obj => move |_| {
                 obj.update_toggle_search_action();
             }));
+
+        let settings = Application::get().settings();
+        settings.connect_sound_trigger_changed(clone!(@weak self as obj => move |_| {
+            obj.update_sound_trigger();
+        }));
+        settings.connect_sound_trigger_threshold_changed(clone!(@weak self as obj => move |_| {
+            obj.imp().sound_trigger.stop();
+            obj.update_sound_trigger();
+        }));
+        settings.connect_ring_recording_changed(clone!(@weak self as obj => move |_| {
+            obj.update_ring_recorder();
+        }));
+        settings.connect_ring_recording_duration_changed(clone!(@weak self as obj => move |_| {
+            obj.imp().ring_recorder.stop();
+            obj.update_ring_recorder();
+        }));
+        settings.connect_preferred_audio_source_changed(clone!(@weak self as obj => move |_| {
+            obj.imp().ring_recorder.stop();
+            obj.update_ring_recorder();
+        }));
+        settings.connect_recording_gain_changed(clone!(@weak self as obj => move |_| {
+            obj.imp().ring_recorder.stop();
+            obj.update_ring_recorder();
+        }));
     }
 
     fn update_song_bar_revealer(&self) {
@@ -476,6 +1839,252 @@ impl Window {
     }
 }
 
+async fn export_songs_to_file(songs: &[Song], file: &gio::File) -> Result<()> {
+    let format = file
+        .basename()
+        .and_then(|file_name| ExportFormat::from_file_name(&file_name.to_string_lossy()))
+        .context("Unsupported file type")?;
+
+    let text = export::export(format, songs)?;
+
+    file.replace_contents_future(
+        text.into_bytes(),
+        None,
+        false,
+        gio::FileCreateFlags::REPLACE_DESTINATION,
+    )
+    .await
+    .map_err(|(_, err)| err)
+    .context("Failed to write file")?;
+
+    Ok(())
+}
+
+/// Saves the recording that `song` was recognized from as the sample of the
+/// song with `song_id`, if enabled.
+async fn save_recognized_sample(song_id: &Uid, song: &Song, recording_bytes: &glib::Bytes) {
+    let app = Application::get();
+    let settings = app.settings();
+
+    if !settings.save_recorded_samples() {
+        return;
+    }
+
+    let tags = settings
+        .tag_recorded_samples()
+        .then(|| SampleTags::for_song(song));
+    if let Err(err) = app
+        .sample_store()
+        .save(song_id, recording_bytes.clone(), tags)
+        .await
+    {
+        tracing::warn!("Failed to save recorded sample: {:?}", err);
+    }
+}
+
+fn stats_window(stats: &database::Stats) -> adw::Window {
+    fn new_row(title: &str, value: &str) -> adw::ActionRow {
+        let row = adw::ActionRow::builder().title(title).build();
+        row.add_suffix(
+            &gtk::Label::builder()
+                .label(value)
+                .css_classes(["dim-label", "numeric"])
+                .build(),
+        );
+        row
+    }
+
+    let percent = |rate: f64| format!("{:.0}%", rate * 100.0);
+
+    let page = adw::PreferencesPage::new();
+
+    let summary_group = adw::PreferencesGroup::builder()
+        .description(gettext(
+            "Each song in the history is counted once, at the time it was last heard.",
+        ))
+        .build();
+    summary_group.add(&new_row(&gettext("Songs"), &stats.n_songs.to_string()));
+    summary_group.add(&new_row(&gettext("Matches"), &stats.n_matches.to_string()));
+    summary_group.add(&new_row(
+        &gettext("No Matches"),
+        &stats.n_no_matches.to_string(),
+    ));
+    if let Some(match_rate) = stats.match_rate() {
+        summary_group.add(&new_row(&gettext("Match Rate"), &percent(match_rate)));
+    }
+    if let Some(weekday) = stats.busiest_weekday() {
+        let weekday_names = [
+            gettext("Monday"),
+            gettext("Tuesday"),
+            gettext("Wednesday"),
+            gettext("Thursday"),
+            gettext("Friday"),
+            gettext("Saturday"),
+            gettext("Sunday"),
+        ];
+        summary_group.add(&new_row(
+            &gettext("Busiest Day"),
+            &weekday_names[weekday as usize - 1],
+        ));
+    }
+    if let Some(hour) = stats.busiest_hour() {
+        summary_group.add(&new_row(
+            &gettext("Busiest Hour"),
+            &format!("{:02}:00–{:02}:00", hour, (hour + 1) % 24),
+        ));
+    }
+    page.add(&summary_group);
+
+    if !stats.top_artists.is_empty() {
+        let top_artists_group = adw::PreferencesGroup::builder()
+            .title(gettext("Top Artists"))
+            .build();
+        for (artist, n_songs) in &stats.top_artists {
+            top_artists_group.add(&new_row(artist, &n_songs.to_string()));
+        }
+        page.add(&top_artists_group);
+    }
+
+    if !stats.match_rate_per_month.is_empty() {
+        let match_rate_group = adw::PreferencesGroup::builder()
+            .title(gettext("Match Rate Over Time"))
+            .build();
+        for ((year, month), match_rate) in stats.match_rate_per_month.iter().rev() {
+            match_rate_group.add(&new_row(
+                &format!("{}-{:02}", year, month),
+                &percent(*match_rate),
+            ));
+        }
+        page.add(&match_rate_group);
+    }
+
+    let toolbar_view = adw::ToolbarView::new();
+    toolbar_view.add_top_bar(&adw::HeaderBar::new());
+    toolbar_view.set_content(Some(&page));
+
+    adw::Window::builder()
+        .title(gettext("History Statistics"))
+        .modal(true)
+        .default_width(400)
+        .default_height(560)
+        .content(&toolbar_view)
+        .build()
+}
+
+/// Monitors with a diagonal up to this size are considered small, which
+/// covers phones and most tablets.
+const SMALL_SCREEN_MAX_DIAGONAL_INCHES: f64 = 11.0;
+
+/// Physical size of a monitor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ScreenSize {
+    width_mm: i32,
+    height_mm: i32,
+}
+
+impl ScreenSize {
+    /// Returns `None` if the monitor does not report its physical size,
+    /// which is common on virtual displays and some projectors.
+    fn of(monitor: &gdk::Monitor) -> Option<Self> {
+        let width_mm = monitor.width_mm();
+        let height_mm = monitor.height_mm();
+
+        if width_mm <= 0 || height_mm <= 0 {
+            return None;
+        }
+
+        Some(Self {
+            width_mm,
+            height_mm,
+        })
+    }
+
+    fn is_small(self) -> bool {
+        let diagonal_mm = (self.width_mm as f64).hypot(self.height_mm as f64);
+        diagonal_mm / 25.4 <= SMALL_SCREEN_MAX_DIAGONAL_INCHES
+    }
+}
+
+/// Returns the window width at or below which the narrow mode is used.
+///
+/// If `small_screens_only` is set, this is 0, i.e., the narrow mode is never
+/// used, on screens that are not small. Screens of unknown size fall back to
+/// the window width alone.
+fn narrow_max_width(
+    max_width: u32,
+    screen_size: Option<ScreenSize>,
+    small_screens_only: bool,
+) -> u32 {
+    match screen_size {
+        Some(screen_size) if small_screens_only && !screen_size.is_small() => 0,
+        _ => max_width,
+    }
+}
+
+/// Returns a breakpoint that switches the view to narrow mode when the width
+/// is at most `max_width` pixels.
+fn new_narrow_breakpoint(max_width: u32, main_view: &HistoryView) -> adw::Breakpoint {
+    let breakpoint = adw::Breakpoint::new(narrow_condition(max_width));
+    breakpoint.add_setter(main_view, "adaptive-mode", &AdaptiveMode::Narrow.to_value());
+    breakpoint
+}
+
+fn narrow_condition(max_width: u32) -> adw::BreakpointCondition {
+    adw::BreakpointCondition::new_length(
+        adw::BreakpointConditionLengthType::MaxWidth,
+        max_width as f64,
+        adw::LengthUnit::Px,
+    )
+}
+
+/// Returns an expander containing a copyable report of `err`, with
+/// the API token redacted.
+fn report_details_expander(err: &anyhow::Error) -> gtk::Expander {
+    let api_token = Application::get().settings().aud_d_api_token();
+    let report = diagnostics::error_report(err, &[&api_token]);
+
+    let label = gtk::Label::builder()
+        .label(&report)
+        .selectable(true)
+        .wrap(true)
+        .wrap_mode(gtk::pango::WrapMode::WordChar)
+        .xalign(0.0)
+        .valign(gtk::Align::Start)
+        .css_classes(["monospace", "caption"])
+        .build();
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .min_content_height(120)
+        .max_content_height(240)
+        .propagate_natural_height(true)
+        .child(&label)
+        .build();
+
+    let copy_button = gtk::Button::builder()
+        .label(gettext("Copy Details"))
+        .halign(gtk::Align::End)
+        .build();
+    copy_button.connect_clicked(move |button| {
+        button.display().clipboard().set_text(&report);
+        Application::get()
+            .window()
+            .add_message_toast(&gettext("Copied to clipboard"));
+    });
+
+    let vbox = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(6)
+        .margin_top(6)
+        .build();
+    vbox.append(&scrolled_window);
+    vbox.append(&copy_button);
+
+    gtk::Expander::builder()
+        .label(gettext("Report Details"))
+        .child(&vbox)
+        .build()
+}
+
 #[gtk::template_callbacks]
 impl Window {
     #[template_callback]
@@ -501,3 +2110,131 @@ impl Window {
         false
     }
 }
+
+/// Registers the app's resources once, so widgets with templates can be
+/// created in tests.
+#[cfg(test)]
+fn init_gresources() {
+    use std::sync::Once;
+
+    static GRESOURCES_INIT: Once = Once::new();
+
+    GRESOURCES_INIT.call_once(|| {
+        let res = gio::Resource::load(crate::RESOURCES_FILE).unwrap();
+        gio::resources_register(&res);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn startup_dispatch() {
+        assert_eq!(
+            StartupDispatch::new(StartupAction::ShowHistory, "query", "id"),
+            StartupDispatch::ShowHistory
+        );
+        assert_eq!(
+            StartupDispatch::new(StartupAction::RestoreLastView, "query", "id"),
+            StartupDispatch::RestoreView {
+                search: "query".to_string(),
+                song_id: Some(Uid::from("id")),
+            }
+        );
+        assert_eq!(
+            StartupDispatch::new(StartupAction::RestoreLastView, "", ""),
+            StartupDispatch::RestoreView {
+                search: String::new(),
+                song_id: None,
+            }
+        );
+        assert_eq!(
+            StartupDispatch::new(StartupAction::StartRecognizing, "query", "id"),
+            StartupDispatch::StartRecognizing
+        );
+    }
+
+    #[gtk::test]
+    fn narrow_breakpoint() {
+        gst::init().unwrap(); // For Player
+
+        let main_view = HistoryView::new();
+        let bin = adw::BreakpointBin::new();
+        bin.set_size_request(360, 300);
+        bin.set_child(Some(&main_view));
+        bin.add_breakpoint(new_narrow_breakpoint(600, &main_view));
+
+        for (width, expected) in [
+            (360, AdaptiveMode::Narrow),
+            (450, AdaptiveMode::Narrow),
+            (600, AdaptiveMode::Narrow),
+            (601, AdaptiveMode::Normal),
+            (1200, AdaptiveMode::Normal),
+            (500, AdaptiveMode::Narrow),
+        ] {
+            bin.measure(gtk::Orientation::Horizontal, -1);
+            bin.allocate(width, 300, -1, None);
+            assert_eq!(main_view.adaptive_mode(), expected, "width: {}", width);
+        }
+
+        let breakpoint = bin.current_breakpoint().unwrap();
+        breakpoint.set_condition(Some(&narrow_condition(400)));
+        bin.allocate(500, 300, -1, None);
+        assert_eq!(main_view.adaptive_mode(), AdaptiveMode::Normal);
+    }
+
+    #[gtk::test]
+    fn narrow_breakpoint_small_screens_only() {
+        gst::init().unwrap(); // For Player
+
+        let main_view = HistoryView::new();
+        let bin = adw::BreakpointBin::new();
+        bin.set_size_request(360, 300);
+        bin.set_child(Some(&main_view));
+        let breakpoint = new_narrow_breakpoint(450, &main_view);
+        bin.add_breakpoint(breakpoint.clone());
+
+        let phone = Some(ScreenSize {
+            width_mm: 68,
+            height_mm: 136,
+        });
+        let tablet = Some(ScreenSize {
+            width_mm: 217,
+            height_mm: 136,
+        });
+        let desktop = Some(ScreenSize {
+            width_mm: 597,
+            height_mm: 336,
+        });
+
+        for (width, screen_size, small_screens_only, expected) in [
+            (360, phone, false, AdaptiveMode::Narrow),
+            (360, phone, true, AdaptiveMode::Narrow),
+            (800, phone, true, AdaptiveMode::Normal),
+            (450, tablet, true, AdaptiveMode::Narrow),
+            (451, tablet, true, AdaptiveMode::Normal),
+            (400, desktop, false, AdaptiveMode::Narrow),
+            (400, desktop, true, AdaptiveMode::Normal),
+            (1200, desktop, true, AdaptiveMode::Normal),
+            (400, None, false, AdaptiveMode::Narrow),
+            (400, None, true, AdaptiveMode::Narrow),
+        ] {
+            breakpoint.set_condition(Some(&narrow_condition(narrow_max_width(
+                450,
+                screen_size,
+                small_screens_only,
+            ))));
+            bin.measure(gtk::Orientation::Horizontal, -1);
+            bin.allocate(width, 300, -1, None);
+            assert_eq!(
+                main_view.adaptive_mode(),
+                expected,
+                "width: {}, screen size: {:?}, small screens only: {}",
+                width,
+                screen_size,
+                small_screens_only
+            );
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+use crate::{song::Song, song_filter};
+
+/// Time since the last typed character before the prefix starts over
+const RESET_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Accumulates typed characters into a prefix to jump to, like in file managers.
+#[derive(Debug, Default)]
+pub struct QuickJump {
+    prefix: String,
+    last_typed: Option<Instant>,
+}
+
+impl QuickJump {
+    /// Appends `c` to the prefix, starting over if the previous character was
+    /// typed too long ago. Returns the normalized prefix.
+    pub fn push(&mut self, c: char, now: Instant) -> &str {
+        let is_expired = self.last_typed.map_or(true, |last_typed| {
+            now.duration_since(last_typed) > RESET_TIMEOUT
+        });
+        if is_expired {
+            self.prefix.clear();
+        }
+
+        self.prefix.push_str(&song_filter::normalize_search_term(
+            c.encode_utf8(&mut [0; 4]),
+        ));
+        self.last_typed = Some(now);
+
+        &self.prefix
+    }
+
+    /// Whether there is a prefix being typed at `now`.
+    pub fn is_typing(&self, now: Instant) -> bool {
+        !self.prefix.is_empty()
+            && self
+                .last_typed
+                .is_some_and(|last_typed| now.duration_since(last_typed) <= RESET_TIMEOUT)
+    }
+
+    pub fn reset(&mut self) {
+        self.prefix.clear();
+        self.last_typed = None;
+    }
+}
+
+/// Returns the index of the first song whose title or artist starts with the
+/// normalized `prefix`.
+pub fn find_target(songs: impl IntoIterator<Item = Song>, prefix: &str) -> Option<usize> {
+    if prefix.is_empty() {
+        return None;
+    }
+
+    songs.into_iter().position(|song| {
+        song_filter::normalize_search_term(&song.title()).starts_with(prefix)
+            || song_filter::normalize_search_term(&song.artist()).starts_with(prefix)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::uid::Uid;
+
+    fn new_test_song(title: &str, artist: &str) -> Song {
+        Song::builder(&Uid::from(title), title, artist, "Album").build()
+    }
+
+    #[test]
+    fn prefix_accumulation() {
+        let start = Instant::now();
+        let mut quick_jump = QuickJump::default();
+        assert!(!quick_jump.is_typing(start));
+
+        assert_eq!(quick_jump.push('D', start), "d");
+        assert_eq!(
+            quick_jump.push('a', start + Duration::from_millis(500)),
+            "da"
+        );
+        assert_eq!(
+            quick_jump.push(' ', start + Duration::from_millis(1000)),
+            "da "
+        );
+        assert!(quick_jump.is_typing(start + Duration::from_millis(1500)));
+
+        // Starts over after the timeout
+        assert!(!quick_jump.is_typing(start + Duration::from_millis(2500)));
+        assert_eq!(
+            quick_jump.push('b', start + Duration::from_millis(2500)),
+            "b"
+        );
+
+        quick_jump.reset();
+        assert!(!quick_jump.is_typing(start + Duration::from_millis(2500)));
+        assert_eq!(
+            quick_jump.push('c', start + Duration::from_millis(2600)),
+            "c"
+        );
+    }
+
+    #[test]
+    fn target() {
+        let songs = vec![
+            new_test_song("Amnesia", "5 Seconds of Summer"),
+            new_test_song("Get Lucky", "Daft Punk"),
+            new_test_song("Dancing Queen", "ABBA"),
+            new_test_song("Around the World", "Daft Punk"),
+        ];
+
+        assert_eq!(find_target(songs.clone(), "a"), Some(0));
+        assert_eq!(find_target(songs.clone(), "d"), Some(1));
+        assert_eq!(find_target(songs.clone(), "dan"), Some(2));
+        assert_eq!(find_target(songs.clone(), "abba"), Some(2));
+        assert_eq!(find_target(songs.clone(), "daft punk"), Some(1));
+        assert_eq!(find_target(songs.clone(), "ar"), Some(3));
+        assert_eq!(find_target(songs.clone(), "z"), None);
+        assert_eq!(find_target(songs, ""), None);
+    }
+}
@@ -8,7 +8,7 @@ use gtk::{
 use std::cell::OnceCell;
 
 use super::waveform::Waveform;
-use crate::recognizer::{Recognizer, RecognizerState};
+use crate::recognizer::{RecognizeMode, Recognizer, RecognizerState};
 
 mod imp {
     use super::*;
@@ -131,7 +131,10 @@ impl RecognizerView {
             RecognizerState::Listening => {
                 imp.waveform.clear_peaks();
                 self.recognizing_animation().pause();
-                imp.title.set_label(&gettext("Listening…"));
+                imp.title.set_label(&match self.recognizer().mode() {
+                    RecognizeMode::Music => gettext("Listening…"),
+                    RecognizeMode::Hum => gettext("Listening for Humming…"),
+                });
             }
             RecognizerState::Recognizing => {
                 imp.waveform.clear_peaks();
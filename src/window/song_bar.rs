@@ -253,7 +253,7 @@ impl SongBar {
             PlayerState::Buffering => {
                 imp.playback_button.set_mode(PlaybackButtonMode::Buffering);
             }
-            PlayerState::Stopped | PlayerState::Paused => {
+            PlayerState::Stopped | PlayerState::Paused | PlayerState::Error => {
                 imp.playback_button.set_mode(PlaybackButtonMode::Play);
             }
             PlayerState::Playing => {
@@ -306,6 +306,35 @@ pub fn format_clock_time_minute_sec(clock_time: gst::ClockTime) -> String {
 mod tests {
     use super::*;
 
+    use crate::{uid::Uid, window::init_gresources};
+
+    #[gtk::test]
+    fn bind_player() {
+        init_gresources();
+        gst::init().unwrap(); // For Player
+
+        let player = Player::new();
+        let bar = SongBar::new();
+        bar.bind_player(&player);
+
+        let imp = bar.imp();
+        assert_eq!(imp.title_label.label(), "");
+        assert!(!imp.playback_position_scale.is_sensitive());
+
+        let song = Song::builder(&Uid::from("a"), "Title", "Artist", "Album")
+            .playback_link("file:///a.ogg")
+            .build();
+        player.set_song(Some(song));
+        assert_eq!(imp.title_label.label(), "Title");
+        assert_eq!(imp.artist_label.label(), "Artist");
+        assert!(imp.playback_position_scale.is_sensitive());
+        assert_eq!(imp.playback_button.mode(), PlaybackButtonMode::Play);
+
+        player.set_song(Song::NONE);
+        assert_eq!(imp.title_label.label(), "");
+        assert!(!imp.playback_position_scale.is_sensitive());
+    }
+
     #[test]
     fn test_format_clock_time_minute_sec() {
         #[track_caller]
@@ -2,10 +2,18 @@ use adw::{
     prelude::*,
     subclass::{navigation_page::NavigationPageImpl, prelude::*},
 };
+use anyhow::{bail, Context, Result};
 use gettextrs::gettext;
-use gtk::glib::{self, clone, closure_local};
+use gtk::{
+    gdk, gio,
+    glib::{self, clone, closure_local},
+    graphene, gsk,
+};
 
-use std::cell::{Cell, RefCell};
+use std::{
+    cell::{Cell, RefCell},
+    time::Duration,
+};
 
 use super::{
     album_cover::AlbumCover,
@@ -15,7 +23,15 @@ use super::{
     AdaptiveMode,
 };
 use crate::{
+    album_art,
+    date_time::DateTime,
+    deep_link, export,
+    external_links::ExternalLinkKey,
+    i18n::gettext_f,
+    lyrics::{self, Lyrics, TimedLine},
+    paths,
     player::{Player, PlayerState},
+    sample_store, share_card,
     song::Song,
     song_list::SongList,
     Application,
@@ -24,6 +40,10 @@ use crate::{
 const NORMAL_ALBUM_COVER_PIXEL_SIZE: i32 = 180;
 const NARROW_ALBUM_COVER_PIXEL_SIZE: i32 = 120;
 
+/// Opacity of the album art accent at the top of the page
+const DARK_ACCENT_OPACITY: f32 = 0.3;
+const LIGHT_ACCENT_OPACITY: f32 = 0.2;
+
 mod imp {
     use super::*;
     use glib::{once_cell::sync::Lazy, subclass::Signal, WeakRef};
@@ -36,10 +56,21 @@ mod imp {
         pub(super) song: RefCell<Option<Song>>,
         #[property(get, set = Self::set_adaptive_mode, explicit_notify, builder(AdaptiveMode::default()))]
         pub(super) adaptive_mode: Cell<AdaptiveMode>,
+        /// Whether to tint the background with the album art's color
+        #[property(get, set = Self::set_shows_album_art_accent, explicit_notify)]
+        pub(super) shows_album_art_accent: Cell<bool>,
 
         #[template_child]
         pub(super) remove_button: TemplateChild<gtk::Button>,
         #[template_child]
+        pub(super) annotate_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub(super) favorite_button: TemplateChild<gtk::ToggleButton>,
+        #[template_child]
+        pub(super) open_lyrics_link_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub(super) open_sample_button: TemplateChild<gtk::Button>,
+        #[template_child]
         pub(super) album_cover: TemplateChild<AlbumCover>,
         #[template_child]
         pub(super) playback_button: TemplateChild<PlaybackButton>,
@@ -50,13 +81,21 @@ mod imp {
         #[template_child]
         pub(super) release_date_row: TemplateChild<InformationRow>,
         #[template_child]
+        pub(super) source_row: TemplateChild<InformationRow>,
+        #[template_child]
         pub(super) external_links_box: TemplateChild<gtk::FlowBox>,
         #[template_child]
         pub(super) lyrics_group: TemplateChild<adw::PreferencesGroup>,
         #[template_child]
         pub(super) lyrics_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub(super) timed_lyrics_box: TemplateChild<gtk::Box>,
+
+        pub(super) timed_lyrics: RefCell<Vec<TimedLine>>,
+        pub(super) current_lyrics_line: Cell<Option<usize>>,
+        pub(super) accent: Cell<Option<gdk::RGBA>>,
 
-        pub(super) player: RefCell<Option<(WeakRef<Player>, glib::SignalHandlerId)>>, // Player and Player's state notify handler id
+        pub(super) player: RefCell<Option<(WeakRef<Player>, [glib::SignalHandlerId; 2])>>, // Player and Player's state and position notify handler ids
         pub(super) song_list: RefCell<Option<(WeakRef<SongList>, glib::SignalHandlerId)>>, // SongList and SongList's items changed handler id
         pub(super) song_binding_group: glib::BindingGroup,
     }
@@ -72,11 +111,74 @@ mod imp {
 
             klass.install_action("song-page.copy-song", None, |obj, _, _| {
                 let song = obj.song().expect("song should be set");
-                obj.display().clipboard().set_text(&song.copy_term());
+                let settings = Application::get().settings();
+                let text = export::copy_text(
+                    settings.copy_format(),
+                    settings.artist_title_order(),
+                    &[song],
+                )
+                .expect("song is given");
+                obj.display().clipboard().set_text(&text);
                 Application::get()
                     .window()
                     .add_message_toast(&gettext("Copied to clipboard"));
             });
+
+            klass.install_action("song-page.copy-link", None, |obj, _, _| {
+                let song = obj.song().expect("song should be set");
+                obj.display()
+                    .clipboard()
+                    .set_text(&deep_link::song_uri(song.id_ref()));
+                Application::get()
+                    .window()
+                    .add_message_toast(&gettext("Copied link to clipboard"));
+            });
+
+            klass.install_action("song-page.copy-card", None, |obj, _, _| {
+                if let Err(err) = obj.copy_card() {
+                    tracing::error!("Failed to copy card: {:?}", err);
+                    Application::get()
+                        .window()
+                        .add_message_toast(&gettext("Failed to copy card"));
+                }
+            });
+
+            klass.install_action("song-page.copy-journal-entry", None, |obj, _, _| {
+                let song = obj.song().expect("song should be set");
+                let Some(last_heard) = song.last_heard() else {
+                    tracing::debug!("Song was never heard; there is no journal entry");
+                    return;
+                };
+                Application::get().window().copy_journal_entry(&last_heard);
+            });
+
+            klass.install_action_async("song-page.rerecognize", None, |obj, _, _| async move {
+                let song = obj.song().expect("song should be set");
+                obj.action_set_enabled("song-page.rerecognize", false);
+                Application::get().window().rerecognize_song(&song).await;
+                obj.action_set_enabled("song-page.rerecognize", true);
+            });
+
+            klass.install_action_async("song-page.annotate", None, |obj, _, _| async move {
+                let song = obj.song().expect("song should be set");
+                obj.annotate(&song).await;
+            });
+
+            klass.install_action("song-page.open-playback-link", None, |obj, _, _| {
+                let song = obj.song().expect("song should be set");
+                obj.open_playback_link(&song);
+            });
+
+            klass.install_action("song-page.open-sample", None, |obj, _, _| {
+                let song = obj.song().expect("song should be set");
+                obj.open_sample(&song);
+            });
+
+            klass.install_action("song-page.open-lyrics-link", None, |obj, _, _| {
+                let song = obj.song().expect("song should be set");
+                let uri = lyrics_link(&song).expect("song should have a lyrics link");
+                obj.launch_uri(uri);
+            });
         }
 
         fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
@@ -112,13 +214,32 @@ mod imp {
                     obj.toggle_playback();
                 }));
 
+            self.album_cover
+                .connect_texture_notify(clone!(@weak obj => move |_| {
+                    obj.update_accent();
+                }));
+
             self.external_links_box.connect_child_activated(|_, child| {
                 let external_link_tile = child.downcast_ref::<ExternalLinkTile>().unwrap();
                 external_link_tile.handle_activation();
             });
 
+            // Last heard and favorite are the only song properties that can
+            // change while the page is shown, e.g., when the song is recognized again,
+            // besides the title and artist of annotated placeholders.
             self.song_binding_group
-                .bind("lyrics", &self.lyrics_label.get(), "label")
+                .bind("title", &*obj, "title")
+                .build();
+            self.song_binding_group
+                .bind("is-favorite", &self.favorite_button.get(), "active")
+                .bidirectional()
+                .build();
+            self.song_binding_group
+                .bind("last-heard", &self.last_heard_row.get(), "value")
+                .transform_to(|_, value| {
+                    let last_heard = value.get::<Option<DateTime>>().unwrap();
+                    Some(last_heard_display(last_heard.as_ref()).into())
+                })
                 .build();
             self.song_binding_group
                 .bind("lyrics", &self.lyrics_group.get(), "visible")
@@ -128,6 +249,8 @@ mod imp {
                 })
                 .build();
 
+            obj.update_lyrics_link();
+            obj.update_sample();
             obj.update_information();
             obj.update_page_title();
             obj.update_album_cover_size();
@@ -141,7 +264,33 @@ mod imp {
         }
     }
 
-    impl WidgetImpl for SongPage {}
+    impl WidgetImpl for SongPage {
+        fn snapshot(&self, snapshot: &gtk::Snapshot) {
+            if let Some(accent) = self.accent.get() {
+                let obj = self.obj();
+                let width = obj.width() as f32;
+                let height = obj.height() as f32;
+
+                let opacity = if adw::StyleManager::default().is_dark() {
+                    DARK_ACCENT_OPACITY
+                } else {
+                    LIGHT_ACCENT_OPACITY
+                };
+
+                snapshot.append_linear_gradient(
+                    &graphene::Rect::new(0.0, 0.0, width, height),
+                    &graphene::Point::new(0.0, 0.0),
+                    &graphene::Point::new(0.0, height),
+                    &[
+                        gsk::ColorStop::new(0.0, accent.with_alpha(opacity)),
+                        gsk::ColorStop::new(1.0, accent.with_alpha(0.0)),
+                    ],
+                );
+            }
+
+            self.parent_snapshot(snapshot);
+        }
+    }
     impl NavigationPageImpl for SongPage {}
 
     impl SongPage {
@@ -165,8 +314,12 @@ mod imp {
             self.album_cover.set_song(song.as_ref());
 
             self.song.replace(song);
+            obj.update_lyrics();
+            obj.update_lyrics_link();
+            obj.update_sample();
             obj.update_playback_ui();
             obj.update_remove_button_sensitivity();
+            obj.update_annotate_button_visibility();
             obj.update_information();
             obj.update_page_title();
 
@@ -184,6 +337,18 @@ mod imp {
             obj.update_album_cover_size();
             obj.notify_adaptive_mode();
         }
+
+        fn set_shows_album_art_accent(&self, shows_album_art_accent: bool) {
+            let obj = self.obj();
+
+            if shows_album_art_accent == obj.shows_album_art_accent() {
+                return;
+            }
+
+            self.shows_album_art_accent.set(shows_album_art_accent);
+            obj.update_accent();
+            obj.notify_shows_album_art_accent();
+        }
     }
 }
 
@@ -212,21 +377,29 @@ impl SongPage {
 
     /// Must only be called when no player was already bound.
     pub fn bind_player(&self, player: &Player) {
-        let handler_id = player.connect_state_notify(clone!(@weak self as obj => move |_| {
+        let state_handler_id = player.connect_state_notify(clone!(@weak self as obj => move |_| {
             obj.update_playback_ui();
         }));
+        let position_handler_id =
+            player.connect_position_notify(clone!(@weak self as obj => move |_| {
+                obj.update_current_lyrics_line();
+            }));
 
-        self.imp()
-            .player
-            .replace(Some((player.downgrade(), handler_id)));
+        self.imp().player.replace(Some((
+            player.downgrade(),
+            [state_handler_id, position_handler_id],
+        )));
 
         self.update_playback_ui();
+        self.update_current_lyrics_line();
     }
 
     pub fn unbind_player(&self) {
-        if let Some((player, handler_id)) = self.imp().player.take() {
+        if let Some((player, handler_ids)) = self.imp().player.take() {
             if let Some(player) = player.upgrade() {
-                player.disconnect(handler_id);
+                for handler_id in handler_ids {
+                    player.disconnect(handler_id);
+                }
             }
         }
     }
@@ -278,15 +451,72 @@ impl SongPage {
         }
     }
 
+    /// Opens the song's playback link in the default media player, instead
+    /// of playing it with the internal player.
+    fn open_playback_link(&self, song: &Song) {
+        let uri = match playback_link_launch_uri(song) {
+            Ok(uri) => uri,
+            Err(err) => {
+                tracing::warn!("Failed to get playback link launch uri: {:?}", err);
+                Application::get()
+                    .window()
+                    .add_message_toast(&gettext("Invalid playback link"));
+                return;
+            }
+        };
+
+        self.launch_uri(uri);
+    }
+
+    /// Opens the recorded sample of the song in the default app.
+    fn open_sample(&self, song: &Song) {
+        let file = gio::File::for_path(Application::get().sample_store().path(song.id_ref()));
+
+        gtk::FileLauncher::new(Some(&file)).launch(
+            self.root()
+                .map(|root| root.downcast::<gtk::Window>().unwrap())
+                .as_ref(),
+            gio::Cancellable::NONE,
+            |res| {
+                if let Err(err) = res {
+                    tracing::warn!("Failed to launch sample: {:?}", err);
+                    Application::get()
+                        .window()
+                        .add_message_toast(&gettext("Failed to play recorded sample"));
+                }
+            },
+        );
+    }
+
+    fn launch_uri(&self, uri: String) {
+        gtk::UriLauncher::new(&uri).launch(
+            self.root()
+                .map(|root| root.downcast::<gtk::Window>().unwrap())
+                .as_ref(),
+            gio::Cancellable::NONE,
+            move |res| {
+                if let Err(err) = res {
+                    tracing::warn!("Failed to launch default for uri `{}`: {:?}", uri, err);
+                    Application::get().window().add_message_toast(&gettext_f(
+                        // Translators: Do NOT translate the contents between '{' and '}', this is a variable name.
+                        "Failed to open {uri}",
+                        &[("uri", &uri)],
+                    ));
+                }
+            },
+        );
+    }
+
     fn update_playback_ui(&self) {
         let imp = self.imp();
         let song = self.song();
 
-        imp.playback_button.set_visible(
-            song.as_ref()
-                .and_then(|song| song.playback_link())
-                .is_some(),
-        );
+        let has_playback_link = song
+            .as_ref()
+            .and_then(|song| song.playback_link())
+            .is_some();
+        imp.playback_button.set_visible(has_playback_link);
+        self.action_set_enabled("song-page.open-playback-link", has_playback_link);
 
         if let Some(ref song) = song {
             let player = self.player();
@@ -300,6 +530,120 @@ impl SongPage {
             } else {
                 imp.playback_button.set_mode(PlaybackButtonMode::Play);
             }
+
+            // Retrying is done by pressing play again
+            if is_active_song && player_state == PlayerState::Error {
+                imp.playback_button.add_css_class("error");
+            } else {
+                imp.playback_button.remove_css_class("error");
+            }
+        }
+    }
+
+    /// Shows timed lyrics line by line, so the current line can be highlighted,
+    /// and other lyrics as a single block.
+    fn update_lyrics(&self) {
+        let imp = self.imp();
+
+        while let Some(child) = imp.timed_lyrics_box.first_child() {
+            imp.timed_lyrics_box.remove(&child);
+        }
+        imp.current_lyrics_line.set(None);
+
+        let lyrics = self
+            .song()
+            .and_then(|song| song.lyrics())
+            .map(|lyrics| Lyrics::parse(&lyrics));
+
+        let timed_lines = match lyrics {
+            Some(Lyrics::Timed(lines)) => {
+                for line in &lines {
+                    let label = gtk::Label::builder()
+                        .label(&line.text)
+                        .wrap(true)
+                        .xalign(0.0)
+                        .css_classes(["lyrics-label", "dim-label"])
+                        .build();
+                    imp.timed_lyrics_box.append(&label);
+                }
+                imp.lyrics_label.set_label("");
+                lines
+            }
+            Some(Lyrics::Plain(text)) => {
+                imp.lyrics_label.set_label(&text);
+                Vec::new()
+            }
+            None => {
+                imp.lyrics_label.set_label("");
+                Vec::new()
+            }
+        };
+
+        imp.lyrics_label.set_visible(timed_lines.is_empty());
+        imp.timed_lyrics_box.set_visible(!timed_lines.is_empty());
+        imp.timed_lyrics.replace(timed_lines);
+
+        if imp.player.borrow().is_some() {
+            self.update_current_lyrics_line();
+        }
+    }
+
+    fn update_lyrics_link(&self) {
+        let has_lyrics_link = self.song().and_then(|song| lyrics_link(&song)).is_some();
+        self.imp()
+            .open_lyrics_link_button
+            .set_visible(has_lyrics_link);
+        self.action_set_enabled("song-page.open-lyrics-link", has_lyrics_link);
+    }
+
+    fn update_sample(&self) {
+        let has_sample = self.song().is_some_and(|song| {
+            sample_store::path_in(&paths::samples_dir(), song.id_ref()).is_file()
+        });
+        self.imp().open_sample_button.set_visible(has_sample);
+        self.action_set_enabled("song-page.open-sample", has_sample);
+    }
+
+    fn update_current_lyrics_line(&self) {
+        let imp = self.imp();
+
+        if imp.timed_lyrics.borrow().is_empty() {
+            return;
+        }
+
+        let player = self.player();
+        let is_active_song = self
+            .song()
+            .map_or(false, |song| player.is_active_song(song.id_ref()));
+        let current_line = is_active_song
+            .then(|| {
+                lyrics::current_line_index(
+                    &imp.timed_lyrics.borrow(),
+                    Duration::from_nanos(player.position().nseconds()),
+                )
+            })
+            .flatten();
+
+        let prev_line = imp.current_lyrics_line.replace(current_line);
+        if prev_line == current_line {
+            return;
+        }
+
+        let line_widget = |index: usize| {
+            let mut child = imp.timed_lyrics_box.first_child();
+            for _ in 0..index {
+                child = child.and_then(|child| child.next_sibling());
+            }
+            child
+        };
+
+        if let Some(prev_widget) = prev_line.and_then(line_widget) {
+            prev_widget.add_css_class("dim-label");
+        }
+
+        if let Some(current_widget) = current_line.and_then(line_widget) {
+            current_widget.remove_css_class("dim-label");
+            scroll_to_widget(&current_widget);
         }
     }
 
@@ -322,6 +666,72 @@ impl SongPage {
         );
     }
 
+    /// Only placeholders can be annotated, as the info of recognized songs
+    /// comes from the provider.
+    fn update_annotate_button_visibility(&self) {
+        let imp = self.imp();
+
+        imp.annotate_button
+            .set_visible(self.song().is_some_and(|song| song.is_placeholder()));
+    }
+
+    /// Asks for the title and artist of the song, e.g., to annotate a
+    /// placeholder.
+    async fn annotate(&self, song: &Song) {
+        const CANCEL_RESPONSE_ID: &str = "cancel";
+        const SAVE_RESPONSE_ID: &str = "save";
+
+        let title_row = adw::EntryRow::builder()
+            .title(gettext("Title"))
+            .text(song.title())
+            .build();
+        let artist_row = adw::EntryRow::builder()
+            .title(gettext("Artist"))
+            .text(song.artist())
+            .build();
+        let list_box = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .css_classes(["boxed-list"])
+            .build();
+        list_box.append(&title_row);
+        list_box.append(&artist_row);
+
+        let window = Application::get().window();
+        let dialog = adw::MessageDialog::builder()
+            .transient_for(&window)
+            .modal(true)
+            .heading(gettext("Edit Song Info"))
+            .extra_child(&list_box)
+            .default_response(SAVE_RESPONSE_ID)
+            .close_response(CANCEL_RESPONSE_ID)
+            .build();
+        dialog.add_response(CANCEL_RESPONSE_ID, &gettext("_Cancel"));
+        dialog.add_response(SAVE_RESPONSE_ID, &gettext("_Save"));
+        dialog.set_response_appearance(SAVE_RESPONSE_ID, adw::ResponseAppearance::Suggested);
+
+        if dialog.choose_future().await != SAVE_RESPONSE_ID {
+            return;
+        }
+
+        let song_list = self
+            .imp()
+            .song_list
+            .borrow()
+            .as_ref()
+            .and_then(|(song_list, _)| song_list.upgrade())
+            .expect("song list must be bound");
+
+        let title = title_row.text();
+        let artist = artist_row.text();
+        if let Err(err) = song_list.update_with(song.id_ref(), |song| {
+            song.set_title(title.trim());
+            song.set_artist(artist.trim());
+        }) {
+            tracing::error!("Failed to annotate song: {:?}", err);
+            window.add_message_toast(&gettext("Failed to update song info"));
+        }
+    }
+
     fn update_information(&self) {
         let imp = self.imp();
 
@@ -343,19 +753,14 @@ impl SongPage {
             },
         );
 
-        imp.last_heard_row.set_value(
-            song.map(|song| {
-                song.last_heard().map_or_else(
-                    || gettext("Unknown").into(),
-                    |last_heard| last_heard.to_local().fuzzy_display(),
-                )
-            })
-            .unwrap_or_default(),
-        );
         imp.album_row
             .set_value(song.map(|song| song.album()).unwrap_or_default());
         imp.release_date_row.set_value(
-            song.and_then(|song| song.release_date())
+            song.and_then(|song| song.release_date_display())
+                .unwrap_or_default(),
+        );
+        imp.source_row.set_value(
+            song.map(|song| song.source().display_name())
                 .unwrap_or_default(),
         );
     }
@@ -370,6 +775,48 @@ impl SongPage {
         );
     }
 
+    /// Copies a card of the song for sharing as an image, using the album
+    /// art that is already shown.
+    fn copy_card(&self) -> Result<()> {
+        let song = self.song().expect("song should be set");
+        let album_art = self.imp().album_cover.texture();
+
+        let bytes = share_card::render(
+            &song,
+            Application::get().settings().artist_title_order(),
+            album_art.as_ref(),
+        )?;
+        self.display()
+            .clipboard()
+            .set_content(Some(&gdk::ContentProvider::for_bytes("image/png", &bytes)))
+            .context("Failed to set clipboard content")?;
+
+        Application::get()
+            .window()
+            .add_message_toast(&gettext("Copied card to clipboard"));
+
+        Ok(())
+    }
+
+    fn update_accent(&self) {
+        let imp = self.imp();
+
+        let accent = if self.shows_album_art_accent() {
+            imp.album_cover
+                .texture()
+                .and_then(|texture| album_art::dominant_color(&texture))
+        } else {
+            None
+        };
+
+        if accent == imp.accent.get() {
+            return;
+        }
+
+        imp.accent.set(accent);
+        self.queue_draw();
+    }
+
     fn update_album_cover_size(&self) {
         self.imp()
             .album_cover
@@ -385,3 +832,197 @@ impl Default for SongPage {
         Self::new()
     }
 }
+
+/// Scrolls the nearest scrolled window ancestor so `widget` is in the upper
+/// third of the view.
+fn scroll_to_widget(widget: &gtk::Widget) {
+    let Some(scrolled_window) = widget
+        .ancestor(gtk::ScrolledWindow::static_type())
+        .and_downcast::<gtk::ScrolledWindow>()
+    else {
+        return;
+    };
+
+    let Some(point) = widget.compute_point(&scrolled_window, &gtk::graphene::Point::zero()) else {
+        return;
+    };
+
+    let vadjustment = scrolled_window.vadjustment();
+    vadjustment.set_value(vadjustment.value() + point.y() as f64 - vadjustment.page_size() / 3.0);
+}
+
+fn last_heard_display(last_heard: Option<&DateTime>) -> String {
+    last_heard.map_or_else(
+        || gettext("Unknown"),
+        |last_heard| last_heard.to_local().fuzzy_display().into(),
+    )
+}
+
+/// Returns the Genius link of the song, to read its lyrics in the browser
+/// when they are not stored.
+fn lyrics_link(song: &Song) -> Option<String> {
+    if song.lyrics().is_some() {
+        return None;
+    }
+
+    song.external_links().get(ExternalLinkKey::GeniusUrl)
+}
+
+/// Returns the uri to launch for the song's playback link.
+///
+/// HTTP links are always allowed. Other schemes must be valid and have an
+/// app that can handle them.
+fn playback_link_launch_uri(song: &Song) -> Result<String> {
+    let playback_link = song
+        .playback_link()
+        .context("Song doesn't have a playback link")?;
+
+    let uri = glib::Uri::parse(&playback_link, glib::UriFlags::ENCODED)
+        .with_context(|| format!("Failed to parse playback link `{}`", playback_link))?;
+
+    let scheme = uri.scheme().to_lowercase();
+    if scheme != "http"
+        && scheme != "https"
+        && gio::AppInfo::default_for_uri_scheme(&scheme).is_none()
+    {
+        bail!("No app can handle playback link scheme `{}`", scheme);
+    }
+
+    Ok(uri.to_str().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{database, uid::Uid, window::init_gresources};
+
+    #[gtk::test]
+    fn bindings() {
+        init_gresources();
+        gst::init().unwrap(); // For Player
+
+        let player = Player::new();
+        let (env, _tempdir) = database::new_test_env();
+        let song_list = SongList::load_from_env(env).unwrap();
+
+        let song = Song::builder(&Uid::from("a"), "Hopppolla", "Sigur Rós", "Takk...")
+            .release_date("2005-09-12")
+            .playback_link("https://example.com/preview.mp3")
+            .lyrics("Some lyrics")
+            .external_link(ExternalLinkKey::AudDUrl, "https://aud_d.link/a")
+            .external_link(ExternalLinkKey::SpotifyUrl, "https://spotify.link/a")
+            .build();
+        song.set_last_heard(Some(DateTime::now_utc()));
+        song_list.insert(song.clone()).unwrap();
+
+        let page = SongPage::new();
+        page.bind_player(&player);
+        page.bind_song_list(&song_list);
+        page.set_song(Some(&song));
+
+        let imp = page.imp();
+        assert_eq!(page.title(), "Hopppolla");
+        assert_eq!(imp.album_row.value(), "Takk...");
+        assert_eq!(
+            imp.release_date_row.value(),
+            song.release_date_display().unwrap()
+        );
+        assert_eq!(imp.source_row.value(), song.source().display_name());
+        assert_eq!(
+            imp.last_heard_row.value(),
+            last_heard_display(song.last_heard().as_ref())
+        );
+        assert!(imp.lyrics_group.is_visible());
+        assert_eq!(imp.lyrics_label.label(), "Some lyrics");
+        assert!(imp.playback_button.is_visible());
+        assert!(imp.remove_button.is_sensitive());
+        assert!(!imp.annotate_button.is_visible());
+        assert!(!imp.favorite_button.is_active());
+        assert!(imp.external_links_box.child_at_index(1).is_some());
+        assert!(imp.external_links_box.child_at_index(2).is_none());
+
+        // Follows the song
+        song.set_last_heard(None);
+        assert_eq!(imp.last_heard_row.value(), "Unknown");
+        song.set_is_favorite(true);
+        assert!(imp.favorite_button.is_active());
+        song.set_title("Glósóli");
+        assert_eq!(page.title(), "Glósóli");
+
+        imp.favorite_button.set_active(false);
+        assert!(!song.is_favorite());
+
+        page.set_song(None::<&Song>);
+        assert_eq!(page.title(), "");
+        assert_eq!(imp.album_row.value(), "");
+        assert!(imp.external_links_box.child_at_index(0).is_none());
+    }
+
+    #[test]
+    fn last_heard_display_unknown() {
+        assert_eq!(last_heard_display(None), "Unknown");
+    }
+
+    #[test]
+    fn last_heard_display_now() {
+        let now = DateTime::now_utc();
+        assert_eq!(
+            last_heard_display(Some(&now)),
+            now.to_local().fuzzy_display().as_str()
+        );
+    }
+
+    #[test]
+    fn lyrics_link_genius() {
+        let song = Song::builder(&Uid::from("a"), "A", "A", "A")
+            .external_link(ExternalLinkKey::AudDUrl, "https://aud_d.link/a")
+            .build();
+        assert_eq!(lyrics_link(&song), None);
+
+        let song = Song::builder(&Uid::from("b"), "B", "B", "B")
+            .external_link(ExternalLinkKey::GeniusUrl, "https://genius.com/b-lyrics")
+            .build();
+        assert_eq!(
+            lyrics_link(&song).as_deref(),
+            Some("https://genius.com/b-lyrics")
+        );
+
+        // Stored lyrics are shown in-app instead
+        let song = Song::builder(&Uid::from("c"), "C", "C", "C")
+            .external_link(ExternalLinkKey::GeniusUrl, "https://genius.com/c-lyrics")
+            .lyrics("Some lyrics")
+            .build();
+        assert_eq!(lyrics_link(&song), None);
+    }
+
+    #[test]
+    fn playback_link_launch_uri_http() {
+        let song = Song::builder(&Uid::from("a"), "A", "A", "A")
+            .playback_link("https://example.com/preview.mp3?a=b")
+            .build();
+        assert_eq!(
+            playback_link_launch_uri(&song).unwrap(),
+            "https://example.com/preview.mp3?a=b"
+        );
+    }
+
+    #[test]
+    fn playback_link_launch_uri_none() {
+        let song = Song::builder(&Uid::from("a"), "A", "A", "A").build();
+        assert!(playback_link_launch_uri(&song).is_err());
+    }
+
+    #[test]
+    fn playback_link_launch_uri_invalid() {
+        let song = Song::builder(&Uid::from("a"), "A", "A", "A")
+            .playback_link("not a uri")
+            .build();
+        assert!(playback_link_launch_uri(&song).is_err());
+
+        let song = Song::builder(&Uid::from("b"), "B", "B", "B")
+            .playback_link("mousai-unknown-scheme://preview")
+            .build();
+        assert!(playback_link_launch_uri(&song).is_err());
+    }
+}
@@ -15,9 +15,12 @@ use super::{
 };
 use crate::{
     player::{Player, PlayerState},
+    settings::ArtistTitleOrder,
     song::Song,
+    Application,
 };
 
+const LARGE_ALBUM_COVER_PIXEL_SIZE: i32 = 260;
 const NORMAL_ALBUM_COVER_PIXEL_SIZE: i32 = 180;
 const NARROW_ALBUM_COVER_PIXEL_SIZE: i32 = 120;
 
@@ -45,9 +48,16 @@ mod imp {
         /// Current adaptive mode
         #[property(get, set = Self::set_adaptive_mode, explicit_notify, builder(AdaptiveMode::default()))]
         pub(super) adaptive_mode: Cell<AdaptiveMode>,
+        /// Whether to use an extra large album cover and text, for accessibility
+        #[property(get, set = Self::set_is_large, explicit_notify)]
+        pub(super) is_large: Cell<bool>,
         /// Whether to show select button on hover
         #[property(get, set = Self::set_shows_select_button_on_hover, explicit_notify)]
         pub(super) shows_select_button_on_hover: Cell<bool>,
+        /// Whether the song is newly heard, i.e., the user has not viewed or
+        /// played it since it was recognized
+        #[property(get, set = Self::set_is_newly_heard, explicit_notify)]
+        pub(super) is_newly_heard: Cell<bool>,
 
         #[template_child]
         pub(super) overlay: TemplateChild<gtk::Overlay>, // Unused
@@ -136,6 +146,28 @@ mod imp {
             }));
             obj.add_controller(gesture_long_press);
 
+            let drag_source = gtk::DragSource::builder()
+                .actions(gdk::DragAction::COPY)
+                .build();
+            drag_source.connect_prepare(clone!(@weak obj => @default-return None, move |_, _, _| {
+                if obj.is_selection_mode_active() {
+                    return None;
+                }
+
+                let song = obj.song()?;
+                let album_art = song.album_art();
+                Some(content_provider(
+                    &song,
+                    Application::get().settings().artist_title_order(),
+                    album_art.as_ref().and_then(|album_art| album_art.loaded_texture()),
+                ))
+            }));
+            drag_source.connect_drag_begin(clone!(@weak obj => move |drag_source, _| {
+                let paintable = gtk::WidgetPaintable::new(Some(&obj.imp().album_cover.get()));
+                drag_source.set_icon(Some(&paintable), 0, 0);
+            }));
+            obj.add_controller(drag_source);
+
             self.select_button_active_notify_handler_id
                 .set(
                     self.select_button
@@ -150,7 +182,11 @@ mod imp {
                 .unwrap();
 
             self.song_binding_group
-                .bind("is-newly-heard", &self.new_label.get(), "visible")
+                .bind("is-newly-heard", &*obj, "is-newly-heard")
+                .sync_create()
+                .build();
+            obj.bind_property("is-newly-heard", &self.new_label.get(), "visible")
+                .sync_create()
                 .build();
 
             obj.update_select_button_tooltip_text();
@@ -177,9 +213,18 @@ mod imp {
             }
 
             self.song_binding_group.set_source(song.as_ref());
+            if song.is_none() {
+                obj.set_is_newly_heard(false);
+            }
 
             self.album_cover.set_song(song.as_ref());
 
+            if song.as_ref().is_some_and(|song| song.is_placeholder()) {
+                obj.add_css_class("placeholder");
+            } else {
+                obj.remove_css_class("placeholder");
+            }
+
             self.song.replace(song);
             obj.update_playback_button_visibility();
 
@@ -208,6 +253,24 @@ mod imp {
             obj.notify_is_selected();
         }
 
+        fn set_is_newly_heard(&self, is_newly_heard: bool) {
+            let obj = self.obj();
+
+            if is_newly_heard == obj.is_newly_heard() {
+                return;
+            }
+
+            self.is_newly_heard.set(is_newly_heard);
+
+            if is_newly_heard {
+                obj.add_css_class("newly-heard");
+            } else {
+                obj.remove_css_class("newly-heard");
+            }
+
+            obj.notify_is_newly_heard();
+        }
+
         fn is_active(&self) -> bool {
             self.select_button.is_active()
         }
@@ -236,6 +299,18 @@ mod imp {
             obj.notify_adaptive_mode();
         }
 
+        fn set_is_large(&self, is_large: bool) {
+            let obj = self.obj();
+
+            if is_large == obj.is_large() {
+                return;
+            }
+
+            self.is_large.set(is_large);
+            obj.update_album_cover_size();
+            obj.notify_is_large();
+        }
+
         fn set_shows_select_button_on_hover(&self, show_select_button_on_hover: bool) {
             let obj = self.obj();
 
@@ -295,7 +370,17 @@ impl SongTile {
         }
     }
 
+    /// See [`AlbumCover::set_is_out_of_view`].
+    pub fn set_is_out_of_view(&self, is_out_of_view: bool) {
+        self.imp().album_cover.set_is_out_of_view(is_out_of_view);
+    }
+
     fn toggle_playback(&self) {
+        // Playing the preview counts as seeing the song, like opening its page
+        if let Some(song) = self.song() {
+            song.set_is_newly_heard(false);
+        }
+
         if let Some(ref player) = self
             .imp()
             .player
@@ -327,6 +412,13 @@ impl SongTile {
             } else {
                 imp.playback_button.set_mode(PlaybackButtonMode::Play);
             }
+
+            // Retrying is done by pressing play again
+            if is_active_song && player_state == PlayerState::Error {
+                imp.playback_button.add_css_class("error");
+            } else {
+                imp.playback_button.remove_css_class("error");
+            }
         }
     }
 
@@ -358,12 +450,18 @@ impl SongTile {
     }
 
     fn update_album_cover_size(&self) {
+        let adaptive_mode = self.adaptive_mode();
+        let is_large = self.is_large();
+
         self.imp()
             .album_cover
-            .set_pixel_size(match self.adaptive_mode() {
-                AdaptiveMode::Normal => NORMAL_ALBUM_COVER_PIXEL_SIZE,
-                AdaptiveMode::Narrow => NARROW_ALBUM_COVER_PIXEL_SIZE,
-            });
+            .set_pixel_size(album_cover_pixel_size(adaptive_mode, is_large));
+
+        if is_large && adaptive_mode != AdaptiveMode::Narrow {
+            self.add_css_class("large");
+        } else {
+            self.remove_css_class("large");
+        }
     }
 }
 
@@ -372,3 +470,139 @@ impl Default for SongTile {
         Self::new()
     }
 }
+
+/// Returns the text offered when dragging `song` to other apps, i.e., its
+/// copy term followed by a link to the song, if there is one.
+fn drag_text(song: &Song, order: ArtistTitleOrder) -> String {
+    match song.web_link() {
+        Some(link) => format!("{}\n{}", song.copy_term(order), link),
+        None => song.copy_term(order),
+    }
+}
+
+/// Returns the content offered when dragging `song` to other apps. The album
+/// art is only offered if it is already loaded, as it can't be downloaded
+/// while dragging.
+fn content_provider(
+    song: &Song,
+    order: ArtistTitleOrder,
+    album_art: Option<&gdk::Texture>,
+) -> gdk::ContentProvider {
+    let mut providers = vec![gdk::ContentProvider::for_value(
+        &drag_text(song, order).to_value(),
+    )];
+
+    if let Some(album_art) = album_art {
+        providers.push(gdk::ContentProvider::for_value(&album_art.to_value()));
+    }
+
+    gdk::ContentProvider::new_union(&providers)
+}
+
+/// Narrow mode takes precedence over large tiles, since there is no space for them.
+fn album_cover_pixel_size(adaptive_mode: AdaptiveMode, is_large: bool) -> i32 {
+    match adaptive_mode {
+        AdaptiveMode::Narrow => NARROW_ALBUM_COVER_PIXEL_SIZE,
+        AdaptiveMode::Normal if is_large => LARGE_ALBUM_COVER_PIXEL_SIZE,
+        AdaptiveMode::Normal => NORMAL_ALBUM_COVER_PIXEL_SIZE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{external_links::ExternalLinkKey, uid::Uid, window::init_gresources};
+
+    #[test]
+    fn album_cover_pixel_size_large() {
+        assert_eq!(
+            album_cover_pixel_size(AdaptiveMode::Normal, false),
+            NORMAL_ALBUM_COVER_PIXEL_SIZE
+        );
+        assert_eq!(
+            album_cover_pixel_size(AdaptiveMode::Normal, true),
+            LARGE_ALBUM_COVER_PIXEL_SIZE
+        );
+        assert_eq!(
+            album_cover_pixel_size(AdaptiveMode::Narrow, false),
+            NARROW_ALBUM_COVER_PIXEL_SIZE
+        );
+        assert_eq!(
+            album_cover_pixel_size(AdaptiveMode::Narrow, true),
+            NARROW_ALBUM_COVER_PIXEL_SIZE
+        );
+    }
+
+    #[gtk::test]
+    fn newly_heard_cleared_on_playback() {
+        init_gresources();
+
+        let song = Song::builder(&Uid::from("a"), "Some song", "Someone", "Album")
+            .newly_heard(true)
+            .build();
+
+        let tile = SongTile::new();
+        tile.set_song(Some(&song));
+        assert!(tile.is_newly_heard());
+        assert!(tile.has_css_class("newly-heard"));
+        assert!(tile.imp().new_label.is_visible());
+
+        tile.activate_action("song-tile.toggle-playback", None)
+            .unwrap();
+        assert!(!song.is_newly_heard());
+        assert!(!tile.is_newly_heard());
+        assert!(!tile.has_css_class("newly-heard"));
+        assert!(!tile.imp().new_label.is_visible());
+
+        // Follows the song
+        song.set_is_newly_heard(true);
+        assert!(tile.has_css_class("newly-heard"));
+
+        tile.set_song(None::<&Song>);
+        assert!(!tile.has_css_class("newly-heard"));
+    }
+
+    #[gtk::test]
+    fn drag_content() {
+        let song = Song::builder(&Uid::from("a"), "Some song", "Someone", "Album")
+            .external_link(ExternalLinkKey::AudDUrl, "https://aud_d.link/a")
+            .external_link(ExternalLinkKey::SpotifyUrl, "https://spotify.link/a")
+            .build();
+        assert_eq!(
+            drag_text(&song, ArtistTitleOrder::ArtistFirst),
+            "Someone - Some song\nhttps://spotify.link/a"
+        );
+        assert_eq!(
+            drag_text(&song, ArtistTitleOrder::TitleFirst),
+            "Some song - Someone\nhttps://spotify.link/a"
+        );
+
+        let provider = content_provider(&song, ArtistTitleOrder::ArtistFirst, None);
+        let formats = provider.formats();
+        assert!(formats.contain_gtype(glib::Type::STRING));
+        assert!(!formats.contain_gtype(gdk::Texture::static_type()));
+
+        let texture = gdk::MemoryTexture::new(
+            1,
+            1,
+            gdk::MemoryFormat::R8g8b8a8,
+            &glib::Bytes::from_static(&[0, 0, 0, 255]),
+            4,
+        );
+        let provider = content_provider(
+            &song,
+            ArtistTitleOrder::ArtistFirst,
+            Some(texture.upcast_ref()),
+        );
+        let formats = provider.formats();
+        assert!(formats.contain_gtype(glib::Type::STRING));
+        assert!(formats.contain_gtype(gdk::Texture::static_type()));
+
+        let song = Song::builder(&Uid::from("b"), "Other song", "Someone", "Album").build();
+        assert_eq!(
+            drag_text(&song, ArtistTitleOrder::ArtistFirst),
+            "Someone - Other song"
+        );
+    }
+}